@@ -0,0 +1,79 @@
+//! Replay an API trace recorded with `ContextDesc::trace_path`.
+//!
+//! Usage: `cargo run -p blade-graphics --example replay -- <trace directory>`
+
+use std::process;
+
+// Same condition as `blade_graphics::trace`: only the null and Vulkan backends can replay.
+#[cfg(any(
+    feature = "null",
+    all(
+        not(gles),
+        any(
+            vulkan,
+            windows,
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd"
+        )
+    )
+))]
+fn main() {
+    use blade_graphics as gpu;
+    use std::{env, path::PathBuf, time::Instant};
+
+    env_logger::init();
+    let dir = match env::args_os().nth(1) {
+        Some(arg) => PathBuf::from(arg),
+        None => {
+            eprintln!("Usage: replay <trace directory>");
+            process::exit(1);
+        }
+    };
+
+    let context = gpu::Context::init(gpu::ContextDesc {
+        validation: true,
+        ..Default::default()
+    })
+    .unwrap();
+    println!("Replaying on {:?}", context.device_information());
+
+    let mut replayer = match gpu::trace::Replayer::new(&context, &dir) {
+        Ok(replayer) => replayer,
+        Err(e) => {
+            eprintln!("Unable to load the trace from {}: {}", dir.display(), e);
+            process::exit(1);
+        }
+    };
+    let start = Instant::now();
+    let action_count = replayer.action_count();
+    for index in 0..action_count {
+        if let Err(e) = replayer.step() {
+            eprintln!("Action {} of {} failed: {}", index, action_count, e);
+            process::exit(1);
+        }
+    }
+    println!(
+        "Replayed {} actions in {:.2}s",
+        action_count,
+        start.elapsed().as_secs_f32()
+    );
+}
+
+#[cfg(not(any(
+    feature = "null",
+    all(
+        not(gles),
+        any(
+            vulkan,
+            windows,
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd"
+        )
+    )
+)))]
+fn main() {
+    eprintln!("Trace replay is only supported by the Vulkan and null backends");
+    process::exit(1);
+}
@@ -18,6 +18,7 @@ pub fn set_background_color(r: u8, g: u8, b: u8, a: u8) {
 
 /// Retrieves the current background color as normalized RGBA floats.
 /// Default is opaque black (0xFF000000).
+#[cfg_attr(feature = "null", allow(dead_code))]
 pub(crate) fn get_background_color() -> [f32; 4] {
     let color = VULKAN_CUSTOM_BACKGROUND_COLOR.get_or_init(|| AtomicU32::new(0xFF000000)).load(Ordering::Relaxed);
     [
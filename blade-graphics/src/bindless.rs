@@ -0,0 +1,181 @@
+//! Slots of the bindless tables.
+//!
+//! A table keeps the resources in large arrays that are indexed by the shaders,
+//! see [`BindlessTableDesc`](crate::BindlessTableDesc). The host side of every array
+//! is a list of slots with generations, so that a handle of a removed resource
+//! is recognized as stale even when its slot is taken by another resource.
+//! The GPU side is covered by retiring the freed slots: a slot is only reused
+//! once the work that could have accessed the removed resource is done.
+
+use std::collections::VecDeque;
+
+/// Handle of a resource in a bindless table.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct BindlessHandle {
+    kind: crate::BindlessKind,
+    index: u32,
+    generation: u32,
+}
+
+impl BindlessHandle {
+    pub fn kind(&self) -> crate::BindlessKind {
+        self.kind
+    }
+
+    /// Index of the resource in its array, to be used by the shaders.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    generation: u32,
+    occupied: bool,
+}
+
+#[derive(Debug)]
+struct SlotArray<R> {
+    slots: Vec<Slot>,
+    /// Slots are reused in the order they are freed, each one after its retirement
+    /// `R` is reached by the GPU.
+    free: VecDeque<(u32, R)>,
+    capacity: u32,
+}
+
+/// Slots of all the arrays of a table.
+/// `R` describes the GPU work that has to be finished before a freed slot is reused.
+#[derive(Debug)]
+pub(crate) struct BindlessSlots<R = ()> {
+    arrays: [SlotArray<R>; 3],
+}
+
+impl<R> BindlessSlots<R> {
+    pub fn new(desc: &crate::BindlessTableDesc) -> Self {
+        let array = |kind| SlotArray {
+            slots: Vec::new(),
+            free: VecDeque::new(),
+            capacity: desc.count(kind),
+        };
+        Self {
+            arrays: [
+                array(crate::BindlessKind::Texture),
+                array(crate::BindlessKind::Buffer),
+                array(crate::BindlessKind::Sampler),
+            ],
+        }
+    }
+
+    /// Take a free slot for a resource of `kind`.
+    ///
+    /// `wait` checks if the retirement of a freed slot is reached, waiting for it
+    /// up to the given number of milliseconds. A new slot is preferred to waiting,
+    /// so the GPU is only waited for when the array is full.
+    pub fn insert(
+        &mut self,
+        kind: crate::BindlessKind,
+        mut wait: impl FnMut(&R, u32) -> bool,
+    ) -> BindlessHandle {
+        let array = &mut self.arrays[kind as usize];
+        let retired = array.free.front().is_some_and(|entry| wait(&entry.1, 0));
+        let index = if retired {
+            array.free.pop_front().unwrap().0
+        } else if (array.slots.len() as u32) < array.capacity {
+            array.slots.push(Slot::default());
+            array.slots.len() as u32 - 1
+        } else {
+            let (index, retirement) = array.free.pop_front().unwrap_or_else(|| {
+                panic!(
+                    "Bindless table has no free {:?} slots out of {}",
+                    kind, array.capacity
+                )
+            });
+            log::warn!(
+                "Bindless table is full of {:?}, waiting for slot {} to be retired",
+                kind,
+                index
+            );
+            wait(&retirement, !0);
+            index
+        };
+        let slot = &mut array.slots[index as usize];
+        slot.occupied = true;
+        BindlessHandle {
+            kind,
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    /// Check that the handle refers to a resource that is still in the table.
+    pub fn contains(&self, handle: BindlessHandle) -> bool {
+        match self.arrays[handle.kind as usize]
+            .slots
+            .get(handle.index as usize)
+        {
+            Some(slot) => slot.occupied && slot.generation == handle.generation,
+            None => false,
+        }
+    }
+
+    /// Free the slot of a resource, so that the handle becomes stale.
+    /// The slot is not reused until `retirement` is reached.
+    pub fn remove(&mut self, handle: BindlessHandle, retirement: R) -> Result<(), String> {
+        if !self.contains(handle) {
+            return Err(format!(
+                "Bindless {:?} {} is removed, but it's not in the table",
+                handle.kind, handle.index
+            ));
+        }
+        let array = &mut self.arrays[handle.kind as usize];
+        let slot = &mut array.slots[handle.index as usize];
+        slot.occupied = false;
+        slot.generation = slot.generation.wrapping_add(1);
+        array.free.push_back((handle.index, retirement));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BindlessSlots;
+    use crate::BindlessKind as Bk;
+
+    fn slots(texture_count: u32) -> BindlessSlots<u32> {
+        BindlessSlots::new(&crate::BindlessTableDesc {
+            name: "test",
+            texture_count,
+            buffer_count: 0,
+            sampler_count: 0,
+        })
+    }
+
+    #[test]
+    fn removed_slot_waits_for_retirement() {
+        let mut slots = slots(2);
+        let a = slots.insert(Bk::Texture, |_, _| true);
+        slots.remove(a, 1).unwrap();
+        assert!(!slots.contains(a));
+        // The GPU hasn't reached 1 yet, so a new slot is taken
+        let b = slots.insert(Bk::Texture, |&retirement, _| retirement == 0);
+        assert_ne!(b.index(), a.index());
+        let c = slots.insert(Bk::Texture, |&retirement, _| retirement <= 1);
+        assert_eq!(c.index(), a.index());
+        assert!(slots.contains(c) && !slots.contains(a));
+    }
+
+    #[test]
+    fn full_array_waits_for_retirement() {
+        let mut slots = slots(1);
+        let a = slots.insert(Bk::Texture, |_, _| true);
+        slots.remove(a, 5).unwrap();
+        let mut waits = Vec::new();
+        let b = slots.insert(Bk::Texture, |_, timeout_ms| {
+            waits.push(timeout_ms);
+            timeout_ms != 0
+        });
+        assert_eq!(b.index(), a.index());
+        assert_eq!(waits, [0, !0]);
+        assert!(slots.remove(a, 6).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! Composition of a WGSL shader from multiple files.
+//!
+//! The directives start with `#` at the beginning of a line:
+//!   - `#import "path"` inserts the file, unless it's already imported by this shader.
+//!   - `#include "path"` inserts the file at every occurrence.
+//!   - `#define NAME` and `#undef NAME` toggle a feature.
+//!   - `#ifdef NAME`, `#ifndef NAME`, `#else`, and `#endif` keep the lines
+//!     depending on the enabled features.
+//!
+//! Paths are relative to the file with the directive, or to the root
+//! of the [`ShaderLoader`] if they start with `/`.
+//!
+//! The composed source keeps a [`SourceMap`], so that the diagnostics of
+//! `Context::try_create_shader` point at the original files and lines.
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label},
+    files::SimpleFiles,
+    term,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    ops::Range,
+    path::PathBuf,
+};
+
+/// Provider of the shader files for the composition.
+pub trait ShaderLoader {
+    /// Return the contents of the file at the normalized `/`-separated path.
+    fn load(&self, path: &str) -> Result<String, String>;
+}
+
+impl<F: Fn(&str) -> Result<String, String>> ShaderLoader for F {
+    fn load(&self, path: &str) -> Result<String, String> {
+        self(path)
+    }
+}
+
+/// Loads the shader files from a directory on disk.
+pub struct DirectoryLoader {
+    pub root: PathBuf,
+}
+
+impl ShaderLoader for DirectoryLoader {
+    fn load(&self, path: &str) -> Result<String, String> {
+        std::fs::read_to_string(self.root.join(path)).map_err(|e| e.to_string())
+    }
+}
+
+pub struct ComposeDesc<'a> {
+    /// Path of the root file, as given to the loader.
+    pub path: &'a str,
+    /// Features enabled for `#ifdef` before processing the root file.
+    pub defines: &'a [&'a str],
+    pub loader: &'a dyn ShaderLoader,
+}
+
+/// Failure to resolve the composition directives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComposeError {
+    /// File with the offending directive.
+    pub path: String,
+    /// 1-based line number of the directive, or 0 if the root file can't be loaded.
+    pub line: u32,
+    pub message: String,
+}
+
+impl fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path, self.line, self.message)
+    }
+}
+
+impl std::error::Error for ComposeError {}
+
+/// Original file that takes part in a composed shader.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub path: String,
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LineOrigin {
+    file: usize,
+    /// 0-based line index in the file.
+    line: usize,
+}
+
+/// Origin of every line of a composed shader.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    lines: Vec<LineOrigin>,
+}
+
+/// Position of a span of the composed source in its original file.
+pub(crate) struct MappedSpan<'a> {
+    pub path: &'a str,
+    pub range: Range<usize>,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl SourceMap {
+    /// Files in the order of loading, starting with the root file.
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// Map a 1-based line of the composed source to the original path and 1-based line.
+    pub fn locate(&self, line: u32) -> Option<(&str, u32)> {
+        let origin = self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[origin.file].path, origin.line as u32 + 1))
+    }
+
+    pub(crate) fn map_span(&self, composed: &str, range: Range<usize>) -> Option<MappedSpan<'_>> {
+        let before = composed.get(..range.start)?;
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        let origin = self.lines.get(before.matches('\n').count())?;
+        let file = &self.files[origin.file];
+        let file_line_start = file
+            .text
+            .split_inclusive('\n')
+            .take(origin.line)
+            .map(str::len)
+            .sum::<usize>();
+        let start = file_line_start + range.start - line_start;
+        Some(MappedSpan {
+            path: &file.path,
+            range: start..(start + range.len()).min(file.text.len()),
+            line: origin.line as u32 + 1,
+            column: (range.start - line_start) as u32 + 1,
+        })
+    }
+
+    /// Render a diagnostic against the original files.
+    pub(crate) fn render(&self, message: &str, labels: &[crate::ShaderErrorLabel]) -> String {
+        let mut files = SimpleFiles::new();
+        for file in self.files.iter() {
+            files.add(file.path.as_str(), file.text.as_str());
+        }
+        let diagnostic = Diagnostic::error().with_message(message).with_labels(
+            labels
+                .iter()
+                .enumerate()
+                .filter_map(|(index, label)| {
+                    let path = label.file.as_deref()?;
+                    let file_id = self.files.iter().position(|file| file.path == path)?;
+                    let label = if index == 0 {
+                        Label::primary(file_id, label.range.clone())
+                    } else {
+                        Label::secondary(file_id, label.range.clone())
+                    };
+                    Some(label.with_message(labels[index].message.clone()))
+                })
+                .collect(),
+        );
+        term::emit_into_string(&term::Config::default(), &files, &diagnostic)
+            .unwrap_or_else(|_| message.to_string())
+    }
+}
+
+/// WGSL source assembled from multiple files.
+#[derive(Clone, Debug)]
+pub struct ComposedShader {
+    pub source: String,
+    pub source_map: SourceMap,
+}
+
+impl ComposedShader {
+    pub fn new(desc: ComposeDesc) -> Result<Self, ComposeError> {
+        let mut composer = Composer {
+            loader: desc.loader,
+            defines: desc.defines.iter().map(|name| name.to_string()).collect(),
+            loaded: HashMap::default(),
+            imported: HashSet::default(),
+            stack: Vec::new(),
+            source: String::new(),
+            source_map: SourceMap::default(),
+        };
+        let path = normalize_path("", desc.path);
+        composer.add_file(path, None)?;
+        Ok(Self {
+            source: composer.source,
+            source_map: composer.source_map,
+        })
+    }
+
+    /// Paths of all the files used by the shader, to be watched for changes.
+    pub fn dependencies(&self) -> impl Iterator<Item = &str> + '_ {
+        self.source_map.files.iter().map(|file| file.path.as_str())
+    }
+
+    pub fn desc(&self) -> crate::ShaderDesc<'_> {
+        crate::ShaderDesc {
+            source: &self.source,
+            source_map: Some(&self.source_map),
+            language: crate::ShaderLanguage::Wgsl,
+        }
+    }
+}
+
+struct Composer<'a> {
+    loader: &'a dyn ShaderLoader,
+    defines: HashSet<String>,
+    /// Index of each loaded file in the source map.
+    loaded: HashMap<String, usize>,
+    imported: HashSet<String>,
+    /// Files being processed, to detect cycles.
+    stack: Vec<String>,
+    source: String,
+    source_map: SourceMap,
+}
+
+/// State of an `#ifdef` block.
+struct Condition {
+    /// Lines are kept in the enclosing block.
+    parent: bool,
+    value: bool,
+    has_else: bool,
+}
+
+impl Composer<'_> {
+    fn add_file(&mut self, path: String, at: Option<(&str, u32)>) -> Result<(), ComposeError> {
+        let error = |message: String| ComposeError {
+            path: at.map_or(path.clone(), |(from, _)| from.to_string()),
+            line: at.map_or(0, |(_, line)| line),
+            message,
+        };
+        if self.stack.contains(&path) {
+            return Err(error(format!("Cyclic inclusion of '{}'", path)));
+        }
+        let file_index = match self.loaded.get(&path) {
+            Some(&index) => index,
+            None => {
+                let text = self
+                    .loader
+                    .load(&path)
+                    .map_err(|message| error(format!("Unable to load '{}': {}", path, message)))?;
+                let index = self.source_map.files.len();
+                self.source_map.files.push(SourceFile {
+                    path: path.clone(),
+                    text,
+                });
+                self.loaded.insert(path.clone(), index);
+                index
+            }
+        };
+        self.imported.insert(path.clone());
+        self.stack.push(path.clone());
+
+        let text = self.source_map.files[file_index].text.clone();
+        let mut conditions = Vec::<Condition>::new();
+        for (line_index, line) in text.lines().enumerate() {
+            let active = conditions.last().is_none_or(|c| c.parent && c.value);
+            let directive = match line.trim_start().strip_prefix('#') {
+                Some(directive) => directive,
+                None => {
+                    if active {
+                        self.source.push_str(line);
+                        self.source.push('\n');
+                        self.source_map.lines.push(LineOrigin {
+                            file: file_index,
+                            line: line_index,
+                        });
+                    }
+                    continue;
+                }
+            };
+
+            let line_number = line_index as u32 + 1;
+            let error = |message: String| ComposeError {
+                path: path.clone(),
+                line: line_number,
+                message,
+            };
+            let (keyword, argument) = match directive.split_once(char::is_whitespace) {
+                Some((keyword, argument)) => (keyword, argument.trim()),
+                None => (directive.trim_end(), ""),
+            };
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains(argument);
+                    conditions.push(Condition {
+                        parent: active,
+                        value: defined == (keyword == "ifdef"),
+                        has_else: false,
+                    });
+                }
+                "else" => match conditions.last_mut() {
+                    Some(condition) if !condition.has_else => {
+                        condition.value = !condition.value;
+                        condition.has_else = true;
+                    }
+                    _ => return Err(error("Unexpected #else".to_string())),
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(error("Unexpected #endif".to_string()));
+                    }
+                }
+                _ if !active => {}
+                "define" => {
+                    self.defines.insert(argument.to_string());
+                }
+                "undef" => {
+                    self.defines.remove(argument);
+                }
+                "import" | "include" => {
+                    let target = match argument
+                        .strip_prefix('"')
+                        .and_then(|rest| rest.strip_suffix('"'))
+                    {
+                        Some(target) => normalize_path(&path, target),
+                        None => {
+                            return Err(error(format!(
+                                "Expected a quoted path after #{}",
+                                keyword
+                            )));
+                        }
+                    };
+                    if keyword == "include" || !self.imported.contains(&target) {
+                        self.add_file(target, Some((&path, line_number)))?;
+                    }
+                }
+                _ => return Err(error(format!("Unknown directive #{}", keyword))),
+            }
+        }
+        if !conditions.is_empty() {
+            return Err(ComposeError {
+                path,
+                line: text.lines().count() as u32,
+                message: "Missing #endif".to_string(),
+            });
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// Resolve `target` against the directory of `base`, removing `.` and `..` components.
+fn normalize_path(base: &str, target: &str) -> String {
+    let mut components = Vec::new();
+    let relative = match target.strip_prefix('/') {
+        Some(rest) => rest,
+        None => {
+            components.extend(base.split('/'));
+            // The last component is the file name of the base
+            components.pop();
+            target
+        }
+    };
+    for component in relative.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components.join("/")
+}
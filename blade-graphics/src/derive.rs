@@ -1,6 +1,6 @@
-use std::mem;
+use std::{marker::PhantomData, mem};
 
-use super::{ResourceIndex, ShaderBinding, VertexFormat};
+use super::{ResourceIndex, ScalarKind, ShaderBinding, ShaderStruct, StructLayout, VertexFormat};
 
 pub trait HasShaderBinding {
     const TYPE: ShaderBinding;
@@ -70,6 +70,37 @@ impl HasVertexAttribute for [i32; 4] {
     const FORMAT: VertexFormat = VertexFormat::I32Vec4;
 }
 
+impl HasVertexAttribute for super::Unorm8x4 {
+    const FORMAT: VertexFormat = VertexFormat::Unorm8x4;
+}
+impl HasVertexAttribute for super::Snorm8x4 {
+    const FORMAT: VertexFormat = VertexFormat::Snorm8x4;
+}
+impl HasVertexAttribute for super::Uint8x4 {
+    const FORMAT: VertexFormat = VertexFormat::Uint8x4;
+}
+impl HasVertexAttribute for super::Unorm16x2 {
+    const FORMAT: VertexFormat = VertexFormat::Unorm16x2;
+}
+impl HasVertexAttribute for super::Unorm16x4 {
+    const FORMAT: VertexFormat = VertexFormat::Unorm16x4;
+}
+impl HasVertexAttribute for super::Snorm16x2 {
+    const FORMAT: VertexFormat = VertexFormat::Snorm16x2;
+}
+impl HasVertexAttribute for super::Snorm16x4 {
+    const FORMAT: VertexFormat = VertexFormat::Snorm16x4;
+}
+impl HasVertexAttribute for super::Float16x2 {
+    const FORMAT: VertexFormat = VertexFormat::Float16x2;
+}
+impl HasVertexAttribute for super::Float16x4 {
+    const FORMAT: VertexFormat = VertexFormat::Float16x4;
+}
+impl HasVertexAttribute for super::Rgb10a2Unorm {
+    const FORMAT: VertexFormat = VertexFormat::Rgb10a2Unorm;
+}
+
 impl HasVertexAttribute for mint::Vector2<f32> {
     const FORMAT: VertexFormat = VertexFormat::F32Vec2;
 }
@@ -97,3 +128,70 @@ impl HasVertexAttribute for mint::Vector3<i32> {
 impl HasVertexAttribute for mint::Vector4<i32> {
     const FORMAT: VertexFormat = VertexFormat::I32Vec4;
 }
+
+pub trait HasScalarKind {
+    /// Kind of the scalars, or `None` for structs.
+    const KIND: Option<ScalarKind>;
+}
+
+impl HasScalarKind for f32 {
+    const KIND: Option<ScalarKind> = Some(ScalarKind::Float);
+}
+impl HasScalarKind for u32 {
+    const KIND: Option<ScalarKind> = Some(ScalarKind::Uint);
+}
+impl HasScalarKind for i32 {
+    const KIND: Option<ScalarKind> = Some(ScalarKind::Sint);
+}
+impl<T: HasScalarKind, const N: usize> HasScalarKind for [T; N] {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+impl<T: HasScalarKind> HasScalarKind for mint::Vector2<T> {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+impl<T: HasScalarKind> HasScalarKind for mint::Vector3<T> {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+impl<T: HasScalarKind> HasScalarKind for mint::Vector4<T> {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+impl<T: HasScalarKind> HasScalarKind for mint::ColumnMatrix2<T> {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+impl<T: HasScalarKind> HasScalarKind for mint::ColumnMatrix3<T> {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+impl<T: HasScalarKind> HasScalarKind for mint::ColumnMatrix4<T> {
+    const KIND: Option<ScalarKind> = T::KIND;
+}
+
+/// Finds the host layout of a `ShaderData` field if it's a `ShaderStruct`.
+///
+/// `(&FieldProbe::<T>::new()).struct_layout()` resolves to `StructFieldProbe`
+/// when `T: ShaderStruct`, and falls back to `OtherFieldProbe` otherwise.
+pub struct FieldProbe<T>(PhantomData<T>);
+
+impl<T> FieldProbe<T> {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+pub trait StructFieldProbe {
+    fn struct_layout(&self) -> Option<StructLayout>;
+}
+impl<T: ShaderStruct> StructFieldProbe for FieldProbe<T> {
+    fn struct_layout(&self) -> Option<StructLayout> {
+        Some(T::struct_layout())
+    }
+}
+
+pub trait OtherFieldProbe {
+    fn struct_layout(&self) -> Option<StructLayout>;
+}
+impl<T> OtherFieldProbe for &FieldProbe<T> {
+    fn struct_layout(&self) -> Option<StructLayout> {
+        None
+    }
+}
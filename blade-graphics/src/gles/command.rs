@@ -1,4 +1,4 @@
-use std::{str, time::Duration};
+use std::{mem, str};
 
 const COLOR_ATTACHMENTS: &[u32] = &[
     glow::COLOR_ATTACHMENT0,
@@ -82,6 +82,7 @@ impl crate::ShaderBindable for super::AccelerationStructure {
 
 impl super::CommandEncoder {
     fn begin_pass(&mut self, label: &str) {
+        self.pass_count += 1;
         if self.needs_scopes {
             let start = self.string_data.len();
             self.string_data.extend_from_slice(label.as_bytes());
@@ -91,11 +92,7 @@ impl super::CommandEncoder {
         }
         if let Some(ref mut timing_datas) = self.timing_datas {
             let td = timing_datas.first_mut().unwrap();
-            let id = td.pass_names.len();
-            self.commands.push(super::Command::QueryCounter {
-                query: td.queries[id],
-            });
-            td.pass_names.push(label.to_string());
+            td.begin(&mut self.commands, label, true);
         }
     }
 
@@ -108,6 +105,12 @@ impl super::CommandEncoder {
             pipeline: Default::default(),
             limits: &self.limits,
             has_scope: self.needs_scopes,
+            stencil_reference: 0,
+            occlusion_queries: None,
+            timing: self
+                .timing_datas
+                .as_mut()
+                .map(|timing_datas| timing_datas.first_mut().unwrap()),
         }
     }
 
@@ -115,27 +118,23 @@ impl super::CommandEncoder {
         use glow::HasContext as _;
         #[allow(trivial_casts)]
         if let Some(ref mut timing_datas) = self.timing_datas {
-            {
-                let td = timing_datas.first_mut().unwrap();
-                let id = td.pass_names.len();
-                self.commands.push(super::Command::QueryCounter {
-                    query: td.queries[id],
-                });
+            let td = timing_datas.first_mut().unwrap();
+            td.end_all(&mut self.commands, false);
+            // Create the queries for the scopes that didn't fit into the recording
+            let required = td.scopes.required_query_count() as usize;
+            for td in timing_datas.iter_mut() {
+                while td.queries.len() < required {
+                    td.queries.push(unsafe { gl.create_query().unwrap() });
+                }
+                td.scopes.grow(td.queries.len() as u32);
             }
 
             timing_datas.rotate_left(1);
-            self.timings.clear();
             let td = timing_datas.first_mut().unwrap();
-            if !td.pass_names.is_empty() {
-                let mut prev = 0;
-                unsafe {
-                    gl.get_query_parameter_u64_with_offset(
-                        td.queries[0],
-                        glow::QUERY_RESULT,
-                        &mut prev as *mut _ as usize,
-                    );
-                }
-                for (pass_name, &query) in td.pass_names.drain(..).zip(td.queries[1..].iter()) {
+            let query_count = td.scopes.query_count() as usize;
+            let timestamps = td.queries[..query_count]
+                .iter()
+                .map(|&query| {
                     let mut result: u64 = 0;
                     unsafe {
                         gl.get_query_parameter_u64_with_offset(
@@ -144,11 +143,10 @@ impl super::CommandEncoder {
                             &mut result as *mut _ as usize,
                         );
                     }
-                    let time = Duration::from_nanos(result - prev);
-                    self.timings.push((pass_name, time));
-                    prev = result
-                }
-            }
+                    result
+                })
+                .collect::<Vec<_>>();
+            self.timings = td.scopes.resolve(&timestamps);
         }
     }
 
@@ -256,20 +254,92 @@ impl super::CommandEncoder {
         pass.invalidate_attachments = invalidate_attachments;
         pass
     }
+
+    /// Begin a render pass, which writes the occlusion queries into `queries`.
+    /// All the queries of the set are reset at the start of the pass.
+    pub fn render_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::RenderCommandEncoder<'_> {
+        assert_eq!(queries.ty, crate::QueryType::Occlusion);
+        let mut pass = self.render(label, targets);
+        for &query in queries.queries() {
+            pass.commands.push(super::Command::BeginQuery {
+                query,
+                target: glow::ANY_SAMPLES_PASSED,
+            });
+            pass.commands.push(super::Command::EndQuery {
+                target: glow::ANY_SAMPLES_PASSED,
+            });
+        }
+        pass.occlusion_queries = Some(queries);
+        pass
+    }
+
+    pub fn render_parallel(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        let target_size = targets
+            .colors
+            .iter()
+            .chain(targets.depth_stencil.as_ref())
+            .next()
+            .map_or([0; 2], |rt| rt.view.target_size);
+        super::ParallelRenderCommandEncoder {
+            pass: self.render(label, targets),
+            target_size,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Begin a parallel render pass, which writes the occlusion queries into `queries`.
+    /// All the queries of the set are reset at the start of the pass.
+    pub fn render_parallel_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        let target_size = targets
+            .colors
+            .iter()
+            .chain(targets.depth_stencil.as_ref())
+            .next()
+            .map_or([0; 2], |rt| rt.view.target_size);
+        super::ParallelRenderCommandEncoder {
+            pass: self.render_with_queries(label, targets, queries),
+            target_size,
+            chunks: Vec::new(),
+        }
+    }
 }
 
 #[hidden_trait::expose]
 impl crate::traits::CommandEncoder for super::CommandEncoder {
     type Texture = super::Texture;
     type Frame = super::Frame;
+    type SyncPoint = super::SyncPoint;
+    type QuerySet = super::QuerySet;
 
     fn start(&mut self) {
+        self.stats = crate::CommandStats {
+            passes: mem::take(&mut self.pass_count),
+            ..Default::default()
+        };
         self.commands.clear();
         self.plain_data.clear();
         self.string_data.clear();
         self.present_frames.clear();
     }
 
+    fn depend_on(&mut self, _sync_point: &super::SyncPoint) {
+        // All the work is executed on one queue in the submission order
+    }
+
     fn init_texture(&mut self, _texture: super::Texture) {}
 
     fn present(&mut self, frame: super::Frame) {
@@ -279,6 +349,17 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
     fn timings(&self) -> &crate::Timings {
         &self.timings
     }
+
+    fn stats(&self) -> &crate::CommandStats {
+        &self.stats
+    }
+
+    fn begin_statistics_query(&mut self, _set: super::QuerySet, index: u32) {
+        // `Capabilities::pipeline_statistics` is false, so there is nothing to collect
+        log::warn!("Pipeline statistics query {index} is skipped");
+    }
+
+    fn end_statistics_query(&mut self, _set: super::QuerySet, _index: u32) {}
 }
 
 impl super::PassEncoder<'_, super::ComputePipeline> {
@@ -295,6 +376,9 @@ impl super::PassEncoder<'_, super::ComputePipeline> {
             topology: 0,
             limits: self.limits,
             vertex_attributes: &[],
+            stencil: None,
+            stencil_reference: &mut self.stencil_reference,
+            occlusion_queries: None,
         }
     }
 }
@@ -311,7 +395,105 @@ impl crate::traits::RenderEncoder for super::PassEncoder<'_, super::RenderPipeli
     }
 
     fn set_stencil_reference(&mut self, reference: u32) {
-        unimplemented!()
+        // Applied together with the stencil function of the next pipeline
+        self.stencil_reference = reference;
+    }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        begin_occlusion_query(self.commands, self.occlusion_queries, index, mode);
+    }
+
+    fn end_occlusion_query(&mut self, _index: u32) {
+        end_occlusion_query(self.commands);
+    }
+}
+
+fn begin_occlusion_query(
+    commands: &mut Vec<super::Command>,
+    queries: Option<super::QuerySet>,
+    index: u32,
+    _mode: crate::OcclusionMode,
+) {
+    let queries = queries.unwrap_or_else(|| {
+        panic!("Occlusion query {index} is begun in a pass without a query set")
+    });
+    // Only the binary mode is available on GLES
+    commands.push(super::Command::BeginQuery {
+        query: queries.queries()[index as usize],
+        target: glow::ANY_SAMPLES_PASSED,
+    });
+}
+
+fn end_occlusion_query(commands: &mut Vec<super::Command>) {
+    commands.push(super::Command::EndQuery {
+        target: glow::ANY_SAMPLES_PASSED,
+    });
+}
+
+fn set_stencil_func(
+    commands: &mut Vec<super::Command>,
+    stencil: &super::StencilState,
+    reference: u32,
+) {
+    for &(face, ref side) in [(glow::FRONT, &stencil.front), (glow::BACK, &stencil.back)].iter() {
+        commands.push(super::Command::SetStencilFunc {
+            face,
+            function: side.function,
+            reference,
+            read_mask: stencil.read_mask,
+        });
+    }
+}
+
+fn render_pipeline_encoder<'b>(
+    commands: &'b mut Vec<super::Command>,
+    plain_data: &'b mut Vec<u8>,
+    limits: &'b super::Limits,
+    stencil_reference: &'b mut u32,
+    pipeline: &'b super::RenderPipeline,
+) -> super::PipelineEncoder<'b> {
+    commands.push(super::Command::SetProgram(pipeline.inner.program));
+
+    match &pipeline.inner.color_targets[..] {
+        &[(blend_state, write_masks)] => {
+            commands.push(super::Command::SetAllColorTargets(blend_state, write_masks))
+        }
+        separate => commands.extend(separate.iter().zip(0..).map(
+            |(&(blend_state, write_masks), i)| {
+                super::Command::SetSingleColorTarget(i, blend_state, write_masks)
+            },
+        )),
+    }
+
+    let mut aspects = crate::TexelAspects::empty();
+    if let Some(depth) = pipeline.depth {
+        aspects |= crate::TexelAspects::DEPTH;
+        commands.push(super::Command::SetDepth(depth));
+    }
+    if let Some(ref stencil) = pipeline.stencil {
+        aspects |= crate::TexelAspects::STENCIL;
+        set_stencil_func(commands, stencil, *stencil_reference);
+        for &(face, ref side) in [(glow::FRONT, &stencil.front), (glow::BACK, &stencil.back)].iter()
+        {
+            commands.push(super::Command::SetStencilOps {
+                face,
+                write_mask: stencil.write_mask,
+                ops: side.ops,
+            });
+        }
+    }
+    commands.push(super::Command::ConfigureDepthStencil(aspects));
+
+    super::PipelineEncoder {
+        commands,
+        plain_data,
+        group_mappings: &pipeline.inner.group_mappings,
+        topology: map_primitive_topology(pipeline.topology),
+        limits,
+        vertex_attributes: &pipeline.inner.vertex_attribute_infos,
+        stencil: pipeline.stencil.as_ref(),
+        stencil_reference,
+        occlusion_queries: None,
     }
 }
 
@@ -320,30 +502,132 @@ impl super::PassEncoder<'_, super::RenderPipeline> {
         &'b mut self,
         pipeline: &'b super::RenderPipeline,
     ) -> super::PipelineEncoder<'b> {
-        self.commands
-            .push(super::Command::SetProgram(pipeline.inner.program));
+        let mut encoder = render_pipeline_encoder(
+            self.commands,
+            self.plain_data,
+            self.limits,
+            &mut self.stencil_reference,
+            pipeline,
+        );
+        encoder.occlusion_queries = self.occlusion_queries;
+        encoder
+    }
+}
 
-        match &pipeline.inner.color_targets[..] {
-            &[(blend_state, write_masks)] => self
-                .commands
-                .push(super::Command::SetAllColorTargets(blend_state, write_masks)),
-            separate => self.commands.extend(separate.iter().zip(0..).map(
-                |(&(blend_state, write_masks), i)| {
-                    super::Command::SetSingleColorTarget(i, blend_state, write_masks)
-                },
-            )),
+impl super::ParallelRenderCommandEncoder<'_> {
+    /// Begin a chunk of the pass, which can be recorded on any thread.
+    /// Chunks are executed in the order they are begun, after being merged back.
+    pub fn chunk(&mut self) -> super::RenderChunk {
+        let [width, height] = self.target_size;
+        let index = self.chunks.len();
+        self.chunks.push(None);
+        super::RenderChunk {
+            index,
+            // Chunks don't inherit the state of each other
+            commands: vec![
+                super::Command::SetViewport(crate::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    w: width as _,
+                    h: height as _,
+                    depth: 0.0..1.0,
+                }),
+                super::Command::SetScissor(crate::ScissorRect {
+                    x: 0,
+                    y: 0,
+                    w: width as u32,
+                    h: height as u32,
+                }),
+            ],
+            plain_data: Vec::new(),
+            limits: self.pass.limits.clone(),
+            stencil_reference: 0,
+            occlusion_queries: self.pass.occlusion_queries,
         }
-        super::PipelineEncoder {
-            commands: self.commands,
-            plain_data: self.plain_data,
-            group_mappings: &pipeline.inner.group_mappings,
-            topology: map_primitive_topology(pipeline.topology),
-            limits: self.limits,
-            vertex_attributes: &pipeline.inner.vertex_attribute_infos,
+    }
+
+    /// Return a recorded chunk into the pass.
+    pub fn merge(&mut self, chunk: super::RenderChunk) {
+        let slot = &mut self.chunks[chunk.index];
+        assert!(
+            slot.is_none(),
+            "Render chunk {} is merged twice",
+            chunk.index
+        );
+        *slot = Some(chunk);
+    }
+}
+
+impl Drop for super::ParallelRenderCommandEncoder<'_> {
+    fn drop(&mut self) {
+        let alignment = self.pass.limits.uniform_buffer_alignment as usize;
+        for (index, chunk) in self.chunks.drain(..).enumerate() {
+            let Some(chunk) = chunk else {
+                log::error!("Render chunk {} is not merged into the pass", index);
+                continue;
+            };
+            let rem = self.pass.plain_data.len() % alignment;
+            if rem != 0 {
+                self.pass
+                    .plain_data
+                    .resize(self.pass.plain_data.len() - rem + alignment, 0);
+            }
+            let base = self.pass.plain_data.len() as u32;
+            self.pass.plain_data.extend_from_slice(&chunk.plain_data);
+            self.pass
+                .commands
+                .extend(chunk.commands.into_iter().map(|mut command| {
+                    if let super::Command::BindUniform { ref mut offset, .. } = command {
+                        *offset += base;
+                    }
+                    command
+                }));
         }
     }
 }
 
+impl super::RenderChunk {
+    pub fn with<'b>(
+        &'b mut self,
+        pipeline: &'b super::RenderPipeline,
+    ) -> super::PipelineEncoder<'b> {
+        let mut encoder = render_pipeline_encoder(
+            &mut self.commands,
+            &mut self.plain_data,
+            &self.limits,
+            &mut self.stencil_reference,
+            pipeline,
+        );
+        encoder.occlusion_queries = self.occlusion_queries;
+        encoder
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderEncoder for super::RenderChunk {
+    fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        self.commands.push(super::Command::SetScissor(rect.clone()));
+    }
+
+    fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        self.commands
+            .push(super::Command::SetViewport(viewport.clone()));
+    }
+
+    fn set_stencil_reference(&mut self, reference: u32) {
+        // Applied together with the stencil function of the next pipeline
+        self.stencil_reference = reference;
+    }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        begin_occlusion_query(&mut self.commands, self.occlusion_queries, index, mode);
+    }
+
+    fn end_occlusion_query(&mut self, _index: u32) {
+        end_occlusion_query(&mut self.commands);
+    }
+}
+
 impl<T> Drop for super::PassEncoder<'_, T> {
     fn drop(&mut self) {
         self.commands.push(super::Command::UnsetProgram);
@@ -358,15 +642,101 @@ impl<T> Drop for super::PassEncoder<'_, T> {
             }
             super::PassKind::Render => {
                 self.commands.push(super::Command::ResetAllSamplers);
+                self.commands.push(super::Command::ConfigureDepthStencil(
+                    crate::TexelAspects::empty(),
+                ));
                 self.commands.push(super::Command::ResetFramebuffer);
             }
         }
+        if let Some(ref mut timing) = self.timing {
+            timing.end_all(self.commands, true);
+        }
         if self.has_scope {
             self.commands.push(super::Command::PopScope);
         }
     }
 }
 
+impl super::TimingData {
+    fn write(&self, commands: &mut Vec<super::Command>, query: u32) {
+        commands.push(super::Command::QueryCounter {
+            query: self.queries[query as usize],
+        });
+    }
+
+    fn begin(&mut self, commands: &mut Vec<super::Command>, name: &str, pass: bool) {
+        let query = if pass {
+            self.scopes.begin_pass(name, true)
+        } else {
+            self.scopes.begin(name, true)
+        };
+        if let Some(query) = query {
+            self.write(commands, query);
+        }
+    }
+
+    fn end(&mut self, commands: &mut Vec<super::Command>) {
+        match self.scopes.end() {
+            Ok(Some(query)) => self.write(commands, query),
+            Ok(None) => {}
+            Err(message) => log::error!("{}", message),
+        }
+    }
+
+    /// End the scopes that are still open at the end of a pass, or the whole recording.
+    fn end_all(&mut self, commands: &mut Vec<super::Command>, pass: bool) {
+        let (queries, unended) = if pass {
+            self.scopes.end_pass()
+        } else {
+            self.scopes.finish()
+        };
+        for name in unended {
+            log::warn!("Timing scope '{}' is not ended", name);
+        }
+        for query in queries {
+            self.write(commands, query);
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::CommandEncoder {
+    fn begin_scope(&mut self, name: &str) {
+        if let Some(ref mut timing_datas) = self.timing_datas {
+            let td = timing_datas.first_mut().unwrap();
+            td.begin(&mut self.commands, name, false);
+        }
+    }
+
+    fn end_scope(&mut self) {
+        if let Some(ref mut timing_datas) = self.timing_datas {
+            let td = timing_datas.first_mut().unwrap();
+            td.end(&mut self.commands);
+        }
+    }
+}
+
+macro_rules! impl_timing_encoder {
+    ($($encoder:ident)*) => {$(
+        #[hidden_trait::expose]
+        impl crate::traits::TimingEncoder for super::$encoder<'_> {
+            fn begin_scope(&mut self, name: &str) {
+                if let Some(ref mut timing) = self.timing {
+                    timing.begin(self.commands, name, false);
+                }
+            }
+
+            fn end_scope(&mut self) {
+                if let Some(ref mut timing) = self.timing {
+                    timing.end(self.commands);
+                }
+            }
+        }
+    )*};
+}
+
+impl_timing_encoder!(TransferCommandEncoder ComputeCommandEncoder RenderCommandEncoder);
+
 #[hidden_trait::expose]
 impl crate::traits::TransferEncoder for super::PassEncoder<'_, ()> {
     type BufferPiece = crate::BufferPiece;
@@ -438,7 +808,14 @@ impl crate::traits::TransferEncoder for super::PassEncoder<'_, ()> {
 
 #[hidden_trait::expose]
 impl crate::traits::PipelineEncoder for super::PipelineEncoder<'_> {
+    type BindlessTable = super::BindlessTable;
+
     fn bind<D: crate::ShaderData>(&mut self, group: u32, data: &D) {
+        if cfg!(debug_assertions)
+            && let Err(message) = crate::reflect::check_plain_data::<D>(&self.group_mappings[group as usize].plain_structs)
+        {
+            panic!("{}", message);
+        }
         data.fill(super::PipelineContext {
             commands: self.commands,
             plain_data: self.plain_data,
@@ -446,6 +823,11 @@ impl crate::traits::PipelineEncoder for super::PipelineEncoder<'_> {
             limits: self.limits,
         });
     }
+
+    fn bind_table(&mut self, group: u32, _table: &super::BindlessTable) {
+        // Pipelines with bindless bindings fail to create, so there is no slot to bind to
+        log::error!("Bindless table can't be bound to group {}", group);
+    }
 }
 
 #[hidden_trait::expose]
@@ -475,7 +857,18 @@ impl crate::traits::RenderEncoder for super::PipelineEncoder<'_> {
     }
 
     fn set_stencil_reference(&mut self, reference: u32) {
-        unimplemented!()
+        *self.stencil_reference = reference;
+        if let Some(stencil) = self.stencil {
+            set_stencil_func(self.commands, stencil, reference);
+        }
+    }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        begin_occlusion_query(self.commands, self.occlusion_queries, index, mode);
+    }
+
+    fn end_occlusion_query(&mut self, _index: u32) {
+        end_occlusion_query(self.commands);
     }
 }
 
@@ -484,11 +877,15 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_> {
     type BufferPiece = crate::BufferPiece;
 
     fn bind_vertex(&mut self, index: u32, vertex_buf: crate::BufferPiece) {
-        assert_eq!(index, 0);
         self.commands.push(super::Command::BindVertex {
             buffer: vertex_buf.buffer.raw,
         });
+        // Attribute pointers capture the currently bound array buffer,
+        // so only the attributes sourced from this slot are updated.
         for (i, info) in self.vertex_attributes.iter().enumerate() {
+            if info.buffer_index != index {
+                continue;
+            }
             self.commands.push(super::Command::SetVertexAttribute {
                 index: i as u32,
                 format: info.attrib.format,
@@ -552,20 +949,32 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_> {
 }
 
 impl crate::VertexFormat {
-    fn describe(&self) -> (i32, u32) {
+    /// Component count, GL type, and whether the components are normalized.
+    /// The normalization is `None` for attributes fetched as integers.
+    fn describe(&self) -> (i32, u32, Option<bool>) {
         match *self {
-            Self::F32 => (1, glow::FLOAT),
-            Self::F32Vec2 => (2, glow::FLOAT),
-            Self::F32Vec3 => (3, glow::FLOAT),
-            Self::F32Vec4 => (4, glow::FLOAT),
-            Self::U32 => (1, glow::UNSIGNED_INT),
-            Self::U32Vec2 => (2, glow::UNSIGNED_INT),
-            Self::U32Vec3 => (3, glow::UNSIGNED_INT),
-            Self::U32Vec4 => (4, glow::UNSIGNED_INT),
-            Self::I32 => (1, glow::INT),
-            Self::I32Vec2 => (2, glow::INT),
-            Self::I32Vec3 => (3, glow::INT),
-            Self::I32Vec4 => (4, glow::INT),
+            Self::F32 => (1, glow::FLOAT, Some(false)),
+            Self::F32Vec2 => (2, glow::FLOAT, Some(false)),
+            Self::F32Vec3 => (3, glow::FLOAT, Some(false)),
+            Self::F32Vec4 => (4, glow::FLOAT, Some(false)),
+            Self::U32 => (1, glow::UNSIGNED_INT, None),
+            Self::U32Vec2 => (2, glow::UNSIGNED_INT, None),
+            Self::U32Vec3 => (3, glow::UNSIGNED_INT, None),
+            Self::U32Vec4 => (4, glow::UNSIGNED_INT, None),
+            Self::I32 => (1, glow::INT, None),
+            Self::I32Vec2 => (2, glow::INT, None),
+            Self::I32Vec3 => (3, glow::INT, None),
+            Self::I32Vec4 => (4, glow::INT, None),
+            Self::Unorm8x4 => (4, glow::UNSIGNED_BYTE, Some(true)),
+            Self::Snorm8x4 => (4, glow::BYTE, Some(true)),
+            Self::Uint8x4 => (4, glow::UNSIGNED_BYTE, None),
+            Self::Unorm16x2 => (2, glow::UNSIGNED_SHORT, Some(true)),
+            Self::Unorm16x4 => (4, glow::UNSIGNED_SHORT, Some(true)),
+            Self::Snorm16x2 => (2, glow::SHORT, Some(true)),
+            Self::Snorm16x4 => (4, glow::SHORT, Some(true)),
+            Self::Float16x2 => (2, glow::HALF_FLOAT, Some(false)),
+            Self::Float16x4 => (4, glow::HALF_FLOAT, Some(false)),
+            Self::Rgb10a2Unorm => (4, glow::UNSIGNED_INT_2_10_10_10_REV, Some(true)),
         }
     }
 }
@@ -1043,14 +1452,23 @@ impl super::Command {
                     );
                 }
             },
-            Self::ClearDepthStencil { depth, stencil } => match (depth, stencil) {
-                (Some(d), Some(s)) => {
-                    gl.clear_buffer_depth_stencil(glow::DEPTH_STENCIL, 0, d, s as i32)
+            Self::ClearDepthStencil { depth, stencil } => {
+                // Clears respect the write masks left by the previous pipeline
+                if depth.is_some() {
+                    gl.depth_mask(true);
                 }
-                (Some(d), None) => gl.clear_buffer_f32_slice(glow::DEPTH, 0, &[d]),
-                (None, Some(s)) => gl.clear_buffer_i32_slice(glow::STENCIL, 0, &[s as i32]),
-                (None, None) => (),
-            },
+                if stencil.is_some() {
+                    gl.stencil_mask(!0);
+                }
+                match (depth, stencil) {
+                    (Some(d), Some(s)) => {
+                        gl.clear_buffer_depth_stencil(glow::DEPTH_STENCIL, 0, d, s as i32)
+                    }
+                    (Some(d), None) => gl.clear_buffer_f32_slice(glow::DEPTH, 0, &[d]),
+                    (None, Some(s)) => gl.clear_buffer_i32_slice(glow::STENCIL, 0, &[s as i32]),
+                    (None, None) => (),
+                }
+            }
             Self::Barrier => unimplemented!(),
             Self::SetViewport(ref vp) => {
                 gl.viewport(vp.x as i32, vp.y as i32, vp.w as i32, vp.h as i32);
@@ -1064,15 +1482,34 @@ impl super::Command {
                 function,
                 reference,
                 read_mask,
-            } => unimplemented!(),
+            } => {
+                gl.stencil_func_separate(face, function, reference as i32, read_mask);
+            }
             Self::SetStencilOps {
                 face,
                 write_mask,
-                //ops: crate::StencilOps,
-            } => unimplemented!(),
-            //SetDepth(DepthState),
+                ref ops,
+            } => {
+                gl.stencil_mask_separate(face, write_mask);
+                gl.stencil_op_separate(face, ops.fail, ops.depth_fail, ops.pass);
+            }
+            Self::SetDepth(ref depth) => {
+                gl.depth_func(depth.function);
+                gl.depth_mask(depth.write);
+            }
             //SetDepthBias(wgt::DepthBiasState),
-            //ConfigureDepthStencil(crate::FormatAspects),
+            Self::ConfigureDepthStencil(aspects) => {
+                if aspects.contains(crate::TexelAspects::DEPTH) {
+                    gl.enable(glow::DEPTH_TEST);
+                } else {
+                    gl.disable(glow::DEPTH_TEST);
+                }
+                if aspects.contains(crate::TexelAspects::STENCIL) {
+                    gl.enable(glow::STENCIL_TEST);
+                } else {
+                    gl.disable(glow::STENCIL_TEST);
+                }
+            }
             Self::SetProgram(raw_program) => {
                 gl.use_program(Some(raw_program));
             }
@@ -1104,15 +1541,14 @@ impl super::Command {
                 stride,
                 instanced,
             } => {
-                let (data_size, data_type) = format.describe();
-                match data_type {
-                    glow::FLOAT => gl.vertex_attrib_pointer_f32(
-                        index, data_size, data_type, false, stride, offset,
+                let (data_size, data_type, normalized) = format.describe();
+                match normalized {
+                    Some(normalized) => gl.vertex_attrib_pointer_f32(
+                        index, data_size, data_type, normalized, stride, offset,
                     ),
-                    glow::INT | glow::UNSIGNED_INT => {
+                    None => {
                         gl.vertex_attrib_pointer_i32(index, data_size, data_type, stride, offset)
                     }
-                    _ => unreachable!(),
                 }
                 gl.vertex_attrib_divisor(index, if instanced { 1 } else { 0 });
                 gl.enable_vertex_attrib_array(index);
@@ -1157,6 +1593,12 @@ impl super::Command {
             Self::QueryCounter { query } => {
                 gl.query_counter(query, glow::TIMESTAMP);
             }
+            Self::BeginQuery { query, target } => {
+                gl.begin_query(target, query);
+            }
+            Self::EndQuery { target } => {
+                gl.end_query(target);
+            }
             Self::PushScope { ref name_range } => {
                 let name = str::from_utf8(&ec.string_data[name_range.clone()]).unwrap();
                 gl.push_debug_group(glow::DEBUG_SOURCE_APPLICATION, super::DEBUG_ID, name);
@@ -152,6 +152,9 @@ impl<'a> Drop for ContextLock<'a> {
 
 impl super::Context {
     pub unsafe fn init(desc: crate::ContextDesc) -> Result<Self, crate::NotSupportedError> {
+        if desc.trace_path.is_some() {
+            log::warn!("API tracing is not supported");
+        }
         let egl = unsafe {
             let egl_result = if cfg!(windows) {
                 egl::DynamicInstance::<egl::EGL1_4>::load_required_from_filename("libEGL.dll")
@@ -257,6 +260,7 @@ impl super::Context {
             toggles,
             limits,
             device_information,
+            tracker: crate::tracking::Tracker::from_desc(&desc),
         })
     }
 
@@ -810,6 +814,24 @@ impl EglContext {
             // glow uses unsuffixed functions like glEnablei instead of glEnableiEXT.
             // Therefore, GL_EXT_draw_buffers_indexed is not sufficient.
         );
+        capabilities.set(
+            super::Capabilities::TEXTURE_COMPRESSION_BC,
+            extensions.contains("GL_EXT_texture_compression_s3tc")
+                && extensions.contains("GL_EXT_texture_compression_rgtc")
+                && extensions.contains("GL_EXT_texture_compression_bptc"),
+        );
+        capabilities.set(
+            super::Capabilities::TEXTURE_COMPRESSION_ETC2,
+            gl.version().is_embedded || extensions.contains("GL_ARB_ES3_compatibility"),
+        );
+        capabilities.set(
+            super::Capabilities::TEXTURE_COMPRESSION_ASTC,
+            extensions.contains("GL_KHR_texture_compression_astc_ldr"),
+        );
+        capabilities.set(
+            super::Capabilities::TEXTURE_NORM16,
+            !gl.version().is_embedded || extensions.contains("GL_EXT_texture_norm16"),
+        );
 
         let toggles = super::Toggles {
             scoping: desc.capture
@@ -10,7 +10,9 @@ use std::{marker::PhantomData, mem, ops::Range};
 type BindTarget = u32;
 const DEBUG_ID: u32 = 0;
 const MAX_TIMEOUT: u64 = 1_000_000_000; // MAX_CLIENT_WAIT_TIMEOUT_WEBGL;
-const MAX_QUERIES: usize = crate::limits::PASS_COUNT + 1;
+/// Initial number of timestamp queries of a recording,
+/// which grows when the timing scopes need more of them.
+const TIMESTAMP_QUERY_COUNT: usize = 128;
 
 pub use platform::PlatformError;
 
@@ -19,6 +21,10 @@ bitflags::bitflags! {
         const BUFFER_STORAGE = 1 << 0;
         const DRAW_BUFFERS_INDEXED = 1 << 1;
         const DISJOINT_TIMER_QUERY = 1 << 2;
+        const TEXTURE_COMPRESSION_BC = 1 << 3;
+        const TEXTURE_COMPRESSION_ETC2 = 1 << 4;
+        const TEXTURE_COMPRESSION_ASTC = 1 << 5;
+        const TEXTURE_NORM16 = 1 << 6;
     }
 }
 
@@ -39,6 +45,7 @@ pub struct Context {
     toggles: Toggles,
     limits: Limits,
     device_information: crate::DeviceInformation,
+    tracker: Option<crate::tracking::Tracker>,
 }
 
 pub struct Surface {
@@ -107,10 +114,41 @@ pub struct Sampler {
 #[derive(Clone, Copy, Debug, Hash, PartialEq)]
 pub struct AccelerationStructure {}
 
+/// Bindless tables are not supported by this backend, see `Capabilities::max_bindless_resources`.
+/// Only the empty tables can be created, and they are tracked on the host.
+#[derive(Debug)]
+pub struct BindlessTable {
+    slots: crate::bindless::BindlessSlots,
+}
+
+impl BindlessTable {
+    /// Check that the handle refers to a resource that is still in the table.
+    pub fn contains(&self, handle: crate::BindlessHandle) -> bool {
+        self.slots.contains(handle)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct QuerySet {
+    raw: *mut glow::Query,
+    ty: crate::QueryType,
+    count: u32,
+}
+
+unsafe impl Send for QuerySet {}
+unsafe impl Sync for QuerySet {}
+
+impl QuerySet {
+    fn queries(&self) -> &[glow::Query] {
+        unsafe { std::slice::from_raw_parts(self.raw, self.count as usize) }
+    }
+}
+
 type SlotList = Vec<u32>;
 
 struct ShaderDataMapping {
     targets: Box<[SlotList]>,
+    plain_structs: Box<[Option<crate::StructReflection>]>,
 }
 
 struct VertexAttributeInfo {
@@ -138,9 +176,38 @@ impl ComputePipeline {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+struct DepthState {
+    function: u32,
+    write: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct StencilOps {
+    fail: u32,
+    depth_fail: u32,
+    pass: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct StencilSide {
+    function: u32,
+    ops: StencilOps,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct StencilState {
+    front: StencilSide,
+    back: StencilSide,
+    read_mask: u32,
+    write_mask: u32,
+}
+
 pub struct RenderPipeline {
     inner: PipelineInner,
     topology: crate::PrimitiveTopology,
+    depth: Option<DepthState>,
+    stencil: Option<StencilState>,
 }
 
 #[derive(Debug)]
@@ -307,11 +374,11 @@ enum Command {
     SetStencilOps {
         face: u32,
         write_mask: u32,
-        //ops: crate::StencilOps,
+        ops: StencilOps,
     },
-    //SetDepth(DepthState),
+    SetDepth(DepthState),
     //SetDepthBias(wgt::DepthBiasState),
-    //ConfigureDepthStencil(crate::FormatAspects),
+    ConfigureDepthStencil(crate::TexelAspects),
     SetProgram(glow::Program),
     UnsetProgram,
     //SetPrimitive(PrimitiveState),
@@ -361,6 +428,13 @@ enum Command {
     QueryCounter {
         query: glow::Query,
     },
+    BeginQuery {
+        query: glow::Query,
+        target: u32,
+    },
+    EndQuery {
+        target: u32,
+    },
     PushScope {
         name_range: Range<usize>,
     },
@@ -368,8 +442,8 @@ enum Command {
 }
 
 struct TimingData {
-    pass_names: Vec<String>,
-    queries: Box<[glow::Query]>,
+    scopes: crate::timing::ScopeRecorder,
+    queries: Vec<glow::Query>,
 }
 
 pub struct CommandEncoder {
@@ -382,6 +456,8 @@ pub struct CommandEncoder {
     limits: Limits,
     timing_datas: Option<Box<[TimingData]>>,
     timings: crate::Timings,
+    pass_count: u32,
+    stats: crate::CommandStats,
 }
 
 enum PassKind {
@@ -398,11 +474,31 @@ pub struct PassEncoder<'a, P> {
     pipeline: PhantomData<P>,
     limits: &'a Limits,
     has_scope: bool,
+    stencil_reference: u32,
+    occlusion_queries: Option<QuerySet>,
+    timing: Option<&'a mut TimingData>,
 }
 
+pub type TransferCommandEncoder<'a> = PassEncoder<'a, ()>;
 pub type ComputeCommandEncoder<'a> = PassEncoder<'a, ComputePipeline>;
 pub type RenderCommandEncoder<'a> = PassEncoder<'a, RenderPipeline>;
 
+pub struct ParallelRenderCommandEncoder<'a> {
+    pass: RenderCommandEncoder<'a>,
+    target_size: [u16; 2],
+    chunks: Vec<Option<RenderChunk>>,
+}
+
+pub struct RenderChunk {
+    index: usize,
+    commands: Vec<Command>,
+    /// Plain data of the chunk, relocated after the data of the pass when merged.
+    plain_data: Vec<u8>,
+    limits: Limits,
+    stencil_reference: u32,
+    occlusion_queries: Option<QuerySet>,
+}
+
 pub struct PipelineEncoder<'a> {
     commands: &'a mut Vec<Command>,
     plain_data: &'a mut Vec<u8>,
@@ -410,6 +506,9 @@ pub struct PipelineEncoder<'a> {
     topology: u32,
     limits: &'a Limits,
     vertex_attributes: &'a [VertexAttributeInfo],
+    stencil: Option<&'a StencilState>,
+    stencil_reference: &'a mut u32,
+    occlusion_queries: Option<QuerySet>,
 }
 
 impl Drop for PipelineEncoder<'_> {
@@ -447,12 +546,84 @@ impl Context {
             ray_query: crate::ShaderVisibility::empty(),
             sample_count_mask: 0x1 | 0x4, //TODO: accurate info
             dual_source_blending: false,
+            precise_occlusion: false,
+            pipeline_statistics: false,
+            max_bindless_resources: 0,
         }
     }
 
     pub fn device_information(&self) -> &crate::DeviceInformation {
         &self.device_information
     }
+
+    /// Usages supported by textures of the given format on this device.
+    pub fn texture_format_usage(&self, format: crate::TextureFormat) -> crate::TextureUsage {
+        use crate::{TextureFormat as Tf, TextureUsage as Tu};
+        let required = match format {
+            Tf::Bc1Unorm
+            | Tf::Bc1UnormSrgb
+            | Tf::Bc2Unorm
+            | Tf::Bc2UnormSrgb
+            | Tf::Bc3Unorm
+            | Tf::Bc3UnormSrgb
+            | Tf::Bc4Unorm
+            | Tf::Bc4Snorm
+            | Tf::Bc5Unorm
+            | Tf::Bc5Snorm
+            | Tf::Bc6hUfloat
+            | Tf::Bc6hFloat
+            | Tf::Bc7Unorm
+            | Tf::Bc7UnormSrgb => Capabilities::TEXTURE_COMPRESSION_BC,
+            Tf::Etc2Rgb8Unorm
+            | Tf::Etc2Rgb8UnormSrgb
+            | Tf::Etc2Rgb8A1Unorm
+            | Tf::Etc2Rgb8A1UnormSrgb
+            | Tf::Etc2Rgba8Unorm
+            | Tf::Etc2Rgba8UnormSrgb
+            | Tf::EacR11Unorm
+            | Tf::EacR11Snorm
+            | Tf::EacRg11Unorm
+            | Tf::EacRg11Snorm => Capabilities::TEXTURE_COMPRESSION_ETC2,
+            _ if format.block_info().dimensions != (1, 1) => Capabilities::TEXTURE_COMPRESSION_ASTC,
+            Tf::R16Unorm | Tf::Rgba16Unorm => Capabilities::TEXTURE_NORM16,
+            _ => Capabilities::empty(),
+        };
+        if !self.capabilities.contains(required) {
+            return Tu::empty();
+        }
+        if format.block_info().dimensions != (1, 1) {
+            return Tu::COPY | Tu::RESOURCE;
+        }
+        match format {
+            // Image load/store formats of GLES 3.1. The sRGB ones are not
+            // among them, and can't be reinterpreted as linear for writing.
+            Tf::Rgba8Unorm
+            | Tf::R32Float
+            | Tf::Rgba16Float
+            | Tf::Rgba32Float
+            | Tf::R32Uint
+            | Tf::Rgba32Uint
+            | Tf::R32Sint => Tu::all(),
+            Tf::Rg8Snorm | Tf::Rgba8Snorm | Tf::Rgb9e5Ufloat => Tu::COPY | Tu::RESOURCE,
+            _ => Tu::COPY | Tu::TARGET | Tu::RESOURCE,
+        }
+    }
+
+    /// Return the resources that are currently alive, in the order of creation.
+    /// Empty unless `ContextDesc::track_resources` is enabled.
+    pub fn live_resources(&self) -> Vec<crate::LiveResource> {
+        self.tracker
+            .as_ref()
+            .map_or_else(Vec::new, crate::tracking::Tracker::snapshot)
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.report_leaks();
+        }
+    }
 }
 
 #[hidden_trait::expose]
@@ -469,9 +640,11 @@ impl crate::traits::CommandDevice for Context {
             // Allocating one extra set of timers because we are resolving them
             // in submit() as opposed to start().
             for _ in 0..desc.buffer_count + 1 {
+                let mut scopes = crate::timing::ScopeRecorder::default();
+                scopes.grow(TIMESTAMP_QUERY_COUNT as u32);
                 array.push(TimingData {
-                    pass_names: Vec::new(),
-                    queries: (0..MAX_QUERIES)
+                    scopes,
+                    queries: (0..TIMESTAMP_QUERY_COUNT)
                         .map(|_| unsafe { gl.create_query().unwrap() })
                         .collect(),
                 });
@@ -490,6 +663,8 @@ impl crate::traits::CommandDevice for Context {
             limits: self.limits.clone(),
             timing_datas,
             timings: Default::default(),
+            pass_count: 0,
+            stats: Default::default(),
         }
     }
 
@@ -611,6 +786,11 @@ fn describe_texture_format(format: crate::TextureFormat) -> FormatInfo {
         Tf::R32Uint => (glow::R32UI, glow::RED, glow::UNSIGNED_INT),
         Tf::Rg32Uint => (glow::RG32UI, glow::RG, glow::UNSIGNED_INT),
         Tf::Rgba32Uint => (glow::RGBA32UI, glow::RGBA, glow::UNSIGNED_INT),
+        Tf::R16Unorm => (glow::R16, glow::RED, glow::UNSIGNED_SHORT),
+        Tf::Rgba16Unorm => (glow::RGBA16, glow::RGBA, glow::UNSIGNED_SHORT),
+        Tf::R8Uint => (glow::R8UI, glow::RED_INTEGER, glow::UNSIGNED_BYTE),
+        Tf::Rg16Uint => (glow::RG16UI, glow::RG_INTEGER, glow::UNSIGNED_SHORT),
+        Tf::R32Sint => (glow::R32I, glow::RED_INTEGER, glow::INT),
         Tf::Depth32Float => (glow::DEPTH_COMPONENT32F, glow::DEPTH_COMPONENT, glow::FLOAT),
         Tf::Depth32FloatStencil8Uint => (
             glow::DEPTH32F_STENCIL8,
@@ -622,6 +802,16 @@ fn describe_texture_format(format: crate::TextureFormat) -> FormatInfo {
             glow::STENCIL_INDEX,
             glow::UNSIGNED_BYTE,
         ),
+        Tf::Depth16Unorm => (
+            glow::DEPTH_COMPONENT16,
+            glow::DEPTH_COMPONENT,
+            glow::UNSIGNED_SHORT,
+        ),
+        Tf::Depth24PlusStencil8 => (
+            glow::DEPTH24_STENCIL8,
+            glow::DEPTH_STENCIL,
+            glow::UNSIGNED_INT_24_8,
+        ),
         Tf::Bc1Unorm => (glow::COMPRESSED_RGBA_S3TC_DXT1_EXT, glow::RGBA, 0),
         Tf::Bc1UnormSrgb => (glow::COMPRESSED_SRGB_ALPHA_S3TC_DXT1_EXT, glow::RGBA, 0),
         Tf::Bc2Unorm => (glow::COMPRESSED_RGBA_S3TC_DXT3_EXT, glow::RGBA, 0),
@@ -647,6 +837,52 @@ fn describe_texture_format(format: crate::TextureFormat) -> FormatInfo {
             glow::UNSIGNED_INT_10F_11F_11F_REV,
         ),
         Tf::Rgb9e5Ufloat => (glow::RGB9_E5, glow::RGB, glow::UNSIGNED_INT_5_9_9_9_REV),
+        Tf::Etc2Rgb8Unorm => (glow::COMPRESSED_RGB8_ETC2, glow::RGB, 0),
+        Tf::Etc2Rgb8UnormSrgb => (glow::COMPRESSED_SRGB8_ETC2, glow::RGB, 0),
+        Tf::Etc2Rgb8A1Unorm => (
+            glow::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            glow::RGBA,
+            0,
+        ),
+        Tf::Etc2Rgb8A1UnormSrgb => (
+            glow::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            glow::RGBA,
+            0,
+        ),
+        Tf::Etc2Rgba8Unorm => (glow::COMPRESSED_RGBA8_ETC2_EAC, glow::RGBA, 0),
+        Tf::Etc2Rgba8UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC, glow::RGBA, 0),
+        Tf::EacR11Unorm => (glow::COMPRESSED_R11_EAC, glow::RED, 0),
+        Tf::EacR11Snorm => (glow::COMPRESSED_SIGNED_R11_EAC, glow::RED, 0),
+        Tf::EacRg11Unorm => (glow::COMPRESSED_RG11_EAC, glow::RG, 0),
+        Tf::EacRg11Snorm => (glow::COMPRESSED_SIGNED_RG11_EAC, glow::RG, 0),
+        Tf::Astc4x4Unorm => (glow::COMPRESSED_RGBA_ASTC_4x4_KHR, glow::RGBA, 0),
+        Tf::Astc4x4UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_4x4_KHR, glow::RGBA, 0),
+        Tf::Astc5x4Unorm => (glow::COMPRESSED_RGBA_ASTC_5x4_KHR, glow::RGBA, 0),
+        Tf::Astc5x4UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_5x4_KHR, glow::RGBA, 0),
+        Tf::Astc5x5Unorm => (glow::COMPRESSED_RGBA_ASTC_5x5_KHR, glow::RGBA, 0),
+        Tf::Astc5x5UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_5x5_KHR, glow::RGBA, 0),
+        Tf::Astc6x5Unorm => (glow::COMPRESSED_RGBA_ASTC_6x5_KHR, glow::RGBA, 0),
+        Tf::Astc6x5UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_6x5_KHR, glow::RGBA, 0),
+        Tf::Astc6x6Unorm => (glow::COMPRESSED_RGBA_ASTC_6x6_KHR, glow::RGBA, 0),
+        Tf::Astc6x6UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_6x6_KHR, glow::RGBA, 0),
+        Tf::Astc8x5Unorm => (glow::COMPRESSED_RGBA_ASTC_8x5_KHR, glow::RGBA, 0),
+        Tf::Astc8x5UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_8x5_KHR, glow::RGBA, 0),
+        Tf::Astc8x6Unorm => (glow::COMPRESSED_RGBA_ASTC_8x6_KHR, glow::RGBA, 0),
+        Tf::Astc8x6UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_8x6_KHR, glow::RGBA, 0),
+        Tf::Astc8x8Unorm => (glow::COMPRESSED_RGBA_ASTC_8x8_KHR, glow::RGBA, 0),
+        Tf::Astc8x8UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_8x8_KHR, glow::RGBA, 0),
+        Tf::Astc10x5Unorm => (glow::COMPRESSED_RGBA_ASTC_10x5_KHR, glow::RGBA, 0),
+        Tf::Astc10x5UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_10x5_KHR, glow::RGBA, 0),
+        Tf::Astc10x6Unorm => (glow::COMPRESSED_RGBA_ASTC_10x6_KHR, glow::RGBA, 0),
+        Tf::Astc10x6UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_10x6_KHR, glow::RGBA, 0),
+        Tf::Astc10x8Unorm => (glow::COMPRESSED_RGBA_ASTC_10x8_KHR, glow::RGBA, 0),
+        Tf::Astc10x8UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_10x8_KHR, glow::RGBA, 0),
+        Tf::Astc10x10Unorm => (glow::COMPRESSED_RGBA_ASTC_10x10_KHR, glow::RGBA, 0),
+        Tf::Astc10x10UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_10x10_KHR, glow::RGBA, 0),
+        Tf::Astc12x10Unorm => (glow::COMPRESSED_RGBA_ASTC_12x10_KHR, glow::RGBA, 0),
+        Tf::Astc12x10UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_12x10_KHR, glow::RGBA, 0),
+        Tf::Astc12x12Unorm => (glow::COMPRESSED_RGBA_ASTC_12x12_KHR, glow::RGBA, 0),
+        Tf::Astc12x12UnormSrgb => (glow::COMPRESSED_SRGB8_ALPHA8_ASTC_12x12_KHR, glow::RGBA, 0),
     };
     FormatInfo {
         internal,
@@ -669,6 +905,31 @@ fn map_compare_func(fun: crate::CompareFunction) -> u32 {
     }
 }
 
+fn map_stencil_op(op: crate::StencilOperation) -> u32 {
+    use crate::StencilOperation as So;
+    match op {
+        So::Keep => glow::KEEP,
+        So::Zero => glow::ZERO,
+        So::Replace => glow::REPLACE,
+        So::Invert => glow::INVERT,
+        So::IncrementClamp => glow::INCR,
+        So::DecrementClamp => glow::DECR,
+        So::IncrementWrap => glow::INCR_WRAP,
+        So::DecrementWrap => glow::DECR_WRAP,
+    }
+}
+
+fn map_stencil_side(face: &crate::StencilFaceState) -> StencilSide {
+    StencilSide {
+        function: map_compare_func(face.compare),
+        ops: StencilOps {
+            fail: map_stencil_op(face.fail_op),
+            depth_fail: map_stencil_op(face.depth_fail_op),
+            pass: map_stencil_op(face.pass_op),
+        },
+    }
+}
+
 unsafe fn present_blit(gl: &glow::Context, source: glow::Framebuffer, size: crate::Extent) {
     use glow::HasContext as _;
 
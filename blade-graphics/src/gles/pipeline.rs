@@ -1,6 +1,8 @@
 use glow::HasContext as _;
 use naga::back::glsl;
 
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+
 fn separate<T: PartialEq>(mut iter: impl Iterator<Item = T>) -> bool {
     if let Some(first) = iter.next() {
         iter.all(|el| el == first)
@@ -25,7 +27,7 @@ impl super::Context {
         vertex_fetch_states: &[crate::VertexFetchState],
         name: &str,
         extra_flags: glsl::WriterFlags,
-    ) -> super::PipelineInner {
+    ) -> Result<super::PipelineInner, crate::Error> {
         let gl = self.lock();
         let force_explicit_bindings = self
             .capabilities
@@ -44,6 +46,7 @@ impl super::Context {
             .iter()
             .map(|layout| super::ShaderDataMapping {
                 targets: vec![Vec::new(); layout.bindings.len()].into_boxed_slice(),
+                plain_structs: Box::new([]),
             })
             .collect::<Box<[_]>>();
         if force_explicit_bindings {
@@ -55,7 +58,7 @@ impl super::Context {
                 .zip(group_layouts.iter())
                 .enumerate()
             {
-                for (binding_index, (slot_list, &(_, ref binding))) in data_mapping
+                for (binding_index, (slot_list, &(name, ref binding))) in data_mapping
                     .targets
                     .iter_mut()
                     .zip(layout.bindings.iter())
@@ -76,7 +79,13 @@ impl super::Context {
                         }
                         crate::ShaderBinding::TextureArray { .. }
                         | crate::ShaderBinding::BufferArray { .. }
-                        | crate::ShaderBinding::AccelerationStructure => unimplemented!(),
+                        | crate::ShaderBinding::AccelerationStructure
+                        | crate::ShaderBinding::Bindless { .. } => {
+                            return Err(crate::Error::UnsupportedBinding {
+                                name: name.to_string(),
+                                binding: *binding,
+                            });
+                        }
                         crate::ShaderBinding::Plain { .. } => {
                             num_buffers += 1;
                             num_buffers - 1
@@ -99,13 +108,8 @@ impl super::Context {
             );
         }
 
-        let program = gl.create_program().unwrap();
-        #[cfg(not(target_arch = "wasm32"))]
-        if !name.is_empty() && gl.supports_debug() {
-            gl.object_label(glow::PROGRAM, std::mem::transmute(program), Some(name));
-        }
-
-        let mut baked_shaders = Vec::with_capacity(shaders.len());
+        // Translate all the stages before creating any GL objects
+        let mut translated = Vec::with_capacity(shaders.len());
         let mut group_infos = group_layouts
             .iter()
             .map(|layout| layout.to_info())
@@ -113,11 +117,11 @@ impl super::Context {
         let mut attributes = Vec::new();
 
         for &sf in shaders {
-            let ep_index = sf.entry_point_index();
+            let ep_index = sf.entry_point_index()?;
             let ep = &sf.shader.module.entry_points[ep_index];
-            let _ = sf.shader.source;
+            let _ = (&sf.shader.source, &sf.shader.source_kind);
 
-            let (mut module, module_info) = sf.shader.resolve_constants(&sf.constants);
+            let (mut module, module_info) = sf.shader.try_resolve_constants(&sf.constants)?;
             if force_explicit_bindings {
                 let ep_info = module_info.get_entry_point(ep_index);
                 crate::Shader::fill_resource_bindings(
@@ -126,10 +130,10 @@ impl super::Context {
                     ep.stage,
                     ep_info,
                     group_layouts,
-                );
+                )?;
             }
             let attribute_mappings =
-                crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states);
+                crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states)?;
 
             for mapping in attribute_mappings {
                 let vf = &vertex_fetch_states[mapping.buffer_index];
@@ -148,7 +152,7 @@ impl super::Context {
                 multiview: None,
             };
             let mut source = String::new();
-            let mut writer = glsl::Writer::new(
+            let reflection = glsl::Writer::new(
                 &mut source,
                 &module,
                 &module_info,
@@ -156,8 +160,8 @@ impl super::Context {
                 &pipeline_options,
                 Default::default(),
             )
-            .unwrap();
-            let reflection = writer.write().unwrap();
+            .and_then(|mut writer| writer.write())
+            .map_err(|e| crate::Error::InvalidShader(e.to_string()))?;
 
             log::debug!(
                 "Naga generated shader for entry point '{}' and stage {:?}\n{}",
@@ -172,30 +176,60 @@ impl super::Context {
                 naga::ShaderStage::Compute => glow::COMPUTE_SHADER,
                 _ => panic!("Unsupported shader stage: {:?}", ep.stage),
             };
-            let shader = gl.create_shader(target).unwrap();
-            gl.shader_source(shader, &source);
-            gl.compile_shader(shader);
+            translated.push((ep.stage, target, source, reflection));
+        }
+        for (data_mapping, info) in group_mappings.iter_mut().zip(group_infos) {
+            data_mapping.plain_structs = info.plain_structs;
+        }
+
+        let program = gl.create_program().map_err(crate::Error::Device)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if !name.is_empty() && gl.supports_debug() {
+            gl.object_label(glow::PROGRAM, std::mem::transmute(program), Some(name));
+        }
 
-            let compiled_ok = gl.get_shader_compile_status(shader);
-            let msg = gl.get_shader_info_log(shader);
-            assert!(compiled_ok, "Compile: {}", msg);
+        let mut baked_shaders = Vec::with_capacity(shaders.len());
+        let discard = |baked_shaders: Vec<(glow::Shader, _)>| {
+            for (shader, _) in baked_shaders {
+                gl.delete_shader(shader);
+            }
+            gl.delete_program(program);
+        };
 
+        for &(_, target, ref source, ref reflection) in translated.iter() {
+            let shader = match gl.create_shader(target) {
+                Ok(shader) => shader,
+                Err(e) => {
+                    discard(baked_shaders);
+                    return Err(crate::Error::Device(e));
+                }
+            };
+            gl.shader_source(shader, source);
+            gl.compile_shader(shader);
             gl.attach_shader(program, shader);
             baked_shaders.push((shader, reflection));
+
+            if !gl.get_shader_compile_status(shader) {
+                let msg = gl.get_shader_info_log(shader);
+                discard(baked_shaders);
+                return Err(crate::Error::InvalidShader(format!("Compile: {}", msg)));
+            }
         }
 
         gl.link_program(program);
         log::info!("\tLinked program {:?}", program);
 
-        let linked_ok = gl.get_program_link_status(program);
-        let msg = gl.get_program_info_log(program);
-        assert!(linked_ok, "Link: {}", msg);
+        if !gl.get_program_link_status(program) {
+            let msg = gl.get_program_info_log(program);
+            discard(baked_shaders);
+            return Err(crate::Error::InvalidShader(format!("Link: {}", msg)));
+        }
         gl.use_program(Some(program));
 
         if !force_explicit_bindings {
             let force_uniform_block_assignment = true;
             let mut variables_to_bind = Vec::new();
-            for (sf, &(_, ref reflection)) in shaders.iter().zip(baked_shaders.iter()) {
+            for (sf, &(stage, _, _, ref reflection)) in shaders.iter().zip(translated.iter()) {
                 for (glsl_name, mapping) in reflection.texture_mapping.iter() {
                     variables_to_bind.push((glsl_name, mapping.texture));
                     if let Some(handle) = mapping.sampler {
@@ -209,7 +243,7 @@ impl super::Context {
                 for (glsl_name, var_handle) in variables_to_bind.drain(..) {
                     let var = &sf.shader.module.global_variables[var_handle];
                     let var_name = var.name.as_ref().unwrap().as_str();
-                    let (group_index, binding_index) = group_layouts
+                    let (group_index, binding_index) = match group_layouts
                         .iter()
                         .enumerate()
                         .find_map(|(group_index, layout)| {
@@ -218,10 +252,17 @@ impl super::Context {
                                 .iter()
                                 .position(|&(name, _)| name == var_name)
                                 .map(|binding_index| (group_index, binding_index))
-                        })
-                        .unwrap_or_else(|| {
-                            panic!("Shader variable {} is not found in the bindings", var_name)
-                        });
+                        }) {
+                        Some(indices) => indices,
+                        None => {
+                            gl.use_program(None);
+                            discard(baked_shaders);
+                            return Err(crate::Error::MissingBinding {
+                                name: var_name.to_string(),
+                                stage,
+                            });
+                        }
+                    };
 
                     let targets = &mut group_mappings[group_index].targets[binding_index];
                     match group_layouts[group_index].bindings[binding_index].1 {
@@ -248,7 +289,8 @@ impl super::Context {
                         }
                         crate::ShaderBinding::TextureArray { .. }
                         | crate::ShaderBinding::BufferArray { .. }
-                        | crate::ShaderBinding::AccelerationStructure => {
+                        | crate::ShaderBinding::AccelerationStructure
+                        | crate::ShaderBinding::Bindless { .. } => {
                             unimplemented!()
                         }
                         crate::ShaderBinding::Plain { size } => {
@@ -259,14 +301,24 @@ impl super::Context {
                                     glow::UNIFORM_BLOCK_DATA_SIZE,
                                 ) as u32;
                                 let rounded_up_size = super::round_up_uniform_size(size);
-                                assert!(
-                                    expected_size <= rounded_up_size,
-                                    "Shader expects block[{}] size {}, but data has size of {} (rounded up to {})",
-                                    index,
-                                    expected_size,
-                                    size,
-                                    rounded_up_size,
-                                );
+                                if expected_size > rounded_up_size {
+                                    log::error!(
+                                        "Shader expects block[{}] size {}, but data has size of {} (rounded up to {})",
+                                        index,
+                                        expected_size,
+                                        size,
+                                        rounded_up_size,
+                                    );
+                                    gl.use_program(None);
+                                    discard(baked_shaders);
+                                    return Err(crate::Error::BindingMismatch {
+                                        name: var_name.to_string(),
+                                        expected: crate::ShaderBinding::Plain {
+                                            size: expected_size,
+                                        },
+                                        provided: crate::ShaderBinding::Plain { size },
+                                    });
+                                }
                                 let slot = if force_uniform_block_assignment {
                                     gl.uniform_block_binding(program, index, index);
                                     index
@@ -290,12 +342,12 @@ impl super::Context {
         }
         gl.use_program(None);
 
-        super::PipelineInner {
+        Ok(super::PipelineInner {
             program,
             group_mappings,
             vertex_attribute_infos: attributes.into_boxed_slice(),
             color_targets: Box::new([]),
-        }
+        })
     }
 
     unsafe fn destroy_pipeline(&self, inner: &mut super::PipelineInner) {
@@ -304,13 +356,23 @@ impl super::Context {
     }
 }
 
+/// Programs are opaque handles on the Web, so they are identified by their hash.
+fn program_key(program: glow::Program) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    program.hash(&mut hasher);
+    hasher.finish()
+}
+
 #[hidden_trait::expose]
 impl crate::traits::ShaderDevice for super::Context {
     type ComputePipeline = super::ComputePipeline;
     type RenderPipeline = super::RenderPipeline;
 
-    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
-        let wg_size = desc.compute.shader.module.entry_points[desc.compute.entry_point_index()]
+    fn try_create_compute_pipeline(
+        &self,
+        desc: crate::ComputePipelineDesc,
+    ) -> Result<super::ComputePipeline, crate::Error> {
+        let wg_size = desc.compute.shader.module.entry_points[desc.compute.entry_point_index()?]
             .workgroup_size;
         let inner = unsafe {
             self.create_pipeline(
@@ -319,18 +381,35 @@ impl crate::traits::ShaderDevice for super::Context {
                 &[],
                 desc.name,
                 glsl::WriterFlags::empty(),
-            )
+            )?
         };
-        super::ComputePipeline { inner, wg_size }
+        if let Some(ref tracker) = self.tracker {
+            let key = program_key(inner.program);
+            tracker.add(crate::tracking::Handle::ComputePipeline(key), desc.name, 0);
+        }
+        Ok(super::ComputePipeline { inner, wg_size })
+    }
+
+    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
+        let name = desc.name;
+        self.try_create_compute_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create compute pipeline '{name}': {e}"))
     }
 
     fn destroy_compute_pipeline(&self, pipeline: &mut super::ComputePipeline) {
+        if let Some(ref tracker) = self.tracker {
+            let key = program_key(pipeline.inner.program);
+            tracker.remove(crate::tracking::Handle::ComputePipeline(key));
+        }
         unsafe {
             self.destroy_pipeline(&mut pipeline.inner);
         }
     }
 
-    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
+    fn try_create_render_pipeline(
+        &self,
+        desc: crate::RenderPipelineDesc,
+    ) -> Result<super::RenderPipeline, crate::Error> {
         let extra_flags = if desc.primitive.topology == crate::PrimitiveTopology::PointList {
             glsl::WriterFlags::FORCE_POINT_SIZE
         } else {
@@ -349,7 +428,7 @@ impl crate::traits::ShaderDevice for super::Context {
                 desc.vertex_fetches,
                 desc.name,
                 extra_flags,
-            )
+            )?
         };
 
         inner.color_targets = conflate(desc.color_targets.iter().map(|t| (t.blend, t.write_mask)));
@@ -364,13 +443,53 @@ impl crate::traits::ShaderDevice for super::Context {
             );
         }
 
-        super::RenderPipeline {
+        let mut depth = None;
+        let mut stencil = None;
+        if let Some(ref ds) = desc.depth_stencil {
+            let aspects = ds.format.aspects();
+            if aspects.contains(crate::TexelAspects::DEPTH)
+                && (ds.depth_write_enabled || ds.depth_compare != crate::CompareFunction::Always)
+            {
+                depth = Some(super::DepthState {
+                    function: super::map_compare_func(ds.depth_compare),
+                    write: ds.depth_write_enabled,
+                });
+            }
+            if aspects.contains(crate::TexelAspects::STENCIL)
+                && ds.stencil != crate::StencilState::default()
+            {
+                stencil = Some(super::StencilState {
+                    front: super::map_stencil_side(&ds.stencil.front),
+                    back: super::map_stencil_side(&ds.stencil.back),
+                    read_mask: ds.stencil.read_mask,
+                    write_mask: ds.stencil.write_mask,
+                });
+            }
+        }
+
+        if let Some(ref tracker) = self.tracker {
+            let key = program_key(inner.program);
+            tracker.add(crate::tracking::Handle::RenderPipeline(key), desc.name, 0);
+        }
+        Ok(super::RenderPipeline {
             inner,
             topology: desc.primitive.topology,
-        }
+            depth,
+            stencil,
+        })
+    }
+
+    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
+        let name = desc.name;
+        self.try_create_render_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create render pipeline '{name}': {e}"))
     }
 
     fn destroy_render_pipeline(&self, pipeline: &mut super::RenderPipeline) {
+        if let Some(ref tracker) = self.tracker {
+            let key = program_key(pipeline.inner.program);
+            tracker.remove(crate::tracking::Handle::RenderPipeline(key));
+        }
         unsafe {
             self.destroy_pipeline(&mut pipeline.inner);
         }
@@ -33,10 +33,10 @@ impl crate::traits::ResourceDevice for super::Context {
     type Sampler = super::Sampler;
     type AccelerationStructure = super::AccelerationStructure;
 
-    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+    fn try_create_buffer(&self, desc: crate::BufferDesc) -> Result<super::Buffer, crate::Error> {
         let gl = self.lock();
 
-        let raw = unsafe { gl.create_buffer() }.unwrap();
+        let raw = unsafe { gl.create_buffer() }.map_err(crate::Error::Device)?;
         let mut data = ptr::null_mut();
 
         let mut storage_flags = 0;
@@ -70,7 +70,11 @@ impl crate::traits::ResourceDevice for super::Context {
                 gl.buffer_storage(glow::ARRAY_BUFFER, desc.size as _, None, storage_flags);
                 if map_flags != 0 {
                     data = gl.map_buffer_range(glow::ARRAY_BUFFER, 0, desc.size as _, map_flags);
-                    assert!(!data.is_null());
+                    if data.is_null() {
+                        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+                        gl.delete_buffer(raw);
+                        return Err(crate::Error::OutOfMemory);
+                    }
                 }
             } else {
                 gl.buffer_data_size(glow::ARRAY_BUFFER, desc.size as _, usage);
@@ -83,11 +87,21 @@ impl crate::traits::ResourceDevice for super::Context {
                 gl.object_label(glow::BUFFER, std::mem::transmute(raw), Some(desc.name));
             }
         }
-        super::Buffer {
+        let buffer = super::Buffer {
             raw,
             size: desc.size,
             data,
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(buffer, desc.name, desc.size);
         }
+        Ok(buffer)
+    }
+
+    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+        let name = desc.name;
+        self.try_create_buffer(desc)
+            .unwrap_or_else(|e| panic!("Unable to create buffer '{name}': {e}"))
     }
 
     fn sync_buffer(&self, buffer: super::Buffer) {
@@ -105,6 +119,9 @@ impl crate::traits::ResourceDevice for super::Context {
     }
 
     fn destroy_buffer(&self, buffer: super::Buffer) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(buffer);
+        }
         let gl = self.lock();
         unsafe { gl.delete_buffer(buffer.raw) };
         if !buffer.data.is_null()
@@ -118,7 +135,7 @@ impl crate::traits::ResourceDevice for super::Context {
         }
     }
 
-    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+    fn try_create_texture(&self, desc: crate::TextureDesc) -> Result<super::Texture, crate::Error> {
         let gl = self.lock();
         let format_desc = super::describe_texture_format(desc.format);
 
@@ -126,7 +143,7 @@ impl crate::traits::ResourceDevice for super::Context {
             && desc.dimension == crate::TextureDimension::D2
             && desc.array_layer_count == 1
         {
-            let raw = unsafe { gl.create_renderbuffer().unwrap() };
+            let raw = unsafe { gl.create_renderbuffer() }.map_err(crate::Error::Device)?;
             unsafe {
                 gl.bind_renderbuffer(glow::RENDERBUFFER, Some(raw));
 
@@ -160,7 +177,7 @@ impl crate::traits::ResourceDevice for super::Context {
 
             super::TextureInner::Renderbuffer { raw }
         } else {
-            let raw = unsafe { gl.create_texture().unwrap() };
+            let raw = unsafe { gl.create_texture() }.map_err(crate::Error::Device)?;
 
             let target = match desc.dimension {
                 crate::TextureDimension::D1 => {
@@ -255,14 +272,27 @@ impl crate::traits::ResourceDevice for super::Context {
             super::TextureInner::Texture { raw, target }
         };
 
-        super::Texture {
+        let texture = super::Texture {
             inner,
             target_size: [desc.size.width as u16, desc.size.height as u16],
             format: desc.format,
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(texture, desc.name, crate::tracking::texture_size(&desc));
         }
+        Ok(texture)
+    }
+
+    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+        let name = desc.name;
+        self.try_create_texture(desc)
+            .unwrap_or_else(|e| panic!("Unable to create texture '{name}': {e}"))
     }
 
     fn destroy_texture(&self, texture: super::Texture) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(texture);
+        }
         let gl = self.lock();
         match texture.inner {
             super::TextureInner::Renderbuffer { raw, .. } => unsafe {
@@ -280,6 +310,7 @@ impl crate::traits::ResourceDevice for super::Context {
         desc: crate::TextureViewDesc,
     ) -> super::TextureView {
         //TODO: actual reinterpretation
+        // Views are not tracked, since they can't be told apart from each other
         super::TextureView {
             inner: texture.inner,
             target_size: texture.target_size,
@@ -345,10 +376,17 @@ impl crate::traits::ResourceDevice for super::Context {
                 gl.object_label(glow::SAMPLER, std::mem::transmute(raw), Some(desc.name));
             }
         }
-        super::Sampler { raw }
+        let sampler = super::Sampler { raw };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(sampler, desc.name, 0);
+        }
+        sampler
     }
 
     fn destroy_sampler(&self, sampler: super::Sampler) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(sampler);
+        }
         let gl = self.lock();
         unsafe { gl.delete_sampler(sampler.raw) };
     }
@@ -368,6 +406,127 @@ impl crate::traits::ResourceDevice for super::Context {
     }
 }
 
+#[hidden_trait::expose]
+impl crate::traits::BindlessDevice for super::Context {
+    type BindlessTable = super::BindlessTable;
+    type TextureView = super::TextureView;
+    type Sampler = super::Sampler;
+
+    fn create_bindless_table(&self, desc: crate::BindlessTableDesc) -> super::BindlessTable {
+        for &(_, kind) in crate::BindlessTableDesc::BINDINGS.iter() {
+            assert_eq!(
+                desc.count(kind),
+                0,
+                "Bindless table {:?} count is over the limit of 0, see `Capabilities::max_bindless_resources`",
+                kind
+            );
+        }
+        super::BindlessTable {
+            slots: crate::bindless::BindlessSlots::new(&desc),
+        }
+    }
+
+    fn destroy_bindless_table(&self, _table: &mut super::BindlessTable) {}
+
+    fn insert_bindless_texture(
+        &self,
+        table: &mut super::BindlessTable,
+        _view: super::TextureView,
+    ) -> crate::BindlessHandle {
+        table
+            .slots
+            .insert(crate::BindlessKind::Texture, |_, _| true)
+    }
+
+    fn insert_bindless_buffer(
+        &self,
+        table: &mut super::BindlessTable,
+        _piece: crate::BufferPiece,
+    ) -> crate::BindlessHandle {
+        table.slots.insert(crate::BindlessKind::Buffer, |_, _| true)
+    }
+
+    fn insert_bindless_sampler(
+        &self,
+        table: &mut super::BindlessTable,
+        _sampler: super::Sampler,
+    ) -> crate::BindlessHandle {
+        table
+            .slots
+            .insert(crate::BindlessKind::Sampler, |_, _| true)
+    }
+
+    fn remove_bindless(&self, table: &mut super::BindlessTable, handle: crate::BindlessHandle) {
+        if let Err(message) = table.slots.remove(handle, ()) {
+            panic!("{}", message);
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::QueryDevice for super::Context {
+    type QuerySet = super::QuerySet;
+    type SyncPoint = super::SyncPoint;
+
+    fn create_query_set(&self, desc: crate::QuerySetDesc) -> super::QuerySet {
+        assert_eq!(
+            desc.ty,
+            crate::QueryType::Occlusion,
+            "Pipeline statistics are not supported"
+        );
+        let gl = self.lock();
+        let queries = (0..desc.count)
+            .map(|_| unsafe {
+                let query = gl.create_query().unwrap();
+                // Make the query an object with a zero result
+                gl.begin_query(glow::ANY_SAMPLES_PASSED, query);
+                gl.end_query(glow::ANY_SAMPLES_PASSED);
+                query
+            })
+            .collect::<Box<[_]>>();
+        let set = super::QuerySet {
+            raw: Box::into_raw(queries).cast(),
+            ty: desc.ty,
+            count: desc.count,
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(set, desc.name, crate::tracking::query_set_size(&desc));
+        }
+        set
+    }
+
+    fn destroy_query_set(&self, set: super::QuerySet) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(set);
+        }
+        let queries =
+            unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(set.raw, set.count as usize)) };
+        let gl = self.lock();
+        for query in queries {
+            unsafe { gl.delete_query(query) };
+        }
+    }
+
+    fn read_query_results(
+        &self,
+        set: super::QuerySet,
+        sync_point: &super::SyncPoint,
+    ) -> Option<Vec<u64>> {
+        if !self.wait_for(sync_point, 0) {
+            return None;
+        }
+        let gl = self.lock();
+        Some(
+            set.queries()
+                .iter()
+                .map(|&query| unsafe {
+                    gl.get_query_parameter_u32(query, glow::QUERY_RESULT) as u64
+                })
+                .collect(),
+        )
+    }
+}
+
 fn map_filter_modes(
     min: crate::FilterMode,
     mag: crate::FilterMode,
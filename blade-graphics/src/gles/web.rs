@@ -50,7 +50,10 @@ impl PlatformContext {
 }
 
 impl super::Context {
-    pub unsafe fn init(_desc: crate::ContextDesc) -> Result<Self, crate::NotSupportedError> {
+    pub unsafe fn init(desc: crate::ContextDesc) -> Result<Self, crate::NotSupportedError> {
+        if desc.trace_path.is_some() {
+            log::warn!("API tracing is not supported");
+        }
         let canvas = web_sys::window()
             .and_then(|win| win.document())
             .expect("Cannot get document")
@@ -76,7 +79,26 @@ impl super::Context {
 
         let glow = glow::Context::from_webgl2_context(webgl2.clone());
 
-        let capabilities = super::Capabilities::empty();
+        let extensions = glow.supported_extensions();
+        let mut capabilities = super::Capabilities::empty();
+        capabilities.set(
+            super::Capabilities::TEXTURE_COMPRESSION_BC,
+            extensions.contains("WEBGL_compressed_texture_s3tc")
+                && extensions.contains("EXT_texture_compression_rgtc")
+                && extensions.contains("EXT_texture_compression_bptc"),
+        );
+        capabilities.set(
+            super::Capabilities::TEXTURE_COMPRESSION_ETC2,
+            extensions.contains("WEBGL_compressed_texture_etc"),
+        );
+        capabilities.set(
+            super::Capabilities::TEXTURE_COMPRESSION_ASTC,
+            extensions.contains("WEBGL_compressed_texture_astc"),
+        );
+        capabilities.set(
+            super::Capabilities::TEXTURE_NORM16,
+            extensions.contains("EXT_texture_norm16"),
+        );
         let limits = super::Limits {
             uniform_buffer_alignment: unsafe {
                 glow.get_parameter_i32(glow::UNIFORM_BUFFER_OFFSET_ALIGNMENT) as u32
@@ -95,6 +117,7 @@ impl super::Context {
             toggles: super::Toggles::default(),
             limits,
             device_information,
+            tracker: crate::tracking::Tracker::from_desc(&desc),
         })
     }
 
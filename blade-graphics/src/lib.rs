@@ -24,9 +24,10 @@
 )]
 
 pub mod background_color_vulkan;
+#[cfg(not(feature = "null"))]
 use crate::background_color_vulkan::get_background_color;
 
-pub use naga::{back::PipelineConstants, StorageAccess, VectorSize};
+pub use naga::{back::PipelineConstants, ScalarKind, ShaderStage, StorageAccess, VectorSize};
 pub type Transform = mint::RowMatrix3x4<f32>;
 
 pub const IDENTITY_TRANSFORM: Transform = mint::RowMatrix3x4 {
@@ -50,13 +51,20 @@ pub const IDENTITY_TRANSFORM: Transform = mint::RowMatrix3x4 {
     },
 };
 
+mod compose;
 pub mod derive;
 #[cfg_attr(
-    all(not(vulkan), not(gles), any(target_os = "ios", target_os = "macos")),
+    all(
+        not(feature = "null"),
+        not(vulkan),
+        not(gles),
+        any(target_os = "ios", target_os = "macos")
+    ),
     path = "metal/mod.rs"
 )]
 #[cfg_attr(
     all(
+        not(feature = "null"),
         not(gles),
         any(
             vulkan,
@@ -68,16 +76,57 @@ pub mod derive;
     ),
     path = "vulkan/mod.rs"
 )]
-#[cfg_attr(any(gles, target_arch = "wasm32"), path = "gles/mod.rs")]
+#[cfg_attr(
+    all(not(feature = "null"), any(gles, target_arch = "wasm32")),
+    path = "gles/mod.rs"
+)]
+#[cfg_attr(feature = "null", path = "null/mod.rs")]
 mod hal;
+mod reflect;
 mod shader;
+#[cfg(any(
+    feature = "null",
+    all(
+        not(gles),
+        any(
+            vulkan,
+            windows,
+            target_os = "linux",
+            target_os = "android",
+            target_os = "freebsd"
+        )
+    )
+))]
+pub mod trace;
+// Only the null and Vulkan backends fill the tables.
+#[cfg_attr(
+    not(any(
+        feature = "null",
+        all(
+            not(gles),
+            any(
+                vulkan,
+                windows,
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd"
+            )
+        )
+    )),
+    allow(dead_code)
+)]
+mod bindless;
+mod timing;
+mod tracking;
 pub mod traits;
 pub mod util;
 pub mod limits {
-    /// Max number of passes inside a command encoder.
-    pub const PASS_COUNT: usize = 100;
-    /// Max plain data size for a pipeline.
+    /// Max plain data size that is passed inline with the other bindings.
+    /// Larger plain data is placed into uniform buffers by the backends that need it.
     pub const PLAIN_DATA_SIZE: u32 = 256;
+    /// Max size of a plain binding, the smallest uniform buffer range
+    /// guaranteed by Vulkan and GLES.
+    pub const MAX_PLAIN_DATA_SIZE: u32 = 16384;
     /// Max number of resources in a bind group.
     pub const RESOURCES_IN_GROUP: u32 = 8;
     /// Min storage buffer alignment.
@@ -86,7 +135,17 @@ pub mod limits {
     pub const ACCELERATION_STRUCTURE_SCRATCH_ALIGNMENT: u64 = 256;
 }
 
+pub use bindless::BindlessHandle;
+pub use compose::{
+    ComposeDesc, ComposeError, ComposedShader, DirectoryLoader, ShaderLoader, SourceFile, SourceMap,
+};
 pub use hal::*;
+pub use reflect::{
+    EntryPointReflection, FieldMismatch, GlobalReflection, ShaderReflection,
+    StructMemberReflection, StructReflection, VertexInputReflection,
+};
+pub use timing::{TimingScope, Timings};
+pub use tracking::{LiveResource, ResourceType};
 
 #[cfg(target_arch = "wasm32")]
 pub const CANVAS_ID: &str = "blade";
@@ -100,7 +159,7 @@ pub struct ContextDesc {
     /// Enable validation of the GAPI, shaders,
     /// and insert crash markers into command buffers.
     pub validation: bool,
-    /// Enable GPU timing of all passes.
+    /// Enable GPU timing of all passes and timing scopes.
     pub timing: bool,
     /// Enable capture support with GAPI tools.
     pub capture: bool,
@@ -108,6 +167,16 @@ pub struct ContextDesc {
     pub overlay: bool,
     /// Force selection of a specific Device ID, unless 0.
     pub device_id: u32,
+    /// Record all API calls into a trace at this directory.
+    /// Only supported by Vulkan and the null backend, see `trace` module.
+    pub trace_path: Option<std::path::PathBuf>,
+    /// Track the live resources, reporting the leaked ones when the context is dropped.
+    /// See `Context::live_resources`.
+    pub track_resources: bool,
+    /// Keep the generated shader code and the driver pipeline cache in this directory,
+    /// speeding up the pipeline creation on the next runs.
+    /// Only supported by Vulkan.
+    pub pipeline_cache_path: Option<std::path::PathBuf>,
 }
 
 #[derive(Debug)]
@@ -123,6 +192,83 @@ impl From<PlatformError> for NotSupportedError {
     }
 }
 
+/// Failure to create a resource or a pipeline, returned by the `try_create_*` methods.
+/// The corresponding `create_*` methods panic with the same message instead.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// Not enough host or device memory.
+    OutOfMemory,
+    /// Texture format isn't supported with the requested size, usage, or sample count.
+    UnsupportedFormat(TextureFormat),
+    /// Entry point isn't found in the shader.
+    MissingEntryPoint(String),
+    /// Shader variable isn't found in any of the data layouts.
+    MissingBinding {
+        name: String,
+        stage: naga::ShaderStage,
+    },
+    /// Shader variable doesn't match the binding of the same name in a data layout.
+    BindingMismatch {
+        name: String,
+        expected: ShaderBinding,
+        provided: ShaderBinding,
+    },
+    /// Vertex shader input isn't provided by any of the vertex fetch states.
+    MissingVertexAttribute(String),
+    /// Vertex attribute format doesn't match the type of the shader input.
+    VertexFormatMismatch { name: String, format: VertexFormat },
+    /// Binding of a data layout isn't supported by the backend, see `Capabilities`.
+    UnsupportedBinding {
+        name: String,
+        binding: ShaderBinding,
+    },
+    /// Pipeline constants can't be applied to the shader.
+    InvalidConstants(String),
+    /// Shader can't be translated for the backend, or fails its validation.
+    InvalidShader(String),
+    /// Other failure reported by the graphics API.
+    Device(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::OutOfMemory => write!(f, "Out of memory"),
+            Self::UnsupportedFormat(format) => write!(f, "Unsupported texture format {format:?}"),
+            Self::MissingEntryPoint(ref name) => {
+                write!(f, "Entry point '{name}' not found in the shader")
+            }
+            Self::MissingBinding { ref name, stage } => write!(
+                f,
+                "Unable to resolve binding for '{name}' in stage '{stage:?}'"
+            ),
+            Self::BindingMismatch {
+                ref name,
+                expected,
+                provided,
+            } => write!(
+                f,
+                "Mismatched type for binding '{name}': shader expects {expected:?}, layout provides {provided:?}"
+            ),
+            Self::MissingVertexAttribute(ref name) => {
+                write!(f, "Field {name} is not covered by the vertex fetch layouts")
+            }
+            Self::VertexFormatMismatch { ref name, format } => write!(
+                f,
+                "Vertex attribute '{name}' format {format:?} doesn't match the shader input"
+            ),
+            Self::UnsupportedBinding { ref name, binding } => {
+                write!(f, "Binding '{name}' of {binding:?} is not supported")
+            }
+            Self::InvalidConstants(ref message) => write!(f, "Invalid pipeline constants: {message}"),
+            Self::InvalidShader(ref message) => write!(f, "Invalid shader: {message}"),
+            Self::Device(ref message) => write!(f, "Device error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Capabilities {
     /// Which shader stages support ray queries
@@ -131,6 +277,13 @@ pub struct Capabilities {
     pub sample_count_mask: u32,
     /// Support for dual-source blending.
     pub dual_source_blending: bool,
+    /// Support for `OcclusionMode::Precise`, otherwise it acts as `Binary`.
+    pub precise_occlusion: bool,
+    /// Support for `QueryType::PipelineStatistics`.
+    pub pipeline_statistics: bool,
+    /// Max number of resources of each kind in a bindless table,
+    /// zero if bindless tables are not supported.
+    pub max_bindless_resources: u32,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -285,6 +438,62 @@ impl<T, const N: ResourceIndex> std::ops::IndexMut<ResourceIndex> for ResourceAr
 pub type BufferArray<const N: ResourceIndex> = ResourceArray<BufferPiece, N>;
 pub type TextureArray<const N: ResourceIndex> = ResourceArray<TextureView, N>;
 
+/// Kind of the resources in an array of a bindless table.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum BindlessKind {
+    /// Sampled texture views.
+    Texture,
+    /// Storage buffers.
+    Buffer,
+    Sampler,
+}
+
+/// Bindless table, which holds many more resources than a bind group,
+/// and is updated while the GPU may be using it.
+///
+/// A table is bound to a whole group with the layout of `BindlessTableDesc::layout`.
+/// The shaders declare the arrays of the group as runtime-sized binding arrays
+/// named `textures`, `buffers` and `samplers`, and index them by `BindlessHandle::index`.
+/// Resources in a table are not tracked by their usage: they are made visible
+/// to the passes that bind the table, and must stay alive while the GPU can access them.
+#[derive(Debug)]
+pub struct BindlessTableDesc<'a> {
+    pub name: &'a str,
+    pub texture_count: u32,
+    pub buffer_count: u32,
+    pub sampler_count: u32,
+}
+
+impl BindlessTableDesc<'_> {
+    /// Names of the arrays in the shaders, in the order of their bindings.
+    pub const BINDINGS: [(&'static str, BindlessKind); 3] = [
+        ("textures", BindlessKind::Texture),
+        ("buffers", BindlessKind::Buffer),
+        ("samplers", BindlessKind::Sampler),
+    ];
+
+    pub fn count(&self, kind: BindlessKind) -> u32 {
+        match kind {
+            BindlessKind::Texture => self.texture_count,
+            BindlessKind::Buffer => self.buffer_count,
+            BindlessKind::Sampler => self.sampler_count,
+        }
+    }
+
+    /// Layout of the group that the table is bound to.
+    pub fn layout(&self) -> ShaderDataLayout {
+        ShaderDataLayout {
+            bindings: Self::BINDINGS
+                .iter()
+                .map(|&(name, kind)| {
+                    let count = self.count(kind);
+                    (name, ShaderBinding::Bindless { kind, count })
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct TexturePiece {
     pub texture: Texture,
@@ -325,10 +534,20 @@ pub enum TextureFormat {
     R32Uint,
     Rg32Uint,
     Rgba32Uint,
+    R16Unorm,
+    Rgba16Unorm,
+    R8Uint,
+    Rg16Uint,
+    R32Sint,
     // depth and stencil
     Depth32Float,
     Depth32FloatStencil8Uint,
     Stencil8Uint,
+    Depth16Unorm,
+    /// Depth with at least 24 bits of precision, and 8-bit stencil.
+    /// Metal, and Vulkan devices that can't render to D24S8, back it with
+    /// 32-bit float depth, so the block size accounts for that.
+    Depth24PlusStencil8,
     // S3TC block compression
     Bc1Unorm,
     Bc1UnormSrgb,
@@ -348,6 +567,46 @@ pub enum TextureFormat {
     Rgb10a2Unorm,
     Rg11b10Ufloat,
     Rgb9e5Ufloat,
+    // ETC2 and EAC block compression
+    Etc2Rgb8Unorm,
+    Etc2Rgb8UnormSrgb,
+    Etc2Rgb8A1Unorm,
+    Etc2Rgb8A1UnormSrgb,
+    Etc2Rgba8Unorm,
+    Etc2Rgba8UnormSrgb,
+    EacR11Unorm,
+    EacR11Snorm,
+    EacRg11Unorm,
+    EacRg11Snorm,
+    // ASTC block compression
+    Astc4x4Unorm,
+    Astc4x4UnormSrgb,
+    Astc5x4Unorm,
+    Astc5x4UnormSrgb,
+    Astc5x5Unorm,
+    Astc5x5UnormSrgb,
+    Astc6x5Unorm,
+    Astc6x5UnormSrgb,
+    Astc6x6Unorm,
+    Astc6x6UnormSrgb,
+    Astc8x5Unorm,
+    Astc8x5UnormSrgb,
+    Astc8x6Unorm,
+    Astc8x6UnormSrgb,
+    Astc8x8Unorm,
+    Astc8x8UnormSrgb,
+    Astc10x5Unorm,
+    Astc10x5UnormSrgb,
+    Astc10x6Unorm,
+    Astc10x6UnormSrgb,
+    Astc10x8Unorm,
+    Astc10x8UnormSrgb,
+    Astc10x10Unorm,
+    Astc10x10UnormSrgb,
+    Astc12x10Unorm,
+    Astc12x10UnormSrgb,
+    Astc12x12Unorm,
+    Astc12x12UnormSrgb,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -573,6 +832,64 @@ pub enum VertexFormat {
     I32Vec2,
     I32Vec3,
     I32Vec4,
+    /// Four `u8` normalized to `[0, 1]`.
+    Unorm8x4,
+    /// Four `i8` normalized to `[-1, 1]`.
+    Snorm8x4,
+    /// Four `u8` fetched as integers.
+    Uint8x4,
+    Unorm16x2,
+    Unorm16x4,
+    Snorm16x2,
+    Snorm16x4,
+    /// Two IEEE half-precision floats.
+    Float16x2,
+    Float16x4,
+    /// Three 10-bit and one 2-bit normalized components packed into `u32`,
+    /// with red in the lowest bits.
+    Rgb10a2Unorm,
+}
+
+macro_rules! vertex_attribute_types {
+    ($($(#[$meta:meta])* $name:ident($inner:ty);)*) => {$(
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+        pub struct $name(pub $inner);
+        unsafe impl bytemuck::Zeroable for $name {}
+        unsafe impl bytemuck::Pod for $name {}
+    )*};
+}
+
+vertex_attribute_types! {
+    /// Host side of [`VertexFormat::Unorm8x4`].
+    Unorm8x4([u8; 4]);
+    /// Host side of [`VertexFormat::Snorm8x4`].
+    Snorm8x4([i8; 4]);
+    /// Host side of [`VertexFormat::Uint8x4`].
+    Uint8x4([u8; 4]);
+    /// Host side of [`VertexFormat::Unorm16x2`].
+    Unorm16x2([u16; 2]);
+    /// Host side of [`VertexFormat::Unorm16x4`].
+    Unorm16x4([u16; 4]);
+    /// Host side of [`VertexFormat::Snorm16x2`].
+    Snorm16x2([i16; 2]);
+    /// Host side of [`VertexFormat::Snorm16x4`].
+    Snorm16x4([i16; 4]);
+    /// Host side of [`VertexFormat::Float16x2`], as raw half-float bits.
+    Float16x2([u16; 2]);
+    /// Host side of [`VertexFormat::Float16x4`], as raw half-float bits.
+    Float16x4([u16; 4]);
+    /// Host side of [`VertexFormat::Rgb10a2Unorm`].
+    Rgb10a2Unorm(u32);
+}
+
+impl Rgb10a2Unorm {
+    /// Pack the components, each clamped to `[0, 1]`.
+    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        let pack = |value: f32, max: u32| (value.clamp(0.0, 1.0) * max as f32).round() as u32;
+        Self(pack(r, 0x3FF) | pack(g, 0x3FF) << 10 | pack(b, 0x3FF) << 20 | pack(a, 3) << 30)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -615,10 +932,20 @@ pub struct AccelerationStructureSizes {
     pub scratch: u64,
 }
 
+/// Language of the source kept by a shader.
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub(crate) enum ShaderSourceKind {
+    Wgsl,
+    Glsl(naga::ShaderStage),
+    /// SPIR-V binary, the text source is empty.
+    SpirV(Vec<u8>),
+}
+
 pub struct Shader {
     module: naga::Module,
     info: naga::valid::ModuleInfo,
     source: String,
+    source_kind: ShaderSourceKind,
 }
 
 #[derive(Clone, Copy)]
@@ -629,25 +956,36 @@ pub struct ShaderFunction<'a> {
 }
 
 impl ShaderFunction<'_> {
-    fn entry_point_index(&self) -> usize {
+    fn entry_point_index(&self) -> Result<usize, Error> {
         self.shader
             .module
             .entry_points
             .iter()
             .position(|ep| ep.name == self.entry_point)
-            .expect("Entry point not found in the shader")
+            .ok_or_else(|| Error::MissingEntryPoint(self.entry_point.to_string()))
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
 pub enum ShaderBinding {
     Texture,
-    TextureArray { count: u32 },
+    TextureArray {
+        count: u32,
+    },
     Sampler,
     Buffer,
-    BufferArray { count: u32 },
+    BufferArray {
+        count: u32,
+    },
     AccelerationStructure,
-    Plain { size: u32 },
+    Plain {
+        size: u32,
+    },
+    /// Array of a bindless table, see `BindlessTableDesc`.
+    Bindless {
+        kind: BindlessKind,
+        count: u32,
+    },
 }
 
 pub trait ShaderBindable: Clone + Copy + derive::HasShaderBinding {
@@ -658,9 +996,11 @@ pub trait ShaderBindable: Clone + Copy + derive::HasShaderBinding {
 struct ShaderDataInfo {
     visibility: ShaderVisibility,
     binding_access: Box<[StorageAccess]>,
+    /// Shader struct of each plain data binding, if it's a struct.
+    plain_structs: Box<[Option<StructReflection>]>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, Hash, PartialEq)]
 pub struct ShaderDataLayout {
     pub bindings: Vec<(&'static str, ShaderBinding)>,
 }
@@ -673,6 +1013,7 @@ impl ShaderDataLayout {
         ShaderDataInfo {
             visibility: ShaderVisibility::empty(),
             binding_access: vec![StorageAccess::empty(); self.bindings.len()].into_boxed_slice(),
+            plain_structs: vec![None; self.bindings.len()].into_boxed_slice(),
         }
     }
 }
@@ -680,9 +1021,37 @@ impl ShaderDataLayout {
 pub trait ShaderData {
     fn layout() -> ShaderDataLayout;
     fn fill(&self, context: PipelineContext);
+    /// Host layouts of the plain data bindings that are structs, by binding index.
+    /// They are checked against the shader structs on `bind` in debug builds.
+    fn plain_layouts() -> Vec<(u32, StructLayout)> {
+        Vec::new()
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Field of a host struct passed as plain data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StructField {
+    pub name: &'static str,
+    pub offset: u32,
+    pub size: u32,
+    /// Kind of the scalars in the field, or `None` for nested structs.
+    pub kind: Option<ScalarKind>,
+}
+
+/// Layout of a host struct passed as plain data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StructLayout {
+    pub size: u32,
+    pub fields: Vec<StructField>,
+}
+
+/// Host struct mirroring a shader struct, see `StructReflection::check_layout`.
+/// Fields with names starting with `_` are considered padding.
+pub trait ShaderStruct: bytemuck::Pod {
+    fn struct_layout() -> StructLayout;
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
 pub struct VertexAttribute {
     pub offset: u32,
     pub format: VertexFormat,
@@ -693,7 +1062,7 @@ struct VertexAttributeMapping {
     attribute_index: usize,
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, Hash, PartialEq)]
 pub struct VertexLayout {
     pub attributes: Vec<(&'static str, VertexAttribute)>,
     pub stride: u32,
@@ -703,30 +1072,122 @@ pub trait Vertex {
     fn layout() -> VertexLayout;
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Hash, PartialEq)]
 pub struct VertexFetchState<'a> {
     pub layout: &'a VertexLayout,
     pub instanced: bool,
 }
 
+/// Language of a shader, see [`ShaderDesc::language`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ShaderLanguage<'a> {
+    #[default]
+    Wgsl,
+    /// GLSL 4.50 with a single `main` entry point of the given stage.
+    Glsl { stage: naga::ShaderStage },
+    /// SPIR-V binary, which is used instead of the text source.
+    SpirV(&'a [u8]),
+}
+
+#[derive(Clone, Copy, Debug, Default)]
 pub struct ShaderDesc<'a> {
     pub source: &'a str,
+    /// Origin of the source lines, if it's composed from multiple files.
+    /// See [`ComposedShader::desc`].
+    pub source_map: Option<&'a SourceMap>,
+    /// GLSL and SPIR-V shaders have their resource bindings and vertex input locations
+    /// discarded, so they are bound by name, like the WGSL ones.
+    pub language: ShaderLanguage<'a>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+/// Phase of the shader compilation that failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShaderErrorStage {
+    /// The source isn't valid WGSL.
+    Parse,
+    /// The module is parsed but fails the validation.
+    Validation,
+}
+
+/// Source region that a shader error refers to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderErrorLabel {
+    pub message: String,
+    /// Path of the original file, if the shader is composed.
+    /// The range, line, and column are relative to this file.
+    pub file: Option<String>,
+    /// Byte range in the source.
+    pub range: std::ops::Range<usize>,
+    /// 1-based line number.
+    pub line: u32,
+    /// 1-based column, in bytes.
+    pub column: u32,
+}
+
+/// Diagnostics of a shader that failed to compile, returned by `Context::try_create_shader`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderError {
+    pub stage: ShaderErrorStage,
+    /// Description of the error, including the chain of its causes.
+    pub message: String,
+    pub labels: Vec<ShaderErrorLabel>,
+    /// Diagnostic with the source snippets, as printed by `codespan-reporting` without colors.
+    pub rendered: String,
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.rendered)
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Kind of work recorded by a command encoder, which selects the queue.
+///
+/// Vulkan submits the transfer and compute work to dedicated queue families
+/// when the device has them, so that it can overlap with rendering.
+/// Other backends, and devices without such families, use the general queue.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
 pub enum CommandType {
+    /// Transfer passes only.
     Transfer,
+    /// Transfer, acceleration structure, and compute passes.
     Compute,
+    /// Any passes, and presentation.
     #[default]
     General,
 }
 
+impl CommandType {
+    /// Check if an encoder of this type can record the work of `other` type.
+    pub fn supports(self, other: Self) -> bool {
+        match self {
+            Self::Transfer => other == Self::Transfer,
+            Self::Compute => other != Self::General,
+            Self::General => true,
+        }
+    }
+}
+
 pub struct CommandEncoderDesc<'a> {
     pub name: &'a str,
     /// Number of buffers that this encoder needs to keep alive.
     /// For example, one buffer is being run on GPU while the
     /// other is being actively encoded, which makes 2.
     pub buffer_count: u32,
+    pub command_type: CommandType,
+}
+
+impl Default for CommandEncoderDesc<'_> {
+    /// General encoder with 2 buffers.
+    fn default() -> Self {
+        Self {
+            name: "",
+            buffer_count: 2,
+            command_type: CommandType::General,
+        }
+    }
 }
 
 pub struct ComputePipelineDesc<'a> {
@@ -1224,4 +1685,70 @@ pub struct Viewport {
     pub depth: std::ops::Range<f32>,
 }
 
-pub type Timings = Vec<(String, std::time::Duration)>;
+bitflags::bitflags! {
+    /// Counters collected by a pipeline statistics query.
+    ///
+    /// A query produces one value per enabled counter, in the order of the bits.
+    #[derive(Copy, Clone, Debug, Default, Hash, Eq, PartialEq)]
+    pub struct PipelineStatistics: u32 {
+        /// Primitives assembled from the vertices.
+        const PRIMITIVES = 1 << 0;
+        /// Invocations of the vertex shader.
+        const VERTEX_INVOCATIONS = 1 << 1;
+        /// Invocations of the fragment shader.
+        const FRAGMENT_INVOCATIONS = 1 << 2;
+        /// Invocations of the compute shader.
+        const COMPUTE_INVOCATIONS = 1 << 3;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum QueryType {
+    /// Samples passing the depth and stencil tests, counted around draws in a render pass.
+    /// The set is given to `CommandEncoder::render_with_queries`.
+    Occlusion,
+    /// Pipeline counters, collected around passes.
+    PipelineStatistics(PipelineStatistics),
+}
+
+impl QueryType {
+    /// Number of values produced by a single query.
+    pub fn value_count(&self) -> usize {
+        match *self {
+            Self::Occlusion => 1,
+            Self::PipelineStatistics(statistics) => statistics.bits().count_ones() as usize,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum OcclusionMode {
+    /// Only tell if any samples passed, which is enough for culling and can be faster.
+    /// The result is zero if nothing passed, and non-zero otherwise.
+    #[default]
+    Binary,
+    /// Count the samples exactly, if `Capabilities::precise_occlusion` is supported.
+    Precise,
+}
+
+#[derive(Debug)]
+pub struct QuerySetDesc<'a> {
+    pub name: &'a str,
+    pub ty: QueryType,
+    pub count: u32,
+}
+
+/// Statistics of the commands recorded into an encoder.
+///
+/// They describe the last recording, and are updated when the encoder
+/// is submitted or started again. Only Vulkan has explicit barriers,
+/// other backends report zero for them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CommandStats {
+    /// Number of passes.
+    pub passes: u32,
+    /// Number of pipeline barriers, including the image layout transitions.
+    pub barriers: u32,
+    /// Number of passes that didn't need a barrier.
+    pub skipped_barriers: u32,
+}
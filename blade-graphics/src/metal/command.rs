@@ -1,15 +1,99 @@
+use objc2::{rc::Retained, runtime::ProtocolObject};
 use objc2_foundation::{NSArray, NSRange, NSString};
 use objc2_metal::{
     self as metal, MTLAccelerationStructureCommandEncoder as _, MTLBlitCommandEncoder,
-    MTLCommandBuffer as _, MTLCommandEncoder, MTLComputeCommandEncoder as _,
-    MTLCounterSampleBuffer, MTLRenderCommandEncoder,
+    MTLBuffer as _, MTLCommandBuffer as _, MTLCommandEncoder, MTLComputeCommandEncoder as _,
+    MTLCounterSampleBuffer, MTLDevice as _, MTLParallelRenderCommandEncoder as _,
+    MTLRenderCommandEncoder,
 };
-use std::{marker::PhantomData, mem, ptr::NonNull, slice, time::Duration};
+use std::{mem, ptr, ptr::NonNull, slice};
+
+/// Max size of the data that can be passed with `setBytes`.
+const MAX_BYTES_LENGTH: usize = 4096;
+/// Alignment of the buffer offsets in the constant address space.
+const PLAIN_ALIGNMENT: usize = 256;
+/// Min size of a buffer with the plain data.
+const PLAIN_CHUNK_SIZE: usize = 1 << 16;
+
+impl super::PlainArena {
+    pub(super) fn new(device: Retained<ProtocolObject<dyn metal::MTLDevice>>) -> Self {
+        Self {
+            device,
+            buffers: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Forget the data of the previous recording, keeping only the largest buffer.
+    fn reset(&mut self) {
+        let last = self.buffers.len().saturating_sub(1);
+        self.buffers.drain(..last);
+        self.offset = 0;
+    }
+
+    /// Copy the data into a buffer, returning the buffer and the offset of the data.
+    fn alloc(&mut self, data: &[u8]) -> (&ProtocolObject<dyn metal::MTLBuffer>, usize) {
+        let offset = self.offset.next_multiple_of(PLAIN_ALIGNMENT);
+        let fits = self
+            .buffers
+            .last()
+            .is_some_and(|buffer| offset + data.len() <= buffer.length());
+        let offset = if fits {
+            offset
+        } else {
+            let size = self
+                .buffers
+                .last()
+                .map_or(PLAIN_CHUNK_SIZE, |buffer| 2 * buffer.length())
+                .max(data.len());
+            log::info!("Creating a buffer of {} bytes for plain data", size);
+            let buffer = self
+                .device
+                .newBufferWithLength_options(size, metal::MTLResourceOptions::StorageModeShared)
+                .unwrap();
+            self.buffers.push(buffer);
+            0
+        };
+        let buffer = self.buffers.last().unwrap();
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                buffer.contents().as_ptr().cast::<u8>().add(offset),
+                data.len(),
+            );
+        }
+        self.offset = offset + data.len();
+        (buffer, offset)
+    }
+
+    /// Keep the buffers of a merged render chunk alive with this arena.
+    fn absorb(&mut self, other: Self) {
+        // The last buffer is the one being filled, so it stays last
+        self.buffers.splice(0..0, other.buffers);
+    }
+}
 
 impl<T: bytemuck::Pod> crate::ShaderBindable for T {
     fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
         let slot = ctx.targets[index as usize] as _;
         let size = mem::size_of::<T>();
+        if size > MAX_BYTES_LENGTH {
+            // Too large for `setBytes`, goes through the arena of the command buffer
+            let (buffer, offset) = ctx.plain_arena.alloc(bytemuck::bytes_of(self));
+            let value = Some(buffer);
+            unsafe {
+                if let Some(encoder) = ctx.vs_encoder {
+                    encoder.setVertexBuffer_offset_atIndex(value, offset, slot);
+                }
+                if let Some(encoder) = ctx.fs_encoder {
+                    encoder.setFragmentBuffer_offset_atIndex(value, offset, slot);
+                }
+                if let Some(encoder) = ctx.cs_encoder {
+                    encoder.setBuffer_offset_atIndex(value, offset, slot);
+                }
+            }
+            return;
+        }
         unsafe {
             let ptr = NonNull::new_unchecked(self as *const _ as *mut _);
             if let Some(encoder) = ctx.vs_encoder {
@@ -104,10 +188,30 @@ impl crate::ShaderBindable for crate::AccelerationStructure {
 }
 
 impl super::TimingData {
-    fn add(&mut self, label: &str) -> usize {
-        let counter_index = self.pass_names.len() * 2;
-        self.pass_names.push(label.to_string());
-        counter_index
+    /// Begin the scope of a pass, returning the index of its starting sample if it gets one.
+    /// The ending sample follows it.
+    fn begin_pass(&mut self, label: &str) -> Option<usize> {
+        self.scopes
+            .begin_pass(label, true)
+            .map(|query| query as usize)
+    }
+}
+
+fn end_pass(scopes: Option<&mut crate::timing::ScopeRecorder>) {
+    if let Some(scopes) = scopes {
+        // The ending sample of the pass is written at the end of the encoder
+        let (_, unended) = scopes.end_pass();
+        for name in unended {
+            log::warn!("Timing scope '{}' is not ended", name);
+        }
+    }
+}
+
+fn end_scope(scopes: Option<&mut crate::timing::ScopeRecorder>) {
+    if let Some(scopes) = scopes
+        && let Err(message) = scopes.end()
+    {
+        log::error!("{}", message);
     }
 }
 
@@ -125,7 +229,32 @@ impl super::CommandEncoder {
         }
     }
 
+    fn scopes(&mut self) -> Option<&mut crate::timing::ScopeRecorder> {
+        let td_array = self.timing_datas.as_mut()?;
+        Some(&mut td_array.first_mut().unwrap().scopes)
+    }
+
+    /// Timing scopes and plain data arena of the current command buffer.
+    fn pass_state(
+        &mut self,
+    ) -> (
+        Option<&mut crate::timing::ScopeRecorder>,
+        &mut super::PlainArena,
+    ) {
+        let scopes = self
+            .timing_datas
+            .as_mut()
+            .map(|td_array| &mut td_array.first_mut().unwrap().scopes);
+        (scopes, self.plain_arenas.first_mut().unwrap())
+    }
+
     pub(super) fn finish(&mut self) -> super::RawCommandBuffer {
+        if let Some(scopes) = self.scopes() {
+            let (_, unended) = scopes.finish();
+            for name in unended {
+                log::warn!("Timing scope '{}' is not ended", name);
+            }
+        }
         if self.has_open_debug_group {
             self.raw.as_mut().unwrap().popDebugGroup();
         }
@@ -133,18 +262,20 @@ impl super::CommandEncoder {
     }
 
     pub fn transfer(&mut self, label: &str) -> super::TransferCommandEncoder<'_> {
+        self.pass_count += 1;
         self.begin_pass(label);
         let raw = objc2::rc::autoreleasepool(|_| unsafe {
             let descriptor = metal::MTLBlitPassDescriptor::new();
             if let Some(ref mut td_array) = self.timing_datas {
                 let td = td_array.first_mut().unwrap();
-                let counter_index = td.add(label);
-                let sba = descriptor
-                    .sampleBufferAttachments()
-                    .objectAtIndexedSubscript(0);
-                sba.setSampleBuffer(Some(&td.sample_buffer));
-                sba.setStartOfEncoderSampleIndex(counter_index);
-                sba.setEndOfEncoderSampleIndex(counter_index + 1);
+                if let Some(counter_index) = td.begin_pass(label) {
+                    let sba = descriptor
+                        .sampleBufferAttachments()
+                        .objectAtIndexedSubscript(0);
+                    sba.setSampleBuffer(Some(&td.sample_buffer));
+                    sba.setStartOfEncoderSampleIndex(counter_index);
+                    sba.setEndOfEncoderSampleIndex(counter_index + 1);
+                }
             }
 
             self.raw
@@ -155,7 +286,7 @@ impl super::CommandEncoder {
         });
         super::TransferCommandEncoder {
             raw,
-            phantom: PhantomData,
+            scopes: self.scopes(),
         }
     }
 
@@ -163,18 +294,20 @@ impl super::CommandEncoder {
         &mut self,
         label: &str,
     ) -> super::AccelerationStructureCommandEncoder<'_> {
+        self.pass_count += 1;
         let raw = objc2::rc::autoreleasepool(|_| unsafe {
             let descriptor = metal::MTLAccelerationStructurePassDescriptor::new();
 
             if let Some(ref mut td_array) = self.timing_datas {
                 let td = td_array.first_mut().unwrap();
-                let counter_index = td.add(label);
-                let sba = descriptor
-                    .sampleBufferAttachments()
-                    .objectAtIndexedSubscript(0);
-                sba.setSampleBuffer(Some(&td.sample_buffer));
-                sba.setStartOfEncoderSampleIndex(counter_index);
-                sba.setEndOfEncoderSampleIndex(counter_index + 1);
+                if let Some(counter_index) = td.begin_pass(label) {
+                    let sba = descriptor
+                        .sampleBufferAttachments()
+                        .objectAtIndexedSubscript(0);
+                    sba.setSampleBuffer(Some(&td.sample_buffer));
+                    sba.setStartOfEncoderSampleIndex(counter_index);
+                    sba.setEndOfEncoderSampleIndex(counter_index + 1);
+                }
             }
 
             self.raw
@@ -184,11 +317,12 @@ impl super::CommandEncoder {
         });
         super::AccelerationStructureCommandEncoder {
             raw,
-            phantom: PhantomData,
+            scopes: self.scopes(),
         }
     }
 
     pub fn compute(&mut self, label: &str) -> super::ComputeCommandEncoder<'_> {
+        self.pass_count += 1;
         let raw = objc2::rc::autoreleasepool(|_| unsafe {
             let descriptor = metal::MTLComputePassDescriptor::new();
             if self.enable_dispatch_type {
@@ -197,13 +331,14 @@ impl super::CommandEncoder {
 
             if let Some(ref mut td_array) = self.timing_datas {
                 let td = td_array.first_mut().unwrap();
-                let counter_index = td.add(label);
-                let sba = descriptor
-                    .sampleBufferAttachments()
-                    .objectAtIndexedSubscript(0);
-                sba.setSampleBuffer(Some(&td.sample_buffer));
-                sba.setStartOfEncoderSampleIndex(counter_index);
-                sba.setEndOfEncoderSampleIndex(counter_index + 1);
+                if let Some(counter_index) = td.begin_pass(label) {
+                    let sba = descriptor
+                        .sampleBufferAttachments()
+                        .objectAtIndexedSubscript(0);
+                    sba.setSampleBuffer(Some(&td.sample_buffer));
+                    sba.setStartOfEncoderSampleIndex(counter_index);
+                    sba.setEndOfEncoderSampleIndex(counter_index + 1);
+                }
             }
 
             self.raw
@@ -212,102 +347,101 @@ impl super::CommandEncoder {
                 .computeCommandEncoderWithDescriptor(&descriptor)
                 .unwrap()
         });
+        let (scopes, plain_arena) = self.pass_state();
         super::ComputeCommandEncoder {
             raw,
-            phantom: PhantomData,
+            scopes,
+            plain_arena,
         }
     }
 
-    pub fn render(
+    fn render_descriptor(
         &mut self,
         label: &str,
-        targets: crate::RenderTargetSet,
-    ) -> super::RenderCommandEncoder<'_> {
-        let raw = objc2::rc::autoreleasepool(|_| {
-            let descriptor = unsafe { metal::MTLRenderPassDescriptor::new() };
+        targets: &crate::RenderTargetSet,
+    ) -> Retained<metal::MTLRenderPassDescriptor> {
+        let descriptor = unsafe { metal::MTLRenderPassDescriptor::new() };
+
+        for (i, rt) in targets.colors.iter().enumerate() {
+            let at_descriptor =
+                unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(i) };
+            at_descriptor.setTexture(Some(rt.view.as_ref()));
+
+            let load_action = match rt.init_op {
+                crate::InitOp::Load => metal::MTLLoadAction::Load,
+                crate::InitOp::Clear(color) => {
+                    let clear_color = map_clear_color(color);
+                    at_descriptor.setClearColor(clear_color);
+                    metal::MTLLoadAction::Clear
+                }
+                crate::InitOp::DontCare => metal::MTLLoadAction::DontCare,
+            };
+            at_descriptor.setLoadAction(load_action);
+
+            let store_action = match rt.finish_op {
+                crate::FinishOp::Store | crate::FinishOp::Ignore => metal::MTLStoreAction::Store,
+                crate::FinishOp::Discard => metal::MTLStoreAction::DontCare,
+                crate::FinishOp::ResolveTo(ref view) => {
+                    at_descriptor.setResolveTexture(Some(view.as_ref()));
+                    metal::MTLStoreAction::MultisampleResolve
+                }
+            };
+            at_descriptor.setStoreAction(store_action);
+        }
 
-            for (i, rt) in targets.colors.iter().enumerate() {
-                let at_descriptor =
-                    unsafe { descriptor.colorAttachments().objectAtIndexedSubscript(i) };
+        if let Some(ref rt) = targets.depth_stencil {
+            if rt.view.aspects.contains(crate::TexelAspects::DEPTH) {
+                let at_descriptor = descriptor.depthAttachment();
                 at_descriptor.setTexture(Some(rt.view.as_ref()));
-
                 let load_action = match rt.init_op {
                     crate::InitOp::Load => metal::MTLLoadAction::Load,
                     crate::InitOp::Clear(color) => {
-                        let clear_color = map_clear_color(color);
-                        at_descriptor.setClearColor(clear_color);
+                        let clear_depth = color.depth_clear_value();
+                        at_descriptor.setClearDepth(clear_depth as f64);
                         metal::MTLLoadAction::Clear
                     }
                     crate::InitOp::DontCare => metal::MTLLoadAction::DontCare,
                 };
-                at_descriptor.setLoadAction(load_action);
-
                 let store_action = match rt.finish_op {
                     crate::FinishOp::Store | crate::FinishOp::Ignore => {
                         metal::MTLStoreAction::Store
                     }
                     crate::FinishOp::Discard => metal::MTLStoreAction::DontCare,
-                    crate::FinishOp::ResolveTo(ref view) => {
-                        at_descriptor.setResolveTexture(Some(view.as_ref()));
-                        metal::MTLStoreAction::MultisampleResolve
-                    }
+                    crate::FinishOp::ResolveTo(_) => panic!("Can't resolve depth texture"),
                 };
+                at_descriptor.setLoadAction(load_action);
                 at_descriptor.setStoreAction(store_action);
             }
 
-            if let Some(ref rt) = targets.depth_stencil {
-                if rt.view.aspects.contains(crate::TexelAspects::DEPTH) {
-                    let at_descriptor = descriptor.depthAttachment();
-                    at_descriptor.setTexture(Some(rt.view.as_ref()));
-                    let load_action = match rt.init_op {
-                        crate::InitOp::Load => metal::MTLLoadAction::Load,
-                        crate::InitOp::Clear(color) => {
-                            let clear_depth = color.depth_clear_value();
-                            at_descriptor.setClearDepth(clear_depth as f64);
-                            metal::MTLLoadAction::Clear
-                        }
-                        crate::InitOp::DontCare => metal::MTLLoadAction::DontCare,
-                    };
-                    let store_action = match rt.finish_op {
-                        crate::FinishOp::Store | crate::FinishOp::Ignore => {
-                            metal::MTLStoreAction::Store
-                        }
-                        crate::FinishOp::Discard => metal::MTLStoreAction::DontCare,
-                        crate::FinishOp::ResolveTo(_) => panic!("Can't resolve depth texture"),
-                    };
-                    at_descriptor.setLoadAction(load_action);
-                    at_descriptor.setStoreAction(store_action);
-                }
+            if rt.view.aspects.contains(crate::TexelAspects::STENCIL) {
+                let at_descriptor = descriptor.stencilAttachment();
+                at_descriptor.setTexture(Some(rt.view.as_ref()));
 
-                if rt.view.aspects.contains(crate::TexelAspects::STENCIL) {
-                    let at_descriptor = descriptor.stencilAttachment();
-                    at_descriptor.setTexture(Some(rt.view.as_ref()));
-
-                    let load_action = match rt.init_op {
-                        crate::InitOp::Load => metal::MTLLoadAction::Load,
-                        crate::InitOp::Clear(color) => {
-                            let clear_stencil = color.stencil_clear_value();
-                            at_descriptor.setClearStencil(clear_stencil);
-                            metal::MTLLoadAction::Clear
-                        }
-                        crate::InitOp::DontCare => metal::MTLLoadAction::DontCare,
-                    };
-                    let store_action = match rt.finish_op {
-                        crate::FinishOp::Store | crate::FinishOp::Ignore => {
-                            metal::MTLStoreAction::Store
-                        }
-                        crate::FinishOp::Discard => metal::MTLStoreAction::DontCare,
-                        crate::FinishOp::ResolveTo(_) => panic!("Can't resolve stencil texture"),
-                    };
-
-                    at_descriptor.setLoadAction(load_action);
-                    at_descriptor.setStoreAction(store_action);
-                }
+                let load_action = match rt.init_op {
+                    crate::InitOp::Load => metal::MTLLoadAction::Load,
+                    crate::InitOp::Clear(color) => {
+                        let clear_stencil = color.stencil_clear_value();
+                        at_descriptor.setClearStencil(clear_stencil);
+                        metal::MTLLoadAction::Clear
+                    }
+                    crate::InitOp::DontCare => metal::MTLLoadAction::DontCare,
+                };
+                let store_action = match rt.finish_op {
+                    crate::FinishOp::Store | crate::FinishOp::Ignore => {
+                        metal::MTLStoreAction::Store
+                    }
+                    crate::FinishOp::Discard => metal::MTLStoreAction::DontCare,
+                    crate::FinishOp::ResolveTo(_) => panic!("Can't resolve stencil texture"),
+                };
+
+                at_descriptor.setLoadAction(load_action);
+                at_descriptor.setStoreAction(store_action);
             }
+        }
 
-            if let Some(ref mut td_array) = self.timing_datas {
-                let td = td_array.first_mut().unwrap();
-                let counter_index = td.add(label);
+        if let Some(ref mut td_array) = self.timing_datas {
+            let td = td_array.first_mut().unwrap();
+            if let Some(counter_index) = td.begin_pass(label) {
                 unsafe {
                     let sba = descriptor
                         .sampleBufferAttachments()
@@ -317,7 +451,54 @@ impl super::CommandEncoder {
                     sba.setEndOfFragmentSampleIndex(counter_index + 1);
                 }
             }
+        }
+        descriptor
+    }
 
+    pub fn render(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::RenderCommandEncoder<'_> {
+        self.pass_count += 1;
+        let raw = objc2::rc::autoreleasepool(|_| {
+            let descriptor = self.render_descriptor(label, &targets);
+            self.raw
+                .as_mut()
+                .unwrap()
+                .renderCommandEncoderWithDescriptor(&descriptor)
+                .unwrap()
+        });
+
+        let (scopes, plain_arena) = self.pass_state();
+        super::RenderCommandEncoder {
+            raw,
+            scopes,
+            plain_arena,
+        }
+    }
+
+    /// Begin a render pass, which writes the occlusion queries into `queries`.
+    /// All the queries of the set are reset at the start of the pass.
+    pub fn render_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::RenderCommandEncoder<'_> {
+        assert_eq!(queries.ty, crate::QueryType::Occlusion);
+        self.pass_count += 1;
+        let raw = objc2::rc::autoreleasepool(|_| {
+            let blit = self.raw.as_mut().unwrap().blitCommandEncoder().unwrap();
+            let range = NSRange {
+                location: 0,
+                length: queries.count as usize * mem::size_of::<u64>(),
+            };
+            blit.fillBuffer_range_value(queries.as_ref(), range, 0);
+            blit.endEncoding();
+
+            let descriptor = self.render_descriptor(label, &targets);
+            unsafe { descriptor.setVisibilityResultBuffer(Some(queries.as_ref())) };
             self.raw
                 .as_mut()
                 .unwrap()
@@ -325,9 +506,73 @@ impl super::CommandEncoder {
                 .unwrap()
         });
 
+        let (scopes, plain_arena) = self.pass_state();
         super::RenderCommandEncoder {
             raw,
-            phantom: PhantomData,
+            scopes,
+            plain_arena,
+        }
+    }
+
+    pub fn render_parallel(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        self.pass_count += 1;
+        let raw = objc2::rc::autoreleasepool(|_| {
+            let descriptor = self.render_descriptor(label, &targets);
+            self.raw
+                .as_mut()
+                .unwrap()
+                .parallelRenderCommandEncoderWithDescriptor(&descriptor)
+                .unwrap()
+        });
+
+        let (scopes, plain_arena) = self.pass_state();
+        super::ParallelRenderCommandEncoder {
+            raw,
+            merged: Vec::new(),
+            scopes,
+            plain_arena,
+        }
+    }
+
+    /// Begin a parallel render pass, which writes the occlusion queries into `queries`.
+    /// All the queries of the set are reset at the start of the pass.
+    pub fn render_parallel_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        assert_eq!(queries.ty, crate::QueryType::Occlusion);
+        self.pass_count += 1;
+        let raw = objc2::rc::autoreleasepool(|_| {
+            let blit = self.raw.as_mut().unwrap().blitCommandEncoder().unwrap();
+            let range = NSRange {
+                location: 0,
+                length: queries.count as usize * mem::size_of::<u64>(),
+            };
+            blit.fillBuffer_range_value(queries.as_ref(), range, 0);
+            blit.endEncoding();
+
+            // Chunks share the visibility result buffer of the pass
+            let descriptor = self.render_descriptor(label, &targets);
+            unsafe { descriptor.setVisibilityResultBuffer(Some(queries.as_ref())) };
+            self.raw
+                .as_mut()
+                .unwrap()
+                .parallelRenderCommandEncoderWithDescriptor(&descriptor)
+                .unwrap()
+        });
+
+        let (scopes, plain_arena) = self.pass_state();
+        super::ParallelRenderCommandEncoder {
+            raw,
+            merged: Vec::new(),
+            scopes,
+            plain_arena,
         }
     }
 }
@@ -336,28 +581,48 @@ impl super::CommandEncoder {
 impl crate::traits::CommandEncoder for super::CommandEncoder {
     type Texture = super::Texture;
     type Frame = super::Frame;
+    type SyncPoint = super::SyncPoint;
+    type QuerySet = super::QuerySet;
 
     fn start(&mut self) {
+        self.stats = crate::CommandStats {
+            passes: mem::take(&mut self.pass_count),
+            ..Default::default()
+        };
+        self.plain_arenas.rotate_left(1);
+        self.plain_arenas[0].reset();
         if let Some(ref mut td_array) = self.timing_datas {
-            self.timings.clear();
             td_array.rotate_left(1);
             let td = td_array.first_mut().unwrap();
-            if !td.pass_names.is_empty() {
+            let sample_count = td.scopes.query_count() as usize;
+            let mut timestamps = Vec::with_capacity(sample_count);
+            if sample_count != 0 {
                 let ns_data = unsafe {
                     td.sample_buffer
-                        .resolveCounterRange(NSRange::new(0, td.pass_names.len() * 2))
+                        .resolveCounterRange(NSRange::new(0, sample_count))
                         .unwrap()
                 };
-                let counters = unsafe {
+                timestamps.extend_from_slice(unsafe {
                     slice::from_raw_parts(
                         ns_data.as_bytes_unchecked().as_ptr() as *const u64,
                         ns_data.len() / mem::size_of::<u64>(),
                     )
+                });
+            }
+            let required = td.scopes.required_query_count() as usize;
+            self.timings = td.scopes.resolve(&timestamps);
+
+            // Recreate the sample buffer for the passes that didn't fit into the last recording
+            if required > td.sample_buffer.sampleCount() {
+                use metal::{MTLCommandQueue as _, MTLDevice as _};
+                let device = self.queue.lock().unwrap().device();
+                td.sample_buffer = unsafe {
+                    td.descriptor.setSampleCount(required.next_power_of_two());
+                    device
+                        .newCounterSampleBufferWithDescriptor_error(&td.descriptor)
+                        .unwrap()
                 };
-                for (name, chunk) in td.pass_names.drain(..).zip(counters.chunks(2)) {
-                    let duration = Duration::from_nanos(chunk[1] - chunk[0]);
-                    self.timings.push((name, duration));
-                }
+                td.scopes.grow(td.sample_buffer.sampleCount() as u32);
             }
         }
 
@@ -373,6 +638,10 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
         self.has_open_debug_group = false;
     }
 
+    fn depend_on(&mut self, _sync_point: &super::SyncPoint) {
+        // All the work is executed on one queue in the submission order
+    }
+
     fn init_texture(&mut self, _texture: super::Texture) {}
 
     fn present(&mut self, frame: super::Frame) {
@@ -382,6 +651,17 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
     fn timings(&self) -> &crate::Timings {
         &self.timings
     }
+
+    fn stats(&self) -> &crate::CommandStats {
+        &self.stats
+    }
+
+    fn begin_statistics_query(&mut self, _set: super::QuerySet, index: u32) {
+        // `Capabilities::pipeline_statistics` is false, so there is nothing to collect
+        log::warn!("Pipeline statistics query {index} is skipped");
+    }
+
+    fn end_statistics_query(&mut self, _set: super::QuerySet, _index: u32) {}
 }
 
 #[hidden_trait::expose]
@@ -486,6 +766,7 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
 impl Drop for super::TransferCommandEncoder<'_> {
     fn drop(&mut self) {
         self.raw.endEncoding();
+        end_pass(self.scopes.as_deref_mut());
     }
 }
 
@@ -551,6 +832,7 @@ impl crate::traits::AccelerationStructureEncoder
 impl Drop for super::AccelerationStructureCommandEncoder<'_> {
     fn drop(&mut self) {
         self.raw.endEncoding();
+        end_pass(self.scopes.as_deref_mut());
     }
 }
 
@@ -583,6 +865,7 @@ impl super::ComputeCommandEncoder<'_> {
             encoder: self.raw.as_ref(),
             wg_size: pipeline.wg_size,
             group_mappings: &pipeline.layout.group_mappings,
+            plain_arena: self.plain_arena,
         }
     }
 }
@@ -590,6 +873,7 @@ impl super::ComputeCommandEncoder<'_> {
 impl Drop for super::ComputeCommandEncoder<'_> {
     fn drop(&mut self) {
         self.raw.endEncoding();
+        end_pass(self.scopes.as_deref_mut());
     }
 }
 
@@ -631,6 +915,69 @@ impl crate::traits::RenderEncoder for super::RenderCommandEncoder<'_> {
     fn set_stencil_reference(&mut self, stencil_reference: u32) {
         self.raw.setStencilReferenceValue(stencil_reference);
     }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        set_visibility_result_mode(&self.raw, index, Some(mode));
+    }
+
+    fn end_occlusion_query(&mut self, index: u32) {
+        set_visibility_result_mode(&self.raw, index, None);
+    }
+}
+
+fn set_visibility_result_mode(
+    encoder: &ProtocolObject<dyn metal::MTLRenderCommandEncoder>,
+    index: u32,
+    mode: Option<crate::OcclusionMode>,
+) {
+    let mtl_mode = match mode {
+        Some(crate::OcclusionMode::Binary) => metal::MTLVisibilityResultMode::Boolean,
+        Some(crate::OcclusionMode::Precise) => metal::MTLVisibilityResultMode::Counting,
+        None => metal::MTLVisibilityResultMode::Disabled,
+    };
+    let offset = index as usize * mem::size_of::<u64>();
+    unsafe { encoder.setVisibilityResultMode_offset(mtl_mode, offset) };
+}
+
+fn render_pipeline_context<'p>(
+    raw: &'p ProtocolObject<dyn metal::MTLRenderCommandEncoder>,
+    plain_arena: &'p mut super::PlainArena,
+    pipeline: &'p super::RenderPipeline,
+) -> super::RenderPipelineContext<'p> {
+    raw.pushDebugGroup(&NSString::from_str(&pipeline.name));
+    raw.setRenderPipelineState(&pipeline.raw);
+    if let Some(index) = pipeline.layout.sizes_buffer_slot {
+        //TODO: get real sizes
+        let runtime_sizes = [0u8; 8];
+        unsafe {
+            raw.setVertexBytes_length_atIndex(
+                NonNull::new(runtime_sizes.as_ptr() as *const _ as *mut _).unwrap(),
+                runtime_sizes.len(),
+                index as _,
+            );
+            raw.setFragmentBytes_length_atIndex(
+                NonNull::new(runtime_sizes.as_ptr() as *const _ as *mut _).unwrap(),
+                runtime_sizes.len(),
+                index as _,
+            );
+        }
+    }
+
+    raw.setFrontFacingWinding(pipeline.front_winding);
+    raw.setCullMode(pipeline.cull_mode);
+    raw.setTriangleFillMode(pipeline.triangle_fill_mode);
+    raw.setDepthClipMode(pipeline.depth_clip_mode);
+    if let Some((ref state, bias)) = pipeline.depth_stencil {
+        raw.setDepthStencilState(Some(state));
+        raw.setDepthBias_slopeScale_clamp(bias.constant as f32, bias.slope_scale, bias.clamp);
+    }
+
+    super::RenderPipelineContext {
+        encoder: raw,
+        primitive_type: pipeline.primitive_type,
+        group_mappings: &pipeline.layout.group_mappings,
+        plain_arena,
+    }
 }
 
 impl super::RenderCommandEncoder<'_> {
@@ -638,56 +985,134 @@ impl super::RenderCommandEncoder<'_> {
         &'p mut self,
         pipeline: &'p super::RenderPipeline,
     ) -> super::RenderPipelineContext<'p> {
-        self.raw.pushDebugGroup(&NSString::from_str(&pipeline.name));
-        self.raw.setRenderPipelineState(&pipeline.raw);
-        if let Some(index) = pipeline.layout.sizes_buffer_slot {
-            //TODO: get real sizes
-            let runtime_sizes = [0u8; 8];
-            unsafe {
-                self.raw.setVertexBytes_length_atIndex(
-                    NonNull::new(runtime_sizes.as_ptr() as *const _ as *mut _).unwrap(),
-                    runtime_sizes.len(),
-                    index as _,
-                );
-                self.raw.setFragmentBytes_length_atIndex(
-                    NonNull::new(runtime_sizes.as_ptr() as *const _ as *mut _).unwrap(),
-                    runtime_sizes.len(),
-                    index as _,
-                );
-            }
+        render_pipeline_context(self.raw.as_ref(), self.plain_arena, pipeline)
+    }
+}
+
+impl Drop for super::RenderCommandEncoder<'_> {
+    fn drop(&mut self) {
+        self.raw.endEncoding();
+        end_pass(self.scopes.as_deref_mut());
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::CommandEncoder {
+    fn begin_scope(&mut self, name: &str) {
+        // Timestamps are only sampled at the stage boundaries of the passes
+        if let Some(scopes) = self.scopes() {
+            scopes.begin(name, false);
         }
+    }
 
-        self.raw.setFrontFacingWinding(pipeline.front_winding);
-        self.raw.setCullMode(pipeline.cull_mode);
-        self.raw.setTriangleFillMode(pipeline.triangle_fill_mode);
-        self.raw.setDepthClipMode(pipeline.depth_clip_mode);
-        if let Some((ref state, bias)) = pipeline.depth_stencil {
-            self.raw.setDepthStencilState(Some(state));
-            self.raw.setDepthBias_slopeScale_clamp(
-                bias.constant as f32,
-                bias.slope_scale,
-                bias.clamp,
-            );
+    fn end_scope(&mut self) {
+        end_scope(self.scopes());
+    }
+}
+
+macro_rules! impl_timing_encoder {
+    ($($encoder:ident)*) => {$(
+        #[hidden_trait::expose]
+        impl crate::traits::TimingEncoder for super::$encoder<'_> {
+            fn begin_scope(&mut self, name: &str) {
+                if let Some(ref mut scopes) = self.scopes {
+                    scopes.begin(name, false);
+                }
+            }
+
+            fn end_scope(&mut self) {
+                end_scope(self.scopes.as_deref_mut());
+            }
         }
+    )*};
+}
 
-        super::RenderPipelineContext {
-            encoder: self.raw.as_ref(),
-            primitive_type: pipeline.primitive_type,
-            group_mappings: &pipeline.layout.group_mappings,
+impl_timing_encoder!(
+    TransferCommandEncoder AccelerationStructureCommandEncoder
+    ComputeCommandEncoder RenderCommandEncoder ParallelRenderCommandEncoder
+);
+
+impl super::ParallelRenderCommandEncoder<'_> {
+    /// Begin a chunk of the pass, which can be recorded on any thread.
+    /// Chunks are executed in the order they are begun, after being merged back.
+    pub fn chunk(&mut self) -> super::RenderChunk {
+        let index = self.merged.len();
+        self.merged.push(false);
+        super::RenderChunk {
+            index,
+            raw: self.raw.renderCommandEncoder().unwrap(),
+            plain_arena: super::PlainArena::new(Retained::clone(&self.plain_arena.device)),
         }
     }
+
+    /// Return a recorded chunk into the pass.
+    pub fn merge(&mut self, chunk: super::RenderChunk) {
+        assert!(
+            !self.merged[chunk.index],
+            "Render chunk {} is merged twice",
+            chunk.index
+        );
+        chunk.raw.endEncoding();
+        self.plain_arena.absorb(chunk.plain_arena);
+        self.merged[chunk.index] = true;
+    }
 }
 
-impl Drop for super::RenderCommandEncoder<'_> {
+impl Drop for super::ParallelRenderCommandEncoder<'_> {
     fn drop(&mut self) {
+        for (index, &merged) in self.merged.iter().enumerate() {
+            if !merged {
+                log::error!("Render chunk {} is not merged into the pass", index);
+            }
+        }
         self.raw.endEncoding();
+        end_pass(self.scopes.as_deref_mut());
+    }
+}
+
+impl super::RenderChunk {
+    pub fn with<'p>(
+        &'p mut self,
+        pipeline: &'p super::RenderPipeline,
+    ) -> super::RenderPipelineContext<'p> {
+        render_pipeline_context(self.raw.as_ref(), &mut self.plain_arena, pipeline)
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderEncoder for super::RenderChunk {
+    fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        self.raw.setScissorRect(rect.to_metal());
+    }
+
+    fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        self.raw.setViewport(viewport.to_metal());
+    }
+
+    fn set_stencil_reference(&mut self, stencil_reference: u32) {
+        self.raw.setStencilReferenceValue(stencil_reference);
+    }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        set_visibility_result_mode(&self.raw, index, Some(mode));
+    }
+
+    fn end_occlusion_query(&mut self, index: u32) {
+        set_visibility_result_mode(&self.raw, index, None);
     }
 }
 
 #[hidden_trait::expose]
 impl crate::traits::PipelineEncoder for super::ComputePipelineContext<'_> {
+    type BindlessTable = super::BindlessTable;
+
     fn bind<D: crate::ShaderData>(&mut self, group: u32, data: &D) {
         let info = &self.group_mappings[group as usize];
+        if cfg!(debug_assertions)
+            && let Err(message) = crate::reflect::check_plain_data::<D>(&info.plain_structs)
+        {
+            panic!("{}", message);
+        }
 
         data.fill(super::PipelineContext {
             cs_encoder: if info.visibility.contains(crate::ShaderVisibility::COMPUTE) {
@@ -698,8 +1123,14 @@ impl crate::traits::PipelineEncoder for super::ComputePipelineContext<'_> {
             vs_encoder: None,
             fs_encoder: None,
             targets: &info.targets,
+            plain_arena: self.plain_arena,
         });
     }
+
+    fn bind_table(&mut self, group: u32, _table: &super::BindlessTable) {
+        // Pipelines with bindless bindings fail to create, so there is no slot to bind to
+        log::error!("Bindless table can't be bound to group {}", group);
+    }
 }
 
 #[hidden_trait::expose]
@@ -736,8 +1167,15 @@ impl Drop for super::ComputePipelineContext<'_> {
 
 #[hidden_trait::expose]
 impl crate::traits::PipelineEncoder for super::RenderPipelineContext<'_> {
+    type BindlessTable = super::BindlessTable;
+
     fn bind<D: crate::ShaderData>(&mut self, group: u32, data: &D) {
         let info = &self.group_mappings[group as usize];
+        if cfg!(debug_assertions)
+            && let Err(message) = crate::reflect::check_plain_data::<D>(&info.plain_structs)
+        {
+            panic!("{}", message);
+        }
 
         data.fill(super::PipelineContext {
             cs_encoder: None,
@@ -752,8 +1190,14 @@ impl crate::traits::PipelineEncoder for super::RenderPipelineContext<'_> {
                 None
             },
             targets: &info.targets,
+            plain_arena: self.plain_arena,
         });
     }
+
+    fn bind_table(&mut self, group: u32, _table: &super::BindlessTable) {
+        // Pipelines with bindless bindings fail to create, so there is no slot to bind to
+        log::error!("Bindless table can't be bound to group {}", group);
+    }
 }
 
 #[hidden_trait::expose]
@@ -767,6 +1211,14 @@ impl crate::traits::RenderEncoder for super::RenderPipelineContext<'_> {
     fn set_stencil_reference(&mut self, stencil_reference: u32) {
         self.encoder.setStencilReferenceValue(stencil_reference);
     }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        set_visibility_result_mode(self.encoder, index, Some(mode));
+    }
+
+    fn end_occlusion_query(&mut self, index: u32) {
+        set_visibility_result_mode(self.encoder, index, None);
+    }
 }
 
 #[hidden_trait::expose]
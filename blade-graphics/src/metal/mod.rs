@@ -1,7 +1,6 @@
 use objc2::{rc::Retained, runtime::ProtocolObject};
 use objc2_metal::{self as metal, MTLDevice};
 use std::{
-    marker::PhantomData,
     ptr,
     sync::{Arc, Mutex},
     thread, time,
@@ -12,7 +11,9 @@ mod pipeline;
 mod resource;
 mod surface;
 
-const MAX_TIMESTAMPS: usize = crate::limits::PASS_COUNT * 2;
+/// Initial number of timestamp samples of a recording,
+/// which grows when the passes need more of them.
+const TIMESTAMP_SAMPLE_COUNT: usize = 128;
 
 pub type PlatformError = ();
 
@@ -59,6 +60,7 @@ pub struct Context {
     timestamp_counter_set: Option<Retained<ProtocolObject<dyn metal::MTLCounterSet>>>,
     info: PrivateInfo,
     device_information: crate::DeviceInformation,
+    tracker: Option<crate::tracking::Tracker>,
 }
 
 // needed for `capture` and `timestamp_counter_set`
@@ -197,6 +199,37 @@ impl AccelerationStructure {
     }
 }
 
+/// Bindless tables are not supported by this backend, see `Capabilities::max_bindless_resources`.
+/// Only the empty tables can be created, and they are tracked on the host.
+#[derive(Debug)]
+pub struct BindlessTable {
+    slots: crate::bindless::BindlessSlots,
+}
+
+impl BindlessTable {
+    /// Check that the handle refers to a resource that is still in the table.
+    pub fn contains(&self, handle: crate::BindlessHandle) -> bool {
+        self.slots.contains(handle)
+    }
+}
+
+/// Occlusion query results, stored in a shared visibility buffer.
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct QuerySet {
+    raw: *mut ProtocolObject<dyn metal::MTLBuffer>,
+    ty: crate::QueryType,
+    count: u32,
+}
+
+unsafe impl Send for QuerySet {}
+unsafe impl Sync for QuerySet {}
+
+impl QuerySet {
+    fn as_ref(&self) -> &ProtocolObject<dyn metal::MTLBuffer> {
+        unsafe { &*self.raw }
+    }
+}
+
 //TODO: make this copyable?
 #[derive(Clone, Debug)]
 pub struct SyncPoint {
@@ -207,7 +240,8 @@ unsafe impl Send for SyncPoint {}
 unsafe impl Sync for SyncPoint {}
 
 struct TimingData {
-    pass_names: Vec<String>,
+    scopes: crate::timing::ScopeRecorder,
+    descriptor: Retained<metal::MTLCounterSampleBufferDescriptor>,
     sample_buffer: Retained<ProtocolObject<dyn metal::MTLCounterSampleBuffer>>,
 }
 
@@ -221,12 +255,25 @@ pub struct CommandEncoder {
     has_open_debug_group: bool,
     timing_datas: Option<Box<[TimingData]>>,
     timings: crate::Timings,
+    pass_count: u32,
+    stats: crate::CommandStats,
+    /// Plain data of each command buffer in flight, rotated by `start`.
+    plain_arenas: Box<[PlainArena]>,
+}
+
+/// Shared buffers with the plain data that is too large for `setBytes`.
+/// They are filled linearly, and reused once the command buffer is done.
+struct PlainArena {
+    device: Retained<ProtocolObject<dyn metal::MTLDevice>>,
+    buffers: Vec<Retained<ProtocolObject<dyn metal::MTLBuffer>>>,
+    offset: usize,
 }
 
 #[derive(Debug)]
 struct ShaderDataMapping {
     visibility: crate::ShaderVisibility,
     targets: Box<[u32]>,
+    plain_structs: Box<[Option<crate::StructReflection>]>,
 }
 
 #[derive(Debug)]
@@ -282,42 +329,64 @@ unsafe impl Sync for RenderPipeline {}
 
 pub struct TransferCommandEncoder<'a> {
     raw: Retained<ProtocolObject<dyn metal::MTLBlitCommandEncoder>>,
-    phantom: PhantomData<&'a CommandEncoder>,
+    scopes: Option<&'a mut crate::timing::ScopeRecorder>,
 }
 
 pub struct AccelerationStructureCommandEncoder<'a> {
     raw: Retained<ProtocolObject<dyn metal::MTLAccelerationStructureCommandEncoder>>,
-    phantom: PhantomData<&'a CommandEncoder>,
+    scopes: Option<&'a mut crate::timing::ScopeRecorder>,
 }
 
 pub struct ComputeCommandEncoder<'a> {
     raw: Retained<ProtocolObject<dyn metal::MTLComputeCommandEncoder>>,
-    phantom: PhantomData<&'a CommandEncoder>,
+    scopes: Option<&'a mut crate::timing::ScopeRecorder>,
+    plain_arena: &'a mut PlainArena,
 }
 
 pub struct RenderCommandEncoder<'a> {
     raw: Retained<ProtocolObject<dyn metal::MTLRenderCommandEncoder>>,
-    phantom: PhantomData<&'a CommandEncoder>,
+    scopes: Option<&'a mut crate::timing::ScopeRecorder>,
+    plain_arena: &'a mut PlainArena,
+}
+
+pub struct ParallelRenderCommandEncoder<'a> {
+    raw: Retained<ProtocolObject<dyn metal::MTLParallelRenderCommandEncoder>>,
+    merged: Vec<bool>,
+    scopes: Option<&'a mut crate::timing::ScopeRecorder>,
+    plain_arena: &'a mut PlainArena,
 }
 
+pub struct RenderChunk {
+    index: usize,
+    raw: Retained<ProtocolObject<dyn metal::MTLRenderCommandEncoder>>,
+    /// Taken over by the pass when the chunk is merged.
+    plain_arena: PlainArena,
+}
+
+// Sub-encoders of a parallel encoder are meant to be recorded on other threads.
+unsafe impl Send for RenderChunk {}
+
 pub struct PipelineContext<'a> {
     //raw: metal::ArgumentEncoderRef,
     cs_encoder: Option<&'a ProtocolObject<dyn metal::MTLComputeCommandEncoder>>,
     vs_encoder: Option<&'a ProtocolObject<dyn metal::MTLRenderCommandEncoder>>,
     fs_encoder: Option<&'a ProtocolObject<dyn metal::MTLRenderCommandEncoder>>,
     targets: &'a [u32],
+    plain_arena: &'a mut PlainArena,
 }
 
 pub struct ComputePipelineContext<'a> {
     encoder: &'a ProtocolObject<dyn metal::MTLComputeCommandEncoder>,
     wg_size: metal::MTLSize,
     group_mappings: &'a [ShaderDataMapping],
+    plain_arena: &'a mut PlainArena,
 }
 
 pub struct RenderPipelineContext<'a> {
     encoder: &'a ProtocolObject<dyn metal::MTLRenderCommandEncoder>,
     primitive_type: metal::MTLPrimitiveType,
     group_mappings: &'a [ShaderDataMapping],
+    plain_arena: &'a mut PlainArena,
 }
 
 fn map_texture_format(format: crate::TextureFormat) -> metal::MTLPixelFormat {
@@ -341,9 +410,17 @@ fn map_texture_format(format: crate::TextureFormat) -> metal::MTLPixelFormat {
         Tf::R32Uint => Mpf::R32Uint,
         Tf::Rg32Uint => Mpf::RG32Uint,
         Tf::Rgba32Uint => Mpf::RGBA32Uint,
+        Tf::R16Unorm => Mpf::R16Unorm,
+        Tf::Rgba16Unorm => Mpf::RGBA16Unorm,
+        Tf::R8Uint => Mpf::R8Uint,
+        Tf::Rg16Uint => Mpf::RG16Uint,
+        Tf::R32Sint => Mpf::R32Sint,
         Tf::Depth32Float => Mpf::Depth32Float,
         Tf::Depth32FloatStencil8Uint => Mpf::Depth32Float_Stencil8,
         Tf::Stencil8Uint => Mpf::Stencil8,
+        Tf::Depth16Unorm => Mpf::Depth16Unorm,
+        // Packed 24-bit depth is not available on Apple GPUs
+        Tf::Depth24PlusStencil8 => Mpf::Depth32Float_Stencil8,
         Tf::Bc1Unorm => Mpf::BC1_RGBA,
         Tf::Bc1UnormSrgb => Mpf::BC1_RGBA_sRGB,
         Tf::Bc2Unorm => Mpf::BC2_RGBA,
@@ -361,6 +438,44 @@ fn map_texture_format(format: crate::TextureFormat) -> metal::MTLPixelFormat {
         Tf::Rgb10a2Unorm => Mpf::RGB10A2Unorm,
         Tf::Rg11b10Ufloat => Mpf::RG11B10Float,
         Tf::Rgb9e5Ufloat => Mpf::RGB9E5Float,
+        Tf::Etc2Rgb8Unorm => Mpf::ETC2_RGB8,
+        Tf::Etc2Rgb8UnormSrgb => Mpf::ETC2_RGB8_sRGB,
+        Tf::Etc2Rgb8A1Unorm => Mpf::ETC2_RGB8A1,
+        Tf::Etc2Rgb8A1UnormSrgb => Mpf::ETC2_RGB8A1_sRGB,
+        Tf::Etc2Rgba8Unorm => Mpf::EAC_RGBA8,
+        Tf::Etc2Rgba8UnormSrgb => Mpf::EAC_RGBA8_sRGB,
+        Tf::EacR11Unorm => Mpf::EAC_R11Unorm,
+        Tf::EacR11Snorm => Mpf::EAC_R11Snorm,
+        Tf::EacRg11Unorm => Mpf::EAC_RG11Unorm,
+        Tf::EacRg11Snorm => Mpf::EAC_RG11Snorm,
+        Tf::Astc4x4Unorm => Mpf::ASTC_4x4_LDR,
+        Tf::Astc4x4UnormSrgb => Mpf::ASTC_4x4_sRGB,
+        Tf::Astc5x4Unorm => Mpf::ASTC_5x4_LDR,
+        Tf::Astc5x4UnormSrgb => Mpf::ASTC_5x4_sRGB,
+        Tf::Astc5x5Unorm => Mpf::ASTC_5x5_LDR,
+        Tf::Astc5x5UnormSrgb => Mpf::ASTC_5x5_sRGB,
+        Tf::Astc6x5Unorm => Mpf::ASTC_6x5_LDR,
+        Tf::Astc6x5UnormSrgb => Mpf::ASTC_6x5_sRGB,
+        Tf::Astc6x6Unorm => Mpf::ASTC_6x6_LDR,
+        Tf::Astc6x6UnormSrgb => Mpf::ASTC_6x6_sRGB,
+        Tf::Astc8x5Unorm => Mpf::ASTC_8x5_LDR,
+        Tf::Astc8x5UnormSrgb => Mpf::ASTC_8x5_sRGB,
+        Tf::Astc8x6Unorm => Mpf::ASTC_8x6_LDR,
+        Tf::Astc8x6UnormSrgb => Mpf::ASTC_8x6_sRGB,
+        Tf::Astc8x8Unorm => Mpf::ASTC_8x8_LDR,
+        Tf::Astc8x8UnormSrgb => Mpf::ASTC_8x8_sRGB,
+        Tf::Astc10x5Unorm => Mpf::ASTC_10x5_LDR,
+        Tf::Astc10x5UnormSrgb => Mpf::ASTC_10x5_sRGB,
+        Tf::Astc10x6Unorm => Mpf::ASTC_10x6_LDR,
+        Tf::Astc10x6UnormSrgb => Mpf::ASTC_10x6_sRGB,
+        Tf::Astc10x8Unorm => Mpf::ASTC_10x8_LDR,
+        Tf::Astc10x8UnormSrgb => Mpf::ASTC_10x8_sRGB,
+        Tf::Astc10x10Unorm => Mpf::ASTC_10x10_LDR,
+        Tf::Astc10x10UnormSrgb => Mpf::ASTC_10x10_sRGB,
+        Tf::Astc12x10Unorm => Mpf::ASTC_12x10_LDR,
+        Tf::Astc12x10UnormSrgb => Mpf::ASTC_12x10_sRGB,
+        Tf::Astc12x12Unorm => Mpf::ASTC_12x12_LDR,
+        Tf::Astc12x12UnormSrgb => Mpf::ASTC_12x12_sRGB,
     }
 }
 
@@ -435,6 +550,46 @@ fn map_vertex_format(
             metal::MTLVertexFormat::Int4,
             metal::MTLAttributeFormat::Int4,
         ),
+        crate::VertexFormat::Unorm8x4 => (
+            metal::MTLVertexFormat::UChar4Normalized,
+            metal::MTLAttributeFormat::UChar4Normalized,
+        ),
+        crate::VertexFormat::Snorm8x4 => (
+            metal::MTLVertexFormat::Char4Normalized,
+            metal::MTLAttributeFormat::Char4Normalized,
+        ),
+        crate::VertexFormat::Uint8x4 => (
+            metal::MTLVertexFormat::UChar4,
+            metal::MTLAttributeFormat::UChar4,
+        ),
+        crate::VertexFormat::Unorm16x2 => (
+            metal::MTLVertexFormat::UShort2Normalized,
+            metal::MTLAttributeFormat::UShort2Normalized,
+        ),
+        crate::VertexFormat::Unorm16x4 => (
+            metal::MTLVertexFormat::UShort4Normalized,
+            metal::MTLAttributeFormat::UShort4Normalized,
+        ),
+        crate::VertexFormat::Snorm16x2 => (
+            metal::MTLVertexFormat::Short2Normalized,
+            metal::MTLAttributeFormat::Short2Normalized,
+        ),
+        crate::VertexFormat::Snorm16x4 => (
+            metal::MTLVertexFormat::Short4Normalized,
+            metal::MTLAttributeFormat::Short4Normalized,
+        ),
+        crate::VertexFormat::Float16x2 => (
+            metal::MTLVertexFormat::Half2,
+            metal::MTLAttributeFormat::Half2,
+        ),
+        crate::VertexFormat::Float16x4 => (
+            metal::MTLVertexFormat::Half4,
+            metal::MTLAttributeFormat::Half4,
+        ),
+        crate::VertexFormat::Rgb10a2Unorm => (
+            metal::MTLVertexFormat::UInt1010102Normalized,
+            metal::MTLAttributeFormat::UInt1010102Normalized,
+        ),
     }
 }
 
@@ -446,6 +601,9 @@ impl Context {
         if desc.overlay {
             std::env::set_var("MTL_HUD_ENABLED", "1");
         }
+        if desc.trace_path.is_some() {
+            log::warn!("API tracing is not supported");
+        }
         if desc.device_id != 0 {
             log::warn!("Unable to filter devices by ID");
         }
@@ -511,6 +669,7 @@ impl Context {
                 enable_dispatch_type: true,
             },
             device_information,
+            tracker: crate::tracking::Tracker::from_desc(&desc),
         })
     }
 
@@ -533,6 +692,9 @@ impl Context {
                 .filter(|&count| device.supportsTextureSampleCount(count as _))
                 .sum(),
             dual_source_blending: true,
+            precise_occlusion: true,
+            pipeline_statistics: false,
+            max_bindless_resources: 0,
         }
     }
 
@@ -540,6 +702,64 @@ impl Context {
         &self.device_information
     }
 
+    /// Usages supported by textures of the given format on this device.
+    pub fn texture_format_usage(&self, format: crate::TextureFormat) -> crate::TextureUsage {
+        use crate::{TextureFormat as Tf, TextureUsage as Tu};
+        use metal::MTLDevice as _;
+        let device = self.device.lock().unwrap();
+        let is_apple = device.supportsFamily(metal::MTLGPUFamily::Apple2);
+
+        match format {
+            Tf::Bc1Unorm
+            | Tf::Bc1UnormSrgb
+            | Tf::Bc2Unorm
+            | Tf::Bc2UnormSrgb
+            | Tf::Bc3Unorm
+            | Tf::Bc3UnormSrgb
+            | Tf::Bc4Unorm
+            | Tf::Bc4Snorm
+            | Tf::Bc5Unorm
+            | Tf::Bc5Snorm
+            | Tf::Bc6hUfloat
+            | Tf::Bc6hFloat
+            | Tf::Bc7Unorm
+            | Tf::Bc7UnormSrgb => {
+                if device.supportsBCTextureCompression() {
+                    Tu::COPY | Tu::RESOURCE
+                } else {
+                    Tu::empty()
+                }
+            }
+            _ if format.block_info().dimensions != (1, 1) => {
+                // ETC2, EAC and ASTC
+                if is_apple {
+                    Tu::COPY | Tu::RESOURCE
+                } else {
+                    Tu::empty()
+                }
+            }
+            _ if !format.aspects().contains(crate::TexelAspects::COLOR) => {
+                Tu::COPY | Tu::TARGET | Tu::RESOURCE
+            }
+            Tf::Rgb9e5Ufloat => {
+                if is_apple {
+                    Tu::COPY | Tu::TARGET | Tu::RESOURCE
+                } else {
+                    Tu::COPY | Tu::RESOURCE
+                }
+            }
+            _ => Tu::all(),
+        }
+    }
+
+    /// Return the resources that are currently alive, in the order of creation.
+    /// Empty unless `ContextDesc::track_resources` is enabled.
+    pub fn live_resources(&self) -> Vec<crate::LiveResource> {
+        self.tracker
+            .as_ref()
+            .map_or_else(Vec::new, crate::tracking::Tracker::snapshot)
+    }
+
     /// Get an MTLDevice of this context.
     /// This is platform specific API.
     pub fn metal_device(&self) -> Retained<ProtocolObject<dyn metal::MTLDevice>> {
@@ -557,26 +777,29 @@ impl crate::traits::CommandDevice for Context {
 
         let timing_datas = if let Some(ref counter_set) = self.timestamp_counter_set {
             let mut array = Vec::with_capacity(desc.buffer_count as usize);
-            let csb_desc = unsafe {
-                let desc = metal::MTLCounterSampleBufferDescriptor::new();
-                desc.setCounterSet(Some(counter_set));
-                desc.setStorageMode(metal::MTLStorageMode::Shared);
-                desc.setSampleCount(MAX_TIMESTAMPS);
-                desc
-            };
             for i in 0..desc.buffer_count {
                 let label = format!("{}/counter{}", desc.name, i);
+                let csb_desc = unsafe {
+                    let desc = metal::MTLCounterSampleBufferDescriptor::new();
+                    desc.setCounterSet(Some(counter_set));
+                    desc.setStorageMode(metal::MTLStorageMode::Shared);
+                    desc.setSampleCount(TIMESTAMP_SAMPLE_COUNT);
+                    desc.setLabel(&objc2_foundation::NSString::from_str(&label));
+                    desc
+                };
                 let sample_buffer = unsafe {
-                    csb_desc.setLabel(&objc2_foundation::NSString::from_str(&label));
                     self.device
                         .lock()
                         .unwrap()
                         .newCounterSampleBufferWithDescriptor_error(&csb_desc)
                         .unwrap()
                 };
+                let mut scopes = crate::timing::ScopeRecorder::default();
+                scopes.grow(TIMESTAMP_SAMPLE_COUNT as u32);
                 array.push(TimingData {
+                    scopes,
+                    descriptor: csb_desc,
                     sample_buffer,
-                    pass_names: Vec::new(),
                 });
             }
             Some(array.into_boxed_slice())
@@ -593,6 +816,14 @@ impl crate::traits::CommandDevice for Context {
             has_open_debug_group: false,
             timing_datas,
             timings: Default::default(),
+            pass_count: 0,
+            stats: Default::default(),
+            plain_arenas: {
+                let device = self.device.lock().unwrap();
+                (0..desc.buffer_count)
+                    .map(|_| PlainArena::new(Retained::clone(&device)))
+                    .collect()
+            },
         }
     }
 
@@ -623,6 +854,9 @@ impl crate::traits::CommandDevice for Context {
 impl Drop for Context {
     fn drop(&mut self) {
         use metal::MTLCaptureScope as _;
+        if let Some(ref tracker) = self.tracker {
+            tracker.report_leaks();
+        }
         if let Some(capture_manager) = self.capture.take() {
             if let Some(scope) = capture_manager.defaultCaptureScope() {
                 scope.endScope();
@@ -133,7 +133,7 @@ fn _align_to(offset: u32, alignment: u32) -> u32 {
 fn make_pipeline_layout(
     bind_group_layouts: &[&crate::ShaderDataLayout],
     reserved_vertex_buffers: u32,
-) -> super::PipelineLayout {
+) -> Result<super::PipelineLayout, crate::Error> {
     let mut group_mappings = Vec::with_capacity(bind_group_layouts.len());
     let mut unsized_buffer_count = 0;
     let mut num_textures = 0u32;
@@ -141,7 +141,7 @@ fn make_pipeline_layout(
     let mut num_buffers = reserved_vertex_buffers;
     for layout in bind_group_layouts.iter() {
         let mut targets = Vec::with_capacity(layout.bindings.len());
-        for &(_, ref binding) in layout.bindings.iter() {
+        for &(name, ref binding) in layout.bindings.iter() {
             targets.push(match *binding {
                 crate::ShaderBinding::Texture => {
                     num_textures += 1;
@@ -157,7 +157,13 @@ fn make_pipeline_layout(
                     num_buffers - 1
                 }
                 crate::ShaderBinding::TextureArray { .. }
-                | crate::ShaderBinding::BufferArray { .. } => unimplemented!(),
+                | crate::ShaderBinding::BufferArray { .. }
+                | crate::ShaderBinding::Bindless { .. } => {
+                    return Err(crate::Error::UnsupportedBinding {
+                        name: name.to_string(),
+                        binding: *binding,
+                    });
+                }
                 crate::ShaderBinding::AccelerationStructure => {
                     num_buffers += 1;
                     num_buffers - 1
@@ -172,10 +178,11 @@ fn make_pipeline_layout(
         group_mappings.push(super::ShaderDataMapping {
             visibility: crate::ShaderVisibility::empty(),
             targets: targets.into_boxed_slice(),
+            plain_structs: Box::new([]),
         });
     }
 
-    super::PipelineLayout {
+    Ok(super::PipelineLayout {
         group_mappings: group_mappings.into_boxed_slice(),
         group_infos: bind_group_layouts
             .iter()
@@ -186,7 +193,7 @@ fn make_pipeline_layout(
         } else {
             None
         },
-    }
+    })
 }
 
 impl super::Context {
@@ -197,22 +204,22 @@ impl super::Context {
         vertex_fetch_states: &[crate::VertexFetchState],
         pipeline_layout: &mut super::PipelineLayout,
         flags: ShaderFlags,
-    ) -> CompiledShader {
-        let ep_index = sf.entry_point_index();
+    ) -> Result<CompiledShader, crate::Error> {
+        let ep_index = sf.entry_point_index()?;
         let ep = &sf.shader.module.entry_points[ep_index];
         let ep_info = sf.shader.info.get_entry_point(ep_index);
-        let _ = sf.shader.source;
+        let _ = (&sf.shader.source, &sf.shader.source_kind);
 
-        let (mut module, module_info) = sf.shader.resolve_constants(&sf.constants);
+        let (mut module, module_info) = sf.shader.try_resolve_constants(&sf.constants)?;
         crate::Shader::fill_resource_bindings(
             &mut module,
             &mut pipeline_layout.group_infos,
             ep.stage,
             ep_info,
             bind_group_layouts,
-        );
+        )?;
         let attribute_mappings =
-            crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states);
+            crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states)?;
 
         // figure out how much workgroup memory is needed for each binding
         let mut wg_memory_sizes = Vec::new();
@@ -224,13 +231,14 @@ impl super::Context {
             }
         }
 
-        // copy the visibility for convenience
+        // copy the visibility and the plain structs for convenience
         for (group_mapping, group_info) in pipeline_layout
             .group_mappings
             .iter_mut()
             .zip(pipeline_layout.group_infos.iter())
         {
             group_mapping.visibility = group_info.visibility;
+            group_mapping.plain_structs = group_info.plain_structs.clone();
         }
 
         let mut naga_resources = msl::EntryPointResources::default();
@@ -242,7 +250,7 @@ impl super::Context {
             .zip(pipeline_layout.group_mappings.iter_mut())
             .enumerate()
         {
-            for (binding_index, (&(_, proto), &slot)) in group_layout
+            for (binding_index, (&(name, proto), &slot)) in group_layout
                 .bindings
                 .iter()
                 .zip(group_mapping.targets.iter())
@@ -268,7 +276,13 @@ impl super::Context {
                         ..Default::default()
                     },
                     crate::ShaderBinding::TextureArray { .. }
-                    | crate::ShaderBinding::BufferArray { .. } => todo!(),
+                    | crate::ShaderBinding::BufferArray { .. }
+                    | crate::ShaderBinding::Bindless { .. } => {
+                        return Err(crate::Error::UnsupportedBinding {
+                            name: name.to_string(),
+                            binding: proto,
+                        });
+                    }
                 };
                 naga_resources.resources.insert(res_binding, bind_target);
             }
@@ -297,7 +311,8 @@ impl super::Context {
             vertex_buffer_mappings: Vec::new(),
         };
         let (source, info) =
-            msl::write_string(&module, &module_info, &naga_options, &pipeline_options).unwrap();
+            msl::write_string(&module, &module_info, &naga_options, &pipeline_options)
+                .map_err(|e| crate::Error::InvalidShader(e.to_string()))?;
 
         log::debug!(
             "Naga generated shader for entry point '{}' and stage {:?}\n{}",
@@ -316,12 +331,17 @@ impl super::Context {
             .lock()
             .unwrap()
             .newLibraryWithSource_options_error(&source_string, Some(&options))
-            .unwrap_or_else(|err| {
-                panic!("MSL compilation error:\n{}", err.localizedDescription());
-            });
+            .map_err(|err| {
+                crate::Error::InvalidShader(format!(
+                    "MSL compilation error:\n{}",
+                    err.localizedDescription()
+                ))
+            })?;
 
         let ep = &module.entry_points[ep_index];
-        let name = info.entry_point_names[ep_index].as_ref().unwrap();
+        let name = info.entry_point_names[ep_index]
+            .as_ref()
+            .map_err(|e| crate::Error::InvalidShader(e.to_string()))?;
         let wg_size = metal::MTLSize {
             width: ep.workgroup_size[0] as _,
             height: ep.workgroup_size[1] as _,
@@ -329,15 +349,17 @@ impl super::Context {
         };
 
         let name_string = NSString::from_str(name);
-        let function = library.newFunctionWithName(&name_string).unwrap();
+        let function = library
+            .newFunctionWithName(&name_string)
+            .ok_or_else(|| crate::Error::MissingEntryPoint(name.clone()))?;
 
-        CompiledShader {
+        Ok(CompiledShader {
             library,
             function,
             attribute_mappings,
             wg_size,
             wg_memory_sizes,
-        }
+        })
     }
 }
 
@@ -346,9 +368,12 @@ impl crate::traits::ShaderDevice for super::Context {
     type ComputePipeline = super::ComputePipeline;
     type RenderPipeline = super::RenderPipeline;
 
-    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
+    fn try_create_compute_pipeline(
+        &self,
+        desc: crate::ComputePipelineDesc,
+    ) -> Result<super::ComputePipeline, crate::Error> {
         use metal::MTLDevice as _;
-        let mut layout = make_pipeline_layout(desc.data_layouts, 0);
+        let mut layout = make_pipeline_layout(desc.data_layouts, 0)?;
 
         objc2::rc::autoreleasepool(|_| {
             let cs = self.load_shader(
@@ -357,7 +382,7 @@ impl crate::traits::ShaderDevice for super::Context {
                 &[],
                 &mut layout,
                 ShaderFlags::empty(),
-            );
+            )?;
 
             //TODO: use `newComputePipelineStateWithDescriptor_options_reflection`
             // https://github.com/madsmtm/objc2/issues/683
@@ -366,25 +391,43 @@ impl crate::traits::ShaderDevice for super::Context {
                 .lock()
                 .unwrap()
                 .newComputePipelineStateWithFunction_error(&cs.function)
-                .unwrap();
+                .map_err(|err| crate::Error::Device(err.localizedDescription().to_string()))?;
+            if let Some(ref tracker) = self.tracker {
+                let key = Retained::as_ptr(&raw) as usize as u64;
+                tracker.add(crate::tracking::Handle::ComputePipeline(key), desc.name, 0);
+            }
 
-            super::ComputePipeline {
+            Ok(super::ComputePipeline {
                 raw,
                 name: desc.name.to_string(),
                 lib: cs.library,
                 layout,
                 wg_size: cs.wg_size,
                 wg_memory_sizes: cs.wg_memory_sizes.into_boxed_slice(),
-            }
+            })
         })
     }
 
-    fn destroy_compute_pipeline(&self, _pipeline: &mut super::ComputePipeline) {
+    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
+        let name = desc.name;
+        self.try_create_compute_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create compute pipeline '{name}': {e}"))
+    }
+
+    fn destroy_compute_pipeline(&self, pipeline: &mut super::ComputePipeline) {
         //TODO: is there a way to release?
+        if let Some(ref tracker) = self.tracker {
+            let key = Retained::as_ptr(&pipeline.raw) as usize as u64;
+            tracker.remove(crate::tracking::Handle::ComputePipeline(key));
+        }
     }
 
-    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
-        let mut layout = make_pipeline_layout(desc.data_layouts, desc.vertex_fetches.len() as u32);
+    fn try_create_render_pipeline(
+        &self,
+        desc: crate::RenderPipelineDesc,
+    ) -> Result<super::RenderPipeline, crate::Error> {
+        let mut layout =
+            make_pipeline_layout(desc.data_layouts, desc.vertex_fetches.len() as u32)?;
 
         let triangle_fill_mode = match desc.primitive.wireframe {
             false => metal::MTLTriangleFillMode::Fill,
@@ -426,7 +469,7 @@ impl crate::traits::ShaderDevice for super::Context {
                     metal::MTLPrimitiveTopologyClass::Point => ShaderFlags::ALLOW_POINT_SIZE,
                     _ => ShaderFlags::empty(),
                 },
-            );
+            )?;
             descriptor.setVertexFunction(Some(&vs.function));
             descriptor.setRasterSampleCount(desc.multisample_state.sample_count as _);
             descriptor.setAlphaToCoverageEnabled(desc.multisample_state.alpha_to_coverage);
@@ -439,7 +482,7 @@ impl crate::traits::ShaderDevice for super::Context {
                     &[],
                     &mut layout,
                     ShaderFlags::empty(),
-                );
+                )?;
                 descriptor.setFragmentFunction(Some(&fs.function));
                 Some(fs.library)
             } else {
@@ -520,7 +563,7 @@ impl crate::traits::ShaderDevice for super::Context {
                         .lock()
                         .unwrap()
                         .newDepthStencilStateWithDescriptor(&ds_descriptor)
-                        .unwrap();
+                        .ok_or(crate::Error::OutOfMemory)?;
                     Some((raw, ds.bias))
                 }
                 None => None,
@@ -535,7 +578,11 @@ impl crate::traits::ShaderDevice for super::Context {
                 .lock()
                 .unwrap()
                 .newRenderPipelineStateWithDescriptor_error(&descriptor)
-                .unwrap();
+                .map_err(|err| crate::Error::Device(err.localizedDescription().to_string()))?;
+            if let Some(ref tracker) = self.tracker {
+                let key = Retained::as_ptr(&raw) as usize as u64;
+                tracker.add(crate::tracking::Handle::RenderPipeline(key), desc.name, 0);
+            }
 
             super::RenderPipeline {
                 raw,
@@ -560,11 +607,21 @@ impl crate::traits::ShaderDevice for super::Context {
                     metal::MTLDepthClipMode::Clip
                 },
                 depth_stencil,
-            }
+            })
         })
     }
 
-    fn destroy_render_pipeline(&self, _pipeline: &mut super::RenderPipeline) {
+    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
+        let name = desc.name;
+        self.try_create_render_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create render pipeline '{name}': {e}"))
+    }
+
+    fn destroy_render_pipeline(&self, pipeline: &mut super::RenderPipeline) {
         //TODO: is there a way to release?
+        if let Some(ref tracker) = self.tracker {
+            let key = Retained::as_ptr(&pipeline.raw) as usize as u64;
+            tracker.remove(crate::tracking::Handle::RenderPipeline(key));
+        }
     }
 }
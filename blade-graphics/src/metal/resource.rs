@@ -1,4 +1,4 @@
-use metal::{MTLDevice as _, MTLResource as _};
+use metal::{MTLBuffer as _, MTLDevice as _, MTLResource as _};
 use objc2::rc::Retained;
 use objc2_foundation::{NSRange, NSString};
 use objc2_metal::{self as metal, MTLTexture};
@@ -177,7 +177,7 @@ impl crate::traits::ResourceDevice for super::Context {
     type Sampler = super::Sampler;
     type AccelerationStructure = super::AccelerationStructure;
 
-    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+    fn try_create_buffer(&self, desc: crate::BufferDesc) -> Result<super::Buffer, crate::Error> {
         let options = match desc.memory {
             crate::Memory::Device => metal::MTLResourceOptions::StorageModePrivate,
             crate::Memory::Shared => metal::MTLResourceOptions::StorageModeShared,
@@ -192,24 +192,46 @@ impl crate::traits::ResourceDevice for super::Context {
                 .lock()
                 .unwrap()
                 .newBufferWithLength_options(desc.size as usize, options)
-                .unwrap()
-        });
+        })
+        .ok_or(crate::Error::OutOfMemory)?;
         if !desc.name.is_empty() {
             object.setLabel(Some(&NSString::from_str(desc.name)));
         }
-        super::Buffer {
+        let buffer = super::Buffer {
             raw: Retained::into_raw(object),
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(buffer, desc.name, desc.size);
         }
+        Ok(buffer)
+    }
+
+    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+        let name = desc.name;
+        self.try_create_buffer(desc)
+            .unwrap_or_else(|e| panic!("Unable to create buffer '{name}': {e}"))
     }
 
     fn sync_buffer(&self, _buffer: super::Buffer) {}
 
     fn destroy_buffer(&self, buffer: super::Buffer) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(buffer);
+        }
         let _ = unsafe { Retained::from_raw(buffer.raw) };
     }
 
-    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+    fn try_create_texture(&self, desc: crate::TextureDesc) -> Result<super::Texture, crate::Error> {
         let mtl_format = super::map_texture_format(desc.format);
+        if desc.sample_count > 1
+            && !self
+                .device
+                .lock()
+                .unwrap()
+                .supportsTextureSampleCount(desc.sample_count as usize)
+        {
+            return Err(crate::Error::UnsupportedFormat(desc.format));
+        }
 
         let mtl_type = match desc.dimension {
             crate::TextureDimension::D1 => {
@@ -236,7 +258,13 @@ impl crate::traits::ResourceDevice for super::Context {
             }
             crate::TextureDimension::D3 => metal::MTLTextureType::Type3D,
         };
-        let mtl_usage = map_texture_usage(desc.usage);
+        let mut mtl_usage = map_texture_usage(desc.usage);
+        if desc.usage.contains(crate::TextureUsage::STORAGE)
+            && desc.format.to_linear() != desc.format
+        {
+            // sRGB formats are written through views of the linear format
+            mtl_usage |= metal::MTLTextureUsage::PixelFormatView;
+        }
 
         let object = objc2::rc::autoreleasepool(|_| unsafe {
             let descriptor = metal::MTLTextureDescriptor::new();
@@ -255,17 +283,30 @@ impl crate::traits::ResourceDevice for super::Context {
                 .lock()
                 .unwrap()
                 .newTextureWithDescriptor(&descriptor)
-                .unwrap()
-        });
+        })
+        .ok_or(crate::Error::OutOfMemory)?;
         if !desc.name.is_empty() {
             object.setLabel(Some(&NSString::from_str(desc.name)));
         }
-        super::Texture {
+        let texture = super::Texture {
             raw: Retained::into_raw(object),
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(texture, desc.name, crate::tracking::texture_size(&desc));
         }
+        Ok(texture)
+    }
+
+    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+        let name = desc.name;
+        self.try_create_texture(desc)
+            .unwrap_or_else(|e| panic!("Unable to create texture '{name}': {e}"))
     }
 
     fn destroy_texture(&self, texture: super::Texture) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(texture);
+        }
         let _ = unsafe { Retained::from_raw(texture.raw) };
     }
 
@@ -305,13 +346,20 @@ impl crate::traits::ResourceDevice for super::Context {
         if !desc.name.is_empty() {
             object.setLabel(Some(&NSString::from_str(desc.name)));
         }
-        super::TextureView {
+        let view = super::TextureView {
             raw: Retained::into_raw(object),
             aspects: desc.format.aspects(),
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(view, desc.name, 0);
         }
+        view
     }
 
     fn destroy_texture_view(&self, view: super::TextureView) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(view);
+        }
         let _ = unsafe { Retained::from_raw(view.raw) };
     }
 
@@ -360,12 +408,19 @@ impl crate::traits::ResourceDevice for super::Context {
                 .unwrap()
         });
 
-        super::Sampler {
+        let sampler = super::Sampler {
             raw: Retained::into_raw(object),
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(sampler, desc.name, 0);
         }
+        sampler
     }
 
     fn destroy_sampler(&self, sampler: super::Sampler) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(sampler);
+        }
         let _ = unsafe { Retained::from_raw(sampler.raw) };
     }
 
@@ -385,12 +440,135 @@ impl crate::traits::ResourceDevice for super::Context {
             object.setLabel(Some(&NSString::from_str(desc.name)));
         }
 
-        super::AccelerationStructure {
+        let acceleration_structure = super::AccelerationStructure {
             raw: Retained::into_raw(object),
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(acceleration_structure, desc.name, desc.size);
         }
+        acceleration_structure
     }
 
     fn destroy_acceleration_structure(&self, acceleration_structure: super::AccelerationStructure) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(acceleration_structure);
+        }
         let _ = unsafe { Retained::from_raw(acceleration_structure.raw) };
     }
 }
+
+#[hidden_trait::expose]
+impl crate::traits::BindlessDevice for super::Context {
+    type BindlessTable = super::BindlessTable;
+    type TextureView = super::TextureView;
+    type Sampler = super::Sampler;
+
+    fn create_bindless_table(&self, desc: crate::BindlessTableDesc) -> super::BindlessTable {
+        for &(_, kind) in crate::BindlessTableDesc::BINDINGS.iter() {
+            assert_eq!(
+                desc.count(kind),
+                0,
+                "Bindless table {:?} count is over the limit of 0, see `Capabilities::max_bindless_resources`",
+                kind
+            );
+        }
+        super::BindlessTable {
+            slots: crate::bindless::BindlessSlots::new(&desc),
+        }
+    }
+
+    fn destroy_bindless_table(&self, _table: &mut super::BindlessTable) {}
+
+    fn insert_bindless_texture(
+        &self,
+        table: &mut super::BindlessTable,
+        _view: super::TextureView,
+    ) -> crate::BindlessHandle {
+        table
+            .slots
+            .insert(crate::BindlessKind::Texture, |_, _| true)
+    }
+
+    fn insert_bindless_buffer(
+        &self,
+        table: &mut super::BindlessTable,
+        _piece: crate::BufferPiece,
+    ) -> crate::BindlessHandle {
+        table.slots.insert(crate::BindlessKind::Buffer, |_, _| true)
+    }
+
+    fn insert_bindless_sampler(
+        &self,
+        table: &mut super::BindlessTable,
+        _sampler: super::Sampler,
+    ) -> crate::BindlessHandle {
+        table
+            .slots
+            .insert(crate::BindlessKind::Sampler, |_, _| true)
+    }
+
+    fn remove_bindless(&self, table: &mut super::BindlessTable, handle: crate::BindlessHandle) {
+        if let Err(message) = table.slots.remove(handle, ()) {
+            panic!("{}", message);
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::QueryDevice for super::Context {
+    type QuerySet = super::QuerySet;
+    type SyncPoint = super::SyncPoint;
+
+    fn create_query_set(&self, desc: crate::QuerySetDesc) -> super::QuerySet {
+        assert_eq!(
+            desc.ty,
+            crate::QueryType::Occlusion,
+            "Pipeline statistics are not supported"
+        );
+        let size = desc.count as usize * mem::size_of::<u64>();
+        let object = objc2::rc::autoreleasepool(|_| {
+            self.device
+                .lock()
+                .unwrap()
+                .newBufferWithLength_options(size, metal::MTLResourceOptions::StorageModeShared)
+                .unwrap()
+        });
+        if !desc.name.is_empty() {
+            object.setLabel(Some(&NSString::from_str(desc.name)));
+        }
+        unsafe { ptr::write_bytes(object.contents().as_ptr().cast::<u8>(), 0, size) };
+        let set = super::QuerySet {
+            raw: Retained::into_raw(object),
+            ty: desc.ty,
+            count: desc.count,
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(set, desc.name, crate::tracking::query_set_size(&desc));
+        }
+        set
+    }
+
+    fn destroy_query_set(&self, set: super::QuerySet) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(set);
+        }
+        let _ = unsafe { Retained::from_raw(set.raw) };
+    }
+
+    fn read_query_results(
+        &self,
+        set: super::QuerySet,
+        sync_point: &super::SyncPoint,
+    ) -> Option<Vec<u64>> {
+        if !self.wait_for(sync_point, 0) {
+            return None;
+        }
+        let values = unsafe {
+            std::slice::from_raw_parts(
+                set.as_ref().contents().as_ptr().cast::<u64>(),
+                set.count as usize,
+            )
+        };
+        Some(values.to_vec())
+    }
+}
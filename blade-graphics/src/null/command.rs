@@ -0,0 +1,1193 @@
+use std::{
+    marker::PhantomData,
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    derive::HasShaderBinding as _,
+    trace::{self, record},
+};
+
+impl super::PipelineContext<'_> {
+    fn add(
+        &mut self,
+        index: u32,
+        binding: crate::ShaderBinding,
+        bound: impl FnOnce() -> trace::Bound,
+    ) {
+        self.bindings.push((index, binding));
+        if let Some(ref mut captured) = self.captured {
+            captured.push((index, bound()));
+        }
+    }
+}
+
+impl<T: bytemuck::Pod> crate::ShaderBindable for T {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        ctx.add(index, Self::TYPE, || {
+            trace::Bound::Plain(bytemuck::bytes_of(self).to_vec())
+        });
+    }
+}
+impl crate::ShaderBindable for super::TextureView {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        ctx.resources.push(self.id);
+        ctx.add(index, Self::TYPE, || trace::Bound::Texture(*self));
+    }
+}
+impl<'a, const N: crate::ResourceIndex> crate::ShaderBindable for &'a crate::TextureArray<N> {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        for (index, view) in self.data.iter().enumerate() {
+            if !self.free_list.contains(&(index as crate::ResourceIndex)) {
+                ctx.resources.push(view.id);
+            }
+        }
+        ctx.add(index, Self::TYPE, || trace::Bound::TextureArray {
+            views: self.data.clone(),
+            count: N,
+        });
+    }
+}
+impl crate::ShaderBindable for super::Sampler {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        ctx.resources.push(self.id);
+        ctx.add(index, Self::TYPE, || trace::Bound::Sampler(*self));
+    }
+}
+impl crate::ShaderBindable for crate::BufferPiece {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        ctx.resources.push(self.buffer.id);
+        ctx.add(index, Self::TYPE, || trace::Bound::Buffer(*self));
+    }
+}
+impl<'a, const N: crate::ResourceIndex> crate::ShaderBindable for &'a crate::BufferArray<N> {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        for (index, piece) in self.data.iter().enumerate() {
+            if !self.free_list.contains(&(index as crate::ResourceIndex)) {
+                ctx.resources.push(piece.buffer.id);
+            }
+        }
+        ctx.add(index, Self::TYPE, || trace::Bound::BufferArray {
+            pieces: self.data.clone(),
+            count: N,
+        });
+    }
+}
+impl crate::ShaderBindable for super::AccelerationStructure {
+    fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
+        ctx.resources.push(self.id);
+        ctx.add(index, Self::TYPE, || {
+            trace::Bound::AccelerationStructure(*self)
+        });
+    }
+}
+
+impl super::Registry {
+    /// Check that `size` bytes starting at the piece fit into the buffer.
+    fn check_range(&mut self, piece: crate::BufferPiece, size: u64, usage: &str) -> bool {
+        if !self.check(piece.buffer.id, usage) {
+            return false;
+        }
+        if piece.offset + size > piece.buffer.size {
+            let message = format!(
+                "{} is accessed by {} at range {}..{} beyond its size {}",
+                self.describe(piece.buffer.id),
+                usage,
+                piece.offset,
+                piece.offset + size,
+                piece.buffer.size
+            );
+            self.report(message);
+            return false;
+        }
+        true
+    }
+
+    /// Check that the query at `index` exists in a live set.
+    fn check_query(&mut self, set: super::QuerySet, index: u32, usage: &str) -> bool {
+        if !self.check(set.id, usage) {
+            return false;
+        }
+        if index >= set.count {
+            let message = format!(
+                "Query {} is used by {} beyond the {} queries of {}",
+                index,
+                usage,
+                set.count,
+                self.describe(set.id)
+            );
+            self.report(message);
+            return false;
+        }
+        true
+    }
+
+    fn check_texture_piece(
+        &mut self,
+        piece: crate::TexturePiece,
+        size: crate::Extent,
+        usage: &str,
+    ) {
+        if !self.check(piece.texture.id, usage) {
+            return;
+        }
+        let mip_size = piece.texture.size.at_mip_level(piece.mip_level);
+        let fits = piece.origin[0] + size.width <= mip_size.width
+            && piece.origin[1] + size.height <= mip_size.height
+            && piece.origin[2] + size.depth <= mip_size.depth;
+        if !fits {
+            let message = format!(
+                "{} is accessed by {} at origin {:?} with size {} beyond its mip level {} of size {}",
+                self.describe(piece.texture.id),
+                usage,
+                piece.origin,
+                size,
+                piece.mip_level,
+                mip_size
+            );
+            self.report(message);
+        }
+    }
+}
+
+impl super::Command {
+    pub(super) fn execute(&self, registry: &mut super::Registry) {
+        match *self {
+            Self::FillBuffer { dst, size, value } => {
+                if registry.check_range(dst, size, "a submitted fill") {
+                    unsafe {
+                        ptr::write_bytes(
+                            dst.buffer.storage.add(dst.offset as usize),
+                            value,
+                            size as usize,
+                        )
+                    };
+                }
+            }
+            Self::CopyBufferToBuffer { src, dst, size } => {
+                if registry.check_range(src, size, "a submitted copy")
+                    && registry.check_range(dst, size, "a submitted copy")
+                {
+                    unsafe {
+                        ptr::copy(
+                            src.buffer.storage.add(src.offset as usize),
+                            dst.buffer.storage.add(dst.offset as usize),
+                            size as usize,
+                        )
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl super::OcclusionState {
+    fn begin(&mut self, registry: &Mutex<super::Registry>, index: u32) {
+        let mut registry = registry.lock().unwrap();
+        match self.set {
+            Some(set) => {
+                registry.check_query(set, index, "`begin_occlusion_query`");
+            }
+            None => registry.report(format!(
+                "Occlusion query {} is begun in a pass without a query set",
+                index
+            )),
+        }
+        if let Some(active) = self.active.replace(index) {
+            registry.report(format!(
+                "Occlusion query {} is begun while query {} is active",
+                index, active
+            ));
+        }
+    }
+
+    fn end(&mut self, registry: &Mutex<super::Registry>, index: u32) {
+        if self.active.take() != Some(index) {
+            registry.lock().unwrap().report(format!(
+                "Occlusion query {} is ended without being active",
+                index
+            ));
+        }
+    }
+}
+
+impl super::CommandEncoder {
+    fn begin_pass(&mut self, label: &str, command_type: crate::CommandType) {
+        if !self.started {
+            self.registry.lock().unwrap().report(format!(
+                "Pass '{}' is recorded into command encoder '{}' without starting it",
+                label, self.name
+            ));
+        }
+        if !self.command_type.supports(command_type) {
+            self.registry.lock().unwrap().report(format!(
+                "Pass '{}' can't be recorded into {:?} command encoder '{}'",
+                label, self.command_type, self.name
+            ));
+        }
+        self.scopes.begin_pass(label, self.timing);
+        self.pass_count += 1;
+    }
+
+    fn pass<P>(&mut self) -> super::PassEncoder<'_, P> {
+        super::PassEncoder {
+            commands: &mut self.commands,
+            registry: &self.registry,
+            color_target_count: 0,
+            occlusion: Default::default(),
+            timing: self.timing,
+            scopes: &mut self.scopes,
+            trace: &mut self.trace,
+            pipeline: PhantomData,
+        }
+    }
+
+    pub fn transfer(&mut self, label: &str) -> super::TransferCommandEncoder<'_> {
+        self.begin_pass(label, crate::CommandType::Transfer);
+        record(&mut self.trace, |_| trace::Command::BeginTransfer {
+            label: label.to_string(),
+        });
+        self.pass()
+    }
+
+    pub fn acceleration_structure(
+        &mut self,
+        label: &str,
+    ) -> super::AccelerationStructureCommandEncoder<'_> {
+        self.begin_pass(label, crate::CommandType::Compute);
+        record(&mut self.trace, |_| {
+            trace::Command::BeginAccelerationStructure {
+                label: label.to_string(),
+            }
+        });
+        self.pass()
+    }
+
+    pub fn compute(&mut self, label: &str) -> super::ComputeCommandEncoder<'_> {
+        self.begin_pass(label, crate::CommandType::Compute);
+        record(&mut self.trace, |_| trace::Command::BeginCompute {
+            label: label.to_string(),
+        });
+        self.pass()
+    }
+
+    pub fn render(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::RenderCommandEncoder<'_> {
+        self.begin_pass(label, crate::CommandType::General);
+        {
+            let mut registry = self.registry.lock().unwrap();
+            let usage = format!("render pass '{}'", label);
+            let mut target_size = None;
+            for rt in targets.colors.iter().chain(targets.depth_stencil.as_ref()) {
+                registry.check(rt.view.id, &usage);
+                if let crate::FinishOp::ResolveTo(view) = rt.finish_op {
+                    registry.check(view.id, &usage);
+                }
+                match target_size {
+                    None => target_size = Some(rt.view.target_size),
+                    Some(size) if size != rt.view.target_size => registry.report(format!(
+                        "Render target sizes {:?} and {:?} don't match in {}",
+                        size, rt.view.target_size, usage
+                    )),
+                    Some(_) => {}
+                }
+            }
+            for rt in targets.colors {
+                if !rt.view.aspects.contains(crate::TexelAspects::COLOR) {
+                    let message = format!(
+                        "{} is not a color target in {}",
+                        registry.describe(rt.view.id),
+                        usage
+                    );
+                    registry.report(message);
+                }
+            }
+            if target_size.is_none() {
+                registry.report(format!("No render targets in {}", usage));
+            }
+        }
+        record(&mut self.trace, |ids| trace::Command::BeginRender {
+            label: label.to_string(),
+            colors: targets
+                .colors
+                .iter()
+                .map(|rt| ids.render_target(rt))
+                .collect(),
+            depth_stencil: targets
+                .depth_stencil
+                .as_ref()
+                .map(|rt| ids.render_target(rt)),
+        });
+        let mut pass = self.pass();
+        pass.color_target_count = targets.colors.len();
+        pass
+    }
+
+    /// Begin a render pass, which writes the occlusion queries into `queries`.
+    pub fn render_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::RenderCommandEncoder<'_> {
+        {
+            let mut registry = self.registry.lock().unwrap();
+            let usage = format!("render pass '{}'", label);
+            if registry.check(queries.id, &usage) && queries.ty != crate::QueryType::Occlusion {
+                let message = format!(
+                    "{} is not an occlusion query set, used by {}",
+                    registry.describe(queries.id),
+                    usage
+                );
+                registry.report(message);
+            }
+        }
+        let mut pass = self.render(label, targets);
+        pass.occlusion.set = Some(queries);
+        pass
+    }
+
+    pub fn render_parallel(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        let target_size = targets
+            .colors
+            .iter()
+            .chain(targets.depth_stencil.as_ref())
+            .next()
+            .map_or([0; 2], |rt| rt.view.target_size);
+        let registry = Arc::clone(&self.registry);
+        super::ParallelRenderCommandEncoder {
+            pass: self.render(label, targets),
+            registry,
+            label: label.to_string(),
+            target_size,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Begin a parallel render pass, which writes the occlusion queries into `queries`.
+    pub fn render_parallel_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        let target_size = targets
+            .colors
+            .iter()
+            .chain(targets.depth_stencil.as_ref())
+            .next()
+            .map_or([0; 2], |rt| rt.view.target_size);
+        let registry = Arc::clone(&self.registry);
+        super::ParallelRenderCommandEncoder {
+            pass: self.render_with_queries(label, targets, queries),
+            registry,
+            label: label.to_string(),
+            target_size,
+            chunks: Vec::new(),
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::CommandEncoder for super::CommandEncoder {
+    type Texture = super::Texture;
+    type Frame = super::Frame;
+    type SyncPoint = super::SyncPoint;
+    type QuerySet = super::QuerySet;
+
+    fn start(&mut self) {
+        self.commands.clear();
+        self.scopes.reset();
+        self.pass_count = 0;
+        self.started = true;
+        if let Some(ref mut trace) = self.trace {
+            trace.start();
+        }
+    }
+
+    fn depend_on(&mut self, _sync_point: &super::SyncPoint) {
+        // There is no asynchronous work, everything is done on submission
+    }
+
+    fn init_texture(&mut self, texture: super::Texture) {
+        record(&mut self.trace, |ids| trace::Command::InitTexture {
+            texture: ids.texture(texture),
+        });
+        let mut registry = self.registry.lock().unwrap();
+        if registry.records[texture.id.0 as usize].alive {
+            registry.set_initialized(texture.id, true);
+        } else {
+            let message = format!(
+                "{} is initialized after being destroyed",
+                registry.describe(texture.id)
+            );
+            registry.report(message);
+        }
+    }
+
+    fn begin_statistics_query(&mut self, set: super::QuerySet, index: u32) {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.check_query(set, index, "`begin_statistics_query`") {
+            match set.ty {
+                crate::QueryType::PipelineStatistics(statistics) => {
+                    let supported = match self.command_type {
+                        crate::CommandType::Transfer => crate::PipelineStatistics::empty(),
+                        crate::CommandType::Compute => {
+                            crate::PipelineStatistics::COMPUTE_INVOCATIONS
+                        }
+                        crate::CommandType::General => crate::PipelineStatistics::all(),
+                    };
+                    if !supported.contains(statistics) {
+                        registry.report(format!(
+                            "{:?} can't be collected by {:?} command encoder '{}'",
+                            statistics, self.command_type, self.name
+                        ));
+                    }
+                }
+                crate::QueryType::Occlusion => {
+                    let message = format!(
+                        "{} is not a pipeline statistics query set",
+                        registry.describe(set.id)
+                    );
+                    registry.report(message);
+                }
+            }
+        }
+        if let Some((active_set, active)) = self.statistics_query.replace((set, index)) {
+            let message = format!(
+                "Query {} is begun while query {} of {} is active",
+                index,
+                active,
+                registry.describe(active_set.id)
+            );
+            registry.report(message);
+        }
+    }
+
+    fn end_statistics_query(&mut self, set: super::QuerySet, index: u32) {
+        if self.statistics_query.take() != Some((set, index)) {
+            let mut registry = self.registry.lock().unwrap();
+            let message = format!(
+                "Query {} of {} is ended without being active",
+                index,
+                registry.describe(set.id)
+            );
+            registry.report(message);
+        }
+    }
+
+    fn present(&mut self, frame: super::Frame) {
+        record(&mut self.trace, |ids| trace::Command::Present {
+            texture: ids.texture(frame.texture),
+        });
+        let mut registry = self.registry.lock().unwrap();
+        if self.command_type != crate::CommandType::General {
+            registry.report(format!(
+                "Presentation is recorded into {:?} command encoder '{}'",
+                self.command_type, self.name
+            ));
+        }
+        registry.check(frame.texture.id, "presentation");
+    }
+
+    fn timings(&self) -> &crate::Timings {
+        &self.timings
+    }
+
+    fn stats(&self) -> &crate::CommandStats {
+        &self.stats
+    }
+}
+
+fn end_scope(scopes: &mut crate::timing::ScopeRecorder, registry: &Mutex<super::Registry>) {
+    if let Err(message) = scopes.end() {
+        registry.lock().unwrap().report(message);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::CommandEncoder {
+    fn begin_scope(&mut self, name: &str) {
+        self.scopes.begin(name, self.timing);
+    }
+
+    fn end_scope(&mut self) {
+        end_scope(&mut self.scopes, &self.registry);
+    }
+}
+
+macro_rules! impl_timing_encoder {
+    ($($name:ident)*) => {$(
+        #[hidden_trait::expose]
+        impl crate::traits::TimingEncoder for super::$name<'_> {
+            fn begin_scope(&mut self, name: &str) {
+                self.scopes.begin(name, self.timing);
+            }
+
+            fn end_scope(&mut self) {
+                end_scope(self.scopes, self.registry);
+            }
+        }
+    )*};
+}
+
+impl_timing_encoder!(
+    TransferCommandEncoder AccelerationStructureCommandEncoder
+    ComputeCommandEncoder RenderCommandEncoder
+);
+
+// Passes end when dropped on the real backends, and user code relies on that
+impl<P> Drop for super::PassEncoder<'_, P> {
+    fn drop(&mut self) {
+        if let Some(index) = self.occlusion.active {
+            self.registry
+                .lock()
+                .unwrap()
+                .report(format!("Occlusion query {} is not ended in the pass", index));
+        }
+        let (_, unended) = self.scopes.end_pass();
+        for name in unended {
+            self.registry
+                .lock()
+                .unwrap()
+                .report(format!("Timing scope '{}' is not ended in the pass", name));
+        }
+        record(self.trace, |_| trace::Command::EndPass);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
+    type BufferPiece = crate::BufferPiece;
+    type TexturePiece = crate::TexturePiece;
+
+    fn fill_buffer(&mut self, dst: crate::BufferPiece, size: u64, value: u8) {
+        record(self.trace, |ids| trace::Command::FillBuffer {
+            dst: ids.buffer(dst),
+            size,
+            value,
+        });
+        if self
+            .registry
+            .lock()
+            .unwrap()
+            .check_range(dst, size, "`fill_buffer`")
+        {
+            self.commands
+                .push(super::Command::FillBuffer { dst, size, value });
+        }
+    }
+
+    fn copy_buffer_to_buffer(
+        &mut self,
+        src: crate::BufferPiece,
+        dst: crate::BufferPiece,
+        size: u64,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        record(self.trace, |ids| trace::Command::CopyBufferToBuffer {
+            src: ids.buffer(src),
+            dst: ids.buffer(dst),
+            size,
+        });
+        let usage = "`copy_buffer_to_buffer`";
+        if registry.check_range(src, size, usage) && registry.check_range(dst, size, usage) {
+            self.commands
+                .push(super::Command::CopyBufferToBuffer { src, dst, size });
+        }
+    }
+
+    fn copy_texture_to_texture(
+        &mut self,
+        src: crate::TexturePiece,
+        dst: crate::TexturePiece,
+        size: crate::Extent,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        record(self.trace, |ids| trace::Command::CopyTextureToTexture {
+            src: ids.texture_piece(src),
+            dst: ids.texture_piece(dst),
+            size,
+        });
+        let usage = "`copy_texture_to_texture`";
+        registry.check_texture_piece(src, size, usage);
+        registry.check_texture_piece(dst, size, usage);
+    }
+
+    fn copy_buffer_to_texture(
+        &mut self,
+        src: crate::BufferPiece,
+        bytes_per_row: u32,
+        dst: crate::TexturePiece,
+        size: crate::Extent,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        record(self.trace, |ids| trace::Command::CopyBufferToTexture {
+            src: ids.buffer(src),
+            bytes_per_row,
+            dst: ids.texture_piece(dst),
+            size,
+        });
+        let usage = "`copy_buffer_to_texture`";
+        let rows = size
+            .height
+            .div_ceil(dst.texture.format.block_info().dimensions.1 as u32);
+        registry.check_range(src, (bytes_per_row * rows * size.depth) as u64, usage);
+        registry.check_texture_piece(dst, size, usage);
+    }
+
+    fn copy_texture_to_buffer(
+        &mut self,
+        src: crate::TexturePiece,
+        dst: crate::BufferPiece,
+        bytes_per_row: u32,
+        size: crate::Extent,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        record(self.trace, |ids| trace::Command::CopyTextureToBuffer {
+            src: ids.texture_piece(src),
+            dst: ids.buffer(dst),
+            bytes_per_row,
+            size,
+        });
+        let usage = "`copy_texture_to_buffer`";
+        let rows = size
+            .height
+            .div_ceil(src.texture.format.block_info().dimensions.1 as u32);
+        registry.check_texture_piece(src, size, usage);
+        registry.check_range(dst, (bytes_per_row * rows * size.depth) as u64, usage);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::AccelerationStructureEncoder
+    for super::AccelerationStructureCommandEncoder<'_>
+{
+    type AccelerationStructure = super::AccelerationStructure;
+    type AccelerationStructureMesh = crate::AccelerationStructureMesh;
+    type BufferPiece = crate::BufferPiece;
+
+    fn build_bottom_level(
+        &mut self,
+        acceleration_structure: super::AccelerationStructure,
+        meshes: &[crate::AccelerationStructureMesh],
+        scratch_data: crate::BufferPiece,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        record(self.trace, |ids| trace::Command::BuildBottomLevel {
+            acceleration_structure: ids.acceleration_structure(acceleration_structure),
+            meshes: meshes.iter().map(|mesh| ids.mesh(mesh)).collect(),
+            scratch_data: ids.buffer(scratch_data),
+        });
+        let usage = "`build_bottom_level`";
+        registry.check(acceleration_structure.id, usage);
+        registry.check(scratch_data.buffer.id, usage);
+        for mesh in meshes {
+            registry.check(mesh.vertex_data.buffer.id, usage);
+            if mesh.index_type.is_some() {
+                registry.check(mesh.index_data.buffer.id, usage);
+            }
+        }
+    }
+
+    fn build_top_level(
+        &mut self,
+        acceleration_structure: super::AccelerationStructure,
+        bottom_level: &[super::AccelerationStructure],
+        instance_count: u32,
+        instance_data: crate::BufferPiece,
+        scratch_data: crate::BufferPiece,
+    ) {
+        record(self.trace, |ids| trace::Command::BuildTopLevel {
+            acceleration_structure: ids.acceleration_structure(acceleration_structure),
+            bottom_level: bottom_level
+                .iter()
+                .map(|&blas| ids.acceleration_structure(blas))
+                .collect(),
+            instance_count,
+            instance_data: ids.buffer(instance_data),
+            scratch_data: ids.buffer(scratch_data),
+        });
+        let mut registry = self.registry.lock().unwrap();
+        let usage = "`build_top_level`";
+        registry.check(acceleration_structure.id, usage);
+        for blas in bottom_level {
+            registry.check(blas.id, usage);
+        }
+        registry.check(instance_data.buffer.id, usage);
+        registry.check(scratch_data.buffer.id, usage);
+    }
+}
+
+impl super::ComputeCommandEncoder<'_> {
+    pub fn with<'b>(
+        &'b mut self,
+        pipeline: &'b super::ComputePipeline,
+    ) -> super::PipelineEncoder<'b> {
+        record(self.trace, |ids| trace::Command::SetComputePipeline {
+            pipeline: ids.pipeline(pipeline.inner.id.0 as u64),
+        });
+        super::PipelineEncoder {
+            registry: self.registry,
+            pipeline: &pipeline.inner,
+            bound_groups: 0,
+            bound_vertex_buffers: 0,
+            occlusion: None,
+            trace: self.trace,
+        }
+    }
+}
+
+fn render_pipeline_encoder<'b>(
+    registry: &'b Mutex<super::Registry>,
+    color_target_count: usize,
+    occlusion: &'b mut super::OcclusionState,
+    trace: &'b mut Option<trace::EncoderTrace>,
+    pipeline: &'b super::RenderPipeline,
+) -> super::PipelineEncoder<'b> {
+    if pipeline.color_target_count != color_target_count {
+        registry.lock().unwrap().report(format!(
+            "Pipeline '{}' has {} color targets, but the pass has {}",
+            pipeline.inner.name, pipeline.color_target_count, color_target_count
+        ));
+    }
+    record(trace, |ids| trace::Command::SetRenderPipeline {
+        pipeline: ids.pipeline(pipeline.inner.id.0 as u64),
+    });
+    super::PipelineEncoder {
+        registry,
+        pipeline: &pipeline.inner,
+        bound_groups: 0,
+        bound_vertex_buffers: 0,
+        occlusion: Some(occlusion),
+        trace,
+    }
+}
+
+impl super::RenderCommandEncoder<'_> {
+    pub fn with<'b>(
+        &'b mut self,
+        pipeline: &'b super::RenderPipeline,
+    ) -> super::PipelineEncoder<'b> {
+        render_pipeline_encoder(
+            self.registry,
+            self.color_target_count,
+            &mut self.occlusion,
+            self.trace,
+            pipeline,
+        )
+    }
+}
+
+impl super::ParallelRenderCommandEncoder<'_> {
+    /// Begin a chunk of the pass, which can be recorded on any thread.
+    /// Chunks are executed in the order they are begun, after being merged back.
+    pub fn chunk(&mut self) -> super::RenderChunk {
+        let index = self.chunks.len();
+        self.chunks.push(None);
+        super::RenderChunk {
+            index,
+            registry: Arc::clone(&self.registry),
+            color_target_count: self.pass.color_target_count,
+            occlusion: super::OcclusionState {
+                set: self.pass.occlusion.set,
+                active: None,
+            },
+            trace: self
+                .pass
+                .trace
+                .as_ref()
+                .map(|trace| trace.chunk(self.target_size)),
+        }
+    }
+
+    /// Return a recorded chunk into the pass.
+    pub fn merge(&mut self, chunk: super::RenderChunk) {
+        match self.chunks.get_mut(chunk.index) {
+            Some(slot) if slot.is_none() => *slot = Some(chunk),
+            _ => self.registry.lock().unwrap().report(format!(
+                "Render chunk {} is merged twice into pass '{}'",
+                chunk.index, self.label
+            )),
+        }
+    }
+}
+
+impl Drop for super::ParallelRenderCommandEncoder<'_> {
+    fn drop(&mut self) {
+        for (index, chunk) in self.chunks.drain(..).enumerate() {
+            match chunk {
+                Some(chunk) => {
+                    if let Some(active) = chunk.occlusion.active {
+                        self.registry.lock().unwrap().report(format!(
+                            "Occlusion query {} is not ended in render chunk {}",
+                            active, index
+                        ));
+                    }
+                    if let Some(ref mut trace) = *self.pass.trace
+                        && let Some(chunk_trace) = chunk.trace
+                    {
+                        trace.join(chunk_trace);
+                    }
+                }
+                None => self.registry.lock().unwrap().report(format!(
+                    "Render chunk {} is not merged into pass '{}'",
+                    index, self.label
+                )),
+            }
+        }
+    }
+}
+
+impl super::RenderChunk {
+    pub fn with<'b>(
+        &'b mut self,
+        pipeline: &'b super::RenderPipeline,
+    ) -> super::PipelineEncoder<'b> {
+        render_pipeline_encoder(
+            &self.registry,
+            self.color_target_count,
+            &mut self.occlusion,
+            &mut self.trace,
+            pipeline,
+        )
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderEncoder for super::RenderChunk {
+    fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        record(&mut self.trace, |_| trace::Command::SetScissorRect {
+            rect: rect.clone(),
+        });
+    }
+    fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        record(&mut self.trace, |_| trace::Command::SetViewport {
+            viewport: viewport.clone(),
+        });
+    }
+    fn set_stencil_reference(&mut self, reference: u32) {
+        record(&mut self.trace, |_| trace::Command::SetStencilReference {
+            reference,
+        });
+    }
+    fn begin_occlusion_query(&mut self, index: u32, _mode: crate::OcclusionMode) {
+        self.occlusion.begin(&self.registry, index);
+    }
+    fn end_occlusion_query(&mut self, index: u32) {
+        self.occlusion.end(&self.registry, index);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderEncoder for super::RenderCommandEncoder<'_> {
+    fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        record(self.trace, |_| trace::Command::SetScissorRect {
+            rect: rect.clone(),
+        });
+    }
+    fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        record(self.trace, |_| trace::Command::SetViewport {
+            viewport: viewport.clone(),
+        });
+    }
+    fn set_stencil_reference(&mut self, reference: u32) {
+        record(self.trace, |_| trace::Command::SetStencilReference {
+            reference,
+        });
+    }
+    fn begin_occlusion_query(&mut self, index: u32, _mode: crate::OcclusionMode) {
+        self.occlusion.begin(self.registry, index);
+    }
+    fn end_occlusion_query(&mut self, index: u32) {
+        self.occlusion.end(self.registry, index);
+    }
+}
+
+impl super::PipelineEncoder<'_> {
+    /// Check that everything the pipeline reads is bound before a draw or dispatch.
+    fn check_bindings(&self, command: &str) {
+        let mut registry = self.registry.lock().unwrap();
+        for (group, visibility) in self.pipeline.group_visibility.iter().enumerate() {
+            if !visibility.is_empty() && self.bound_groups & (1 << group) == 0 {
+                registry.report(format!(
+                    "Group {} of pipeline '{}' is not bound for {}",
+                    group, self.pipeline.name, command
+                ));
+            }
+        }
+        for index in 0..self.pipeline.vertex_buffer_count {
+            let used = self.pipeline.used_vertex_buffers & (1 << index) != 0;
+            if used && self.bound_vertex_buffers & (1 << index) == 0 {
+                registry.report(format!(
+                    "Vertex buffer {} of pipeline '{}' is not bound for {}",
+                    index, self.pipeline.name, command
+                ));
+            }
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::PipelineEncoder for super::PipelineEncoder<'_> {
+    type BindlessTable = super::BindlessTable;
+
+    fn bind<D: crate::ShaderData>(&mut self, group: u32, data: &D) {
+        let mut registry = self.registry.lock().unwrap();
+        let expected = match self.pipeline.group_layouts.get(group as usize) {
+            Some(layout) => layout,
+            None => {
+                registry.report(format!(
+                    "Pipeline '{}' has only {} groups, can't bind group {}",
+                    self.pipeline.name,
+                    self.pipeline.group_layouts.len(),
+                    group
+                ));
+                return;
+            }
+        };
+
+        let mut resources = Vec::new();
+        let mut bindings = Vec::new();
+        let mut captured = self.trace.as_ref().map(|_| Vec::new());
+        data.fill(super::PipelineContext {
+            resources: &mut resources,
+            bindings: &mut bindings,
+            captured: captured.as_mut(),
+        });
+        if let Some(captured) = captured {
+            record(self.trace, |ids| trace::Command::Bind {
+                group,
+                bindings: ids.bindings(captured),
+            });
+        }
+        // Compare what is actually bound, since the layout of the data
+        // may be only known at run time, like when replaying a trace.
+        bindings.sort_by_key(|&(index, _)| index);
+        let matches = bindings.len() == expected.bindings.len()
+            && bindings
+                .iter()
+                .zip(expected.bindings.iter())
+                .enumerate()
+                .all(|(i, (&(index, binding), &(_, expected)))| {
+                    index as usize == i && binding == expected
+                });
+        if !matches {
+            registry.report(format!(
+                "Data bound to group {} doesn't match the layout of pipeline '{}'",
+                group, self.pipeline.name
+            ));
+            return;
+        }
+        if cfg!(debug_assertions)
+            && let Err(message) = crate::reflect::check_plain_data::<D>(
+                &self.pipeline.group_plain_structs[group as usize],
+            )
+        {
+            registry.report(format!(
+                "Group {} of pipeline '{}': {}",
+                group, self.pipeline.name, message
+            ));
+            return;
+        }
+        let usage = format!("group {} of pipeline '{}'", group, self.pipeline.name);
+        for id in resources {
+            registry.check(id, &usage);
+        }
+        self.bound_groups |= 1 << group;
+    }
+
+    fn bind_table(&mut self, group: u32, table: &super::BindlessTable) {
+        let mut registry = self.registry.lock().unwrap();
+        if !registry.check(table.id, "`bind_table`") {
+            return;
+        }
+        match self.pipeline.group_layouts.get(group as usize) {
+            Some(layout) if *layout == table.layout => {}
+            Some(_) => {
+                let message = format!(
+                    "{} doesn't match the layout of group {} of pipeline '{}'",
+                    registry.describe(table.id),
+                    group,
+                    self.pipeline.name
+                );
+                registry.report(message);
+                return;
+            }
+            None => {
+                registry.report(format!(
+                    "Pipeline '{}' has only {} groups, can't bind group {}",
+                    self.pipeline.name,
+                    self.pipeline.group_layouts.len(),
+                    group
+                ));
+                return;
+            }
+        }
+        let usage = registry.describe(table.id);
+        for &id in table.resources.values() {
+            registry.check(id, &usage);
+        }
+        self.bound_groups |= 1 << group;
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::ComputePipelineEncoder for super::PipelineEncoder<'_> {
+    type BufferPiece = crate::BufferPiece;
+
+    fn dispatch(&mut self, groups: [u32; 3]) {
+        record(self.trace, |_| trace::Command::Dispatch { groups });
+        self.check_bindings("`dispatch`");
+    }
+
+    fn dispatch_indirect(&mut self, indirect_buf: crate::BufferPiece) {
+        record(self.trace, |ids| trace::Command::DispatchIndirect {
+            indirect_buf: ids.buffer(indirect_buf),
+        });
+        self.check_bindings("`dispatch_indirect`");
+        self.registry
+            .lock()
+            .unwrap()
+            .check_range(indirect_buf, 12, "`dispatch_indirect`");
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderEncoder for super::PipelineEncoder<'_> {
+    fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        record(self.trace, |_| trace::Command::SetScissorRect {
+            rect: rect.clone(),
+        });
+    }
+    fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        record(self.trace, |_| trace::Command::SetViewport {
+            viewport: viewport.clone(),
+        });
+    }
+    fn set_stencil_reference(&mut self, reference: u32) {
+        record(self.trace, |_| trace::Command::SetStencilReference {
+            reference,
+        });
+    }
+    fn begin_occlusion_query(&mut self, index: u32, _mode: crate::OcclusionMode) {
+        match self.occlusion {
+            Some(ref mut occlusion) => occlusion.begin(self.registry, index),
+            None => self.registry.lock().unwrap().report(format!(
+                "Occlusion query {} is begun by compute pipeline '{}'",
+                index, self.pipeline.name
+            )),
+        }
+    }
+    fn end_occlusion_query(&mut self, index: u32) {
+        if let Some(ref mut occlusion) = self.occlusion {
+            occlusion.end(self.registry, index);
+        }
+    }
+}
+
+fn index_size(index_type: crate::IndexType) -> u64 {
+    match index_type {
+        crate::IndexType::U16 => 2,
+        crate::IndexType::U32 => 4,
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_> {
+    type BufferPiece = crate::BufferPiece;
+
+    fn bind_vertex(&mut self, index: u32, vertex_buf: crate::BufferPiece) {
+        record(self.trace, |ids| trace::Command::BindVertex {
+            index,
+            vertex_buf: ids.buffer(vertex_buf),
+        });
+        let mut registry = self.registry.lock().unwrap();
+        if index as usize >= self.pipeline.vertex_buffer_count {
+            registry.report(format!(
+                "Pipeline '{}' has only {} vertex buffers, can't bind buffer {}",
+                self.pipeline.name, self.pipeline.vertex_buffer_count, index
+            ));
+            return;
+        }
+        registry.check(vertex_buf.buffer.id, "`bind_vertex`");
+        self.bound_vertex_buffers |= 1 << index;
+    }
+
+    fn draw(
+        &mut self,
+        first_vertex: u32,
+        vertex_count: u32,
+        first_instance: u32,
+        instance_count: u32,
+    ) {
+        record(self.trace, |_| trace::Command::Draw {
+            first_vertex,
+            vertex_count,
+            first_instance,
+            instance_count,
+        });
+        self.check_bindings("`draw`");
+    }
+
+    fn draw_indexed(
+        &mut self,
+        index_buf: crate::BufferPiece,
+        index_type: crate::IndexType,
+        index_count: u32,
+        base_vertex: i32,
+        start_instance: u32,
+        instance_count: u32,
+    ) {
+        record(self.trace, |ids| trace::Command::DrawIndexed {
+            index_buf: ids.buffer(index_buf),
+            index_type,
+            index_count,
+            base_vertex,
+            start_instance,
+            instance_count,
+        });
+        self.check_bindings("`draw_indexed`");
+        let size = index_count as u64 * index_size(index_type);
+        self.registry
+            .lock()
+            .unwrap()
+            .check_range(index_buf, size, "`draw_indexed`");
+    }
+
+    fn draw_indirect(&mut self, indirect_buf: crate::BufferPiece) {
+        record(self.trace, |ids| trace::Command::DrawIndirect {
+            indirect_buf: ids.buffer(indirect_buf),
+        });
+        self.check_bindings("`draw_indirect`");
+        self.registry
+            .lock()
+            .unwrap()
+            .check_range(indirect_buf, 16, "`draw_indirect`");
+    }
+
+    fn draw_indexed_indirect(
+        &mut self,
+        index_buf: crate::BufferPiece,
+        index_type: crate::IndexType,
+        indirect_buf: crate::BufferPiece,
+    ) {
+        record(self.trace, |ids| trace::Command::DrawIndexedIndirect {
+            index_buf: ids.buffer(index_buf),
+            index_type,
+            indirect_buf: ids.buffer(indirect_buf),
+        });
+        self.check_bindings("`draw_indexed_indirect`");
+        let mut registry = self.registry.lock().unwrap();
+        registry.check(index_buf.buffer.id, "`draw_indexed_indirect`");
+        registry.check_range(indirect_buf, 20, "`draw_indexed_indirect`");
+    }
+}
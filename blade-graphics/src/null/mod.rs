@@ -0,0 +1,568 @@
+//! Backend that doesn't talk to any GPU driver.
+//!
+//! Buffers live in host memory, pipelines go through the same naga binding
+//! resolution as the real backends, and commands are validated but not executed,
+//! with the exception of buffer transfers. API misuse, such as using a resource
+//! after it was destroyed or rendering into a texture that was never initialized,
+//! is reported via `log::error!` and collected for `Context::take_validation_errors`.
+
+mod command;
+mod pipeline;
+mod resource;
+
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+pub type PlatformError = ();
+
+const MAX_BINDLESS_RESOURCES: u32 = 1 << 20;
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+struct ResourceId(u32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ResourceKind {
+    Buffer,
+    Texture,
+    TextureView,
+    Sampler,
+    AccelerationStructure,
+    QuerySet,
+    BindlessTable,
+    Pipeline,
+}
+
+#[derive(Debug)]
+struct ResourceRecord {
+    kind: ResourceKind,
+    name: String,
+    alive: bool,
+    /// Only meaningful for textures: set by `init_texture`.
+    initialized: bool,
+    /// The texture a view was created from.
+    parent: Option<ResourceId>,
+}
+
+/// Every resource ever created by the context. Identifiers are never reused,
+/// so a stale handle is always recognized as such.
+#[derive(Default)]
+struct Registry {
+    records: Vec<ResourceRecord>,
+    errors: Vec<String>,
+}
+
+impl Registry {
+    fn add(&mut self, kind: ResourceKind, name: &str, parent: Option<ResourceId>) -> ResourceId {
+        let id = ResourceId(self.records.len() as u32);
+        self.records.push(ResourceRecord {
+            kind,
+            name: name.to_string(),
+            alive: true,
+            initialized: false,
+            parent,
+        });
+        id
+    }
+
+    fn report(&mut self, message: String) {
+        log::error!("{}", message);
+        self.errors.push(message);
+    }
+
+    fn describe(&self, id: ResourceId) -> String {
+        let record = &self.records[id.0 as usize];
+        format!("{:?} '{}'", record.kind, record.name)
+    }
+
+    fn remove(&mut self, id: ResourceId) {
+        let record = &mut self.records[id.0 as usize];
+        if record.alive {
+            record.alive = false;
+        } else {
+            let message = format!("{} is destroyed twice", self.describe(id));
+            self.report(message);
+        }
+    }
+
+    /// Check that the resource can be used by a command.
+    /// Views are also checked for their parent texture.
+    fn check(&mut self, id: ResourceId, usage: &str) -> bool {
+        let record = &self.records[id.0 as usize];
+        if !record.alive {
+            let message = format!(
+                "{} is used by {} after being destroyed",
+                self.describe(id),
+                usage
+            );
+            self.report(message);
+            return false;
+        }
+        match record.parent {
+            Some(parent) => self.check(parent, usage),
+            None if record.kind == ResourceKind::Texture && !record.initialized => {
+                let message = format!(
+                    "{} is used by {} without being initialized with `init_texture`",
+                    self.describe(id),
+                    usage
+                );
+                self.report(message);
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn set_initialized(&mut self, id: ResourceId, initialized: bool) {
+        self.records[id.0 as usize].initialized = initialized;
+    }
+}
+
+pub struct Context {
+    registry: Arc<Mutex<Registry>>,
+    timing: bool,
+    device_information: crate::DeviceInformation,
+    trace: Option<Arc<crate::trace::Recorder>>,
+    tracker: Option<crate::tracking::Tracker>,
+}
+
+pub struct Surface {
+    registry: Arc<Mutex<Registry>>,
+    frame: Option<Frame>,
+    info: crate::SurfaceInfo,
+    trace: Option<Arc<crate::trace::Recorder>>,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct Buffer {
+    id: ResourceId,
+    size: u64,
+    /// Host storage backing the buffer, regardless of the memory type.
+    storage: *mut u8,
+    /// Same as `storage` if the memory is host-visible, null otherwise.
+    data: *mut u8,
+    owned: bool,
+}
+
+unsafe impl Send for Buffer {}
+unsafe impl Sync for Buffer {}
+
+impl Buffer {
+    pub fn data(&self) -> *mut u8 {
+        self.data
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct Texture {
+    id: ResourceId,
+    format: crate::TextureFormat,
+    size: crate::Extent,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct TextureView {
+    id: ResourceId,
+    target_size: [u16; 2],
+    aspects: crate::TexelAspects,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct Sampler {
+    id: ResourceId,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct AccelerationStructure {
+    id: ResourceId,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct QuerySet {
+    id: ResourceId,
+    ty: crate::QueryType,
+    count: u32,
+}
+
+pub struct BindlessTable {
+    id: ResourceId,
+    layout: crate::ShaderDataLayout,
+    slots: crate::bindless::BindlessSlots,
+    /// Resources in the occupied slots, checked when the table is bound.
+    resources: HashMap<crate::BindlessHandle, ResourceId>,
+}
+
+impl BindlessTable {
+    /// Check that the handle refers to a resource that is still in the table.
+    pub fn contains(&self, handle: crate::BindlessHandle) -> bool {
+        self.slots.contains(handle)
+    }
+}
+
+struct PipelineInner {
+    id: ResourceId,
+    name: String,
+    group_layouts: Box<[crate::ShaderDataLayout]>,
+    /// Stages that access each group. Groups nobody uses don't have to be bound.
+    group_visibility: Box<[crate::ShaderVisibility]>,
+    /// Shader structs of the plain data in each group, checked against the host structs.
+    group_plain_structs: Box<[Box<[Option<crate::StructReflection>]>]>,
+    vertex_buffer_count: usize,
+    /// Vertex buffers read by the vertex shader, which have to be bound.
+    used_vertex_buffers: u32,
+}
+
+pub struct ComputePipeline {
+    inner: PipelineInner,
+    wg_size: [u32; 3],
+}
+
+impl ComputePipeline {
+    pub fn get_workgroup_size(&self) -> [u32; 3] {
+        self.wg_size
+    }
+}
+
+pub struct RenderPipeline {
+    inner: PipelineInner,
+    color_target_count: usize,
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    texture: Texture,
+    view: TextureView,
+}
+
+impl Frame {
+    pub fn texture(&self) -> Texture {
+        self.texture
+    }
+
+    pub fn texture_view(&self) -> TextureView {
+        self.view
+    }
+}
+
+/// Commands that have an effect on host memory, executed at submission.
+#[derive(Debug)]
+enum Command {
+    FillBuffer {
+        dst: crate::BufferPiece,
+        size: u64,
+        value: u8,
+    },
+    CopyBufferToBuffer {
+        src: crate::BufferPiece,
+        dst: crate::BufferPiece,
+        size: u64,
+    },
+}
+
+pub struct CommandEncoder {
+    name: String,
+    command_type: crate::CommandType,
+    registry: Arc<Mutex<Registry>>,
+    commands: Vec<Command>,
+    started: bool,
+    timing: bool,
+    /// Scopes are recorded even without timing, to validate them.
+    scopes: crate::timing::ScopeRecorder,
+    timings: crate::Timings,
+    pass_count: u32,
+    stats: crate::CommandStats,
+    /// Statistics query that is begun and not ended yet.
+    statistics_query: Option<(QuerySet, u32)>,
+    trace: Option<crate::trace::EncoderTrace>,
+}
+
+/// Occlusion queries of a render pass.
+#[derive(Default)]
+struct OcclusionState {
+    set: Option<QuerySet>,
+    active: Option<u32>,
+}
+
+pub struct PassEncoder<'a, P> {
+    commands: &'a mut Vec<Command>,
+    registry: &'a Mutex<Registry>,
+    color_target_count: usize,
+    occlusion: OcclusionState,
+    timing: bool,
+    scopes: &'a mut crate::timing::ScopeRecorder,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
+    pipeline: PhantomData<P>,
+}
+
+pub type TransferCommandEncoder<'a> = PassEncoder<'a, ()>;
+pub type AccelerationStructureCommandEncoder<'a> = PassEncoder<'a, AccelerationStructure>;
+pub type ComputeCommandEncoder<'a> = PassEncoder<'a, ComputePipeline>;
+pub type RenderCommandEncoder<'a> = PassEncoder<'a, RenderPipeline>;
+
+pub struct ParallelRenderCommandEncoder<'a> {
+    pass: RenderCommandEncoder<'a>,
+    registry: Arc<Mutex<Registry>>,
+    label: String,
+    target_size: [u16; 2],
+    chunks: Vec<Option<RenderChunk>>,
+}
+
+pub struct RenderChunk {
+    index: usize,
+    registry: Arc<Mutex<Registry>>,
+    color_target_count: usize,
+    occlusion: OcclusionState,
+    trace: Option<crate::trace::EncoderTrace>,
+}
+
+pub struct PipelineEncoder<'a> {
+    registry: &'a Mutex<Registry>,
+    pipeline: &'a PipelineInner,
+    bound_groups: u32,
+    bound_vertex_buffers: u32,
+    /// Only set for render pipelines.
+    occlusion: Option<&'a mut OcclusionState>,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
+}
+
+pub struct PipelineContext<'a> {
+    resources: &'a mut Vec<ResourceId>,
+    /// Kinds of the bound resources, to be checked against the pipeline layout.
+    bindings: &'a mut Vec<(u32, crate::ShaderBinding)>,
+    captured: Option<&'a mut Vec<(u32, crate::trace::Bound)>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SyncPoint {}
+
+impl Context {
+    pub fn init(desc: crate::ContextDesc) -> Result<Self, crate::NotSupportedError> {
+        Ok(Self {
+            registry: Default::default(),
+            timing: desc.timing,
+            device_information: crate::DeviceInformation {
+                is_software_emulated: true,
+                device_name: "Null".to_string(),
+                driver_name: "blade-null".to_string(),
+                driver_info: String::new(),
+            },
+            trace: crate::trace::Recorder::from_desc(&desc),
+            tracker: crate::tracking::Tracker::from_desc(&desc),
+        })
+    }
+
+    pub fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities {
+            ray_query: crate::ShaderVisibility::empty(),
+            sample_count_mask: 0x1 | 0x4,
+            dual_source_blending: true,
+            precise_occlusion: true,
+            pipeline_statistics: true,
+            max_bindless_resources: MAX_BINDLESS_RESOURCES,
+        }
+    }
+
+    pub fn device_information(&self) -> &crate::DeviceInformation {
+        &self.device_information
+    }
+
+    /// Usages supported by textures of the given format.
+    /// Follows what a typical desktop GPU offers.
+    pub fn texture_format_usage(&self, format: crate::TextureFormat) -> crate::TextureUsage {
+        use crate::{TextureFormat as Tf, TextureUsage as Tu};
+        if format.block_info().dimensions != (1, 1) {
+            return Tu::COPY | Tu::RESOURCE;
+        }
+        if !format.aspects().contains(crate::TexelAspects::COLOR) {
+            return Tu::COPY | Tu::TARGET | Tu::RESOURCE;
+        }
+        match format {
+            Tf::Rgb9e5Ufloat => Tu::COPY | Tu::RESOURCE,
+            // No views of other formats to write sRGB textures through
+            Tf::Rgba8UnormSrgb | Tf::Bgra8UnormSrgb => Tu::COPY | Tu::TARGET | Tu::RESOURCE,
+            _ => Tu::all(),
+        }
+    }
+
+    /// Return the resources that are currently alive, in the order of creation.
+    /// Empty unless `ContextDesc::track_resources` is enabled.
+    pub fn live_resources(&self) -> Vec<crate::LiveResource> {
+        self.tracker
+            .as_ref()
+            .map_or_else(Vec::new, crate::tracking::Tracker::snapshot)
+    }
+
+    /// Return the API misuse reported since the last call.
+    pub fn take_validation_errors(&self) -> Vec<String> {
+        std::mem::take(&mut self.lock().errors)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap()
+    }
+
+    pub fn create_surface<
+        I: raw_window_handle::HasWindowHandle + raw_window_handle::HasDisplayHandle,
+    >(
+        &self,
+        _window: &I,
+    ) -> Result<Surface, crate::NotSupportedError> {
+        Ok(Surface {
+            registry: Arc::clone(&self.registry),
+            frame: None,
+            info: crate::SurfaceInfo {
+                format: crate::TextureFormat::Bgra8Unorm,
+                alpha: crate::AlphaMode::Ignored,
+            },
+            trace: self.trace.clone(),
+        })
+    }
+
+    pub fn destroy_surface(&self, surface: &mut Surface) {
+        if let Some(frame) = surface.frame.take() {
+            let mut registry = self.lock();
+            registry.remove(frame.view.id);
+            registry.remove(frame.texture.id);
+        }
+    }
+
+    pub fn reconfigure_surface(&self, surface: &mut Surface, config: crate::SurfaceConfig) {
+        self.destroy_surface(surface);
+        let format = match config.color_space {
+            crate::ColorSpace::Linear => crate::TextureFormat::Bgra8UnormSrgb,
+            crate::ColorSpace::Srgb => crate::TextureFormat::Bgra8Unorm,
+        };
+        let mut registry = self.lock();
+        let texture = Texture {
+            id: registry.add(ResourceKind::Texture, "frame", None),
+            format,
+            size: config.size,
+        };
+        let view = TextureView {
+            id: registry.add(ResourceKind::TextureView, "frame", Some(texture.id)),
+            target_size: [config.size.width as u16, config.size.height as u16],
+            aspects: crate::TexelAspects::COLOR,
+        };
+        surface.frame = Some(Frame { texture, view });
+        surface.info = crate::SurfaceInfo {
+            format,
+            alpha: if config.transparent {
+                crate::AlphaMode::PostMultiplied
+            } else {
+                crate::AlphaMode::Ignored
+            },
+        };
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.report_leaks();
+        }
+    }
+}
+
+impl Surface {
+    pub fn info(&self) -> crate::SurfaceInfo {
+        self.info
+    }
+
+    pub fn acquire_frame(&mut self) -> Frame {
+        let frame = self.frame.as_ref().expect("Surface is not configured");
+        // Contents of a freshly acquired image are undefined
+        self.registry
+            .lock()
+            .unwrap()
+            .set_initialized(frame.texture.id, false);
+        if let Some(ref trace) = self.trace {
+            let texture = frame.texture;
+            trace.acquire_frame(texture, frame.view, texture.format, texture.size);
+        }
+        Frame {
+            texture: frame.texture,
+            view: frame.view,
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::CommandDevice for Context {
+    type CommandEncoder = CommandEncoder;
+    type SyncPoint = SyncPoint;
+
+    fn create_command_encoder(&self, desc: crate::CommandEncoderDesc) -> CommandEncoder {
+        CommandEncoder {
+            name: desc.name.to_string(),
+            command_type: desc.command_type,
+            registry: Arc::clone(&self.registry),
+            commands: Vec::new(),
+            started: false,
+            timing: self.timing,
+            scopes: Default::default(),
+            timings: Default::default(),
+            pass_count: 0,
+            stats: Default::default(),
+            statistics_query: None,
+            trace: self.trace.as_ref().map(|trace| trace.encoder(desc.name)),
+        }
+    }
+
+    fn destroy_command_encoder(&self, _encoder: &mut CommandEncoder) {}
+
+    fn submit(&self, encoder: &mut CommandEncoder) -> SyncPoint {
+        let mut registry = self.lock();
+        if !encoder.started {
+            registry.report(format!(
+                "Command encoder '{}' is submitted without being started",
+                encoder.name
+            ));
+        }
+        encoder.started = false;
+        if let Some((set, index)) = encoder.statistics_query.take() {
+            let message = format!(
+                "Query {} of {} is not ended in command encoder '{}'",
+                index,
+                registry.describe(set.id),
+                encoder.name
+            );
+            registry.report(message);
+        }
+        let (_, unended) = encoder.scopes.finish();
+        for name in unended {
+            registry.report(format!(
+                "Timing scope '{}' is not ended in command encoder '{}'",
+                name, encoder.name
+            ));
+        }
+        if let Some(ref mut trace) = encoder.trace {
+            trace.submit();
+        }
+        for command in encoder.commands.drain(..) {
+            log::trace!("{:?}", command);
+            command.execute(&mut registry);
+        }
+        if encoder.timing {
+            // Nothing takes any time on the GPU, and the queries grow like on the real backends
+            let required = encoder.scopes.required_query_count();
+            let timestamps = vec![0; encoder.scopes.query_count() as usize];
+            encoder.timings = encoder.scopes.resolve(&timestamps);
+            encoder.scopes.grow(required);
+        } else {
+            encoder.scopes.reset();
+        }
+        encoder.stats = crate::CommandStats {
+            passes: mem::take(&mut encoder.pass_count),
+            ..Default::default()
+        };
+        // There is no asynchronous work, everything is done by now
+        SyncPoint {}
+    }
+
+    fn wait_for(&self, _sp: &SyncPoint, _timeout_ms: u32) -> bool {
+        true
+    }
+}
@@ -0,0 +1,222 @@
+impl super::Context {
+    /// Resolve the bindings of all the stages the same way the real backends do,
+    /// then validate each stage as a standalone module with the bindings in place.
+    fn create_pipeline(
+        &self,
+        shaders: &[crate::ShaderFunction],
+        group_layouts: &[&crate::ShaderDataLayout],
+        vertex_fetch_states: &[crate::VertexFetchState],
+        name: &str,
+    ) -> Result<(super::PipelineInner, Vec<naga::Module>), crate::Error> {
+        let mut group_infos = group_layouts
+            .iter()
+            .map(|layout| layout.to_info())
+            .collect::<Vec<_>>();
+        let mut modules = Vec::with_capacity(shaders.len());
+        let mut used_vertex_buffers = 0;
+
+        for &sf in shaders {
+            let ep_index = sf.entry_point_index()?;
+            let ep = &sf.shader.module.entry_points[ep_index];
+            let ep_info = sf.shader.info.get_entry_point(ep_index);
+
+            let (mut module, _) = sf.shader.try_resolve_constants(sf.constants)?;
+            crate::Shader::fill_resource_bindings(
+                &mut module,
+                &mut group_infos,
+                ep.stage,
+                ep_info,
+                group_layouts,
+            )?;
+            let attribute_mappings =
+                crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states)?;
+            for mapping in attribute_mappings.iter() {
+                used_vertex_buffers |= 1 << mapping.buffer_index;
+            }
+
+            // Other entry points may use globals that are not bound by this pipeline
+            let mut index = 0;
+            module.entry_points.retain(|_| {
+                index += 1;
+                index - 1 == ep_index
+            });
+            naga::compact::compact(&mut module, naga::compact::KeepUnused::No);
+
+            let device_caps = self.capabilities();
+            let mut caps = naga::valid::Capabilities::empty();
+            caps.set(
+                naga::valid::Capabilities::RAY_QUERY
+                    | naga::valid::Capabilities::SAMPLER_NON_UNIFORM_INDEXING,
+                !device_caps.ray_query.is_empty(),
+            );
+            caps.set(
+                naga::valid::Capabilities::DUAL_SOURCE_BLENDING,
+                device_caps.dual_source_blending,
+            );
+            if device_caps.max_bindless_resources != 0 {
+                caps |= naga::valid::Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                    | naga::valid::Capabilities::SAMPLER_NON_UNIFORM_INDEXING;
+            }
+            if let Err(e) = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), caps)
+                .validate(&module)
+            {
+                // SPIR-V shaders have no text source for the spans
+                let details = match sf.shader.source_kind {
+                    crate::ShaderSourceKind::SpirV(_) => e.to_string(),
+                    _ => e.emit_to_string(&sf.shader.source),
+                };
+                return Err(crate::Error::InvalidShader(format!(
+                    "entry point '{}' is not valid\n{}",
+                    sf.entry_point, details
+                )));
+            }
+            modules.push(module);
+        }
+
+        let inner = super::PipelineInner {
+            id: self.lock().add(super::ResourceKind::Pipeline, name, None),
+            name: name.to_string(),
+            group_layouts: group_layouts.iter().map(|&layout| layout.clone()).collect(),
+            group_visibility: group_infos.iter().map(|info| info.visibility).collect(),
+            group_plain_structs: group_infos
+                .into_iter()
+                .map(|info| info.plain_structs)
+                .collect(),
+            vertex_buffer_count: vertex_fetch_states.len(),
+            used_vertex_buffers,
+        };
+        Ok((inner, modules))
+    }
+}
+
+/// Locations of the color outputs written by a fragment entry point.
+fn fragment_output_locations(module: &naga::Module) -> Vec<u32> {
+    let result = match module.entry_points[0].function.result {
+        Some(ref result) => result,
+        None => return Vec::new(),
+    };
+    let location = |binding: &Option<naga::Binding>| match *binding {
+        Some(naga::Binding::Location { location, .. }) => Some(location),
+        _ => None,
+    };
+    match module.types[result.ty].inner {
+        naga::TypeInner::Struct { ref members, .. } => members
+            .iter()
+            .filter_map(|member| location(&member.binding))
+            .collect(),
+        _ => location(&result.binding).into_iter().collect(),
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::ShaderDevice for super::Context {
+    type ComputePipeline = super::ComputePipeline;
+    type RenderPipeline = super::RenderPipeline;
+
+    fn try_create_compute_pipeline(
+        &self,
+        desc: crate::ComputePipelineDesc,
+    ) -> Result<super::ComputePipeline, crate::Error> {
+        let (inner, modules) =
+            self.create_pipeline(&[desc.compute], desc.data_layouts, &[], desc.name)?;
+        if let Some(ref trace) = self.trace {
+            trace.create_compute_pipeline(&desc, inner.id.0 as u64);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(
+                crate::tracking::Handle::ComputePipeline(inner.id.0 as u64),
+                desc.name,
+                0,
+            );
+        }
+        Ok(super::ComputePipeline {
+            inner,
+            wg_size: modules[0].entry_points[0].workgroup_size,
+        })
+    }
+
+    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
+        let name = desc.name;
+        self.try_create_compute_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create compute pipeline '{name}': {e}"))
+    }
+
+    fn destroy_compute_pipeline(&self, pipeline: &mut super::ComputePipeline) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_compute_pipeline(pipeline.inner.id.0 as u64);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(crate::tracking::Handle::ComputePipeline(
+                pipeline.inner.id.0 as u64,
+            ));
+        }
+        self.lock().remove(pipeline.inner.id);
+    }
+
+    fn try_create_render_pipeline(
+        &self,
+        desc: crate::RenderPipelineDesc,
+    ) -> Result<super::RenderPipeline, crate::Error> {
+        if let Some(ref ds) = desc.depth_stencil
+            && (ds.format.aspects().is_empty()
+                || ds.format.aspects().contains(crate::TexelAspects::COLOR))
+        {
+            return Err(crate::Error::UnsupportedFormat(ds.format));
+        }
+        if desc.multisample_state.sample_count & self.capabilities().sample_count_mask == 0 {
+            return Err(crate::Error::Device(format!(
+                "Unsupported sample count {}",
+                desc.multisample_state.sample_count
+            )));
+        }
+
+        let mut shaders = vec![desc.vertex];
+        shaders.extend(desc.fragment);
+        let (inner, modules) =
+            self.create_pipeline(&shaders, desc.data_layouts, desc.vertex_fetches, desc.name)?;
+        if let Some(module) = modules.get(1)
+            && let Some(location) = fragment_output_locations(module)
+                .into_iter()
+                .find(|&location| location as usize >= desc.color_targets.len())
+        {
+            self.lock().remove(inner.id);
+            return Err(crate::Error::InvalidShader(format!(
+                "fragment output location {} has no color target",
+                location
+            )));
+        }
+
+        if let Some(ref trace) = self.trace {
+            trace.create_render_pipeline(&desc, inner.id.0 as u64);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(
+                crate::tracking::Handle::RenderPipeline(inner.id.0 as u64),
+                desc.name,
+                0,
+            );
+        }
+        Ok(super::RenderPipeline {
+            inner,
+            color_target_count: desc.color_targets.len(),
+        })
+    }
+
+    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
+        let name = desc.name;
+        self.try_create_render_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create render pipeline '{name}': {e}"))
+    }
+
+    fn destroy_render_pipeline(&self, pipeline: &mut super::RenderPipeline) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_render_pipeline(pipeline.inner.id.0 as u64);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(crate::tracking::Handle::RenderPipeline(
+                pipeline.inner.id.0 as u64,
+            ));
+        }
+        self.lock().remove(pipeline.inner.id);
+    }
+}
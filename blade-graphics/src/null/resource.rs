@@ -0,0 +1,391 @@
+use std::ptr;
+
+use super::ResourceKind as Rk;
+
+impl super::Context {
+    pub fn get_bottom_level_acceleration_structure_sizes(
+        &self,
+        meshes: &[crate::AccelerationStructureMesh],
+    ) -> crate::AccelerationStructureSizes {
+        // Nothing is built, but the sizes should still grow with the input
+        let triangles = meshes
+            .iter()
+            .map(|mesh| mesh.triangle_count as u64)
+            .sum::<u64>();
+        crate::AccelerationStructureSizes {
+            data: 64 * triangles.max(1),
+            scratch: 16 * triangles.max(1),
+        }
+    }
+
+    pub fn get_top_level_acceleration_structure_sizes(
+        &self,
+        instance_count: u32,
+    ) -> crate::AccelerationStructureSizes {
+        crate::AccelerationStructureSizes {
+            data: 64 * instance_count.max(1) as u64,
+            scratch: 16 * instance_count.max(1) as u64,
+        }
+    }
+
+    pub fn create_acceleration_structure_instance_buffer(
+        &self,
+        instances: &[crate::AccelerationStructureInstance],
+        bottom_level: &[super::AccelerationStructure],
+    ) -> super::Buffer {
+        let mut registry = self.lock();
+        for instance in instances {
+            match bottom_level.get(instance.acceleration_structure_index as usize) {
+                Some(blas) => {
+                    registry.check(blas.id, "an instance buffer");
+                }
+                None => registry.report(format!(
+                    "Instance refers to bottom level acceleration structure {}, but only {} are given",
+                    instance.acceleration_structure_index,
+                    bottom_level.len()
+                )),
+            }
+        }
+        drop(registry);
+        self.create_buffer(crate::BufferDesc {
+            name: "instance buffer",
+            size: (instances.len().max(1) * size_of::<crate::AccelerationStructureInstance>())
+                as u64,
+            memory: crate::Memory::Shared,
+        })
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::QueryDevice for super::Context {
+    type QuerySet = super::QuerySet;
+    type SyncPoint = super::SyncPoint;
+
+    fn create_query_set(&self, desc: crate::QuerySetDesc) -> super::QuerySet {
+        let set = super::QuerySet {
+            id: self.lock().add(Rk::QuerySet, desc.name, None),
+            ty: desc.ty,
+            count: desc.count,
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(set, desc.name, crate::tracking::query_set_size(&desc));
+        }
+        set
+    }
+
+    fn destroy_query_set(&self, set: super::QuerySet) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(set);
+        }
+        self.lock().remove(set.id);
+    }
+
+    fn read_query_results(
+        &self,
+        set: super::QuerySet,
+        _sync_point: &super::SyncPoint,
+    ) -> Option<Vec<u64>> {
+        self.lock().check(set.id, "`read_query_results`");
+        // Nothing is drawn, so nothing passes or gets invoked
+        Some(vec![0; set.count as usize * set.ty.value_count()])
+    }
+}
+
+impl super::Context {
+    fn insert_bindless(
+        &self,
+        table: &mut super::BindlessTable,
+        kind: crate::BindlessKind,
+        id: super::ResourceId,
+    ) -> crate::BindlessHandle {
+        let mut registry = self.lock();
+        let usage = format!(
+            "bindless table '{}'",
+            registry.records[table.id.0 as usize].name
+        );
+        registry.check(table.id, "an insertion");
+        registry.check(id, &usage);
+        // Submissions are executed right away, so the freed slots are retired already
+        let handle = table.slots.insert(kind, |_, _| true);
+        table.resources.insert(handle, id);
+        handle
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::BindlessDevice for super::Context {
+    type BindlessTable = super::BindlessTable;
+    type TextureView = super::TextureView;
+    type Sampler = super::Sampler;
+
+    fn create_bindless_table(&self, desc: crate::BindlessTableDesc) -> super::BindlessTable {
+        for &(_, kind) in crate::BindlessTableDesc::BINDINGS.iter() {
+            assert!(
+                desc.count(kind) <= super::MAX_BINDLESS_RESOURCES,
+                "Bindless table {:?} count {} is over the limit of {}",
+                kind,
+                desc.count(kind),
+                super::MAX_BINDLESS_RESOURCES
+            );
+        }
+        super::BindlessTable {
+            id: self.lock().add(Rk::BindlessTable, desc.name, None),
+            layout: desc.layout(),
+            slots: crate::bindless::BindlessSlots::new(&desc),
+            resources: Default::default(),
+        }
+    }
+
+    fn destroy_bindless_table(&self, table: &mut super::BindlessTable) {
+        table.resources.clear();
+        self.lock().remove(table.id);
+    }
+
+    fn insert_bindless_texture(
+        &self,
+        table: &mut super::BindlessTable,
+        view: super::TextureView,
+    ) -> crate::BindlessHandle {
+        self.insert_bindless(table, crate::BindlessKind::Texture, view.id)
+    }
+
+    fn insert_bindless_buffer(
+        &self,
+        table: &mut super::BindlessTable,
+        piece: crate::BufferPiece,
+    ) -> crate::BindlessHandle {
+        self.insert_bindless(table, crate::BindlessKind::Buffer, piece.buffer.id)
+    }
+
+    fn insert_bindless_sampler(
+        &self,
+        table: &mut super::BindlessTable,
+        sampler: super::Sampler,
+    ) -> crate::BindlessHandle {
+        self.insert_bindless(table, crate::BindlessKind::Sampler, sampler.id)
+    }
+
+    fn remove_bindless(&self, table: &mut super::BindlessTable, handle: crate::BindlessHandle) {
+        match table.slots.remove(handle, ()) {
+            Ok(()) => {
+                table.resources.remove(&handle);
+            }
+            Err(message) => self.lock().report(message),
+        }
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::ResourceDevice for super::Context {
+    type Buffer = super::Buffer;
+    type Texture = super::Texture;
+    type TextureView = super::TextureView;
+    type Sampler = super::Sampler;
+    type AccelerationStructure = super::AccelerationStructure;
+
+    fn try_create_buffer(&self, desc: crate::BufferDesc) -> Result<super::Buffer, crate::Error> {
+        let (storage, owned) = match desc.memory {
+            crate::Memory::External(crate::ExternalMemorySource::HostAllocation(address)) => {
+                (address as *mut u8, false)
+            }
+            _ => {
+                let mut data = Vec::new();
+                data.try_reserve_exact(desc.size as usize)
+                    .map_err(|_| crate::Error::OutOfMemory)?;
+                data.resize(desc.size as usize, 0u8);
+                (Box::into_raw(data.into_boxed_slice()) as *mut u8, true)
+            }
+        };
+        let id = self.lock().add(Rk::Buffer, desc.name, None);
+        let buffer = super::Buffer {
+            id,
+            size: desc.size,
+            storage,
+            data: if desc.memory.is_host_visible() {
+                storage
+            } else {
+                ptr::null_mut()
+            },
+            owned,
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_buffer(&desc, buffer);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(buffer, desc.name, desc.size);
+        }
+        Ok(buffer)
+    }
+
+    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+        let name = desc.name;
+        self.try_create_buffer(desc)
+            .unwrap_or_else(|e| panic!("Unable to create buffer '{name}': {e}"))
+    }
+
+    fn sync_buffer(&self, buffer: super::Buffer) {
+        if self.lock().check(buffer.id, "`sync_buffer`")
+            && let Some(ref trace) = self.trace
+        {
+            trace.sync_buffer(buffer);
+        }
+    }
+
+    fn destroy_buffer(&self, buffer: super::Buffer) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_buffer(buffer);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(buffer);
+        }
+        let mut registry = self.lock();
+        let was_alive = registry.records[buffer.id.0 as usize].alive;
+        registry.remove(buffer.id);
+        if was_alive && buffer.owned {
+            let slice = ptr::slice_from_raw_parts_mut(buffer.storage, buffer.size as usize);
+            drop(unsafe { Box::from_raw(slice) });
+        }
+    }
+
+    fn try_create_texture(&self, desc: crate::TextureDesc) -> Result<super::Texture, crate::Error> {
+        if desc.sample_count & self.capabilities().sample_count_mask == 0
+            || !self.texture_format_usage(desc.format).contains(desc.usage)
+        {
+            return Err(crate::Error::UnsupportedFormat(desc.format));
+        }
+        let mut registry = self.lock();
+        if desc.usage.is_empty() {
+            registry.report(format!("Texture '{}' has no usage", desc.name));
+        }
+        if desc.mip_level_count == 0 || desc.mip_level_count > desc.size.max_mip_levels() + 1 {
+            registry.report(format!(
+                "Texture '{}' of size {} can't have {} mip levels",
+                desc.name, desc.size, desc.mip_level_count
+            ));
+        }
+        let texture = super::Texture {
+            id: registry.add(Rk::Texture, desc.name, None),
+            format: desc.format,
+            size: desc.size,
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_texture(&desc, texture);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(texture, desc.name, crate::tracking::texture_size(&desc));
+        }
+        Ok(texture)
+    }
+
+    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+        let name = desc.name;
+        self.try_create_texture(desc)
+            .unwrap_or_else(|e| panic!("Unable to create texture '{name}': {e}"))
+    }
+
+    fn destroy_texture(&self, texture: super::Texture) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_texture(texture);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(texture);
+        }
+        self.lock().remove(texture.id);
+    }
+
+    fn create_texture_view(
+        &self,
+        texture: super::Texture,
+        desc: crate::TextureViewDesc,
+    ) -> super::TextureView {
+        let mut registry = self.lock();
+        if !registry.records[texture.id.0 as usize].alive {
+            let message = format!(
+                "View '{}' is created for destroyed {}",
+                desc.name,
+                registry.describe(texture.id)
+            );
+            registry.report(message);
+        }
+        if texture.format.aspects() == crate::TexelAspects::COLOR
+            && desc.format.block_info().size != texture.format.block_info().size
+        {
+            registry.report(format!(
+                "View '{}' format {:?} is not compatible with texture format {:?}",
+                desc.name, desc.format, texture.format
+            ));
+        }
+        let mip_size = texture.size.at_mip_level(desc.subresources.base_mip_level);
+        let view = super::TextureView {
+            id: registry.add(Rk::TextureView, desc.name, Some(texture.id)),
+            target_size: [mip_size.width as u16, mip_size.height as u16],
+            aspects: desc.format.aspects(),
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_texture_view(texture, &desc, view);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(view, desc.name, 0);
+        }
+        view
+    }
+
+    fn destroy_texture_view(&self, view: super::TextureView) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_texture_view(view);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(view);
+        }
+        self.lock().remove(view.id);
+    }
+
+    fn create_sampler(&self, desc: crate::SamplerDesc) -> super::Sampler {
+        let sampler = super::Sampler {
+            id: self.lock().add(Rk::Sampler, desc.name, None),
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_sampler(&desc, sampler);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(sampler, desc.name, 0);
+        }
+        sampler
+    }
+
+    fn destroy_sampler(&self, sampler: super::Sampler) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_sampler(sampler);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(sampler);
+        }
+        self.lock().remove(sampler.id);
+    }
+
+    fn create_acceleration_structure(
+        &self,
+        desc: crate::AccelerationStructureDesc,
+    ) -> super::AccelerationStructure {
+        let acceleration_structure = super::AccelerationStructure {
+            id: self.lock().add(Rk::AccelerationStructure, desc.name, None),
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_acceleration_structure(&desc, acceleration_structure);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(acceleration_structure, desc.name, desc.size);
+        }
+        acceleration_structure
+    }
+
+    fn destroy_acceleration_structure(&self, acceleration_structure: super::AccelerationStructure) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_acceleration_structure(acceleration_structure);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(acceleration_structure);
+        }
+        self.lock().remove(acceleration_structure.id);
+    }
+}
@@ -0,0 +1,559 @@
+//! Reflection of the shader interface.
+//!
+//! Allows building the data and vertex layouts at runtime, as well as
+//! validating the layouts of host types against the shader.
+
+use std::fmt;
+
+/// Resource variable of a shader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlobalReflection {
+    pub name: String,
+    /// Binding kind, as expected by `ShaderDataLayout`.
+    /// Uniform variables are reported as `Plain`, but they can also be bound as `Buffer`.
+    pub binding: crate::ShaderBinding,
+    pub access: crate::StorageAccess,
+    /// Name of the variable type, if it's a named struct.
+    pub type_name: Option<String>,
+}
+
+/// Input of a vertex shader, fetched from a vertex buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexInputReflection {
+    pub name: String,
+    /// Format matching the input type, if there is one.
+    pub format: Option<crate::VertexFormat>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryPointReflection {
+    pub name: String,
+    pub stage: crate::ShaderStage,
+    /// Workgroup size, only non-zero for compute shaders.
+    pub workgroup_size: [u32; 3],
+    /// Indices of the globals used by the entry point.
+    pub globals: Vec<usize>,
+    pub vertex_inputs: Vec<VertexInputReflection>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructMemberReflection {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+    /// Kind of the scalars in the member, or `None` for nested structs.
+    pub kind: Option<crate::ScalarKind>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructReflection {
+    pub name: String,
+    pub size: u32,
+    pub members: Vec<StructMemberReflection>,
+}
+
+/// Difference between a host struct and a shader struct.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldMismatch {
+    /// The shader has no struct with this name.
+    MissingStruct {
+        name: String,
+    },
+    /// Layout of the shader types couldn't be computed.
+    Layout {
+        error: String,
+    },
+    /// Shader struct member isn't present in the host struct.
+    Missing {
+        name: String,
+    },
+    /// Host struct field isn't present in the shader struct.
+    Unexpected {
+        name: String,
+    },
+    Offset {
+        name: String,
+        host: u32,
+        shader: u32,
+    },
+    Size {
+        name: String,
+        host: u32,
+        shader: u32,
+    },
+    Kind {
+        name: String,
+        host: Option<crate::ScalarKind>,
+        shader: Option<crate::ScalarKind>,
+    },
+    StructSize {
+        host: u32,
+        shader: u32,
+    },
+}
+
+impl fmt::Display for FieldMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::MissingStruct { ref name } => {
+                write!(f, "struct '{name}' is not found in the shader")
+            }
+            Self::Layout { ref error } => write!(f, "shader layout failed: {error}"),
+            Self::Missing { ref name } => write!(f, "field '{name}' is missing in the host struct"),
+            Self::Unexpected { ref name } => {
+                write!(f, "field '{name}' is not present in the shader struct")
+            }
+            Self::Offset {
+                ref name,
+                host,
+                shader,
+            } => write!(
+                f,
+                "field '{name}' is at offset {host}, but the shader expects {shader}"
+            ),
+            Self::Size {
+                ref name,
+                host,
+                shader,
+            } => write!(
+                f,
+                "field '{name}' has size {host}, but the shader expects {shader}"
+            ),
+            Self::Kind {
+                ref name,
+                host,
+                shader,
+            } => {
+                let describe = |kind: Option<crate::ScalarKind>| match kind {
+                    Some(kind) => format!("{kind:?} scalars"),
+                    None => "a struct".to_string(),
+                };
+                write!(
+                    f,
+                    "field '{name}' has {}, but the shader expects {}",
+                    describe(host),
+                    describe(shader)
+                )
+            }
+            Self::StructSize { host, shader } => {
+                write!(f, "struct has size {host}, but the shader expects {shader}")
+            }
+        }
+    }
+}
+
+impl StructReflection {
+    /// Compare a host struct against this struct, reporting every field that doesn't match.
+    pub fn check_layout(&self, host: &crate::StructLayout) -> Result<(), Vec<FieldMismatch>> {
+        let mut mismatches = Vec::new();
+        for member in self.members.iter() {
+            let field = match host.fields.iter().find(|field| field.name == member.name) {
+                Some(field) => field,
+                None => {
+                    mismatches.push(FieldMismatch::Missing {
+                        name: member.name.clone(),
+                    });
+                    continue;
+                }
+            };
+            if field.offset != member.offset {
+                mismatches.push(FieldMismatch::Offset {
+                    name: member.name.clone(),
+                    host: field.offset,
+                    shader: member.offset,
+                });
+            }
+            if field.size != member.size {
+                mismatches.push(FieldMismatch::Size {
+                    name: member.name.clone(),
+                    host: field.size,
+                    shader: member.size,
+                });
+            }
+            if field.kind != member.kind {
+                mismatches.push(FieldMismatch::Kind {
+                    name: member.name.clone(),
+                    host: field.kind,
+                    shader: member.kind,
+                });
+            }
+        }
+        for field in host.fields.iter() {
+            if !field.name.starts_with('_')
+                && !self.members.iter().any(|member| member.name == field.name)
+            {
+                mismatches.push(FieldMismatch::Unexpected {
+                    name: field.name.to_string(),
+                });
+            }
+        }
+        if host.size != self.size {
+            mismatches.push(FieldMismatch::StructSize {
+                host: host.size,
+                shader: self.size,
+            });
+        }
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatches)
+        }
+    }
+}
+
+/// Check the host structs of the plain data in a group against the shader structs,
+/// given the shader struct of each binding.
+pub(crate) fn check_plain_data<D: crate::ShaderData>(
+    plain_structs: &[Option<StructReflection>],
+) -> Result<(), String> {
+    use std::fmt::Write as _;
+    for (index, host) in D::plain_layouts() {
+        let shader_struct = match plain_structs.get(index as usize).and_then(Option::as_ref) {
+            Some(shader_struct) => shader_struct,
+            None => continue,
+        };
+        if let Err(mismatches) = shader_struct.check_layout(&host) {
+            let mut message = format!(
+                "Host data of binding {} doesn't match struct '{}' of the shader:",
+                index, shader_struct.name
+            );
+            for mismatch in mismatches {
+                let _ = write!(message, "\n\t{}", mismatch);
+            }
+            return Err(message);
+        }
+    }
+    Ok(())
+}
+
+/// Interface of a shader, see `Shader::reflect`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPointReflection>,
+    /// Resource variables in the order of declaration.
+    pub globals: Vec<GlobalReflection>,
+    /// Named structs in the order of declaration.
+    pub structs: Vec<StructReflection>,
+}
+
+/// Layouts refer to the names with a static lifetime,
+/// so the layouts built at runtime keep them forever.
+fn leak(name: &str) -> &'static str {
+    Box::leak(name.to_string().into_boxed_str())
+}
+
+impl EntryPointReflection {
+    /// Build a vertex layout with all the inputs tightly packed in one buffer.
+    /// Returns `None` if any of the inputs doesn't have a matching vertex format.
+    ///
+    /// The attribute names are leaked, so this is meant to be called once per pipeline.
+    pub fn vertex_layout(&self) -> Option<crate::VertexLayout> {
+        let mut layout = crate::VertexLayout::default();
+        for input in self.vertex_inputs.iter() {
+            let format = input.format?;
+            layout.attributes.push((
+                leak(&input.name),
+                crate::VertexAttribute {
+                    offset: layout.stride,
+                    format,
+                },
+            ));
+            layout.stride += format.size();
+        }
+        Some(layout)
+    }
+}
+
+impl ShaderReflection {
+    pub fn entry_point(&self, name: &str) -> Option<&EntryPointReflection> {
+        self.entry_points.iter().find(|ep| ep.name == name)
+    }
+
+    pub fn find_struct(&self, name: &str) -> Option<&StructReflection> {
+        self.structs.iter().find(|st| st.name == name)
+    }
+
+    /// Build a data layout with all the globals used by the given entry points.
+    ///
+    /// The binding names are leaked, so this is meant to be called once per pipeline.
+    pub fn data_layout(&self, entry_points: &[&str]) -> crate::ShaderDataLayout {
+        let mut used = vec![false; self.globals.len()];
+        for ep in self.entry_points.iter() {
+            if entry_points.contains(&ep.name.as_str()) {
+                for &index in ep.globals.iter() {
+                    used[index] = true;
+                }
+            }
+        }
+        crate::ShaderDataLayout {
+            bindings: self
+                .globals
+                .iter()
+                .zip(used)
+                .filter(|&(_, used)| used)
+                .map(|(global, _)| (leak(&global.name), global.binding))
+                .collect(),
+        }
+    }
+
+    /// Check that the bindings of a host layout match the kinds of the shader globals.
+    /// Bindings that the shader doesn't have are ignored.
+    pub fn check_data_layout(&self, layout: &crate::ShaderDataLayout) -> Result<(), crate::Error> {
+        for &(name, provided) in layout.bindings.iter() {
+            let global = match self.globals.iter().find(|global| global.name == name) {
+                Some(global) => global,
+                None => continue,
+            };
+            let matches = match (global.binding, provided) {
+                (crate::ShaderBinding::Plain { .. }, crate::ShaderBinding::Buffer) => {
+                    global.access.is_empty()
+                }
+                (
+                    crate::ShaderBinding::TextureArray { count: 0 },
+                    crate::ShaderBinding::TextureArray { .. },
+                )
+                | (
+                    crate::ShaderBinding::BufferArray { count: 0 },
+                    crate::ShaderBinding::BufferArray { .. },
+                ) => true,
+                (
+                    crate::ShaderBinding::TextureArray { count },
+                    crate::ShaderBinding::Bindless {
+                        kind: crate::BindlessKind::Texture,
+                        count: provided_count,
+                    },
+                )
+                | (
+                    crate::ShaderBinding::BufferArray { count },
+                    crate::ShaderBinding::Bindless {
+                        kind: crate::BindlessKind::Buffer,
+                        count: provided_count,
+                    },
+                )
+                | (
+                    crate::ShaderBinding::Bindless {
+                        kind: crate::BindlessKind::Sampler,
+                        count,
+                    },
+                    crate::ShaderBinding::Bindless {
+                        kind: crate::BindlessKind::Sampler,
+                        count: provided_count,
+                    },
+                ) => count == 0 || count == provided_count,
+                (expected, provided) => expected == provided,
+            };
+            if !matches {
+                return Err(crate::Error::BindingMismatch {
+                    name: name.to_string(),
+                    expected: global.binding,
+                    provided,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn vertex_format(inner: &naga::TypeInner) -> Option<crate::VertexFormat> {
+    use crate::VertexFormat as Vf;
+    use naga::{ScalarKind as Sk, VectorSize as Vs};
+    let (size, scalar) = match *inner {
+        naga::TypeInner::Scalar(scalar) => (None, scalar),
+        naga::TypeInner::Vector { size, scalar } => (Some(size), scalar),
+        _ => return None,
+    };
+    if scalar.width != 4 {
+        return None;
+    }
+    Some(match (scalar.kind, size) {
+        (Sk::Float, None) => Vf::F32,
+        (Sk::Float, Some(Vs::Bi)) => Vf::F32Vec2,
+        (Sk::Float, Some(Vs::Tri)) => Vf::F32Vec3,
+        (Sk::Float, Some(Vs::Quad)) => Vf::F32Vec4,
+        (Sk::Uint, None) => Vf::U32,
+        (Sk::Uint, Some(Vs::Bi)) => Vf::U32Vec2,
+        (Sk::Uint, Some(Vs::Tri)) => Vf::U32Vec3,
+        (Sk::Uint, Some(Vs::Quad)) => Vf::U32Vec4,
+        (Sk::Sint, None) => Vf::I32,
+        (Sk::Sint, Some(Vs::Bi)) => Vf::I32Vec2,
+        (Sk::Sint, Some(Vs::Tri)) => Vf::I32Vec3,
+        (Sk::Sint, Some(Vs::Quad)) => Vf::I32Vec4,
+        _ => return None,
+    })
+}
+
+fn reflect_global(
+    module: &naga::Module,
+    layouter: &naga::proc::Layouter,
+    var: &naga::GlobalVariable,
+) -> Option<GlobalReflection> {
+    let access = match var.space {
+        naga::AddressSpace::Storage { access } => access,
+        naga::AddressSpace::Uniform | naga::AddressSpace::Handle => naga::StorageAccess::empty(),
+        _ => return None,
+    };
+    let ty = &module.types[var.ty];
+    let (binding, access) = match ty.inner {
+        naga::TypeInner::Image {
+            class: naga::ImageClass::Storage { access, format: _ },
+            ..
+        } => (crate::ShaderBinding::Texture, access),
+        naga::TypeInner::Image { .. } => (crate::ShaderBinding::Texture, access),
+        naga::TypeInner::Sampler { .. } => (crate::ShaderBinding::Sampler, access),
+        naga::TypeInner::AccelerationStructure { vertex_return: _ } => {
+            (crate::ShaderBinding::AccelerationStructure, access)
+        }
+        naga::TypeInner::BindingArray { base, size } => {
+            let count = match size {
+                naga::ArraySize::Constant(count) => count.get(),
+                naga::ArraySize::Pending(_) | naga::ArraySize::Dynamic => 0,
+            };
+            let binding = match module.types[base].inner {
+                naga::TypeInner::Image { .. } => crate::ShaderBinding::TextureArray { count },
+                // Arrays of samplers are only available in bindless tables
+                naga::TypeInner::Sampler { .. } => crate::ShaderBinding::Bindless {
+                    kind: crate::BindlessKind::Sampler,
+                    count,
+                },
+                _ => crate::ShaderBinding::BufferArray { count },
+            };
+            (binding, access)
+        }
+        _ if var.space == naga::AddressSpace::Uniform => (
+            crate::ShaderBinding::Plain {
+                size: layouter[var.ty].size,
+            },
+            access,
+        ),
+        _ => (crate::ShaderBinding::Buffer, access),
+    };
+    Some(GlobalReflection {
+        name: var.name.clone().unwrap_or_default(),
+        binding,
+        access,
+        type_name: match ty.inner {
+            naga::TypeInner::Struct { .. } => ty.name.clone(),
+            _ => None,
+        },
+    })
+}
+
+fn reflect_vertex_inputs(
+    module: &naga::Module,
+    function: &naga::Function,
+) -> Vec<VertexInputReflection> {
+    let mut inputs = Vec::new();
+    for argument in function.arguments.iter() {
+        if argument.binding.is_some() {
+            continue;
+        }
+        match module.types[argument.ty].inner {
+            naga::TypeInner::Struct { ref members, .. } => {
+                for member in members.iter() {
+                    if member.binding.is_none() {
+                        inputs.push(VertexInputReflection {
+                            name: member.name.clone().unwrap_or_default(),
+                            format: vertex_format(&module.types[member.ty].inner),
+                        });
+                    }
+                }
+            }
+            ref other => inputs.push(VertexInputReflection {
+                name: argument.name.clone().unwrap_or_default(),
+                format: vertex_format(other),
+            }),
+        }
+    }
+    inputs
+}
+
+fn scalar_kind(
+    types: &naga::UniqueArena<naga::Type>,
+    ty: naga::Handle<naga::Type>,
+) -> Option<crate::ScalarKind> {
+    match types[ty].inner {
+        naga::TypeInner::Scalar(scalar)
+        | naga::TypeInner::Vector { scalar, .. }
+        | naga::TypeInner::Matrix { scalar, .. }
+        | naga::TypeInner::Atomic(scalar) => Some(scalar.kind),
+        naga::TypeInner::Array { base, .. } => scalar_kind(types, base),
+        _ => None,
+    }
+}
+
+/// Describe a named struct type, returning `None` for other types.
+pub(crate) fn reflect_struct(
+    types: &naga::UniqueArena<naga::Type>,
+    layouter: &naga::proc::Layouter,
+    ty: naga::Handle<naga::Type>,
+) -> Option<StructReflection> {
+    match types[ty].inner {
+        naga::TypeInner::Struct { ref members, span } => Some(StructReflection {
+            name: types[ty].name.clone()?,
+            size: span,
+            members: members
+                .iter()
+                .map(|member| StructMemberReflection {
+                    name: member.name.clone().unwrap_or_default(),
+                    offset: member.offset,
+                    size: layouter[member.ty].size,
+                    kind: scalar_kind(types, member.ty),
+                })
+                .collect(),
+        }),
+        _ => None,
+    }
+}
+
+impl crate::Shader {
+    /// Describe the interface of the shader: entry points, resources, and structs.
+    pub fn reflect(&self) -> ShaderReflection {
+        let module = &self.module;
+        let mut layouter = naga::proc::Layouter::default();
+        layouter.update(module.to_ctx()).unwrap();
+
+        let mut globals = Vec::new();
+        let mut global_indices = Vec::new();
+        for (handle, var) in module.global_variables.iter() {
+            if let Some(global) = reflect_global(module, &layouter, var) {
+                global_indices.push((handle, globals.len()));
+                globals.push(global);
+            }
+        }
+
+        let entry_points = module
+            .entry_points
+            .iter()
+            .enumerate()
+            .map(|(ep_index, ep)| {
+                let ep_info = self.info.get_entry_point(ep_index);
+                EntryPointReflection {
+                    name: ep.name.clone(),
+                    stage: ep.stage,
+                    workgroup_size: ep.workgroup_size,
+                    globals: global_indices
+                        .iter()
+                        .filter(|&&(handle, _)| !ep_info[handle].is_empty())
+                        .map(|&(_, index)| index)
+                        .collect(),
+                    vertex_inputs: match ep.stage {
+                        naga::ShaderStage::Vertex => reflect_vertex_inputs(module, &ep.function),
+                        _ => Vec::new(),
+                    },
+                }
+            })
+            .collect();
+
+        let structs = module
+            .types
+            .iter()
+            .filter_map(|(handle, _)| reflect_struct(&module.types, &layouter, handle))
+            .collect();
+
+        ShaderReflection {
+            entry_points,
+            globals,
+            structs,
+        }
+    }
+}
@@ -12,15 +12,136 @@ impl From<naga::ShaderStage> for super::ShaderVisibility {
     }
 }
 
+impl super::ShaderErrorLabel {
+    fn new(span: naga::Span, message: &str, source: &str) -> Option<Self> {
+        let range = span.to_range()?;
+        // Spans of SPIR-V shaders don't refer to a text source
+        source.get(range.clone())?;
+        let location = span.location(source);
+        Some(Self {
+            message: message.to_string(),
+            file: None,
+            range,
+            line: location.line_number,
+            column: location.line_position,
+        })
+    }
+}
+
+impl super::ShaderError {
+    fn from_parse(error: &naga::front::wgsl::ParseError, source: &str) -> Self {
+        Self {
+            stage: super::ShaderErrorStage::Parse,
+            message: error.message().to_string(),
+            labels: error
+                .labels()
+                .filter_map(|(span, message)| super::ShaderErrorLabel::new(span, message, source))
+                .collect(),
+            rendered: error.emit_to_string_with_path(source, ""),
+        }
+    }
+
+    fn from_glsl(errors: &naga::front::glsl::ParseErrors, source: &str) -> Self {
+        Self {
+            stage: super::ShaderErrorStage::Parse,
+            message: errors
+                .errors
+                .iter()
+                .map(|e| e.kind.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            labels: errors
+                .errors
+                .iter()
+                .filter_map(|e| super::ShaderErrorLabel::new(e.meta, "", source))
+                .collect(),
+            rendered: errors.emit_to_string(source),
+        }
+    }
+
+    fn from_spirv(error: &naga::front::spv::Error) -> Self {
+        Self {
+            stage: super::ShaderErrorStage::Parse,
+            message: error.to_string(),
+            labels: Vec::new(),
+            rendered: error.to_string(),
+        }
+    }
+
+    fn from_validation(error: &naga::WithSpan<naga::valid::ValidationError>, source: &str) -> Self {
+        let mut message = error.as_inner().to_string();
+        let mut cause = std::error::Error::source(error.as_inner());
+        while let Some(e) = cause {
+            message += &format!(": {}", e);
+            cause = e.source();
+        }
+        Self {
+            stage: super::ShaderErrorStage::Validation,
+            labels: error
+                .spans()
+                .filter_map(|&(span, ref message)| {
+                    super::ShaderErrorLabel::new(span, message, source)
+                })
+                .collect(),
+            rendered: if source.is_empty() {
+                message.clone()
+            } else {
+                error.emit_to_string_with_path(source, "")
+            },
+            message,
+        }
+    }
+}
+
+impl super::ShaderError {
+    /// Point the labels at the original files of a composed shader.
+    fn remap(mut self, source_map: &super::SourceMap, source: &str) -> Self {
+        for label in self.labels.iter_mut() {
+            if let Some(span) = source_map.map_span(source, label.range.clone()) {
+                label.file = Some(span.path.to_string());
+                label.range = span.range;
+                label.line = span.line;
+                label.column = span.column;
+            }
+        }
+        self.rendered = source_map.render(&self.message, &self.labels);
+        self
+    }
+}
+
 impl super::Context {
     pub fn try_create_shader(
         &self,
         desc: super::ShaderDesc,
-    ) -> Result<super::Shader, &'static str> {
-        let module = naga::front::wgsl::parse_str(desc.source).map_err(|e| {
-            eprintln!("{}", e.emit_to_string_with_path(desc.source, ""));
-            "compilation failed"
-        })?;
+    ) -> Result<super::Shader, super::ShaderError> {
+        let remap = |error: super::ShaderError| match desc.source_map {
+            Some(source_map) => error.remap(source_map, desc.source),
+            None => error,
+        };
+        let (module, source_kind) = match desc.language {
+            super::ShaderLanguage::Wgsl => {
+                let module = naga::front::wgsl::parse_str(desc.source)
+                    .map_err(|e| remap(super::ShaderError::from_parse(&e, desc.source)))?;
+                (module, super::ShaderSourceKind::Wgsl)
+            }
+            super::ShaderLanguage::Glsl { stage } => {
+                let module = naga::front::glsl::Frontend::default()
+                    .parse(&stage.into(), desc.source)
+                    .map_err(|e| remap(super::ShaderError::from_glsl(&e, desc.source)))?;
+                (
+                    discard_bindings(module)?,
+                    super::ShaderSourceKind::Glsl(stage),
+                )
+            }
+            super::ShaderLanguage::SpirV(data) => {
+                let module = naga::front::spv::parse_u8_slice(data, &Default::default())
+                    .map_err(|e| super::ShaderError::from_spirv(&e))?;
+                (
+                    discard_bindings(module)?,
+                    super::ShaderSourceKind::SpirV(data.to_vec()),
+                )
+            }
+        };
 
         let device_caps = self.capabilities();
 
@@ -35,24 +156,56 @@ impl super::Context {
             naga::valid::Capabilities::DUAL_SOURCE_BLENDING,
             device_caps.dual_source_blending,
         );
+        if device_caps.max_bindless_resources != 0 {
+            caps |= naga::valid::Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                | naga::valid::Capabilities::SAMPLER_NON_UNIFORM_INDEXING;
+        }
         let info = naga::valid::Validator::new(flags, caps)
             .validate(&module)
-            .map_err(|e| {
-                crate::util::emit_annotated_error(&e, "", desc.source);
-                crate::util::print_err(&e);
-                "validation failed"
-            })?;
+            .map_err(|e| remap(super::ShaderError::from_validation(&e, desc.source)))?;
 
         Ok(super::Shader {
             module,
             info,
-            source: desc.source.to_owned(),
+            source: match source_kind {
+                super::ShaderSourceKind::SpirV(_) => String::new(),
+                _ => desc.source.to_owned(),
+            },
+            source_kind,
         })
     }
 
     pub fn create_shader(&self, desc: super::ShaderDesc) -> super::Shader {
-        self.try_create_shader(desc).unwrap()
+        self.try_create_shader(desc)
+            .unwrap_or_else(|e| panic!("Unable to create shader:\n{e}"))
+    }
+}
+
+/// Remove the explicit resource bindings and vertex input locations
+/// of a GLSL or SPIR-V shader, so that they are assigned by name at pipeline creation.
+fn discard_bindings(mut module: naga::Module) -> Result<naga::Module, super::ShaderError> {
+    for (_, var) in module.global_variables.iter_mut() {
+        if var.binding.take().is_some() && var.name.is_none() {
+            let message = "Resource variable has no name to be bound with".to_string();
+            return Err(super::ShaderError {
+                stage: super::ShaderErrorStage::Validation,
+                labels: Vec::new(),
+                rendered: message.clone(),
+                message,
+            });
+        }
     }
+    for ep in module.entry_points.iter_mut() {
+        if ep.stage != naga::ShaderStage::Vertex {
+            continue;
+        }
+        for argument in ep.function.arguments.iter_mut() {
+            if let Some(naga::Binding::Location { .. }) = argument.binding {
+                argument.binding = None;
+            }
+        }
+    }
+    Ok(module)
 }
 
 pub static EMPTY_CONSTANTS: Lazy<super::PipelineConstants> = Lazy::new(Default::default);
@@ -82,14 +235,21 @@ impl super::Shader {
         &'a self,
         constants: &super::PipelineConstants,
     ) -> (naga::Module, Cow<'a, naga::valid::ModuleInfo>) {
+        self.try_resolve_constants(constants).unwrap()
+    }
+
+    pub(crate) fn try_resolve_constants<'a>(
+        &'a self,
+        constants: &super::PipelineConstants,
+    ) -> Result<(naga::Module, Cow<'a, naga::valid::ModuleInfo>), super::Error> {
         let (module, info) = naga::back::pipeline_constants::process_overrides(
             &self.module,
             &self.info,
             None,
             constants,
         )
-        .unwrap();
-        (module.into_owned(), info)
+        .map_err(|e| super::Error::InvalidConstants(e.to_string()))?;
+        Ok((module.into_owned(), info))
     }
 
     pub fn get_struct_size(&self, struct_name: &str) -> u32 {
@@ -117,13 +277,41 @@ impl super::Shader {
         );
     }
 
+    /// Compare every field of a host struct against the named struct of the shader.
+    /// A missing struct is reported as a single `FieldMismatch::MissingStruct`.
+    pub fn check_struct_layout<T: super::ShaderStruct>(
+        &self,
+        struct_name: &str,
+    ) -> Result<(), Vec<super::FieldMismatch>> {
+        let mut layouter = naga::proc::Layouter::default();
+        layouter.update(self.module.to_ctx()).map_err(|e| {
+            vec![super::FieldMismatch::Layout {
+                error: e.to_string(),
+            }]
+        })?;
+        let shader_struct = self
+            .module
+            .types
+            .iter()
+            .filter(|&(_, ty)| ty.name.as_deref() == Some(struct_name))
+            .find_map(|(handle, _)| {
+                crate::reflect::reflect_struct(&self.module.types, &layouter, handle)
+            })
+            .ok_or_else(|| {
+                vec![super::FieldMismatch::MissingStruct {
+                    name: struct_name.to_string(),
+                }]
+            })?;
+        shader_struct.check_layout(&T::struct_layout())
+    }
+
     pub(crate) fn fill_resource_bindings(
         module: &mut naga::Module,
         sd_infos: &mut [crate::ShaderDataInfo],
         naga_stage: naga::ShaderStage,
         ep_info: &naga::valid::FunctionInfo,
         group_layouts: &[&crate::ShaderDataLayout],
-    ) {
+    ) -> Result<(), crate::Error> {
         let mut layouter = naga::proc::Layouter::default();
         layouter.update(module.to_ctx()).unwrap();
 
@@ -167,19 +355,43 @@ impl super::Shader {
                         ),
                         naga::TypeInner::BindingArray { base, size: _ } => {
                             //Note: we could extract the count from `size` for more rigor
-                            let count = match proto_binding {
-                                crate::ShaderBinding::TextureArray { count } => count,
-                                crate::ShaderBinding::BufferArray { count } => count,
-                                _ => 0,
+                            let (count, bindless) = match proto_binding {
+                                crate::ShaderBinding::TextureArray { count } => (count, false),
+                                crate::ShaderBinding::BufferArray { count } => (count, false),
+                                crate::ShaderBinding::Bindless { kind: _, count } => (count, true),
+                                _ => (0, false),
                             };
                             let proto = match module.types[base].inner {
+                                naga::TypeInner::Image { .. } if bindless => {
+                                    crate::ShaderBinding::Bindless {
+                                        kind: crate::BindlessKind::Texture,
+                                        count,
+                                    }
+                                }
+                                naga::TypeInner::Struct { .. } if bindless => {
+                                    crate::ShaderBinding::Bindless {
+                                        kind: crate::BindlessKind::Buffer,
+                                        count,
+                                    }
+                                }
+                                naga::TypeInner::Sampler { .. } if bindless => {
+                                    crate::ShaderBinding::Bindless {
+                                        kind: crate::BindlessKind::Sampler,
+                                        count,
+                                    }
+                                }
                                 naga::TypeInner::Image { .. } => {
                                     crate::ShaderBinding::TextureArray { count }
                                 }
                                 naga::TypeInner::Struct { .. } => {
                                     crate::ShaderBinding::BufferArray { count }
                                 }
-                                ref other => panic!("Unsupported binding array for {:?}", other),
+                                ref other => {
+                                    return Err(crate::Error::InvalidShader(format!(
+                                        "Unsupported binding array of {:?} for '{}'",
+                                        other, var_name
+                                    )));
+                                }
                             };
                             (proto, var_access)
                         }
@@ -197,11 +409,21 @@ impl super::Shader {
                             (proto, var_access)
                         }
                     };
-                    assert_eq!(
-                        proto_binding, expected_proto,
-                        "Mismatched type for binding '{}'",
-                        var_name
-                    );
+                    if proto_binding != expected_proto {
+                        return Err(crate::Error::BindingMismatch {
+                            name: var_name.clone(),
+                            expected: expected_proto,
+                            provided: proto_binding,
+                        });
+                    }
+                    if let crate::ShaderBinding::Plain { size } = expected_proto
+                        && size > crate::limits::MAX_PLAIN_DATA_SIZE
+                    {
+                        return Err(crate::Error::UnsupportedBinding {
+                            name: var_name.clone(),
+                            binding: expected_proto,
+                        });
+                    }
                     assert_eq!(var.binding, None);
                     var.binding = Some(naga::ResourceBinding {
                         group: group_index as u32,
@@ -209,32 +431,37 @@ impl super::Shader {
                     });
                     info.visibility |= naga_stage.into();
                     info.binding_access[binding_index] |= access;
+                    if let crate::ShaderBinding::Plain { .. } = expected_proto {
+                        info.plain_structs[binding_index] =
+                            crate::reflect::reflect_struct(&module.types, &layouter, var.ty);
+                    }
                     break;
                 }
             }
 
-            assert!(
-                var.binding.is_some(),
-                "Unable to resolve binding for '{}' in stage '{:?}'",
-                var_name,
-                naga_stage,
-            );
+            if var.binding.is_none() {
+                return Err(crate::Error::MissingBinding {
+                    name: var_name.clone(),
+                    stage: naga_stage,
+                });
+            }
         }
+        Ok(())
     }
 
     pub(crate) fn fill_vertex_locations(
         module: &mut naga::Module,
         selected_ep_index: usize,
         fetch_states: &[crate::VertexFetchState],
-    ) -> Vec<crate::VertexAttributeMapping> {
+    ) -> Result<Vec<crate::VertexAttributeMapping>, crate::Error> {
         let mut attribute_mappings = Vec::new();
-        for (ep_index, ep) in module.entry_points.iter().enumerate() {
+        for (ep_index, ep) in module.entry_points.iter_mut().enumerate() {
             let mut location = 0;
             if ep.stage != naga::ShaderStage::Vertex {
                 continue;
             }
 
-            for argument in ep.function.arguments.iter() {
+            for argument in ep.function.arguments.iter_mut() {
                 if argument.binding.is_some() {
                     continue;
                 }
@@ -243,6 +470,43 @@ impl super::Shader {
                     Some(ref name) => name.as_str(),
                     None => "?",
                 };
+                if let naga::TypeInner::Scalar(_) | naga::TypeInner::Vector { .. } =
+                    module.types[argument.ty].inner
+                {
+                    // Inputs declared one by one, like in GLSL and SPIR-V shaders
+                    let index = if ep_index == selected_ep_index {
+                        let (buffer_index, attribute_index) = fetch_states
+                            .iter()
+                            .enumerate()
+                            .find_map(|(buffer_index, vertex_fetch)| {
+                                vertex_fetch
+                                    .layout
+                                    .attributes
+                                    .iter()
+                                    .position(|&(at_name, _)| at_name == arg_name)
+                                    .map(|attribute_index| (buffer_index, attribute_index))
+                            })
+                            .ok_or_else(|| {
+                                crate::Error::MissingVertexAttribute(arg_name.to_string())
+                            })?;
+                        attribute_mappings.push(crate::VertexAttributeMapping {
+                            buffer_index,
+                            attribute_index,
+                        });
+                        attribute_mappings.len() - 1
+                    } else {
+                        location += 1;
+                        location as usize - 1
+                    };
+                    argument.binding = Some(naga::Binding::Location {
+                        location: index as u32,
+                        interpolation: None,
+                        sampling: None,
+                        blend_src: None,
+                    });
+                    continue;
+                }
+
                 let mut ty = module.types[argument.ty].clone();
                 let members = match ty.inner {
                     naga::TypeInner::Struct {
@@ -292,11 +556,9 @@ impl super::Shader {
                                 }
                             }
                         }
-                        assert_ne!(
-                            member.binding, None,
-                            "Field {} is not covered by the vertex fetch layouts!",
-                            member_name
-                        );
+                        return Err(crate::Error::MissingVertexAttribute(
+                            member_name.to_string(),
+                        ));
                     }
                 } else {
                     // Just fill out the locations for the module to be valid
@@ -316,6 +578,71 @@ impl super::Shader {
                 module.types.replace(argument.ty, ty);
             }
         }
-        attribute_mappings
+        check_vertex_formats(module, selected_ep_index, &attribute_mappings, fetch_states)?;
+        Ok(attribute_mappings)
+    }
+}
+
+fn vertex_format_kind(format: super::VertexFormat) -> naga::ScalarKind {
+    use super::VertexFormat as Vf;
+    use naga::ScalarKind as Sk;
+    match format {
+        Vf::F32 | Vf::F32Vec2 | Vf::F32Vec3 | Vf::F32Vec4 => Sk::Float,
+        Vf::U32 | Vf::U32Vec2 | Vf::U32Vec3 | Vf::U32Vec4 => Sk::Uint,
+        Vf::I32 | Vf::I32Vec2 | Vf::I32Vec3 | Vf::I32Vec4 => Sk::Sint,
+        Vf::Unorm8x4 | Vf::Snorm8x4 => Sk::Float,
+        Vf::Uint8x4 => Sk::Uint,
+        Vf::Unorm16x2 | Vf::Snorm16x2 | Vf::Float16x2 => Sk::Float,
+        Vf::Unorm16x4 | Vf::Snorm16x4 | Vf::Float16x4 => Sk::Float,
+        Vf::Rgb10a2Unorm => Sk::Float,
+    }
+}
+
+/// Check that the fetched vertex attributes match the scalar kinds of the shader inputs.
+/// Component counts may differ: missing components are filled with defaults by the fetch.
+/// Locations are assigned by `fill_vertex_locations` in the order of the mappings.
+fn check_vertex_formats(
+    module: &naga::Module,
+    ep_index: usize,
+    attribute_mappings: &[super::VertexAttributeMapping],
+    fetch_states: &[super::VertexFetchState],
+) -> Result<(), super::Error> {
+    let ep = &module.entry_points[ep_index];
+    if ep.stage != naga::ShaderStage::Vertex {
+        return Ok(());
+    }
+    // Inputs are either declared one by one, or gathered in structs
+    let mut inputs = Vec::new();
+    for argument in ep.function.arguments.iter() {
+        match module.types[argument.ty].inner {
+            naga::TypeInner::Struct { ref members, .. } => {
+                inputs.extend(members.iter().map(|member| (&member.binding, member.ty)));
+            }
+            _ => inputs.push((&argument.binding, argument.ty)),
+        }
+    }
+    for (binding, ty) in inputs {
+        let mapping = match *binding {
+            Some(naga::Binding::Location { location, .. }) => {
+                match attribute_mappings.get(location as usize) {
+                    Some(mapping) => mapping,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let (at_name, attribute) =
+            fetch_states[mapping.buffer_index].layout.attributes[mapping.attribute_index];
+        let input_kind = match module.types[ty].inner {
+            naga::TypeInner::Scalar(scalar) | naga::TypeInner::Vector { scalar, .. } => scalar.kind,
+            _ => continue,
+        };
+        if vertex_format_kind(attribute.format) != input_kind {
+            return Err(super::Error::VertexFormatMismatch {
+                name: at_name.to_string(),
+                format: attribute.format,
+            });
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,331 @@
+//! Timing scopes of the command encoders.
+//!
+//! Timing is enabled by [`ContextDesc::timing`](crate::ContextDesc::timing).
+//! Every pass is a scope, and more scopes can be nested in the encoders and passes
+//! with [`TimingEncoder`](crate::traits::TimingEncoder). The backends write GPU timestamps
+//! at the boundaries of the scopes, growing their query storage when a recording
+//! needs more of them, and the results come back from `timings()` as a tree.
+//!
+//! A scope that doesn't get its own timestamps, because the backend can't sample there
+//! or the storage hasn't grown yet, spans the GPU time of its timed children.
+
+use std::{
+    fmt::{self, Write as _},
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// Timing scope of a command encoder.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingScope {
+    pub name: String,
+    /// Time of recording the scope on the CPU,
+    /// relative to the start of the first scope of the encoder.
+    pub cpu: Range<Duration>,
+    /// Time of executing the scope on the GPU,
+    /// relative to the first GPU timestamp of the encoder.
+    /// `None` if there are no timestamps for the scope.
+    pub gpu: Option<Range<Duration>>,
+    /// Nested scopes, in the recording order.
+    pub children: Vec<TimingScope>,
+}
+
+impl TimingScope {
+    pub fn cpu_duration(&self) -> Duration {
+        self.cpu.end.saturating_sub(self.cpu.start)
+    }
+
+    pub fn gpu_duration(&self) -> Option<Duration> {
+        self.gpu
+            .as_ref()
+            .map(|range| range.end.saturating_sub(range.start))
+    }
+}
+
+/// Tree of the timing scopes of the last recording of a command encoder.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timings {
+    /// Top level scopes, in the recording order.
+    pub scopes: Vec<TimingScope>,
+}
+
+impl Timings {
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    /// Iterate over all the scopes depth-first, together with their nesting depth.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &TimingScope)> {
+        let mut stack = vec![self.scopes.iter()];
+        std::iter::from_fn(move || {
+            loop {
+                let depth = stack.len().checked_sub(1)?;
+                match stack[depth].next() {
+                    Some(scope) => {
+                        stack.push(scope.children.iter());
+                        return Some((depth, scope));
+                    }
+                    None => {
+                        stack.pop();
+                    }
+                }
+            }
+        })
+    }
+
+    /// Total GPU time of the top level scopes.
+    pub fn gpu_total(&self) -> Duration {
+        self.scopes
+            .iter()
+            .filter_map(TimingScope::gpu_duration)
+            .sum()
+    }
+
+    /// Export the scopes in the Chrome trace event format,
+    /// which can be opened by `chrome://tracing` and Perfetto.
+    /// CPU and GPU times are shown on separate tracks.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut out = String::from("[\n");
+        for (tid, track) in ["CPU", "GPU"].iter().enumerate() {
+            writeln!(
+                out,
+                r#"{{"name":"thread_name","ph":"M","pid":0,"tid":{tid},"args":{{"name":"{track}"}}}},"#
+            )
+            .unwrap();
+        }
+        for (_, scope) in self.iter() {
+            let ranges = [Some(&scope.cpu), scope.gpu.as_ref()];
+            for (tid, range) in ranges.into_iter().enumerate() {
+                if let Some(range) = range {
+                    writeln!(
+                        out,
+                        r#"{{"name":{},"ph":"X","pid":0,"tid":{},"ts":{:.3},"dur":{:.3}}},"#,
+                        JsonString(&scope.name),
+                        tid,
+                        range.start.as_secs_f64() * 1e6,
+                        range.end.saturating_sub(range.start).as_secs_f64() * 1e6,
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        // Drop the trailing comma
+        out.truncate(out.len() - 2);
+        out.push_str("\n]\n");
+        out
+    }
+}
+
+struct JsonString<'a>(&'a str);
+
+impl fmt::Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_char('"')?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+#[derive(Debug)]
+struct RecordedScope {
+    name: String,
+    parent: Option<usize>,
+    /// Index of the starting timestamp, followed by the ending one.
+    query: Option<u32>,
+    cpu: Range<Instant>,
+    is_pass: bool,
+}
+
+/// Scopes of a recording, with the timestamp queries assigned to them.
+#[derive(Debug, Default)]
+pub(crate) struct ScopeRecorder {
+    scopes: Vec<RecordedScope>,
+    open: Vec<usize>,
+    query_count: u32,
+    /// Number of timestamp queries the backend has for a recording.
+    capacity: u32,
+    /// Number of queries the recording needs, including the ones beyond the capacity.
+    required: u32,
+}
+
+impl ScopeRecorder {
+    /// Number of the timestamps that are written by the recording.
+    pub fn query_count(&self) -> u32 {
+        self.query_count
+    }
+
+    /// Number of the timestamps that the recording would need to time every scope.
+    /// The backends grow their storage to it before the next recording.
+    pub fn required_query_count(&self) -> u32 {
+        self.required
+    }
+
+    /// Make more timestamp queries available to the following recordings.
+    pub fn grow(&mut self, capacity: u32) {
+        self.capacity = self.capacity.max(capacity);
+    }
+
+    /// Forget the recorded scopes.
+    pub fn reset(&mut self) {
+        self.scopes.clear();
+        self.open.clear();
+        self.query_count = 0;
+        self.required = 0;
+    }
+
+    fn push(&mut self, name: &str, timed: bool, is_pass: bool) -> Option<u32> {
+        let query = if timed {
+            self.required += 2;
+            if self.query_count + 2 <= self.capacity {
+                self.query_count += 2;
+                Some(self.query_count - 2)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        let now = Instant::now();
+        self.open.push(self.scopes.len());
+        self.scopes.push(RecordedScope {
+            name: name.to_string(),
+            parent: self.open.iter().rev().nth(1).cloned(),
+            query,
+            cpu: now..now,
+            is_pass,
+        });
+        query
+    }
+
+    fn pop(&mut self) -> Option<u32> {
+        let index = self.open.pop()?;
+        let scope = &mut self.scopes[index];
+        scope.cpu.end = Instant::now();
+        scope.query.map(|query| query + 1)
+    }
+
+    /// Begin a scope, returning the index of its starting timestamp if it gets one.
+    pub fn begin(&mut self, name: &str, timed: bool) -> Option<u32> {
+        self.push(name, timed, false)
+    }
+
+    /// Begin the scope of a pass, which closes all the scopes nested in it when ended.
+    pub fn begin_pass(&mut self, name: &str, timed: bool) -> Option<u32> {
+        self.push(name, timed, true)
+    }
+
+    /// End the innermost scope, returning the index of its ending timestamp if it has one.
+    /// Fails if there is no scope begun at the current level.
+    pub fn end(&mut self) -> Result<Option<u32>, String> {
+        match self.open.last() {
+            Some(&index) if !self.scopes[index].is_pass => Ok(self.pop()),
+            Some(&index) => Err(format!(
+                "Timing scope is ended in pass '{}' without being begun there",
+                self.scopes[index].name
+            )),
+            None => Err("Timing scope is ended without being begun".to_string()),
+        }
+    }
+
+    /// End the innermost pass with the scopes nested in it.
+    /// Returns the indices of the ending timestamps, and the names of the nested scopes
+    /// that weren't ended.
+    pub fn end_pass(&mut self) -> (Vec<u32>, Vec<String>) {
+        let mut queries = Vec::new();
+        let mut unended = Vec::new();
+        while let Some(&index) = self.open.last() {
+            let is_pass = self.scopes[index].is_pass;
+            if !is_pass {
+                unended.push(self.scopes[index].name.clone());
+            }
+            queries.extend(self.pop());
+            if is_pass {
+                break;
+            }
+        }
+        (queries, unended)
+    }
+
+    /// End all the remaining scopes at the end of the recording.
+    /// Returns the same as `end_pass`.
+    pub fn finish(&mut self) -> (Vec<u32>, Vec<String>) {
+        let mut queries = Vec::new();
+        let mut unended = Vec::new();
+        while let Some(&index) = self.open.last() {
+            unended.push(self.scopes[index].name.clone());
+            queries.extend(self.pop());
+        }
+        (queries, unended)
+    }
+
+    /// Build the tree out of the recorded scopes and the values of their timestamps
+    /// in nanoseconds, and get ready for the next recording.
+    /// The required query count is reset, so it has to be read before.
+    pub fn resolve(&mut self, timestamps: &[u64]) -> Timings {
+        let base_cpu = self.scopes.first().map(|scope| scope.cpu.start);
+        let base_gpu = self
+            .scopes
+            .iter()
+            .filter_map(|scope| scope.query)
+            .filter_map(|query| timestamps.get(query as usize))
+            .min()
+            .cloned()
+            .unwrap_or_default();
+
+        let mut nodes = self
+            .scopes
+            .iter()
+            .map(|scope| {
+                let gpu = scope.query.and_then(|query| {
+                    let start = *timestamps.get(query as usize)?;
+                    let end = *timestamps.get(query as usize + 1)?;
+                    Some(
+                        Duration::from_nanos(start.saturating_sub(base_gpu))
+                            ..Duration::from_nanos(end.saturating_sub(base_gpu)),
+                    )
+                });
+                let base = base_cpu.unwrap();
+                TimingScope {
+                    name: scope.name.clone(),
+                    cpu: scope.cpu.start.duration_since(base)..scope.cpu.end.duration_since(base),
+                    gpu,
+                    children: Vec::new(),
+                }
+            })
+            .map(Some)
+            .collect::<Vec<_>>();
+
+        // Children always follow their parents, so attaching them in reverse
+        // leaves every node complete by the time it's attached itself.
+        let mut timings = Timings::default();
+        for (index, scope) in self.scopes.iter().enumerate().rev() {
+            let mut node = nodes[index].take().unwrap();
+            node.children.reverse();
+            if node.gpu.is_none() {
+                let children = node.children.iter().filter_map(|child| child.gpu.as_ref());
+                node.gpu = children.fold(None, |range: Option<Range<Duration>>, child| {
+                    Some(match range {
+                        Some(range) => range.start.min(child.start)..range.end.max(child.end),
+                        None => child.clone(),
+                    })
+                });
+            }
+            match scope.parent {
+                Some(parent) => nodes[parent].as_mut().unwrap().children.push(node),
+                None => timings.scopes.push(node),
+            }
+        }
+        timings.scopes.reverse();
+
+        self.reset();
+        timings
+    }
+}
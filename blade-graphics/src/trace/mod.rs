@@ -0,0 +1,552 @@
+//! Capture of the API calls into a trace, and their replay.
+//!
+//! Tracing is enabled by [`ContextDesc::trace_path`](crate::ContextDesc::trace_path).
+//! The trace directory contains:
+//!   - `trace.bin` with every resource, pipeline, and command encoded in the order of calls,
+//!   - `shader-<hash>.<ext>` with the source of each shader used by a pipeline,
+//!     where the extension is `wgsl`, `vert`, `frag`, or `comp` for GLSL, or `spv`.
+//!
+//! Contents of host-visible buffers are captured on `sync_buffer`, and on submission
+//! of any command encoder that refers to them, if the contents changed since the last capture.
+//! Frames acquired from a surface are replaced by regular textures when replaying,
+//! so a trace can be replayed without a window, on any backend, with [`Replayer`].
+//!
+//! Tracing is supported by the Vulkan backend and the null backend.
+
+mod record;
+mod replay;
+mod serial;
+
+use serial::{Element, Serial, serial_enum, serial_struct};
+
+pub(crate) use record::{EncoderTrace, Recorder, record};
+pub use replay::Replayer;
+
+const TRACE_FILE: &str = "trace.bin";
+const MAGIC: &[u8; 8] = b"BLADETR1";
+
+pub(crate) type Id = u32;
+/// Handles that weren't created by the context, such as `Buffer::default()`.
+const NULL: Id = !0;
+
+/// File extension of a GLSL shader of the given stage.
+fn glsl_extension(stage: naga::ShaderStage) -> &'static str {
+    match stage {
+        naga::ShaderStage::Vertex => "vert",
+        naga::ShaderStage::Fragment => "frag",
+        _ => "comp",
+    }
+}
+
+impl Element for u32 {}
+impl<T: Element> Element for Vec<T> {}
+
+/// Resource bound to a shader, as captured from a backend `PipelineContext`.
+pub(crate) enum Bound {
+    Texture(crate::TextureView),
+    TextureArray {
+        views: Vec<crate::TextureView>,
+        count: u32,
+    },
+    Sampler(crate::Sampler),
+    Buffer(crate::BufferPiece),
+    BufferArray {
+        pieces: Vec<crate::BufferPiece>,
+        count: u32,
+    },
+    AccelerationStructure(crate::AccelerationStructure),
+    Plain(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BufferPiece {
+    buffer: Id,
+    offset: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TexturePiece {
+    texture: Id,
+    mip_level: u32,
+    array_layer: u32,
+    origin: [u32; 3],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum FinishOp {
+    Store,
+    Discard,
+    ResolveTo { view: Id },
+    Ignore,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RenderTarget {
+    view: Id,
+    init_op: crate::InitOp,
+    finish_op: FinishOp,
+}
+
+#[derive(Debug)]
+pub(crate) struct AccelerationStructureMesh {
+    vertex_data: BufferPiece,
+    vertex_format: crate::VertexFormat,
+    vertex_stride: u32,
+    vertex_count: u32,
+    index_data: BufferPiece,
+    index_type: Option<crate::IndexType>,
+    triangle_count: u32,
+    /// Has `NULL` buffer if there is no transform.
+    transform_data: BufferPiece,
+    is_opaque: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct ShaderFunction {
+    /// Name of the file with the shader source.
+    shader: String,
+    entry_point: String,
+    constants: Vec<(String, f64)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct VertexFetch {
+    attributes: Vec<(String, crate::VertexAttribute)>,
+    stride: u32,
+    instanced: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum Binding {
+    Texture {
+        view: Id,
+    },
+    TextureArray {
+        views: Vec<Id>,
+        count: u32,
+    },
+    Sampler {
+        sampler: Id,
+    },
+    Buffer {
+        piece: BufferPiece,
+    },
+    BufferArray {
+        pieces: Vec<BufferPiece>,
+        count: u32,
+    },
+    AccelerationStructure {
+        acceleration_structure: Id,
+    },
+    Plain {
+        data: Vec<u8>,
+    },
+}
+
+pub(crate) type DataLayout = Vec<(String, crate::ShaderBinding)>;
+
+#[derive(Debug)]
+pub(crate) enum Action {
+    CreateBuffer {
+        id: Id,
+        name: String,
+        size: u64,
+        memory: crate::Memory,
+    },
+    WriteBuffer {
+        id: Id,
+        data: Vec<u8>,
+    },
+    DestroyBuffer {
+        id: Id,
+    },
+    CreateTexture {
+        id: Id,
+        name: String,
+        format: crate::TextureFormat,
+        size: crate::Extent,
+        array_layer_count: u32,
+        mip_level_count: u32,
+        sample_count: u32,
+        dimension: crate::TextureDimension,
+        usage: crate::TextureUsage,
+    },
+    DestroyTexture {
+        id: Id,
+    },
+    CreateTextureView {
+        id: Id,
+        texture: Id,
+        name: String,
+        format: crate::TextureFormat,
+        dimension: crate::ViewDimension,
+        subresources: crate::TextureSubresources,
+    },
+    DestroyTextureView {
+        id: Id,
+    },
+    CreateSampler {
+        id: Id,
+        name: String,
+        address_modes: [crate::AddressMode; 3],
+        mag_filter: crate::FilterMode,
+        min_filter: crate::FilterMode,
+        mipmap_filter: crate::FilterMode,
+        lod_min_clamp: f32,
+        lod_max_clamp: Option<f32>,
+        compare: Option<crate::CompareFunction>,
+        anisotropy_clamp: u32,
+        border_color: Option<crate::TextureColor>,
+    },
+    DestroySampler {
+        id: Id,
+    },
+    CreateAccelerationStructure {
+        id: Id,
+        name: String,
+        ty: crate::AccelerationStructureType,
+        size: u64,
+    },
+    DestroyAccelerationStructure {
+        id: Id,
+    },
+    CreateComputePipeline {
+        id: Id,
+        name: String,
+        data_layouts: Vec<DataLayout>,
+        compute: ShaderFunction,
+    },
+    DestroyComputePipeline {
+        id: Id,
+    },
+    CreateRenderPipeline {
+        id: Id,
+        name: String,
+        data_layouts: Vec<DataLayout>,
+        vertex: ShaderFunction,
+        vertex_fetches: Vec<VertexFetch>,
+        primitive: crate::PrimitiveState,
+        depth_stencil: Option<crate::DepthStencilState>,
+        fragment: Option<ShaderFunction>,
+        color_targets: Vec<crate::ColorTargetState>,
+        multisample_state: crate::MultisampleState,
+    },
+    DestroyRenderPipeline {
+        id: Id,
+    },
+    AcquireFrame {
+        texture: Id,
+        view: Id,
+        format: crate::TextureFormat,
+        size: crate::Extent,
+    },
+    Submit {
+        encoder: String,
+        commands: Vec<Command>,
+    },
+}
+
+#[derive(Debug)]
+pub(crate) enum Command {
+    InitTexture {
+        texture: Id,
+    },
+    Present {
+        texture: Id,
+    },
+    BeginTransfer {
+        label: String,
+    },
+    BeginAccelerationStructure {
+        label: String,
+    },
+    BeginCompute {
+        label: String,
+    },
+    BeginRender {
+        label: String,
+        colors: Vec<RenderTarget>,
+        depth_stencil: Option<RenderTarget>,
+    },
+    EndPass,
+    FillBuffer {
+        dst: BufferPiece,
+        size: u64,
+        value: u8,
+    },
+    CopyBufferToBuffer {
+        src: BufferPiece,
+        dst: BufferPiece,
+        size: u64,
+    },
+    CopyTextureToTexture {
+        src: TexturePiece,
+        dst: TexturePiece,
+        size: crate::Extent,
+    },
+    CopyBufferToTexture {
+        src: BufferPiece,
+        bytes_per_row: u32,
+        dst: TexturePiece,
+        size: crate::Extent,
+    },
+    CopyTextureToBuffer {
+        src: TexturePiece,
+        dst: BufferPiece,
+        bytes_per_row: u32,
+        size: crate::Extent,
+    },
+    BuildBottomLevel {
+        acceleration_structure: Id,
+        meshes: Vec<AccelerationStructureMesh>,
+        scratch_data: BufferPiece,
+    },
+    BuildTopLevel {
+        acceleration_structure: Id,
+        bottom_level: Vec<Id>,
+        instance_count: u32,
+        instance_data: BufferPiece,
+        scratch_data: BufferPiece,
+    },
+    SetComputePipeline {
+        pipeline: Id,
+    },
+    SetRenderPipeline {
+        pipeline: Id,
+    },
+    Bind {
+        group: u32,
+        bindings: Vec<(u32, Binding)>,
+    },
+    SetScissorRect {
+        rect: crate::ScissorRect,
+    },
+    SetViewport {
+        viewport: crate::Viewport,
+    },
+    SetStencilReference {
+        reference: u32,
+    },
+    BindVertex {
+        index: u32,
+        vertex_buf: BufferPiece,
+    },
+    Draw {
+        first_vertex: u32,
+        vertex_count: u32,
+        first_instance: u32,
+        instance_count: u32,
+    },
+    DrawIndexed {
+        index_buf: BufferPiece,
+        index_type: crate::IndexType,
+        index_count: u32,
+        base_vertex: i32,
+        start_instance: u32,
+        instance_count: u32,
+    },
+    DrawIndirect {
+        indirect_buf: BufferPiece,
+    },
+    DrawIndexedIndirect {
+        index_buf: BufferPiece,
+        index_type: crate::IndexType,
+        indirect_buf: BufferPiece,
+    },
+    Dispatch {
+        groups: [u32; 3],
+    },
+    DispatchIndirect {
+        indirect_buf: BufferPiece,
+    },
+}
+
+impl Element for BufferPiece {}
+impl Element for RenderTarget {}
+impl Element for AccelerationStructureMesh {}
+impl Element for VertexFetch {}
+impl Element for Binding {}
+impl Element for Command {}
+
+serial_struct! {
+    BufferPiece { buffer, offset }
+    TexturePiece {
+        texture,
+        mip_level,
+        array_layer,
+        origin,
+    }
+    RenderTarget {
+        view,
+        init_op,
+        finish_op,
+    }
+    AccelerationStructureMesh {
+        vertex_data,
+        vertex_format,
+        vertex_stride,
+        vertex_count,
+        index_data,
+        index_type,
+        triangle_count,
+        transform_data,
+        is_opaque,
+    }
+    ShaderFunction {
+        shader,
+        entry_point,
+        constants,
+    }
+    VertexFetch {
+        attributes,
+        stride,
+        instanced,
+    }
+}
+
+serial_enum! {
+    FinishOp {
+        Store,
+        Discard,
+        ResolveTo { view },
+        Ignore,
+    }
+    Binding {
+        Texture { view },
+        TextureArray { views, count },
+        Sampler { sampler },
+        Buffer { piece },
+        BufferArray { pieces, count },
+        AccelerationStructure { acceleration_structure },
+        Plain { data },
+    }
+    Action {
+        CreateBuffer { id, name, size, memory },
+        WriteBuffer { id, data },
+        DestroyBuffer { id },
+        CreateTexture {
+            id,
+            name,
+            format,
+            size,
+            array_layer_count,
+            mip_level_count,
+            sample_count,
+            dimension,
+            usage
+        },
+        DestroyTexture { id },
+        CreateTextureView {
+            id,
+            texture,
+            name,
+            format,
+            dimension,
+            subresources
+        },
+        DestroyTextureView { id },
+        CreateSampler {
+            id,
+            name,
+            address_modes,
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            lod_min_clamp,
+            lod_max_clamp,
+            compare,
+            anisotropy_clamp,
+            border_color
+        },
+        DestroySampler { id },
+        CreateAccelerationStructure { id, name, ty, size },
+        DestroyAccelerationStructure { id },
+        CreateComputePipeline {
+            id,
+            name,
+            data_layouts,
+            compute
+        },
+        DestroyComputePipeline { id },
+        CreateRenderPipeline {
+            id,
+            name,
+            data_layouts,
+            vertex,
+            vertex_fetches,
+            primitive,
+            depth_stencil,
+            fragment,
+            color_targets,
+            multisample_state
+        },
+        DestroyRenderPipeline { id },
+        AcquireFrame { texture, view, format, size },
+        Submit { encoder, commands },
+    }
+    Command {
+        InitTexture { texture },
+        Present { texture },
+        BeginTransfer { label },
+        BeginAccelerationStructure { label },
+        BeginCompute { label },
+        BeginRender { label, colors, depth_stencil },
+        EndPass,
+        FillBuffer { dst, size, value },
+        CopyBufferToBuffer { src, dst, size },
+        CopyTextureToTexture { src, dst, size },
+        CopyBufferToTexture {
+            src,
+            bytes_per_row,
+            dst,
+            size
+        },
+        CopyTextureToBuffer {
+            src,
+            dst,
+            bytes_per_row,
+            size
+        },
+        BuildBottomLevel {
+            acceleration_structure,
+            meshes,
+            scratch_data
+        },
+        BuildTopLevel {
+            acceleration_structure,
+            bottom_level,
+            instance_count,
+            instance_data,
+            scratch_data
+        },
+        SetComputePipeline { pipeline },
+        SetRenderPipeline { pipeline },
+        Bind { group, bindings },
+        SetScissorRect { rect },
+        SetViewport { viewport },
+        SetStencilReference { reference },
+        BindVertex { index, vertex_buf },
+        Draw {
+            first_vertex,
+            vertex_count,
+            first_instance,
+            instance_count
+        },
+        DrawIndexed {
+            index_buf,
+            index_type,
+            index_count,
+            base_vertex,
+            start_instance,
+            instance_count
+        },
+        DrawIndirect { indirect_buf },
+        DrawIndexedIndirect {
+            index_buf,
+            index_type,
+            indirect_buf
+        },
+        Dispatch { groups },
+        DispatchIndirect { indirect_buf },
+    }
+}
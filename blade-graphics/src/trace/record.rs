@@ -0,0 +1,642 @@
+use std::{
+    collections::{HashMap, HashSet, hash_map::DefaultHasher},
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    slice,
+    sync::{Arc, Mutex},
+};
+
+use super::{Action, Binding, BufferPiece, Command, Id, NULL, Serial as _, serial::Writer};
+
+/// Makes the resource handles usable as hash map keys.
+#[derive(Clone, Copy, Hash, PartialEq)]
+struct Key<T>(T);
+impl<T: PartialEq> Eq for Key<T> {}
+
+struct BufferState {
+    id: Id,
+    buffer: crate::Buffer,
+    size: u64,
+    /// Hash of the contents at the last capture.
+    content_hash: Option<u64>,
+}
+
+/// Trace identifiers of the live resources.
+#[derive(Default)]
+struct Handles {
+    buffers: HashMap<Key<crate::Buffer>, BufferState>,
+    textures: HashMap<Key<crate::Texture>, Id>,
+    texture_views: HashMap<Key<crate::TextureView>, Id>,
+    samplers: HashMap<Key<crate::Sampler>, Id>,
+    acceleration_structures: HashMap<Key<crate::AccelerationStructure>, Id>,
+    /// Pipelines are identified by a key provided by the backend.
+    pipelines: HashMap<u64, Id>,
+}
+
+struct State {
+    dir: PathBuf,
+    file: io::BufWriter<fs::File>,
+    next_id: Id,
+    handles: Handles,
+    shader_files: HashSet<String>,
+}
+
+/// Writes the trace of a context, see the [module docs](super).
+pub(crate) struct Recorder {
+    state: Mutex<State>,
+}
+
+impl State {
+    fn write(&mut self, action: &Action) {
+        let mut writer = Writer::default();
+        action.write(&mut writer);
+        if let Err(e) = self.file.write_all(&writer.data) {
+            log::error!("Unable to write the trace: {:?}", e);
+        }
+    }
+
+    fn alloc_id(&mut self) -> Id {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Capture the contents of a host-visible buffer, unless they didn't change.
+    fn capture_buffer(&mut self, buffer: crate::Buffer) {
+        let state = match self.handles.buffers.get_mut(&Key(buffer)) {
+            Some(state) if !state.buffer.data().is_null() => state,
+            _ => return,
+        };
+        let data = unsafe { slice::from_raw_parts(state.buffer.data(), state.size as usize) };
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let content_hash = hasher.finish();
+        if state.content_hash == Some(content_hash) {
+            return;
+        }
+        state.content_hash = Some(content_hash);
+        let action = Action::WriteBuffer {
+            id: state.id,
+            data: data.to_vec(),
+        };
+        self.write(&action);
+    }
+
+    /// Write the shader source next to the trace, return the file name.
+    fn shader_function(&mut self, sf: &crate::ShaderFunction) -> super::ShaderFunction {
+        let mut hasher = DefaultHasher::new();
+        sf.shader.source.hash(&mut hasher);
+        let (extension, contents) = match sf.shader.source_kind {
+            crate::ShaderSourceKind::Wgsl => ("wgsl", sf.shader.source.as_bytes()),
+            crate::ShaderSourceKind::Glsl(stage) => {
+                (super::glsl_extension(stage), sf.shader.source.as_bytes())
+            }
+            crate::ShaderSourceKind::SpirV(ref data) => {
+                data.hash(&mut hasher);
+                ("spv", data.as_slice())
+            }
+        };
+        let file_name = format!("shader-{:016x}.{}", hasher.finish(), extension);
+        if self.shader_files.insert(file_name.clone())
+            && let Err(e) = fs::write(self.dir.join(&file_name), contents)
+        {
+            log::error!("Unable to write shader {}: {:?}", file_name, e);
+        }
+        let mut constants = sf
+            .constants
+            .iter()
+            .map(|(name, &value)| (name.clone(), value))
+            .collect::<Vec<_>>();
+        constants.sort_by(|a, b| a.0.cmp(&b.0));
+        super::ShaderFunction {
+            shader: file_name,
+            entry_point: sf.entry_point.to_string(),
+            constants,
+        }
+    }
+}
+
+fn data_layouts(layouts: &[&crate::ShaderDataLayout]) -> Vec<super::DataLayout> {
+    layouts
+        .iter()
+        .map(|layout| {
+            layout
+                .bindings
+                .iter()
+                .map(|&(name, binding)| (name.to_string(), binding))
+                .collect()
+        })
+        .collect()
+}
+
+fn remove<K: Eq + Hash>(map: &mut HashMap<K, Id>, key: &K) -> Id {
+    map.remove(key).unwrap_or(NULL)
+}
+
+impl Recorder {
+    /// Start a new trace in the given directory.
+    pub(crate) fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut file = io::BufWriter::new(fs::File::create(dir.join(super::TRACE_FILE))?);
+        file.write_all(super::MAGIC)?;
+        log::info!("Recording API trace into {}", dir.display());
+        Ok(Self {
+            state: Mutex::new(State {
+                dir: dir.to_path_buf(),
+                file,
+                next_id: 0,
+                handles: Handles::default(),
+                shader_files: HashSet::default(),
+            }),
+        })
+    }
+
+    /// Create a recorder if requested by the context descriptor.
+    /// Failure to start a trace is not fatal.
+    pub(crate) fn from_desc(desc: &crate::ContextDesc) -> Option<Arc<Self>> {
+        let dir = desc.trace_path.as_ref()?;
+        match Self::new(dir) {
+            Ok(recorder) => Some(Arc::new(recorder)),
+            Err(e) => {
+                log::error!("Unable to start the trace at {}: {:?}", dir.display(), e);
+                None
+            }
+        }
+    }
+
+    fn with<R>(&self, fun: impl FnOnce(&mut State) -> R) -> R {
+        fun(&mut self.state.lock().unwrap())
+    }
+
+    pub(crate) fn create_buffer(&self, desc: &crate::BufferDesc, buffer: crate::Buffer) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state.handles.buffers.insert(
+                Key(buffer),
+                BufferState {
+                    id,
+                    buffer,
+                    size: desc.size,
+                    content_hash: None,
+                },
+            );
+            state.write(&Action::CreateBuffer {
+                id,
+                name: desc.name.to_string(),
+                size: desc.size,
+                memory: desc.memory,
+            });
+        })
+    }
+
+    pub(crate) fn sync_buffer(&self, buffer: crate::Buffer) {
+        self.with(|state| state.capture_buffer(buffer))
+    }
+
+    pub(crate) fn destroy_buffer(&self, buffer: crate::Buffer) {
+        self.with(|state| {
+            if let Some(bs) = state.handles.buffers.remove(&Key(buffer)) {
+                state.write(&Action::DestroyBuffer { id: bs.id });
+            }
+        })
+    }
+
+    pub(crate) fn create_texture(&self, desc: &crate::TextureDesc, texture: crate::Texture) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state.handles.textures.insert(Key(texture), id);
+            state.write(&Action::CreateTexture {
+                id,
+                name: desc.name.to_string(),
+                format: desc.format,
+                size: desc.size,
+                array_layer_count: desc.array_layer_count,
+                mip_level_count: desc.mip_level_count,
+                sample_count: desc.sample_count,
+                dimension: desc.dimension,
+                usage: desc.usage,
+            });
+        })
+    }
+
+    pub(crate) fn destroy_texture(&self, texture: crate::Texture) {
+        self.with(|state| {
+            let id = remove(&mut state.handles.textures, &Key(texture));
+            state.write(&Action::DestroyTexture { id });
+        })
+    }
+
+    pub(crate) fn create_texture_view(
+        &self,
+        texture: crate::Texture,
+        desc: &crate::TextureViewDesc,
+        view: crate::TextureView,
+    ) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state.handles.texture_views.insert(Key(view), id);
+            let texture = *state.handles.textures.get(&Key(texture)).unwrap_or(&NULL);
+            state.write(&Action::CreateTextureView {
+                id,
+                texture,
+                name: desc.name.to_string(),
+                format: desc.format,
+                dimension: desc.dimension,
+                subresources: desc.subresources.clone(),
+            });
+        })
+    }
+
+    pub(crate) fn destroy_texture_view(&self, view: crate::TextureView) {
+        self.with(|state| {
+            let id = remove(&mut state.handles.texture_views, &Key(view));
+            state.write(&Action::DestroyTextureView { id });
+        })
+    }
+
+    pub(crate) fn create_sampler(&self, desc: &crate::SamplerDesc, sampler: crate::Sampler) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state.handles.samplers.insert(Key(sampler), id);
+            state.write(&Action::CreateSampler {
+                id,
+                name: desc.name.to_string(),
+                address_modes: desc.address_modes,
+                mag_filter: desc.mag_filter,
+                min_filter: desc.min_filter,
+                mipmap_filter: desc.mipmap_filter,
+                lod_min_clamp: desc.lod_min_clamp,
+                lod_max_clamp: desc.lod_max_clamp,
+                compare: desc.compare,
+                anisotropy_clamp: desc.anisotropy_clamp,
+                border_color: desc.border_color,
+            });
+        })
+    }
+
+    pub(crate) fn destroy_sampler(&self, sampler: crate::Sampler) {
+        self.with(|state| {
+            let id = remove(&mut state.handles.samplers, &Key(sampler));
+            state.write(&Action::DestroySampler { id });
+        })
+    }
+
+    pub(crate) fn create_acceleration_structure(
+        &self,
+        desc: &crate::AccelerationStructureDesc,
+        acceleration_structure: crate::AccelerationStructure,
+    ) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state
+                .handles
+                .acceleration_structures
+                .insert(Key(acceleration_structure), id);
+            state.write(&Action::CreateAccelerationStructure {
+                id,
+                name: desc.name.to_string(),
+                ty: match desc.ty {
+                    crate::AccelerationStructureType::TopLevel => {
+                        crate::AccelerationStructureType::TopLevel
+                    }
+                    crate::AccelerationStructureType::BottomLevel => {
+                        crate::AccelerationStructureType::BottomLevel
+                    }
+                },
+                size: desc.size,
+            });
+        })
+    }
+
+    pub(crate) fn destroy_acceleration_structure(
+        &self,
+        acceleration_structure: crate::AccelerationStructure,
+    ) {
+        self.with(|state| {
+            let id = remove(
+                &mut state.handles.acceleration_structures,
+                &Key(acceleration_structure),
+            );
+            state.write(&Action::DestroyAccelerationStructure { id });
+        })
+    }
+
+    pub(crate) fn create_compute_pipeline(&self, desc: &crate::ComputePipelineDesc, key: u64) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state.handles.pipelines.insert(key, id);
+            let action = Action::CreateComputePipeline {
+                id,
+                name: desc.name.to_string(),
+                data_layouts: data_layouts(desc.data_layouts),
+                compute: state.shader_function(&desc.compute),
+            };
+            state.write(&action);
+        })
+    }
+
+    pub(crate) fn destroy_compute_pipeline(&self, key: u64) {
+        self.with(|state| {
+            let id = remove(&mut state.handles.pipelines, &key);
+            state.write(&Action::DestroyComputePipeline { id });
+        })
+    }
+
+    pub(crate) fn create_render_pipeline(&self, desc: &crate::RenderPipelineDesc, key: u64) {
+        self.with(|state| {
+            let id = state.alloc_id();
+            state.handles.pipelines.insert(key, id);
+            let action = Action::CreateRenderPipeline {
+                id,
+                name: desc.name.to_string(),
+                data_layouts: data_layouts(desc.data_layouts),
+                vertex: state.shader_function(&desc.vertex),
+                vertex_fetches: desc
+                    .vertex_fetches
+                    .iter()
+                    .map(|vf| super::VertexFetch {
+                        attributes: vf
+                            .layout
+                            .attributes
+                            .iter()
+                            .map(|&(name, attribute)| (name.to_string(), attribute))
+                            .collect(),
+                        stride: vf.layout.stride,
+                        instanced: vf.instanced,
+                    })
+                    .collect(),
+                primitive: desc.primitive.clone(),
+                depth_stencil: desc.depth_stencil.clone(),
+                fragment: desc.fragment.as_ref().map(|sf| state.shader_function(sf)),
+                color_targets: desc.color_targets.to_vec(),
+                multisample_state: desc.multisample_state,
+            };
+            state.write(&action);
+        })
+    }
+
+    pub(crate) fn destroy_render_pipeline(&self, key: u64) {
+        self.with(|state| {
+            let id = remove(&mut state.handles.pipelines, &key);
+            state.write(&Action::DestroyRenderPipeline { id });
+        })
+    }
+
+    /// Frames are recorded when acquired, since they don't come from `create_texture`.
+    pub(crate) fn acquire_frame(
+        &self,
+        texture: crate::Texture,
+        view: crate::TextureView,
+        format: crate::TextureFormat,
+        size: crate::Extent,
+    ) {
+        self.with(|state| {
+            let texture_id = match state.handles.textures.get(&Key(texture)) {
+                Some(&id) => id,
+                None => {
+                    let id = state.alloc_id();
+                    state.handles.textures.insert(Key(texture), id);
+                    id
+                }
+            };
+            let view_id = match state.handles.texture_views.get(&Key(view)) {
+                Some(&id) => id,
+                None => {
+                    let id = state.alloc_id();
+                    state.handles.texture_views.insert(Key(view), id);
+                    id
+                }
+            };
+            state.write(&Action::AcquireFrame {
+                texture: texture_id,
+                view: view_id,
+                format,
+                size,
+            });
+        })
+    }
+
+    pub(crate) fn encoder(self: &Arc<Self>, name: &str) -> EncoderTrace {
+        EncoderTrace {
+            recorder: Arc::clone(self),
+            name: name.to_string(),
+            commands: Vec::new(),
+            buffers: HashSet::default(),
+        }
+    }
+}
+
+/// Commands of a command encoder, recorded as they are encoded.
+pub(crate) struct EncoderTrace {
+    recorder: Arc<Recorder>,
+    name: String,
+    commands: Vec<Command>,
+    /// Host-visible buffers referenced by the commands.
+    buffers: HashSet<Key<crate::Buffer>>,
+}
+
+/// Translates resource handles into trace identifiers.
+pub(crate) struct Translator<'a> {
+    handles: &'a Handles,
+    buffers: &'a mut HashSet<Key<crate::Buffer>>,
+}
+
+fn lookup<T: Hash + PartialEq>(map: &HashMap<Key<T>, Id>, handle: T) -> Id {
+    *map.get(&Key(handle)).unwrap_or(&NULL)
+}
+
+impl Translator<'_> {
+    pub(crate) fn buffer(&mut self, piece: crate::BufferPiece) -> BufferPiece {
+        let buffer = match self.handles.buffers.get(&Key(piece.buffer)) {
+            Some(state) => {
+                if !piece.buffer.data().is_null() {
+                    self.buffers.insert(Key(piece.buffer));
+                }
+                state.id
+            }
+            None => NULL,
+        };
+        BufferPiece {
+            buffer,
+            offset: piece.offset,
+        }
+    }
+
+    pub(crate) fn texture(&self, texture: crate::Texture) -> Id {
+        lookup(&self.handles.textures, texture)
+    }
+
+    pub(crate) fn texture_piece(&self, piece: crate::TexturePiece) -> super::TexturePiece {
+        super::TexturePiece {
+            texture: self.texture(piece.texture),
+            mip_level: piece.mip_level,
+            array_layer: piece.array_layer,
+            origin: piece.origin,
+        }
+    }
+
+    pub(crate) fn texture_view(&self, view: crate::TextureView) -> Id {
+        lookup(&self.handles.texture_views, view)
+    }
+
+    pub(crate) fn acceleration_structure(
+        &self,
+        acceleration_structure: crate::AccelerationStructure,
+    ) -> Id {
+        lookup(
+            &self.handles.acceleration_structures,
+            acceleration_structure,
+        )
+    }
+
+    pub(crate) fn pipeline(&self, key: u64) -> Id {
+        *self.handles.pipelines.get(&key).unwrap_or(&NULL)
+    }
+
+    pub(crate) fn render_target(&self, rt: &crate::RenderTarget) -> super::RenderTarget {
+        super::RenderTarget {
+            view: self.texture_view(rt.view),
+            init_op: rt.init_op,
+            finish_op: match rt.finish_op {
+                crate::FinishOp::Store => super::FinishOp::Store,
+                crate::FinishOp::Discard => super::FinishOp::Discard,
+                crate::FinishOp::ResolveTo(view) => super::FinishOp::ResolveTo {
+                    view: self.texture_view(view),
+                },
+                crate::FinishOp::Ignore => super::FinishOp::Ignore,
+            },
+        }
+    }
+
+    pub(crate) fn mesh(
+        &mut self,
+        mesh: &crate::AccelerationStructureMesh,
+    ) -> super::AccelerationStructureMesh {
+        super::AccelerationStructureMesh {
+            vertex_data: self.buffer(mesh.vertex_data),
+            vertex_format: mesh.vertex_format,
+            vertex_stride: mesh.vertex_stride,
+            vertex_count: mesh.vertex_count,
+            index_data: self.buffer(mesh.index_data),
+            index_type: mesh.index_type,
+            triangle_count: mesh.triangle_count,
+            transform_data: self.buffer(mesh.transform_data),
+            is_opaque: mesh.is_opaque,
+        }
+    }
+
+    pub(crate) fn bindings(&mut self, bound: Vec<(u32, super::Bound)>) -> Vec<(u32, Binding)> {
+        bound
+            .into_iter()
+            .map(|(index, bound)| {
+                let binding = match bound {
+                    super::Bound::Texture(view) => Binding::Texture {
+                        view: self.texture_view(view),
+                    },
+                    super::Bound::TextureArray { views, count } => Binding::TextureArray {
+                        views: views.into_iter().map(|v| self.texture_view(v)).collect(),
+                        count,
+                    },
+                    super::Bound::Sampler(sampler) => Binding::Sampler {
+                        sampler: lookup(&self.handles.samplers, sampler),
+                    },
+                    super::Bound::Buffer(piece) => Binding::Buffer {
+                        piece: self.buffer(piece),
+                    },
+                    super::Bound::BufferArray { pieces, count } => Binding::BufferArray {
+                        pieces: pieces.into_iter().map(|p| self.buffer(p)).collect(),
+                        count,
+                    },
+                    super::Bound::AccelerationStructure(acceleration_structure) => {
+                        Binding::AccelerationStructure {
+                            acceleration_structure: self
+                                .acceleration_structure(acceleration_structure),
+                        }
+                    }
+                    super::Bound::Plain(data) => Binding::Plain { data },
+                };
+                (index, binding)
+            })
+            .collect()
+    }
+}
+
+impl EncoderTrace {
+    /// Forget the commands of a previous recording that was never submitted.
+    pub(crate) fn start(&mut self) {
+        self.commands.clear();
+        self.buffers.clear();
+    }
+
+    /// Start the trace of a render chunk, to be joined back with `join`.
+    /// Chunks don't inherit the state of the pass, so the full-target viewport
+    /// and scissor are recorded explicitly.
+    pub(crate) fn chunk(&self, target_size: [u16; 2]) -> Self {
+        let [w, h] = target_size;
+        Self {
+            recorder: Arc::clone(&self.recorder),
+            name: self.name.clone(),
+            commands: vec![
+                Command::SetViewport {
+                    viewport: crate::Viewport {
+                        x: 0.0,
+                        y: 0.0,
+                        w: w as f32,
+                        h: h as f32,
+                        depth: 0.0..1.0,
+                    },
+                },
+                Command::SetScissorRect {
+                    rect: crate::ScissorRect {
+                        x: 0,
+                        y: 0,
+                        w: w as u32,
+                        h: h as u32,
+                    },
+                },
+            ],
+            buffers: HashSet::default(),
+        }
+    }
+
+    /// Append the commands of a render chunk.
+    pub(crate) fn join(&mut self, chunk: Self) {
+        self.commands.extend(chunk.commands);
+        self.buffers.extend(chunk.buffers);
+    }
+
+    /// Capture the referenced buffers, and write all the recorded commands.
+    pub(crate) fn submit(&mut self) {
+        let mut state = self.recorder.state.lock().unwrap();
+        for key in self.buffers.drain() {
+            state.capture_buffer(key.0);
+        }
+        let action = Action::Submit {
+            encoder: self.name.clone(),
+            commands: std::mem::take(&mut self.commands),
+        };
+        state.write(&action);
+        // Submissions are the likely points of a crash, so don't keep them in memory
+        if let Err(e) = state.file.flush() {
+            log::error!("Unable to flush the trace: {:?}", e);
+        }
+    }
+}
+
+/// Record a command into the encoder trace, if tracing is enabled.
+pub(crate) fn record(
+    trace: &mut Option<EncoderTrace>,
+    make: impl FnOnce(&mut Translator) -> Command,
+) {
+    if let Some(ref mut trace) = *trace {
+        let state = trace.recorder.state.lock().unwrap();
+        let mut translator = Translator {
+            handles: &state.handles,
+            buffers: &mut trace.buffers,
+        };
+        let command = make(&mut translator);
+        drop(state);
+        trace.commands.push(command);
+    }
+}
@@ -0,0 +1,1037 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    Action, Binding, BufferPiece, Command, Id, NULL, TexturePiece,
+    serial::{Reader, Serial as _, invalid},
+};
+use crate::{ShaderBindable as _, traits as t};
+
+fn unknown(what: &str, id: Id) -> io::Error {
+    invalid(format!("Unknown {} {}", what, id))
+}
+
+fn unexpected(command: &Command) -> io::Error {
+    invalid(format!("Unexpected command {:?}", command))
+}
+
+fn next(commands: &[Command]) -> io::Result<(&Command, &[Command])> {
+    commands
+        .split_first()
+        .ok_or_else(|| invalid("Pass is not finished"))
+}
+
+/// Pipeline descriptors refer to binding names with static lifetime,
+/// so the names of the replayed pipelines are leaked.
+fn leak(name: &str) -> &'static str {
+    Box::leak(name.to_string().into_boxed_str())
+}
+
+fn leak_layout(layout: &super::DataLayout) -> crate::ShaderDataLayout {
+    crate::ShaderDataLayout {
+        bindings: layout
+            .iter()
+            .map(|&(ref name, binding)| (leak(name), binding))
+            .collect(),
+    }
+}
+
+/// Array slots that were free at the time of recording may refer to destroyed resources.
+/// They are filled with the first valid resource of the array instead.
+fn resolve_array<I, T: Copy>(
+    items: &[I],
+    lookup: impl Fn(&I) -> io::Result<T>,
+) -> io::Result<Vec<T>> {
+    let first = match items.iter().find_map(|item| lookup(item).ok()) {
+        Some(first) => first,
+        None if items.is_empty() => return Ok(Vec::new()),
+        None => return Err(invalid("Resource array has no valid resources")),
+    };
+    Ok(items
+        .iter()
+        .map(|item| lookup(item).unwrap_or(first))
+        .collect())
+}
+
+/// Bound resources are only known at run time, while the binding code
+/// needs the plain data size and array sizes as constants.
+macro_rules! with_size {
+    ($size:expr, $fun:ident $args:tt, [$($n:literal)*]) => {
+        match $size {
+            $( $n => $fun::<$n> $args, )*
+            other => log::error!(
+                "Replay doesn't support {} of size {}",
+                stringify!($fun),
+                other
+            ),
+        }
+    };
+}
+
+fn bind_plain<const N: usize>(data: &[u8], context: &mut crate::PipelineContext, index: u32) {
+    let value: [u8; N] = data.try_into().unwrap();
+    value.bind_to(context, index);
+}
+
+fn bind_texture_array<const N: crate::ResourceIndex>(
+    views: &[crate::TextureView],
+    context: &mut crate::PipelineContext,
+    index: u32,
+) {
+    let mut array = crate::TextureArray::<N>::new();
+    for &view in views {
+        array.alloc(view);
+    }
+    (&array).bind_to(context, index);
+}
+
+fn bind_buffer_array<const N: crate::ResourceIndex>(
+    pieces: &[crate::BufferPiece],
+    context: &mut crate::PipelineContext,
+    index: u32,
+) {
+    let mut array = crate::BufferArray::<N>::new();
+    for &piece in pieces {
+        array.alloc(piece);
+    }
+    (&array).bind_to(context, index);
+}
+
+enum Resolved<'a> {
+    Texture(crate::TextureView),
+    TextureArray(Vec<crate::TextureView>, u32),
+    Sampler(crate::Sampler),
+    Buffer(crate::BufferPiece),
+    BufferArray(Vec<crate::BufferPiece>, u32),
+    AccelerationStructure(crate::AccelerationStructure),
+    Plain(&'a [u8]),
+}
+
+/// Shader data of a recorded `bind` call.
+struct ReplayData<'a> {
+    bindings: Vec<(u32, Resolved<'a>)>,
+}
+
+impl crate::ShaderData for ReplayData<'_> {
+    fn layout() -> crate::ShaderDataLayout {
+        // Pipelines are created from the recorded layouts instead
+        crate::ShaderDataLayout::default()
+    }
+
+    fn fill(&self, mut context: crate::PipelineContext) {
+        let ctx = &mut context;
+        for &(index, ref resolved) in self.bindings.iter() {
+            match *resolved {
+                Resolved::Texture(view) => view.bind_to(ctx, index),
+                Resolved::TextureArray(ref views, count) => with_size!(
+                    count,
+                    bind_texture_array(views, ctx, index),
+                    [1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30
+                    31 32 48 64 96 100 128 256 500 512 1000 1024 2048 4096]
+                ),
+                Resolved::Sampler(sampler) => sampler.bind_to(ctx, index),
+                Resolved::Buffer(piece) => piece.bind_to(ctx, index),
+                Resolved::BufferArray(ref pieces, count) => with_size!(
+                    count,
+                    bind_buffer_array(pieces, ctx, index),
+                    [1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30
+                    31 32 48 64 96 100 128 256 500 512 1000 1024 2048 4096]
+                ),
+                Resolved::AccelerationStructure(acceleration_structure) => {
+                    acceleration_structure.bind_to(ctx, index)
+                }
+                Resolved::Plain(data) => with_size!(
+                    data.len(),
+                    bind_plain(data, ctx, index),
+                    [4 8 12 16 20 24 28 32 36 40 44 48 52 56 60 64 68 72 76 80 84 88 92 96 100 104
+                    108 112 116 120 124 128 132 136 140 144 148 152 156 160 164 168 172 176 180 184
+                    188 192 196 200 204 208 212 216 220 224 228 232 236 240 244 248 252 256]
+                ),
+            }
+        }
+    }
+}
+
+struct Resources {
+    buffers: HashMap<Id, (crate::Buffer, String)>,
+    textures: HashMap<Id, crate::Texture>,
+    texture_views: HashMap<Id, crate::TextureView>,
+    samplers: HashMap<Id, crate::Sampler>,
+    acceleration_structures: HashMap<Id, crate::AccelerationStructure>,
+    compute_pipelines: HashMap<Id, crate::ComputePipeline>,
+    render_pipelines: HashMap<Id, crate::RenderPipeline>,
+    /// Used by meshes that were built without a transform.
+    identity_transform: crate::Buffer,
+}
+
+impl Resources {
+    fn buffer(&self, id: Id) -> io::Result<crate::Buffer> {
+        match self.buffers.get(&id) {
+            Some(&(buffer, _)) => Ok(buffer),
+            None => Err(unknown("buffer", id)),
+        }
+    }
+
+    fn buffer_piece(&self, piece: BufferPiece) -> io::Result<crate::BufferPiece> {
+        Ok(self.buffer(piece.buffer)?.at(piece.offset))
+    }
+
+    fn texture(&self, id: Id) -> io::Result<crate::Texture> {
+        self.textures
+            .get(&id)
+            .copied()
+            .ok_or_else(|| unknown("texture", id))
+    }
+
+    fn texture_piece(&self, piece: TexturePiece) -> io::Result<crate::TexturePiece> {
+        Ok(crate::TexturePiece {
+            texture: self.texture(piece.texture)?,
+            mip_level: piece.mip_level,
+            array_layer: piece.array_layer,
+            origin: piece.origin,
+        })
+    }
+
+    fn texture_view(&self, id: Id) -> io::Result<crate::TextureView> {
+        self.texture_views
+            .get(&id)
+            .copied()
+            .ok_or_else(|| unknown("texture view", id))
+    }
+
+    fn acceleration_structure(&self, id: Id) -> io::Result<crate::AccelerationStructure> {
+        self.acceleration_structures
+            .get(&id)
+            .copied()
+            .ok_or_else(|| unknown("acceleration structure", id))
+    }
+
+    fn render_target(&self, rt: &super::RenderTarget) -> io::Result<crate::RenderTarget> {
+        Ok(crate::RenderTarget {
+            view: self.texture_view(rt.view)?,
+            init_op: rt.init_op,
+            finish_op: match rt.finish_op {
+                super::FinishOp::Store => crate::FinishOp::Store,
+                super::FinishOp::Discard => crate::FinishOp::Discard,
+                super::FinishOp::ResolveTo { view } => {
+                    crate::FinishOp::ResolveTo(self.texture_view(view)?)
+                }
+                super::FinishOp::Ignore => crate::FinishOp::Ignore,
+            },
+        })
+    }
+
+    fn mesh(
+        &self,
+        mesh: &super::AccelerationStructureMesh,
+    ) -> io::Result<crate::AccelerationStructureMesh> {
+        let vertex_data = self.buffer_piece(mesh.vertex_data)?;
+        Ok(crate::AccelerationStructureMesh {
+            vertex_data,
+            vertex_format: mesh.vertex_format,
+            vertex_stride: mesh.vertex_stride,
+            vertex_count: mesh.vertex_count,
+            index_data: match mesh.index_type {
+                Some(_) => self.buffer_piece(mesh.index_data)?,
+                None => vertex_data,
+            },
+            index_type: mesh.index_type,
+            triangle_count: mesh.triangle_count,
+            transform_data: match mesh.transform_data.buffer {
+                NULL => self.identity_transform.into(),
+                _ => self.buffer_piece(mesh.transform_data)?,
+            },
+            is_opaque: mesh.is_opaque,
+        })
+    }
+
+    fn bind(
+        &self,
+        pc: &mut impl t::PipelineEncoder,
+        group: u32,
+        bindings: &[(u32, Binding)],
+    ) -> io::Result<()> {
+        let mut data = ReplayData {
+            bindings: Vec::with_capacity(bindings.len()),
+        };
+        for &(index, ref binding) in bindings {
+            let resolved = match *binding {
+                Binding::Texture { view } => Resolved::Texture(self.texture_view(view)?),
+                Binding::TextureArray { ref views, count } => Resolved::TextureArray(
+                    resolve_array(views, |&id| self.texture_view(id))?,
+                    count,
+                ),
+                Binding::Sampler { sampler } => Resolved::Sampler(
+                    self.samplers
+                        .get(&sampler)
+                        .copied()
+                        .ok_or_else(|| unknown("sampler", sampler))?,
+                ),
+                Binding::Buffer { piece } => Resolved::Buffer(self.buffer_piece(piece)?),
+                Binding::BufferArray { ref pieces, count } => Resolved::BufferArray(
+                    resolve_array(pieces, |&piece| self.buffer_piece(piece))?,
+                    count,
+                ),
+                Binding::AccelerationStructure {
+                    acceleration_structure,
+                } => Resolved::AccelerationStructure(
+                    self.acceleration_structure(acceleration_structure)?,
+                ),
+                Binding::Plain { ref data } => Resolved::Plain(data),
+            };
+            data.bindings.push((index, resolved));
+        }
+        pc.bind(group, &data);
+        Ok(())
+    }
+
+    fn transfer<'c>(
+        &self,
+        pass: &mut impl t::TransferEncoder<
+            BufferPiece = crate::BufferPiece,
+            TexturePiece = crate::TexturePiece,
+        >,
+        mut rest: &'c [Command],
+    ) -> io::Result<&'c [Command]> {
+        loop {
+            let (command, tail) = next(rest)?;
+            rest = tail;
+            match *command {
+                Command::FillBuffer { dst, size, value } => {
+                    pass.fill_buffer(self.buffer_piece(dst)?, size, value)
+                }
+                Command::CopyBufferToBuffer { src, dst, size } => pass.copy_buffer_to_buffer(
+                    self.buffer_piece(src)?,
+                    self.buffer_piece(dst)?,
+                    size,
+                ),
+                Command::CopyTextureToTexture { src, dst, size } => pass.copy_texture_to_texture(
+                    self.texture_piece(src)?,
+                    self.texture_piece(dst)?,
+                    size,
+                ),
+                Command::CopyBufferToTexture {
+                    src,
+                    bytes_per_row,
+                    dst,
+                    size,
+                } => pass.copy_buffer_to_texture(
+                    self.buffer_piece(src)?,
+                    bytes_per_row,
+                    self.texture_piece(dst)?,
+                    size,
+                ),
+                Command::CopyTextureToBuffer {
+                    src,
+                    dst,
+                    bytes_per_row,
+                    size,
+                } => pass.copy_texture_to_buffer(
+                    self.texture_piece(src)?,
+                    self.buffer_piece(dst)?,
+                    bytes_per_row,
+                    size,
+                ),
+                Command::EndPass => return Ok(rest),
+                ref other => return Err(unexpected(other)),
+            }
+        }
+    }
+
+    fn acceleration_structure_pass<'c>(
+        &self,
+        pass: &mut impl t::AccelerationStructureEncoder<
+            AccelerationStructure = crate::AccelerationStructure,
+            AccelerationStructureMesh = crate::AccelerationStructureMesh,
+            BufferPiece = crate::BufferPiece,
+        >,
+        mut rest: &'c [Command],
+    ) -> io::Result<&'c [Command]> {
+        loop {
+            let (command, tail) = next(rest)?;
+            rest = tail;
+            match *command {
+                Command::BuildBottomLevel {
+                    acceleration_structure,
+                    ref meshes,
+                    scratch_data,
+                } => {
+                    let meshes = meshes
+                        .iter()
+                        .map(|mesh| self.mesh(mesh))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    pass.build_bottom_level(
+                        self.acceleration_structure(acceleration_structure)?,
+                        &meshes,
+                        self.buffer_piece(scratch_data)?,
+                    );
+                }
+                Command::BuildTopLevel {
+                    acceleration_structure,
+                    ref bottom_level,
+                    instance_count,
+                    instance_data,
+                    scratch_data,
+                } => {
+                    let bottom_level = bottom_level
+                        .iter()
+                        .map(|&id| self.acceleration_structure(id))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    pass.build_top_level(
+                        self.acceleration_structure(acceleration_structure)?,
+                        &bottom_level,
+                        instance_count,
+                        self.buffer_piece(instance_data)?,
+                        self.buffer_piece(scratch_data)?,
+                    );
+                }
+                Command::EndPass => return Ok(rest),
+                ref other => return Err(unexpected(other)),
+            }
+        }
+    }
+
+    /// Execute the commands of a compute pipeline, until the pipeline is changed.
+    fn compute_pipeline<'c>(
+        &self,
+        pc: &mut impl t::ComputePipelineEncoder<BufferPiece = crate::BufferPiece>,
+        mut rest: &'c [Command],
+    ) -> io::Result<&'c [Command]> {
+        while let Some((command, tail)) = rest.split_first() {
+            match *command {
+                Command::Bind {
+                    group,
+                    ref bindings,
+                } => self.bind(pc, group, bindings)?,
+                Command::Dispatch { groups } => pc.dispatch(groups),
+                Command::DispatchIndirect { indirect_buf } => {
+                    pc.dispatch_indirect(self.buffer_piece(indirect_buf)?)
+                }
+                _ => break,
+            }
+            rest = tail;
+        }
+        Ok(rest)
+    }
+
+    /// Execute a render state command, return false if it's something else.
+    fn render_state(&self, encoder: &mut impl t::RenderEncoder, command: &Command) -> bool {
+        match *command {
+            Command::SetScissorRect { ref rect } => encoder.set_scissor_rect(rect),
+            Command::SetViewport { ref viewport } => encoder.set_viewport(viewport),
+            Command::SetStencilReference { reference } => encoder.set_stencil_reference(reference),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Execute the commands of a render pipeline, until the pipeline is changed.
+    fn render_pipeline<'c>(
+        &self,
+        pc: &mut impl t::RenderPipelineEncoder<BufferPiece = crate::BufferPiece>,
+        mut rest: &'c [Command],
+    ) -> io::Result<&'c [Command]> {
+        while let Some((command, tail)) = rest.split_first() {
+            match *command {
+                Command::Bind {
+                    group,
+                    ref bindings,
+                } => self.bind(pc, group, bindings)?,
+                Command::BindVertex { index, vertex_buf } => {
+                    pc.bind_vertex(index, self.buffer_piece(vertex_buf)?)
+                }
+                Command::Draw {
+                    first_vertex,
+                    vertex_count,
+                    first_instance,
+                    instance_count,
+                } => pc.draw(first_vertex, vertex_count, first_instance, instance_count),
+                Command::DrawIndexed {
+                    index_buf,
+                    index_type,
+                    index_count,
+                    base_vertex,
+                    start_instance,
+                    instance_count,
+                } => pc.draw_indexed(
+                    self.buffer_piece(index_buf)?,
+                    index_type,
+                    index_count,
+                    base_vertex,
+                    start_instance,
+                    instance_count,
+                ),
+                Command::DrawIndirect { indirect_buf } => {
+                    pc.draw_indirect(self.buffer_piece(indirect_buf)?)
+                }
+                Command::DrawIndexedIndirect {
+                    index_buf,
+                    index_type,
+                    indirect_buf,
+                } => pc.draw_indexed_indirect(
+                    self.buffer_piece(index_buf)?,
+                    index_type,
+                    self.buffer_piece(indirect_buf)?,
+                ),
+                _ if self.render_state(pc, command) => {}
+                _ => break,
+            }
+            rest = tail;
+        }
+        Ok(rest)
+    }
+}
+
+/// Executes a trace recorded with [`ContextDesc::trace_path`](crate::ContextDesc::trace_path).
+///
+/// Submissions are executed one at a time, waiting for each to finish.
+/// Presentation is skipped, and frames are rendered into regular textures.
+pub struct Replayer<'a> {
+    context: &'a crate::Context,
+    dir: PathBuf,
+    actions: Vec<Action>,
+    next: usize,
+    encoder: crate::CommandEncoder,
+    shaders: HashMap<String, crate::Shader>,
+    resources: Resources,
+    /// Frame textures that need to be initialized by the next submission.
+    new_frames: Vec<crate::Texture>,
+}
+
+impl<'a> Replayer<'a> {
+    /// Load the trace from a directory.
+    pub fn new(context: &'a crate::Context, dir: &Path) -> io::Result<Self> {
+        let data = fs::read(dir.join(super::TRACE_FILE))?;
+        let mut reader = match data.strip_prefix(super::MAGIC) {
+            Some(rest) => Reader::new(rest),
+            None => return Err(invalid("Not a trace file")),
+        };
+        let mut actions = Vec::new();
+        while !reader.is_empty() {
+            match Action::read(&mut reader) {
+                Ok(action) => actions.push(action),
+                // The application may have crashed in the middle of writing
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    log::warn!("Trace is truncated after {} actions", actions.len());
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let identity_transform = context.create_buffer(crate::BufferDesc {
+            name: "identity transform",
+            size: size_of::<crate::Transform>() as u64,
+            memory: crate::Memory::Shared,
+        });
+        unsafe {
+            (identity_transform.data() as *mut crate::Transform)
+                .write_unaligned(crate::IDENTITY_TRANSFORM);
+        }
+        context.sync_buffer(identity_transform);
+
+        Ok(Self {
+            context,
+            dir: dir.to_path_buf(),
+            actions,
+            next: 0,
+            encoder: context.create_command_encoder(crate::CommandEncoderDesc {
+                name: "replay",
+                buffer_count: 1,
+                ..Default::default()
+            }),
+            shaders: HashMap::default(),
+            resources: Resources {
+                buffers: HashMap::default(),
+                textures: HashMap::default(),
+                texture_views: HashMap::default(),
+                samplers: HashMap::default(),
+                acceleration_structures: HashMap::default(),
+                compute_pipelines: HashMap::default(),
+                render_pipelines: HashMap::default(),
+                identity_transform,
+            },
+            new_frames: Vec::new(),
+        })
+    }
+
+    /// Total number of recorded actions.
+    pub fn action_count(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Execute the next action. Returns false when the trace is finished.
+    pub fn step(&mut self) -> io::Result<bool> {
+        if self.next == self.actions.len() {
+            return Ok(false);
+        }
+        let actions = std::mem::take(&mut self.actions);
+        let result = self.execute(&actions[self.next]);
+        self.actions = actions;
+        self.next += 1;
+        result.map(|()| true)
+    }
+
+    /// Execute all the remaining actions.
+    pub fn run(&mut self) -> io::Result<()> {
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Find the most recently created live buffer with a given name.
+    pub fn buffer(&self, name: &str) -> Option<crate::Buffer> {
+        self.resources
+            .buffers
+            .iter()
+            .filter(|&(_, entry)| entry.1 == name)
+            .max_by_key(|&(&id, _)| id)
+            .map(|(_, &(buffer, _))| buffer)
+    }
+
+    fn load_shader(&mut self, file_name: &str) -> io::Result<()> {
+        if !self.shaders.contains_key(file_name) {
+            let path = self.dir.join(file_name);
+            let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+            let shader = if extension == "spv" {
+                let data = fs::read(&path)?;
+                self.context.create_shader(crate::ShaderDesc {
+                    language: crate::ShaderLanguage::SpirV(&data),
+                    ..Default::default()
+                })
+            } else {
+                let source = fs::read_to_string(&path)?;
+                let language = [
+                    naga::ShaderStage::Vertex,
+                    naga::ShaderStage::Fragment,
+                    naga::ShaderStage::Compute,
+                ]
+                .into_iter()
+                .find(|&stage| super::glsl_extension(stage) == extension)
+                .map_or(crate::ShaderLanguage::Wgsl, |stage| {
+                    crate::ShaderLanguage::Glsl { stage }
+                });
+                self.context.create_shader(crate::ShaderDesc {
+                    source: &source,
+                    language,
+                    ..Default::default()
+                })
+            };
+            self.shaders.insert(file_name.to_string(), shader);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, action: &Action) -> io::Result<()> {
+        let context = self.context;
+        let res = &mut self.resources;
+        match *action {
+            Action::CreateBuffer {
+                id,
+                ref name,
+                size,
+                memory,
+            } => {
+                let buffer = context.create_buffer(crate::BufferDesc { name, size, memory });
+                res.buffers.insert(id, (buffer, name.clone()));
+            }
+            Action::WriteBuffer { id, ref data } => {
+                let buffer = res.buffer(id)?;
+                if buffer.data().is_null() {
+                    return Err(invalid(format!("Buffer {} is not host-visible", id)));
+                }
+                unsafe {
+                    std::ptr::copy_nonoverlapping(data.as_ptr(), buffer.data(), data.len());
+                }
+                context.sync_buffer(buffer);
+            }
+            Action::DestroyBuffer { id } => {
+                let (buffer, _) = res
+                    .buffers
+                    .remove(&id)
+                    .ok_or_else(|| unknown("buffer", id))?;
+                context.destroy_buffer(buffer);
+            }
+            Action::CreateTexture {
+                id,
+                ref name,
+                format,
+                size,
+                array_layer_count,
+                mip_level_count,
+                sample_count,
+                dimension,
+                usage,
+            } => {
+                let texture = context.create_texture(crate::TextureDesc {
+                    name,
+                    format,
+                    size,
+                    array_layer_count,
+                    mip_level_count,
+                    sample_count,
+                    dimension,
+                    usage,
+                    external: None,
+                });
+                res.textures.insert(id, texture);
+            }
+            Action::DestroyTexture { id } => {
+                let texture = res
+                    .textures
+                    .remove(&id)
+                    .ok_or_else(|| unknown("texture", id))?;
+                context.destroy_texture(texture);
+            }
+            Action::CreateTextureView {
+                id,
+                texture,
+                ref name,
+                format,
+                dimension,
+                ref subresources,
+            } => {
+                let view = context.create_texture_view(
+                    res.texture(texture)?,
+                    crate::TextureViewDesc {
+                        name,
+                        format,
+                        dimension,
+                        subresources,
+                    },
+                );
+                res.texture_views.insert(id, view);
+            }
+            Action::DestroyTextureView { id } => {
+                let view = res
+                    .texture_views
+                    .remove(&id)
+                    .ok_or_else(|| unknown("texture view", id))?;
+                context.destroy_texture_view(view);
+            }
+            Action::CreateSampler {
+                id,
+                ref name,
+                address_modes,
+                mag_filter,
+                min_filter,
+                mipmap_filter,
+                lod_min_clamp,
+                lod_max_clamp,
+                compare,
+                anisotropy_clamp,
+                border_color,
+            } => {
+                let sampler = context.create_sampler(crate::SamplerDesc {
+                    name,
+                    address_modes,
+                    mag_filter,
+                    min_filter,
+                    mipmap_filter,
+                    lod_min_clamp,
+                    lod_max_clamp,
+                    compare,
+                    anisotropy_clamp,
+                    border_color,
+                });
+                res.samplers.insert(id, sampler);
+            }
+            Action::DestroySampler { id } => {
+                let sampler = res
+                    .samplers
+                    .remove(&id)
+                    .ok_or_else(|| unknown("sampler", id))?;
+                context.destroy_sampler(sampler);
+            }
+            Action::CreateAccelerationStructure {
+                id,
+                ref name,
+                ref ty,
+                size,
+            } => {
+                let acceleration_structure =
+                    context.create_acceleration_structure(crate::AccelerationStructureDesc {
+                        name,
+                        ty: match *ty {
+                            crate::AccelerationStructureType::TopLevel => {
+                                crate::AccelerationStructureType::TopLevel
+                            }
+                            crate::AccelerationStructureType::BottomLevel => {
+                                crate::AccelerationStructureType::BottomLevel
+                            }
+                        },
+                        size,
+                    });
+                res.acceleration_structures
+                    .insert(id, acceleration_structure);
+            }
+            Action::DestroyAccelerationStructure { id } => {
+                let acceleration_structure = res
+                    .acceleration_structures
+                    .remove(&id)
+                    .ok_or_else(|| unknown("acceleration structure", id))?;
+                context.destroy_acceleration_structure(acceleration_structure);
+            }
+            Action::CreateComputePipeline {
+                id,
+                ref name,
+                ref data_layouts,
+                ref compute,
+            } => {
+                self.load_shader(&compute.shader)?;
+                let layouts = data_layouts.iter().map(leak_layout).collect::<Vec<_>>();
+                let layout_refs = layouts.iter().collect::<Vec<_>>();
+                let constants = compute.constants.iter().cloned().collect();
+                let pipeline = context.create_compute_pipeline(crate::ComputePipelineDesc {
+                    name,
+                    data_layouts: &layout_refs,
+                    compute: crate::ShaderFunction {
+                        shader: &self.shaders[&compute.shader],
+                        entry_point: &compute.entry_point,
+                        constants: &constants,
+                    },
+                });
+                self.resources.compute_pipelines.insert(id, pipeline);
+            }
+            Action::DestroyComputePipeline { id } => {
+                let mut pipeline = res
+                    .compute_pipelines
+                    .remove(&id)
+                    .ok_or_else(|| unknown("compute pipeline", id))?;
+                context.destroy_compute_pipeline(&mut pipeline);
+            }
+            Action::CreateRenderPipeline {
+                id,
+                ref name,
+                ref data_layouts,
+                ref vertex,
+                ref vertex_fetches,
+                ref primitive,
+                ref depth_stencil,
+                ref fragment,
+                ref color_targets,
+                multisample_state,
+            } => {
+                self.load_shader(&vertex.shader)?;
+                if let Some(ref sf) = *fragment {
+                    self.load_shader(&sf.shader)?;
+                }
+                let layouts = data_layouts.iter().map(leak_layout).collect::<Vec<_>>();
+                let layout_refs = layouts.iter().collect::<Vec<_>>();
+                let vertex_layouts = vertex_fetches
+                    .iter()
+                    .map(|vf| crate::VertexLayout {
+                        attributes: vf
+                            .attributes
+                            .iter()
+                            .map(|&(ref name, attribute)| (leak(name), attribute))
+                            .collect(),
+                        stride: vf.stride,
+                    })
+                    .collect::<Vec<_>>();
+                let vertex_fetch_states = vertex_fetches
+                    .iter()
+                    .zip(vertex_layouts.iter())
+                    .map(|(vf, layout)| crate::VertexFetchState {
+                        layout,
+                        instanced: vf.instanced,
+                    })
+                    .collect::<Vec<_>>();
+                let vertex_constants = vertex.constants.iter().cloned().collect();
+                let fragment_constants = fragment
+                    .as_ref()
+                    .map(|sf| sf.constants.iter().cloned().collect())
+                    .unwrap_or_default();
+                let pipeline = context.create_render_pipeline(crate::RenderPipelineDesc {
+                    name,
+                    data_layouts: &layout_refs,
+                    vertex: crate::ShaderFunction {
+                        shader: &self.shaders[&vertex.shader],
+                        entry_point: &vertex.entry_point,
+                        constants: &vertex_constants,
+                    },
+                    vertex_fetches: &vertex_fetch_states,
+                    primitive: primitive.clone(),
+                    depth_stencil: depth_stencil.clone(),
+                    fragment: fragment.as_ref().map(|sf| crate::ShaderFunction {
+                        shader: &self.shaders[&sf.shader],
+                        entry_point: &sf.entry_point,
+                        constants: &fragment_constants,
+                    }),
+                    color_targets,
+                    multisample_state,
+                });
+                self.resources.render_pipelines.insert(id, pipeline);
+            }
+            Action::DestroyRenderPipeline { id } => {
+                let mut pipeline = res
+                    .render_pipelines
+                    .remove(&id)
+                    .ok_or_else(|| unknown("render pipeline", id))?;
+                context.destroy_render_pipeline(&mut pipeline);
+            }
+            Action::AcquireFrame {
+                texture,
+                view,
+                format,
+                size,
+            } => {
+                if let Entry::Vacant(entry) = res.textures.entry(texture) {
+                    let frame_texture = context.create_texture(crate::TextureDesc {
+                        name: "frame",
+                        format,
+                        size,
+                        array_layer_count: 1,
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: crate::TextureDimension::D2,
+                        usage: crate::TextureUsage::TARGET | crate::TextureUsage::COPY,
+                        external: None,
+                    });
+                    let frame_view = context.create_texture_view(
+                        frame_texture,
+                        crate::TextureViewDesc {
+                            name: "frame",
+                            format,
+                            dimension: crate::ViewDimension::D2,
+                            subresources: &Default::default(),
+                        },
+                    );
+                    entry.insert(frame_texture);
+                    res.texture_views.insert(view, frame_view);
+                    self.new_frames.push(frame_texture);
+                }
+            }
+            Action::Submit {
+                ref encoder,
+                ref commands,
+            } => {
+                log::debug!("Submitting {} commands of '{}'", commands.len(), encoder);
+                self.submit(commands)?
+            }
+        }
+        Ok(())
+    }
+
+    fn submit(&mut self, commands: &[Command]) -> io::Result<()> {
+        let res = &self.resources;
+        self.encoder.start();
+        for texture in self.new_frames.drain(..) {
+            self.encoder.init_texture(texture);
+        }
+
+        let mut rest = commands;
+        while let Some((command, tail)) = rest.split_first() {
+            rest = tail;
+            match *command {
+                Command::InitTexture { texture } => {
+                    self.encoder.init_texture(res.texture(texture)?);
+                }
+                // There is nothing to present to
+                Command::Present { texture } => log::debug!("Skipping present of {}", texture),
+                Command::BeginTransfer { ref label } => {
+                    let mut pass = self.encoder.transfer(label);
+                    rest = res.transfer(&mut pass, rest)?;
+                }
+                Command::BeginAccelerationStructure { ref label } => {
+                    let mut pass = self.encoder.acceleration_structure(label);
+                    rest = res.acceleration_structure_pass(&mut pass, rest)?;
+                }
+                Command::BeginCompute { ref label } => {
+                    let mut pass = self.encoder.compute(label);
+                    loop {
+                        let (command, tail) = next(rest)?;
+                        rest = tail;
+                        match *command {
+                            Command::SetComputePipeline { pipeline } => {
+                                let pipeline = res
+                                    .compute_pipelines
+                                    .get(&pipeline)
+                                    .ok_or_else(|| unknown("compute pipeline", pipeline))?;
+                                let mut pc = pass.with(pipeline);
+                                rest = res.compute_pipeline(&mut pc, rest)?;
+                            }
+                            Command::EndPass => break,
+                            ref other => return Err(unexpected(other)),
+                        }
+                    }
+                }
+                Command::BeginRender {
+                    ref label,
+                    ref colors,
+                    ref depth_stencil,
+                } => {
+                    let colors = colors
+                        .iter()
+                        .map(|rt| res.render_target(rt))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    let depth_stencil = depth_stencil
+                        .as_ref()
+                        .map(|rt| res.render_target(rt))
+                        .transpose()?;
+                    let mut pass = self.encoder.render(
+                        label,
+                        crate::RenderTargetSet {
+                            colors: &colors,
+                            depth_stencil,
+                        },
+                    );
+                    loop {
+                        let (command, tail) = next(rest)?;
+                        rest = tail;
+                        match *command {
+                            Command::SetRenderPipeline { pipeline } => {
+                                let pipeline = res
+                                    .render_pipelines
+                                    .get(&pipeline)
+                                    .ok_or_else(|| unknown("render pipeline", pipeline))?;
+                                let mut pc = pass.with(pipeline);
+                                rest = res.render_pipeline(&mut pc, rest)?;
+                            }
+                            Command::EndPass => break,
+                            _ if res.render_state(&mut pass, command) => {}
+                            ref other => return Err(unexpected(other)),
+                        }
+                    }
+                }
+                ref other => return Err(unexpected(other)),
+            }
+        }
+
+        let sync_point = self.context.submit(&mut self.encoder);
+        self.context.wait_for(&sync_point, !0);
+        Ok(())
+    }
+}
+
+impl Drop for Replayer<'_> {
+    fn drop(&mut self) {
+        let context = self.context;
+        let res = &mut self.resources;
+        for (_, mut pipeline) in res.compute_pipelines.drain() {
+            context.destroy_compute_pipeline(&mut pipeline);
+        }
+        for (_, mut pipeline) in res.render_pipelines.drain() {
+            context.destroy_render_pipeline(&mut pipeline);
+        }
+        for (_, acceleration_structure) in res.acceleration_structures.drain() {
+            context.destroy_acceleration_structure(acceleration_structure);
+        }
+        for (_, sampler) in res.samplers.drain() {
+            context.destroy_sampler(sampler);
+        }
+        for (_, view) in res.texture_views.drain() {
+            context.destroy_texture_view(view);
+        }
+        for (_, texture) in res.textures.drain() {
+            context.destroy_texture(texture);
+        }
+        for (_, (buffer, _)) in res.buffers.drain() {
+            context.destroy_buffer(buffer);
+        }
+        context.destroy_buffer(res.identity_transform);
+        context.destroy_command_encoder(&mut self.encoder);
+    }
+}
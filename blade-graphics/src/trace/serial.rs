@@ -0,0 +1,527 @@
+//! Binary encoding of the trace.
+//!
+//! Everything is little-endian. Enum variants are stored by name,
+//! so that reordering variants doesn't break existing traces.
+
+use std::{io, num::NonZeroU32, ops::Range};
+
+pub(super) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[derive(Default)]
+pub(super) struct Writer {
+    pub data: Vec<u8>,
+}
+
+pub(super) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if count > self.data.len() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        let (head, tail) = self.data.split_at(count);
+        self.data = tail;
+        Ok(head)
+    }
+}
+
+pub(super) trait Serial: Sized {
+    fn write(&self, w: &mut Writer);
+    fn read(r: &mut Reader) -> io::Result<Self>;
+}
+
+macro_rules! serial_primitive {
+    ($($ty:ty)*) => {$(
+        impl Serial for $ty {
+            fn write(&self, w: &mut Writer) {
+                w.data.extend_from_slice(&self.to_le_bytes());
+            }
+            fn read(r: &mut Reader) -> io::Result<Self> {
+                let bytes = r.take(size_of::<Self>())?;
+                Ok(Self::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )*};
+}
+
+serial_primitive!(u8 u16 u32 u64 i32 f32 f64);
+
+impl Serial for bool {
+    fn write(&self, w: &mut Writer) {
+        (*self as u8).write(w);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        match u8::read(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(invalid(format!("Invalid boolean {}", other))),
+        }
+    }
+}
+
+impl Serial for NonZeroU32 {
+    fn write(&self, w: &mut Writer) {
+        self.get().write(w);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        NonZeroU32::new(u32::read(r)?).ok_or_else(|| invalid("Unexpected zero"))
+    }
+}
+
+impl Serial for String {
+    fn write(&self, w: &mut Writer) {
+        self.as_bytes().to_vec().write(w);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        String::from_utf8(Vec::read(r)?).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+impl Serial for Vec<u8> {
+    fn write(&self, w: &mut Writer) {
+        (self.len() as u32).write(w);
+        w.data.extend_from_slice(self);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        let count = u32::read(r)? as usize;
+        Ok(r.take(count)?.to_vec())
+    }
+}
+
+/// Vectors of anything but bytes.
+pub(super) trait Element: Serial {}
+
+impl<T: Element> Serial for Vec<T> {
+    fn write(&self, w: &mut Writer) {
+        (self.len() as u32).write(w);
+        for item in self {
+            item.write(w);
+        }
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        let count = u32::read(r)? as usize;
+        (0..count).map(|_| T::read(r)).collect()
+    }
+}
+
+impl<T: Serial> Serial for Option<T> {
+    fn write(&self, w: &mut Writer) {
+        match *self {
+            Some(ref value) => {
+                true.write(w);
+                value.write(w);
+            }
+            None => false.write(w),
+        }
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        Ok(match bool::read(r)? {
+            true => Some(T::read(r)?),
+            false => None,
+        })
+    }
+}
+
+impl<T: Serial, const N: usize> Serial for [T; N] {
+    fn write(&self, w: &mut Writer) {
+        for item in self {
+            item.write(w);
+        }
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        let items = (0..N).map(|_| T::read(r)).collect::<io::Result<Vec<_>>>()?;
+        Ok(items.try_into().ok().unwrap())
+    }
+}
+
+impl<A: Serial, B: Serial> Serial for (A, B) {
+    fn write(&self, w: &mut Writer) {
+        self.0.write(w);
+        self.1.write(w);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        Ok((A::read(r)?, B::read(r)?))
+    }
+}
+impl<A: Serial, B: Serial> Element for (A, B) {}
+
+impl Serial for Range<f32> {
+    fn write(&self, w: &mut Writer) {
+        self.start.write(w);
+        self.end.write(w);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        Ok(f32::read(r)?..f32::read(r)?)
+    }
+}
+
+/// Implement `Serial` for structures by listing their fields.
+macro_rules! serial_struct {
+    ($($ty:path { $($field:ident),* $(,)? })*) => {$(
+        impl $crate::trace::serial::Serial for $ty {
+            fn write(&self, w: &mut $crate::trace::serial::Writer) {
+                $( self.$field.write(w); )*
+            }
+            fn read(r: &mut $crate::trace::serial::Reader) -> std::io::Result<Self> {
+                Ok(Self {
+                    $( $field: $crate::trace::serial::Serial::read(r)?, )*
+                })
+            }
+        }
+    )*};
+}
+
+/// Implement `Serial` for enums by listing their variants.
+/// Only unit and struct-like variants are supported.
+macro_rules! serial_enum {
+    ($($ty:path { $($variant:ident $({ $($field:ident),* })?),* $(,)? })*) => {$(
+        impl $crate::trace::serial::Serial for $ty {
+            fn write(&self, w: &mut $crate::trace::serial::Writer) {
+                match *self {
+                    $( Self::$variant $({ $(ref $field),* })? => {
+                        stringify!($variant).to_string().write(w);
+                        $($( $field.write(w); )*)?
+                    } )*
+                }
+            }
+            fn read(r: &mut $crate::trace::serial::Reader) -> std::io::Result<Self> {
+                let name = String::read(r)?;
+                Ok(match name.as_str() {
+                    $( stringify!($variant) => Self::$variant $({
+                        $( $field: $crate::trace::serial::Serial::read(r)?, )*
+                    })?, )*
+                    other => {
+                        return Err($crate::trace::serial::invalid(format!(
+                            "Unknown {} variant '{}'",
+                            stringify!($ty),
+                            other
+                        )))
+                    }
+                })
+            }
+        }
+    )*};
+}
+
+macro_rules! serial_bits {
+    ($($ty:path)*) => {$(
+        impl Serial for $ty {
+            fn write(&self, w: &mut Writer) {
+                self.bits().write(w);
+            }
+            fn read(r: &mut Reader) -> io::Result<Self> {
+                Self::from_bits(Serial::read(r)?)
+                    .ok_or_else(|| invalid(concat!("Invalid ", stringify!($ty))))
+            }
+        }
+    )*};
+}
+
+pub(super) use {serial_enum, serial_struct};
+
+serial_bits!(crate::TextureUsage crate::ColorWrites);
+
+serial_struct! {
+    crate::Extent { width, height, depth }
+    crate::TextureSubresources {
+        base_mip_level,
+        mip_level_count,
+        base_array_layer,
+        array_layer_count,
+    }
+    crate::VertexAttribute { offset, format }
+    crate::PrimitiveState {
+        topology,
+        front_face,
+        cull_mode,
+        unclipped_depth,
+        wireframe,
+    }
+    crate::StencilFaceState {
+        compare,
+        fail_op,
+        depth_fail_op,
+        pass_op,
+    }
+    crate::StencilState {
+        front,
+        back,
+        read_mask,
+        write_mask,
+    }
+    crate::DepthBiasState { constant, slope_scale, clamp }
+    crate::DepthStencilState {
+        format,
+        depth_write_enabled,
+        depth_compare,
+        stencil,
+        bias,
+    }
+    crate::BlendComponent { src_factor, dst_factor, operation }
+    crate::BlendState { color, alpha }
+    crate::ColorTargetState { format, blend, write_mask }
+    crate::MultisampleState {
+        sample_count,
+        sample_mask,
+        alpha_to_coverage,
+    }
+    crate::ScissorRect { x, y, w, h }
+    crate::Viewport { x, y, w, h, depth }
+}
+
+impl Element for crate::ColorTargetState {}
+
+serial_enum! {
+    crate::TextureFormat {
+        R8Unorm,
+        Rg8Unorm,
+        Rg8Snorm,
+        Rgba8Unorm,
+        Rgba8UnormSrgb,
+        Bgra8Unorm,
+        Bgra8UnormSrgb,
+        Rgba8Snorm,
+        R16Float,
+        Rg16Float,
+        Rgba16Float,
+        R32Float,
+        Rg32Float,
+        Rgba32Float,
+        R32Uint,
+        Rg32Uint,
+        Rgba32Uint,
+        R16Unorm,
+        Rgba16Unorm,
+        R8Uint,
+        Rg16Uint,
+        R32Sint,
+        Depth32Float,
+        Depth32FloatStencil8Uint,
+        Stencil8Uint,
+        Depth16Unorm,
+        Depth24PlusStencil8,
+        Bc1Unorm,
+        Bc1UnormSrgb,
+        Bc2Unorm,
+        Bc2UnormSrgb,
+        Bc3Unorm,
+        Bc3UnormSrgb,
+        Bc4Unorm,
+        Bc4Snorm,
+        Bc5Unorm,
+        Bc5Snorm,
+        Bc6hUfloat,
+        Bc6hFloat,
+        Bc7Unorm,
+        Bc7UnormSrgb,
+        Rgb10a2Unorm,
+        Rg11b10Ufloat,
+        Rgb9e5Ufloat,
+        Etc2Rgb8Unorm,
+        Etc2Rgb8UnormSrgb,
+        Etc2Rgb8A1Unorm,
+        Etc2Rgb8A1UnormSrgb,
+        Etc2Rgba8Unorm,
+        Etc2Rgba8UnormSrgb,
+        EacR11Unorm,
+        EacR11Snorm,
+        EacRg11Unorm,
+        EacRg11Snorm,
+        Astc4x4Unorm,
+        Astc4x4UnormSrgb,
+        Astc5x4Unorm,
+        Astc5x4UnormSrgb,
+        Astc5x5Unorm,
+        Astc5x5UnormSrgb,
+        Astc6x5Unorm,
+        Astc6x5UnormSrgb,
+        Astc6x6Unorm,
+        Astc6x6UnormSrgb,
+        Astc8x5Unorm,
+        Astc8x5UnormSrgb,
+        Astc8x6Unorm,
+        Astc8x6UnormSrgb,
+        Astc8x8Unorm,
+        Astc8x8UnormSrgb,
+        Astc10x5Unorm,
+        Astc10x5UnormSrgb,
+        Astc10x6Unorm,
+        Astc10x6UnormSrgb,
+        Astc10x8Unorm,
+        Astc10x8UnormSrgb,
+        Astc10x10Unorm,
+        Astc10x10UnormSrgb,
+        Astc12x10Unorm,
+        Astc12x10UnormSrgb,
+        Astc12x12Unorm,
+        Astc12x12UnormSrgb,
+    }
+    crate::TextureDimension { D1, D2, D3 }
+    crate::ViewDimension {
+        D1,
+        D1Array,
+        D2,
+        D2Array,
+        Cube,
+        CubeArray,
+        D3,
+    }
+    crate::AddressMode {
+        ClampToEdge,
+        Repeat,
+        MirrorRepeat,
+        ClampToBorder,
+    }
+    crate::FilterMode { Nearest, Linear }
+    crate::CompareFunction {
+        Never,
+        Less,
+        Equal,
+        LessEqual,
+        Greater,
+        NotEqual,
+        GreaterEqual,
+        Always,
+    }
+    crate::TextureColor {
+        TransparentBlack,
+        OpaqueBlack,
+        White,
+    }
+    crate::AccelerationStructureType { TopLevel, BottomLevel }
+    crate::VertexFormat {
+        F32,
+        F32Vec2,
+        F32Vec3,
+        F32Vec4,
+        U32,
+        U32Vec2,
+        U32Vec3,
+        U32Vec4,
+        I32,
+        I32Vec2,
+        I32Vec3,
+        I32Vec4,
+        Unorm8x4,
+        Snorm8x4,
+        Uint8x4,
+        Unorm16x2,
+        Unorm16x4,
+        Snorm16x2,
+        Snorm16x4,
+        Float16x2,
+        Float16x4,
+        Rgb10a2Unorm,
+    }
+    crate::IndexType { U16, U32 }
+    crate::ShaderBinding {
+        Texture,
+        TextureArray { count },
+        Sampler,
+        Buffer,
+        BufferArray { count },
+        AccelerationStructure,
+        Plain { size },
+        Bindless { kind, count },
+    }
+    crate::BindlessKind { Texture, Buffer, Sampler }
+    crate::PrimitiveTopology {
+        PointList,
+        LineList,
+        LineStrip,
+        TriangleList,
+        TriangleStrip,
+    }
+    crate::FrontFace { Ccw, Cw }
+    crate::Face { Front, Back }
+    crate::StencilOperation {
+        Keep,
+        Zero,
+        Replace,
+        Invert,
+        IncrementClamp,
+        DecrementClamp,
+        IncrementWrap,
+        DecrementWrap,
+    }
+    crate::BlendFactor {
+        Zero,
+        One,
+        Src,
+        OneMinusSrc,
+        SrcAlpha,
+        OneMinusSrcAlpha,
+        Dst,
+        OneMinusDst,
+        DstAlpha,
+        OneMinusDstAlpha,
+        SrcAlphaSaturated,
+        Constant,
+        OneMinusConstant,
+        Src1,
+        OneMinusSrc1,
+        Src1Alpha,
+        OneMinusSrc1Alpha,
+    }
+    crate::BlendOperation {
+        Add,
+        Subtract,
+        ReverseSubtract,
+        Min,
+        Max,
+    }
+}
+
+/// External memory can't be carried over to another process,
+/// so it's recorded as shared memory.
+impl Serial for crate::Memory {
+    fn write(&self, w: &mut Writer) {
+        let name = match *self {
+            Self::Device => "Device",
+            Self::Shared | Self::External(_) => "Shared",
+            Self::Upload => "Upload",
+        };
+        name.to_string().write(w);
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        match String::read(r)?.as_str() {
+            "Device" => Ok(Self::Device),
+            "Shared" => Ok(Self::Shared),
+            "Upload" => Ok(Self::Upload),
+            other => Err(invalid(format!("Unknown memory '{}'", other))),
+        }
+    }
+}
+
+impl Serial for crate::InitOp {
+    fn write(&self, w: &mut Writer) {
+        match *self {
+            Self::Load => "Load".to_string().write(w),
+            Self::Clear(color) => {
+                "Clear".to_string().write(w);
+                color.write(w);
+            }
+            Self::DontCare => "DontCare".to_string().write(w),
+        }
+    }
+    fn read(r: &mut Reader) -> io::Result<Self> {
+        match String::read(r)?.as_str() {
+            "Load" => Ok(Self::Load),
+            "Clear" => Ok(Self::Clear(Serial::read(r)?)),
+            "DontCare" => Ok(Self::DontCare),
+            other => Err(invalid(format!("Unknown init op '{}'", other))),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Registry of the live resources of a context.
+//!
+//! Tracking is enabled by [`ContextDesc::track_resources`](crate::ContextDesc::track_resources).
+//! Every buffer, texture, view, sampler, acceleration structure, query set, and pipeline
+//! is registered under the name given in its descriptor, until it's destroyed.
+//! The registry is available via `Context::live_resources`, and the resources
+//! that are still alive when the context is dropped are reported as leaks.
+//!
+//! Creation backtraces are captured according to `RUST_LIB_BACKTRACE`
+//! (or `RUST_BACKTRACE`), see [`std::backtrace::Backtrace::capture`].
+
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Kind of a tracked resource.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum ResourceType {
+    Buffer,
+    Texture,
+    TextureView,
+    Sampler,
+    AccelerationStructure,
+    QuerySet,
+    ComputePipeline,
+    RenderPipeline,
+}
+
+/// Snapshot of a resource that is alive.
+#[derive(Clone, Debug)]
+pub struct LiveResource {
+    pub ty: ResourceType,
+    pub name: String,
+    /// Size of the contents in bytes, as requested by the descriptor.
+    /// Zero for the objects that don't own any memory, such as views and pipelines.
+    pub size: u64,
+    /// Where the resource was created, if backtraces are enabled.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl fmt::Display for LiveResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} '{}'", self.ty, self.name)?;
+        if self.size != 0 {
+            write!(f, " of {} bytes", self.size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Makes the resource handles usable as hash map keys.
+#[derive(Clone, Copy, Hash, PartialEq)]
+pub(crate) struct Key<T>(T);
+impl<T: PartialEq> Eq for Key<T> {}
+
+#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+pub(crate) enum Handle {
+    Buffer(Key<crate::Buffer>),
+    Texture(Key<crate::Texture>),
+    TextureView(Key<crate::TextureView>),
+    Sampler(Key<crate::Sampler>),
+    AccelerationStructure(Key<crate::AccelerationStructure>),
+    QuerySet(Key<crate::QuerySet>),
+    /// Pipelines are identified by a key provided by the backend.
+    ComputePipeline(u64),
+    RenderPipeline(u64),
+}
+
+macro_rules! impl_handle {
+    ($($name:ident)*) => {$(
+        impl From<crate::$name> for Handle {
+            fn from(resource: crate::$name) -> Self {
+                Self::$name(Key(resource))
+            }
+        }
+    )*};
+}
+impl_handle!(Buffer Texture TextureView Sampler AccelerationStructure QuerySet);
+
+impl Handle {
+    fn ty(&self) -> ResourceType {
+        match *self {
+            Self::Buffer(_) => ResourceType::Buffer,
+            Self::Texture(_) => ResourceType::Texture,
+            Self::TextureView(_) => ResourceType::TextureView,
+            Self::Sampler(_) => ResourceType::Sampler,
+            Self::AccelerationStructure(_) => ResourceType::AccelerationStructure,
+            Self::QuerySet(_) => ResourceType::QuerySet,
+            Self::ComputePipeline(_) => ResourceType::ComputePipeline,
+            Self::RenderPipeline(_) => ResourceType::RenderPipeline,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Creation index, to list the resources in the order of creation.
+    next_index: u64,
+    live: HashMap<Handle, (u64, LiveResource)>,
+}
+
+/// Tracks the live resources of a context, see the [module docs](self).
+#[derive(Default)]
+pub(crate) struct Tracker {
+    state: Mutex<State>,
+}
+
+impl Tracker {
+    pub(crate) fn from_desc(desc: &crate::ContextDesc) -> Option<Self> {
+        if desc.track_resources {
+            Some(Self::default())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn add(&self, handle: impl Into<Handle>, name: &str, size: u64) {
+        let handle = handle.into();
+        let backtrace = Backtrace::capture();
+        let resource = LiveResource {
+            ty: handle.ty(),
+            name: name.to_string(),
+            size,
+            backtrace: match backtrace.status() {
+                BacktraceStatus::Captured => Some(Arc::new(backtrace)),
+                _ => None,
+            },
+        };
+        let mut state = self.state.lock().unwrap();
+        let index = state.next_index;
+        state.next_index += 1;
+        state.live.insert(handle, (index, resource));
+    }
+
+    pub(crate) fn remove(&self, handle: impl Into<Handle>) {
+        self.state.lock().unwrap().live.remove(&handle.into());
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<LiveResource> {
+        let state = self.state.lock().unwrap();
+        let mut list = state.live.values().collect::<Vec<_>>();
+        list.sort_by_key(|&&(index, _)| index);
+        list.into_iter().map(|entry| entry.1.clone()).collect()
+    }
+
+    /// Log the resources that are still alive.
+    pub(crate) fn report_leaks(&self) {
+        let leaks = self.snapshot();
+        if leaks.is_empty() {
+            return;
+        }
+        log::warn!(
+            "{} resources are leaked, with {} bytes in total:",
+            leaks.len(),
+            leaks.iter().map(|resource| resource.size).sum::<u64>()
+        );
+        for resource in leaks {
+            match resource.backtrace {
+                Some(ref backtrace) => log::warn!("\t{}, created at:\n{}", resource, backtrace),
+                None => log::warn!("\t{}", resource),
+            }
+        }
+    }
+}
+
+/// Size of the query results in bytes.
+pub(crate) fn query_set_size(desc: &crate::QuerySetDesc) -> u64 {
+    (desc.count as usize * desc.ty.value_count() * size_of::<u64>()) as u64
+}
+
+/// Size of the texture contents in bytes, including all mips, layers, and samples.
+pub(crate) fn texture_size(desc: &crate::TextureDesc) -> u64 {
+    let block = desc.format.block_info();
+    let layer_size = (0..desc.mip_level_count)
+        .map(|level| {
+            let extent = desc.size.at_mip_level(level);
+            let columns = extent.width.div_ceil(block.dimensions.0 as u32) as u64;
+            let rows = extent.height.div_ceil(block.dimensions.1 as u32) as u64;
+            columns * rows * extent.depth as u64 * block.size as u64
+        })
+        .sum::<u64>();
+    layer_size * desc.array_layer_count as u64 * desc.sample_count as u64
+}
@@ -7,9 +7,11 @@ pub trait ResourceDevice {
     type Sampler: Send + Sync + Clone + Copy + Debug + Hash + PartialEq;
     type AccelerationStructure: Send + Sync + Clone + Copy + Debug + Hash + PartialEq;
 
+    fn try_create_buffer(&self, desc: super::BufferDesc) -> Result<Self::Buffer, super::Error>;
     fn create_buffer(&self, desc: super::BufferDesc) -> Self::Buffer;
     fn sync_buffer(&self, buffer: Self::Buffer);
     fn destroy_buffer(&self, buffer: Self::Buffer);
+    fn try_create_texture(&self, desc: super::TextureDesc) -> Result<Self::Texture, super::Error>;
     fn create_texture(&self, desc: super::TextureDesc) -> Self::Texture;
     fn destroy_texture(&self, texture: Self::Texture);
     fn create_texture_view(
@@ -31,12 +33,68 @@ pub trait ShaderDevice {
     type ComputePipeline: Send + Sync;
     type RenderPipeline: Send + Sync;
 
+    fn try_create_compute_pipeline(
+        &self,
+        desc: super::ComputePipelineDesc,
+    ) -> Result<Self::ComputePipeline, super::Error>;
     fn create_compute_pipeline(&self, desc: super::ComputePipelineDesc) -> Self::ComputePipeline;
     fn destroy_compute_pipeline(&self, pipeline: &mut Self::ComputePipeline);
+    fn try_create_render_pipeline(
+        &self,
+        desc: super::RenderPipelineDesc,
+    ) -> Result<Self::RenderPipeline, super::Error>;
     fn create_render_pipeline(&self, desc: super::RenderPipelineDesc) -> Self::RenderPipeline;
     fn destroy_render_pipeline(&self, pipeline: &mut Self::RenderPipeline);
 }
 
+pub trait QueryDevice {
+    type QuerySet: Send + Sync + Clone + Copy + Debug + Hash + PartialEq;
+    type SyncPoint: Clone + Debug;
+
+    fn create_query_set(&self, desc: super::QuerySetDesc) -> Self::QuerySet;
+    fn destroy_query_set(&self, set: Self::QuerySet);
+    /// Read the results of a set, written by the submission of `sync_point`.
+    /// Returns `None` without blocking if the submission is not finished yet.
+    /// There are `QueryType::value_count` values for each query,
+    /// and the queries that weren't written are zero.
+    fn read_query_results(
+        &self,
+        set: Self::QuerySet,
+        sync_point: &Self::SyncPoint,
+    ) -> Option<Vec<u64>>;
+}
+
+pub trait BindlessDevice {
+    type BindlessTable;
+    type TextureView: Send + Sync + Clone + Copy + Debug + Hash + PartialEq;
+    type Sampler: Send + Sync + Clone + Copy + Debug + Hash + PartialEq;
+
+    fn create_bindless_table(&self, desc: super::BindlessTableDesc) -> Self::BindlessTable;
+    fn destroy_bindless_table(&self, table: &mut Self::BindlessTable);
+    /// Put a texture view into a free slot of the table.
+    fn insert_bindless_texture(
+        &self,
+        table: &mut Self::BindlessTable,
+        view: Self::TextureView,
+    ) -> super::BindlessHandle;
+    /// Put a storage buffer into a free slot of the table.
+    fn insert_bindless_buffer(
+        &self,
+        table: &mut Self::BindlessTable,
+        piece: super::BufferPiece,
+    ) -> super::BindlessHandle;
+    /// Put a sampler into a free slot of the table.
+    fn insert_bindless_sampler(
+        &self,
+        table: &mut Self::BindlessTable,
+        sampler: Self::Sampler,
+    ) -> super::BindlessHandle;
+    /// Free the slot of a resource. The work that accesses the resource has to be
+    /// submitted by now, and the slot isn't reused until the GPU is done with it.
+    /// The removed resource must not be accessed by the following submissions.
+    fn remove_bindless(&self, table: &mut Self::BindlessTable, handle: super::BindlessHandle);
+}
+
 pub trait CommandDevice {
     type CommandEncoder;
     type SyncPoint: Clone + Debug;
@@ -50,10 +108,27 @@ pub trait CommandDevice {
 pub trait CommandEncoder {
     type Texture: Send + Sync + Clone + Copy + Debug;
     type Frame: Send + Sync + Debug;
+    type SyncPoint: Clone + Debug;
+    type QuerySet: Send + Sync + Clone + Copy + Debug;
     fn start(&mut self);
+    /// Make the next submission wait on the GPU for a previous one,
+    /// which can be on a different queue.
+    fn depend_on(&mut self, sync_point: &Self::SyncPoint);
     fn init_texture(&mut self, texture: Self::Texture);
+    /// Start collecting the pipeline statistics of the following passes
+    /// into the query at `index` of a `QueryType::PipelineStatistics` set.
+    fn begin_statistics_query(&mut self, set: Self::QuerySet, index: u32);
+    fn end_statistics_query(&mut self, set: Self::QuerySet, index: u32);
     fn present(&mut self, frame: Self::Frame);
     fn timings(&self) -> &super::Timings;
+    fn stats(&self) -> &super::CommandStats;
+}
+
+/// Nested timing scopes, implemented by the command encoders and their passes.
+/// Scopes begun by a pass are ended together with it, if they weren't ended before.
+pub trait TimingEncoder {
+    fn begin_scope(&mut self, name: &str);
+    fn end_scope(&mut self);
 }
 
 pub trait TransferEncoder {
@@ -112,10 +187,18 @@ pub trait RenderEncoder {
     fn set_scissor_rect(&mut self, rect: &super::ScissorRect);
     fn set_viewport(&mut self, viewport: &super::Viewport);
     fn set_stencil_reference(&mut self, reference: u32);
+    /// Start counting the samples of the following draws into the query at `index`
+    /// of the occlusion set given to `render_with_queries`.
+    fn begin_occlusion_query(&mut self, index: u32, mode: super::OcclusionMode);
+    fn end_occlusion_query(&mut self, index: u32);
 }
 
 pub trait PipelineEncoder {
+    type BindlessTable;
+
     fn bind<D: super::ShaderData>(&mut self, group: u32, data: &D);
+    /// Bind a bindless table to a group with the layout of `BindlessTableDesc::layout`.
+    fn bind_table(&mut self, group: u32, table: &Self::BindlessTable);
 }
 
 pub trait ComputePipelineEncoder: PipelineEncoder {
@@ -55,6 +55,12 @@ impl super::TextureFormat {
                 size,
             }
         }
+        const fn cx_astc(width: u8, height: u8) -> super::TexelBlockInfo {
+            super::TexelBlockInfo {
+                dimensions: (width, height),
+                size: 16,
+            }
+        }
         match *self {
             Self::R8Unorm => uncompressed(1),
             Self::Rg8Unorm => uncompressed(2),
@@ -73,9 +79,16 @@ impl super::TextureFormat {
             Self::R32Uint => uncompressed(4),
             Self::Rg32Uint => uncompressed(8),
             Self::Rgba32Uint => uncompressed(16),
+            Self::R16Unorm => uncompressed(2),
+            Self::Rgba16Unorm => uncompressed(8),
+            Self::R8Uint => uncompressed(1),
+            Self::Rg16Uint => uncompressed(4),
+            Self::R32Sint => uncompressed(4),
             Self::Depth32Float => uncompressed(4),
             Self::Depth32FloatStencil8Uint => uncompressed(5),
             Self::Stencil8Uint => uncompressed(1),
+            Self::Depth16Unorm => uncompressed(2),
+            Self::Depth24PlusStencil8 => uncompressed(5),
             Self::Bc1Unorm => cx_bc(8),
             Self::Bc1UnormSrgb => cx_bc(8),
             Self::Bc2Unorm => cx_bc(16),
@@ -93,14 +106,82 @@ impl super::TextureFormat {
             Self::Rgb10a2Unorm => uncompressed(4),
             Self::Rg11b10Ufloat => uncompressed(4),
             Self::Rgb9e5Ufloat => uncompressed(4),
+            Self::Etc2Rgb8Unorm => cx_bc(8),
+            Self::Etc2Rgb8UnormSrgb => cx_bc(8),
+            Self::Etc2Rgb8A1Unorm => cx_bc(8),
+            Self::Etc2Rgb8A1UnormSrgb => cx_bc(8),
+            Self::Etc2Rgba8Unorm => cx_bc(16),
+            Self::Etc2Rgba8UnormSrgb => cx_bc(16),
+            Self::EacR11Unorm => cx_bc(8),
+            Self::EacR11Snorm => cx_bc(8),
+            Self::EacRg11Unorm => cx_bc(16),
+            Self::EacRg11Snorm => cx_bc(16),
+            Self::Astc4x4Unorm => cx_astc(4, 4),
+            Self::Astc4x4UnormSrgb => cx_astc(4, 4),
+            Self::Astc5x4Unorm => cx_astc(5, 4),
+            Self::Astc5x4UnormSrgb => cx_astc(5, 4),
+            Self::Astc5x5Unorm => cx_astc(5, 5),
+            Self::Astc5x5UnormSrgb => cx_astc(5, 5),
+            Self::Astc6x5Unorm => cx_astc(6, 5),
+            Self::Astc6x5UnormSrgb => cx_astc(6, 5),
+            Self::Astc6x6Unorm => cx_astc(6, 6),
+            Self::Astc6x6UnormSrgb => cx_astc(6, 6),
+            Self::Astc8x5Unorm => cx_astc(8, 5),
+            Self::Astc8x5UnormSrgb => cx_astc(8, 5),
+            Self::Astc8x6Unorm => cx_astc(8, 6),
+            Self::Astc8x6UnormSrgb => cx_astc(8, 6),
+            Self::Astc8x8Unorm => cx_astc(8, 8),
+            Self::Astc8x8UnormSrgb => cx_astc(8, 8),
+            Self::Astc10x5Unorm => cx_astc(10, 5),
+            Self::Astc10x5UnormSrgb => cx_astc(10, 5),
+            Self::Astc10x6Unorm => cx_astc(10, 6),
+            Self::Astc10x6UnormSrgb => cx_astc(10, 6),
+            Self::Astc10x8Unorm => cx_astc(10, 8),
+            Self::Astc10x8UnormSrgb => cx_astc(10, 8),
+            Self::Astc10x10Unorm => cx_astc(10, 10),
+            Self::Astc10x10UnormSrgb => cx_astc(10, 10),
+            Self::Astc12x10Unorm => cx_astc(12, 10),
+            Self::Astc12x10UnormSrgb => cx_astc(12, 10),
+            Self::Astc12x12Unorm => cx_astc(12, 12),
+            Self::Astc12x12UnormSrgb => cx_astc(12, 12),
+        }
+    }
+
+    /// The same format without the sRGB encoding.
+    pub const fn to_linear(&self) -> Self {
+        match *self {
+            Self::Rgba8UnormSrgb => Self::Rgba8Unorm,
+            Self::Bgra8UnormSrgb => Self::Bgra8Unorm,
+            Self::Bc1UnormSrgb => Self::Bc1Unorm,
+            Self::Bc2UnormSrgb => Self::Bc2Unorm,
+            Self::Bc3UnormSrgb => Self::Bc3Unorm,
+            Self::Bc7UnormSrgb => Self::Bc7Unorm,
+            Self::Etc2Rgb8UnormSrgb => Self::Etc2Rgb8Unorm,
+            Self::Etc2Rgb8A1UnormSrgb => Self::Etc2Rgb8A1Unorm,
+            Self::Etc2Rgba8UnormSrgb => Self::Etc2Rgba8Unorm,
+            Self::Astc4x4UnormSrgb => Self::Astc4x4Unorm,
+            Self::Astc5x4UnormSrgb => Self::Astc5x4Unorm,
+            Self::Astc5x5UnormSrgb => Self::Astc5x5Unorm,
+            Self::Astc6x5UnormSrgb => Self::Astc6x5Unorm,
+            Self::Astc6x6UnormSrgb => Self::Astc6x6Unorm,
+            Self::Astc8x5UnormSrgb => Self::Astc8x5Unorm,
+            Self::Astc8x6UnormSrgb => Self::Astc8x6Unorm,
+            Self::Astc8x8UnormSrgb => Self::Astc8x8Unorm,
+            Self::Astc10x5UnormSrgb => Self::Astc10x5Unorm,
+            Self::Astc10x6UnormSrgb => Self::Astc10x6Unorm,
+            Self::Astc10x8UnormSrgb => Self::Astc10x8Unorm,
+            Self::Astc10x10UnormSrgb => Self::Astc10x10Unorm,
+            Self::Astc12x10UnormSrgb => Self::Astc12x10Unorm,
+            Self::Astc12x12UnormSrgb => Self::Astc12x12Unorm,
+            other => other,
         }
     }
 
     pub fn aspects(&self) -> super::TexelAspects {
         match *self {
-            Self::Depth32Float => super::TexelAspects::DEPTH,
+            Self::Depth32Float | Self::Depth16Unorm => super::TexelAspects::DEPTH,
 
-            Self::Depth32FloatStencil8Uint => {
+            Self::Depth32FloatStencil8Uint | Self::Depth24PlusStencil8 => {
                 super::TexelAspects::DEPTH | super::TexelAspects::STENCIL
             }
 
@@ -111,6 +192,22 @@ impl super::TextureFormat {
     }
 }
 
+impl super::VertexFormat {
+    /// Size of an attribute in bytes.
+    pub const fn size(&self) -> u32 {
+        match *self {
+            Self::F32 | Self::U32 | Self::I32 => 4,
+            Self::Unorm8x4 | Self::Snorm8x4 | Self::Uint8x4 => 4,
+            Self::Unorm16x2 | Self::Snorm16x2 | Self::Float16x2 => 4,
+            Self::Rgb10a2Unorm => 4,
+            Self::Unorm16x4 | Self::Snorm16x4 | Self::Float16x4 => 8,
+            Self::F32Vec2 | Self::U32Vec2 | Self::I32Vec2 => 8,
+            Self::F32Vec3 | Self::U32Vec3 | Self::I32Vec3 => 12,
+            Self::F32Vec4 | Self::U32Vec4 | Self::I32Vec4 => 16,
+        }
+    }
+}
+
 impl super::TextureColor {
     pub const fn stencil_clear_value(&self) -> u32 {
         match *self {
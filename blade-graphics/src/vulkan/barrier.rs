@@ -0,0 +1,814 @@
+//! Tracking of the resource usage for the pipeline barriers.
+//!
+//! Every pass records how it uses the buffers and images. A barrier is only
+//! inserted when a usage depends on the writes of the previous passes (or,
+//! for a write, on their reads), and it covers just the stages and accesses
+//! involved. Transfer and compute commands are synchronized right before
+//! being recorded. Render passes are synchronized when they begin, since
+//! barriers are not allowed inside of the dynamic rendering, so the writes
+//! are made visible to all of the graphics stages at once, and they wait
+//! for all the previous accesses in case the shaders write to anything.
+//!
+//! Resources of the bindless tables are not tracked individually. A pass
+//! that binds a table sees all the writes of the previous passes, and the
+//! passes after it wait for its shader stages before doing anything.
+//!
+//! Images are in `GENERAL` layout after `init_texture`, which is what the
+//! shaders always see. Attachments and copies transition the subresources
+//! they touch into the optimal layouts, which are tracked per image. These
+//! go back to `GENERAL` when the image is bound to a shader, when a render
+//! pass begins (since the images bound in it can't be transitioned anymore),
+//! and at the end of the recording.
+
+use ash::vk;
+use std::{collections::HashMap, mem, slice};
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub(super) enum Resource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+}
+
+const WRITE_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw()
+        | vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+);
+
+/// Mips and layers of an image.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub(super) struct ImageRange {
+    base_mip_level: u32,
+    mip_level_count: u32,
+    base_array_layer: u32,
+    array_layer_count: u32,
+}
+
+impl ImageRange {
+    pub(super) const WHOLE: Self = Self {
+        base_mip_level: 0,
+        mip_level_count: vk::REMAINING_MIP_LEVELS,
+        base_array_layer: 0,
+        array_layer_count: vk::REMAINING_ARRAY_LAYERS,
+    };
+
+    pub(super) fn from_vk(range: &vk::ImageSubresourceRange) -> Self {
+        Self {
+            base_mip_level: range.base_mip_level,
+            mip_level_count: range.level_count,
+            base_array_layer: range.base_array_layer,
+            array_layer_count: range.layer_count,
+        }
+    }
+
+    pub(super) fn single(mip_level: u32, array_layer: u32) -> Self {
+        Self {
+            base_mip_level: mip_level,
+            mip_level_count: 1,
+            base_array_layer: array_layer,
+            array_layer_count: 1,
+        }
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        fn overlap(base_a: u32, count_a: u32, base_b: u32, count_b: u32) -> bool {
+            base_a < base_b.saturating_add(count_b) && base_b < base_a.saturating_add(count_a)
+        }
+        overlap(
+            self.base_mip_level,
+            self.mip_level_count,
+            other.base_mip_level,
+            other.mip_level_count,
+        ) && overlap(
+            self.base_array_layer,
+            self.array_layer_count,
+            other.base_array_layer,
+            other.array_layer_count,
+        )
+    }
+
+    fn to_vk(self, aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: self.base_mip_level,
+            level_count: self.mip_level_count,
+            base_array_layer: self.base_array_layer,
+            layer_count: self.array_layer_count,
+        }
+    }
+}
+
+/// Pipeline stages together with the memory accesses done by them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct Usage {
+    pub(super) stages: vk::PipelineStageFlags,
+    pub(super) access: vk::AccessFlags,
+}
+
+impl Usage {
+    const fn new(stages: vk::PipelineStageFlags, access: vk::AccessFlags) -> Self {
+        Self { stages, access }
+    }
+
+    pub(super) const TRANSFER_READ: Self = Self::new(
+        vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::TRANSFER_READ,
+    );
+    pub(super) const TRANSFER_WRITE: Self = Self::new(
+        vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::TRANSFER_WRITE,
+    );
+    pub(super) const VERTEX: Self = Self::new(
+        vk::PipelineStageFlags::VERTEX_INPUT,
+        vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+    );
+    pub(super) const INDEX: Self = Self::new(
+        vk::PipelineStageFlags::VERTEX_INPUT,
+        vk::AccessFlags::INDEX_READ,
+    );
+    pub(super) const INDIRECT: Self = Self::new(
+        vk::PipelineStageFlags::DRAW_INDIRECT,
+        vk::AccessFlags::INDIRECT_COMMAND_READ,
+    );
+    pub(super) const COLOR_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags::from_raw(
+            vk::AccessFlags::COLOR_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw(),
+        ),
+    );
+    pub(super) const RESOLVE_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+    );
+    pub(super) const DEPTH_STENCIL_ATTACHMENT: Self = Self::new(
+        vk::PipelineStageFlags::from_raw(
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS.as_raw()
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS.as_raw(),
+        ),
+        vk::AccessFlags::from_raw(
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ.as_raw()
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw(),
+        ),
+    );
+    /// Everything that a render pass can read, outside of its attachments.
+    pub(super) const GRAPHICS_READ: Self = Self::new(
+        vk::PipelineStageFlags::from_raw(
+            vk::PipelineStageFlags::DRAW_INDIRECT.as_raw()
+                | vk::PipelineStageFlags::VERTEX_INPUT.as_raw()
+                | vk::PipelineStageFlags::VERTEX_SHADER.as_raw()
+                | vk::PipelineStageFlags::FRAGMENT_SHADER.as_raw(),
+        ),
+        vk::AccessFlags::from_raw(
+            vk::AccessFlags::INDIRECT_COMMAND_READ.as_raw()
+                | vk::AccessFlags::INDEX_READ.as_raw()
+                | vk::AccessFlags::VERTEX_ATTRIBUTE_READ.as_raw()
+                | vk::AccessFlags::SHADER_READ.as_raw(),
+        ),
+    );
+    /// Everything that a render pass can access, outside of its attachments.
+    pub(super) const GRAPHICS: Self = Self::new(
+        Self::GRAPHICS_READ.stages,
+        vk::AccessFlags::from_raw(
+            Self::GRAPHICS_READ.access.as_raw() | vk::AccessFlags::SHADER_WRITE.as_raw(),
+        ),
+    );
+    const ALL: Self = Self::new(
+        vk::PipelineStageFlags::ALL_COMMANDS,
+        vk::AccessFlags::from_raw(
+            vk::AccessFlags::MEMORY_READ.as_raw() | vk::AccessFlags::MEMORY_WRITE.as_raw(),
+        ),
+    );
+
+    /// Usage of a resource bound to the shaders of the given stages.
+    pub(super) fn shader(stages: vk::PipelineStageFlags, access: crate::StorageAccess) -> Self {
+        let mut vk_access = vk::AccessFlags::empty();
+        if access.is_empty() || access.contains(crate::StorageAccess::LOAD) {
+            vk_access |= vk::AccessFlags::SHADER_READ;
+        }
+        if access.contains(crate::StorageAccess::STORE) {
+            vk_access |= vk::AccessFlags::SHADER_WRITE;
+        }
+        Self::new(stages, vk_access)
+    }
+
+    fn is_write(&self) -> bool {
+        self.access.intersects(WRITE_ACCESS)
+    }
+
+    fn include(&mut self, other: Self) {
+        self.stages |= other.stages;
+        self.access |= other.access;
+    }
+
+    fn contains(&self, other: Self) -> bool {
+        self.stages.contains(other.stages) && self.access.contains(other.access)
+    }
+}
+
+/// Dependency between the previous and the following commands.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Barrier {
+    pub(super) src: Usage,
+    pub(super) dst: Usage,
+}
+
+impl Barrier {
+    pub(super) fn include(&mut self, other: Self) {
+        self.src.include(other.src);
+        self.dst.include(other.dst);
+    }
+}
+
+/// Usage of a resource by the finished passes.
+#[derive(Debug, Default)]
+struct State {
+    /// The last write.
+    write: Usage,
+    /// Usage that already sees the last write.
+    visible: Usage,
+    /// Stages that read the resource after the last write.
+    read_stages: vk::PipelineStageFlags,
+    /// Stages that are already ordered after these reads.
+    read_ordered: vk::PipelineStageFlags,
+}
+
+impl State {
+    fn depend(&mut self, usage: Usage, barrier: &mut Barrier) {
+        if !self.write.stages.is_empty() && !self.visible.contains(usage) {
+            barrier.src.include(self.write);
+            barrier.dst.include(usage);
+            self.visible.include(usage);
+        }
+        if usage.is_write()
+            && !self.read_stages.is_empty()
+            && !self.read_ordered.contains(usage.stages)
+        {
+            // write-after-read only needs an execution dependency
+            barrier.src.stages |= self.read_stages;
+            barrier.dst.stages |= usage.stages;
+            self.read_ordered |= usage.stages;
+        }
+    }
+}
+
+/// Subresources of an image that are not in `GENERAL` layout.
+#[derive(Debug)]
+struct LayoutState {
+    range: ImageRange,
+    aspects: vk::ImageAspectFlags,
+    layout: vk::ImageLayout,
+}
+
+/// Layout transition that is not recorded yet.
+#[derive(Debug)]
+struct Transition {
+    image: vk::Image,
+    range: ImageRange,
+    aspects: vk::ImageAspectFlags,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    usage: Usage,
+}
+
+#[derive(Default)]
+pub(super) struct Tracker {
+    states: HashMap<Resource, State>,
+    /// Images with subresources out of `GENERAL` layout. The ranges don't overlap.
+    layouts: HashMap<vk::Image, Vec<LayoutState>>,
+    transitions: Vec<Transition>,
+    /// Transitions waiting to be recorded with the next barrier.
+    image_barriers: Vec<vk::ImageMemoryBarrier<'static>>,
+    /// Usage by the current pass, applied to the states when it ends.
+    pass: HashMap<Resource, Usage>,
+    /// Usage by the current pass that is not checked for dependencies yet.
+    pending: Vec<(Resource, Usage)>,
+    /// Some writes are not tracked by resource, i.e. the acceleration structure
+    /// builds, and they need a full barrier after.
+    untracked_writes: bool,
+    /// Usage of the resources in the bindless tables that is not checked yet.
+    pending_untracked: Usage,
+    /// Stages of the finished pass that read the resources in the bindless tables.
+    untracked_reads: vk::PipelineStageFlags,
+    in_pass: bool,
+    pass_has_barrier: bool,
+    stats: crate::CommandStats,
+}
+
+impl Tracker {
+    /// Reset the tracking for a new recording,
+    /// returning the statistics of the previous one.
+    pub(super) fn start(&mut self) -> crate::CommandStats {
+        self.states.clear();
+        self.layouts.clear();
+        self.transitions.clear();
+        self.image_barriers.clear();
+        self.pass.clear();
+        self.pending.clear();
+        self.untracked_writes = false;
+        self.pending_untracked = Usage::default();
+        self.untracked_reads = vk::PipelineStageFlags::empty();
+        self.in_pass = false;
+        mem::take(&mut self.stats)
+    }
+
+    /// Use a resource by the shaders, or a buffer by any command.
+    pub(super) fn use_resource(&mut self, resource: Resource, usage: Usage) {
+        match resource {
+            Resource::Buffer(_) => self.pending.push((resource, usage)),
+            Resource::Image(image) => {
+                self.use_image(
+                    image,
+                    vk::ImageAspectFlags::empty(),
+                    ImageRange::WHOLE,
+                    usage,
+                    vk::ImageLayout::GENERAL,
+                );
+            }
+        }
+    }
+
+    /// Use a range of an image in the given layout.
+    ///
+    /// Returns the layout to access the range in. It's `GENERAL` if the range
+    /// partially overlaps with another one that isn't in `GENERAL` layout.
+    pub(super) fn use_image(
+        &mut self,
+        image: vk::Image,
+        aspects: vk::ImageAspectFlags,
+        range: ImageRange,
+        usage: Usage,
+        layout: vk::ImageLayout,
+    ) -> vk::ImageLayout {
+        self.pending.push((Resource::Image(image), usage));
+        let transitions = &mut self.transitions;
+        let states = self.layouts.entry(image).or_default();
+        let mut result = layout;
+        let mut found = false;
+        states.retain_mut(|state| {
+            if !state.range.overlaps(&range) {
+                return true;
+            }
+            if state.range == range && layout != vk::ImageLayout::GENERAL {
+                found = true;
+                if state.layout != layout {
+                    transitions.push(Transition {
+                        image,
+                        range,
+                        aspects: state.aspects,
+                        old_layout: state.layout,
+                        new_layout: layout,
+                        usage,
+                    });
+                    state.layout = layout;
+                }
+                return true;
+            }
+            transitions.push(Transition {
+                image,
+                range: state.range,
+                aspects: state.aspects,
+                old_layout: state.layout,
+                new_layout: vk::ImageLayout::GENERAL,
+                usage,
+            });
+            result = vk::ImageLayout::GENERAL;
+            false
+        });
+        if !found && result != vk::ImageLayout::GENERAL {
+            transitions.push(Transition {
+                image,
+                range,
+                aspects,
+                old_layout: vk::ImageLayout::GENERAL,
+                new_layout: layout,
+                usage,
+            });
+            states.push(LayoutState {
+                range,
+                aspects,
+                layout,
+            });
+        }
+        if states.is_empty() {
+            self.layouts.remove(&image);
+        }
+        result
+    }
+
+    /// Bring all the images back to `GENERAL` layout, except for the given ranges.
+    pub(super) fn restore_layouts(&mut self, keep: &[(vk::Image, ImageRange)], usage: Usage) {
+        let transitions = &mut self.transitions;
+        self.layouts.retain(|&image, states| {
+            states.retain(|state| {
+                if keep.contains(&(image, state.range)) {
+                    return true;
+                }
+                transitions.push(Transition {
+                    image,
+                    range: state.range,
+                    aspects: state.aspects,
+                    old_layout: state.layout,
+                    new_layout: vk::ImageLayout::GENERAL,
+                    usage,
+                });
+                false
+            });
+            !states.is_empty()
+        });
+    }
+
+    /// Stop tracking the layout of an image, returning the current one.
+    /// Used when the image is transitioned outside of the tracker.
+    pub(super) fn forget_layout(&mut self, image: vk::Image) -> vk::ImageLayout {
+        self.transitions.retain(|t| t.image != image);
+        match self.layouts.remove(&image) {
+            Some(states) => states
+                .first()
+                .map_or(vk::ImageLayout::GENERAL, |state| state.layout),
+            None => vk::ImageLayout::GENERAL,
+        }
+    }
+
+    /// Turn the queued transitions into image barriers, ordering them after
+    /// all the previous accesses to the images.
+    fn resolve_transitions(&mut self, barrier: &mut Barrier) {
+        for t in self.transitions.drain(..) {
+            let resource = Resource::Image(t.image);
+            let mut src = Usage::default();
+            if let Some(usage) = self.pass.get(&resource) {
+                src.stages |= usage.stages;
+                src.access |= usage.access & WRITE_ACCESS;
+            }
+            let state = self.states.entry(resource).or_default();
+            src.stages |= state.write.stages | state.read_stages;
+            src.access |= state.write.access;
+            if src.stages.is_empty() {
+                src.stages = vk::PipelineStageFlags::TOP_OF_PIPE;
+            }
+            // Following accesses are ordered after the transition
+            *state = State {
+                write: Usage::new(t.usage.stages, t.usage.access & WRITE_ACCESS),
+                visible: t.usage,
+                read_stages: vk::PipelineStageFlags::empty(),
+                read_ordered: t.usage.stages,
+            };
+            barrier.src.include(src);
+            barrier.dst.stages |= t.usage.stages;
+            self.image_barriers.push(vk::ImageMemoryBarrier {
+                src_access_mask: src.access,
+                dst_access_mask: t.usage.access,
+                old_layout: t.old_layout,
+                new_layout: t.new_layout,
+                image: t.image,
+                subresource_range: t.range.to_vk(t.aspects),
+                ..Default::default()
+            });
+        }
+    }
+
+    /// Use the resources that aren't known to the tracker, i.e. in a bindless table.
+    pub(super) fn use_untracked(&mut self, usage: Usage) {
+        self.pending_untracked.include(usage);
+    }
+
+    /// Take over the pending usage of another tracker, i.e. of a render chunk.
+    pub(super) fn absorb(&mut self, other: &mut Self) {
+        self.pending.append(&mut other.pending);
+        self.transitions.append(&mut other.transitions);
+        self.pending_untracked
+            .include(mem::take(&mut other.pending_untracked));
+    }
+
+    /// Check the pending usage against the finished passes.
+    pub(super) fn check_pending(&mut self) -> Barrier {
+        let mut barrier = Barrier::default();
+        for (resource, usage) in self.pending.drain(..) {
+            if let Some(state) = self.states.get_mut(&resource) {
+                state.depend(usage, &mut barrier);
+            }
+            self.pass.entry(resource).or_default().include(usage);
+        }
+        let untracked = mem::take(&mut self.pending_untracked);
+        if !untracked.stages.is_empty() {
+            barrier.include(self.make_visible(untracked));
+            self.untracked_reads |= untracked.stages;
+            self.restore_layouts(&[], untracked);
+        }
+        self.resolve_transitions(&mut barrier);
+        barrier
+    }
+
+    /// Make the writes of the finished passes visible to `usage`,
+    /// regardless of the resources it's going to access.
+    pub(super) fn make_visible(&mut self, usage: Usage) -> Barrier {
+        let mut barrier = Barrier::default();
+        for state in self.states.values_mut() {
+            state.depend(usage, &mut barrier);
+        }
+        barrier
+    }
+
+    /// Synchronize everything, including untracked writes, with all of the following commands.
+    pub(super) fn sync_all(&mut self) -> Barrier {
+        let mut barrier = Barrier::default();
+        for (_, state) in self.states.drain() {
+            barrier.src.stages |= state.write.stages | state.read_stages;
+            barrier.src.access |= state.write.access;
+        }
+        barrier.src.stages |= mem::take(&mut self.untracked_reads);
+        if mem::take(&mut self.untracked_writes) {
+            barrier.src = Usage::new(
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::MEMORY_WRITE,
+            );
+        }
+        if !barrier.src.stages.is_empty() {
+            barrier.dst = Usage::new(
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            );
+        }
+        barrier
+    }
+
+    /// Finish the current pass and start a new one.
+    pub(super) fn begin_pass(&mut self) -> Barrier {
+        self.end_pass();
+        // transitions requested within a render pass are recorded after it
+        let mut transitions = Barrier::default();
+        self.resolve_transitions(&mut transitions);
+        let mut barrier = if self.untracked_writes {
+            self.sync_all()
+        } else {
+            Barrier::default()
+        };
+        barrier.include(transitions);
+        let untracked_reads = mem::take(&mut self.untracked_reads);
+        if !untracked_reads.is_empty() {
+            // anything read through the tables may be overwritten by this pass
+            barrier.src.stages |= untracked_reads;
+            barrier.dst.stages = vk::PipelineStageFlags::ALL_COMMANDS;
+        }
+        self.stats.passes += 1;
+        self.in_pass = true;
+        self.pass_has_barrier = false;
+        barrier
+    }
+
+    /// Finish the current pass and synchronize with the following submissions.
+    pub(super) fn finish(&mut self) -> Barrier {
+        self.end_pass();
+        // the following recordings expect all images in `GENERAL` layout
+        self.restore_layouts(&[], Usage::ALL);
+        let mut transitions = Barrier::default();
+        self.resolve_transitions(&mut transitions);
+        let mut barrier = self.sync_all();
+        barrier.include(transitions);
+        barrier
+    }
+
+    /// Note that the current pass writes to resources that aren't tracked.
+    pub(super) fn add_untracked_writes(&mut self) {
+        self.untracked_writes = true;
+    }
+
+    fn end_pass(&mut self) {
+        if mem::take(&mut self.in_pass) && !self.pass_has_barrier {
+            self.stats.skipped_barriers += 1;
+        }
+        // the remaining usage can't be synchronized within the pass
+        for (resource, usage) in self.pending.drain(..) {
+            self.pass.entry(resource).or_default().include(usage);
+        }
+        self.untracked_reads |= mem::take(&mut self.pending_untracked).stages;
+        for (resource, usage) in self.pass.drain() {
+            let state = self.states.entry(resource).or_default();
+            if usage.is_write() {
+                *state = State {
+                    write: Usage::new(usage.stages, usage.access & WRITE_ACCESS),
+                    ..Default::default()
+                };
+            } else {
+                state.read_stages |= usage.stages;
+                state.read_ordered = vk::PipelineStageFlags::empty();
+            }
+        }
+    }
+
+    /// Record the barrier, if it's not empty.
+    pub(super) fn emit(
+        &mut self,
+        device: &super::Device,
+        cmd_buf: vk::CommandBuffer,
+        barrier: Barrier,
+    ) {
+        if barrier.src.stages.is_empty() {
+            return;
+        }
+        self.stats.barriers += 1;
+        self.pass_has_barrier = true;
+        let mut memory_barrier = vk::MemoryBarrier {
+            src_access_mask: barrier.src.access,
+            dst_access_mask: barrier.dst.access,
+            ..Default::default()
+        };
+        if barrier.dst.stages == vk::PipelineStageFlags::ALL_COMMANDS {
+            let wa = &device.workarounds;
+            memory_barrier.src_access_mask |= wa.extra_sync_src_access;
+            memory_barrier.dst_access_mask |= wa.extra_sync_dst_access;
+        }
+        let memory_barriers = if barrier.src.access.is_empty() {
+            &[][..]
+        } else {
+            slice::from_ref(&memory_barrier)
+        };
+        unsafe {
+            device.core.cmd_pipeline_barrier(
+                cmd_buf,
+                barrier.src.stages,
+                barrier.dst.stages,
+                vk::DependencyFlags::empty(),
+                memory_barriers,
+                &[],
+                &self.image_barriers,
+            );
+        }
+        self.image_barriers.clear();
+    }
+
+    /// Account for a barrier recorded outside of the passes.
+    pub(super) fn count_barrier(&mut self) {
+        self.stats.barriers += 1;
+    }
+
+    /// Synchronize the pending usage, recording the barrier if needed.
+    pub(super) fn flush(&mut self, device: &super::Device, cmd_buf: vk::CommandBuffer) {
+        let barrier = self.check_pending();
+        self.emit(device, cmd_buf, barrier);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImageRange, Resource, Tracker, Usage};
+    use ash::vk::{self, Handle as _};
+
+    const COMPUTE_READ: Usage = Usage::new(
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ,
+    );
+
+    fn layouts(tracker: &Tracker) -> Vec<(vk::ImageLayout, vk::ImageLayout)> {
+        tracker
+            .image_barriers
+            .iter()
+            .map(|b| (b.old_layout, b.new_layout))
+            .collect()
+    }
+
+    #[test]
+    fn attachment_layout_is_restored_for_shaders() {
+        let image = vk::Image::from_raw(1);
+        let mut tracker = Tracker::default();
+        tracker.start();
+        tracker.begin_pass();
+        let layout = tracker.use_image(
+            image,
+            vk::ImageAspectFlags::COLOR,
+            ImageRange::single(0, 0),
+            Usage::COLOR_ATTACHMENT,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
+        assert_eq!(layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        tracker.check_pending();
+        assert_eq!(
+            layouts(&tracker),
+            [(
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            )]
+        );
+        tracker.image_barriers.clear();
+
+        tracker.begin_pass();
+        tracker.use_resource(Resource::Image(image), COMPUTE_READ);
+        let barrier = tracker.check_pending();
+        assert_eq!(
+            layouts(&tracker),
+            [(
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::GENERAL
+            )]
+        );
+        assert!(
+            barrier
+                .src
+                .stages
+                .contains(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        );
+        assert!(
+            barrier
+                .src
+                .access
+                .contains(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        );
+    }
+
+    #[test]
+    fn overlapping_range_falls_back_to_general() {
+        let image = vk::Image::from_raw(1);
+        let mut tracker = Tracker::default();
+        tracker.start();
+        tracker.begin_pass();
+        tracker.use_image(
+            image,
+            vk::ImageAspectFlags::COLOR,
+            ImageRange::single(0, 1),
+            Usage::COLOR_ATTACHMENT,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        );
+        tracker.check_pending();
+        tracker.image_barriers.clear();
+
+        tracker.begin_pass();
+        let layout = tracker.use_image(
+            image,
+            vk::ImageAspectFlags::COLOR,
+            ImageRange::WHOLE,
+            Usage::TRANSFER_READ,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        assert_eq!(layout, vk::ImageLayout::GENERAL);
+        tracker.check_pending();
+        assert_eq!(
+            layouts(&tracker),
+            [(
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::GENERAL
+            )]
+        );
+    }
+
+    #[test]
+    fn finish_restores_layouts() {
+        let image = vk::Image::from_raw(1);
+        let mut tracker = Tracker::default();
+        tracker.start();
+        tracker.begin_pass();
+        tracker.use_image(
+            image,
+            vk::ImageAspectFlags::COLOR,
+            ImageRange::single(2, 0),
+            Usage::TRANSFER_WRITE,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        tracker.check_pending();
+        tracker.image_barriers.clear();
+
+        let barrier = tracker.finish();
+        assert_eq!(
+            layouts(&tracker),
+            [(
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::GENERAL
+            )]
+        );
+        assert!(
+            barrier
+                .src
+                .stages
+                .contains(vk::PipelineStageFlags::TRANSFER)
+        );
+    }
+
+    #[test]
+    fn render_pass_waits_for_reads() {
+        let buffer = Resource::Buffer(vk::Buffer::from_raw(1));
+        let mut tracker = Tracker::default();
+        tracker.start();
+        tracker.begin_pass();
+        tracker.use_resource(buffer, COMPUTE_READ);
+        tracker.check_pending();
+
+        // The render pass may write the buffer from the fragment shader
+        tracker.begin_pass();
+        let barrier = tracker.make_visible(Usage::GRAPHICS);
+        assert!(
+            barrier
+                .src
+                .stages
+                .contains(vk::PipelineStageFlags::COMPUTE_SHADER)
+        );
+        assert!(
+            barrier
+                .dst
+                .stages
+                .contains(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        );
+    }
+}
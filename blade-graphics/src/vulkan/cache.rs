@@ -0,0 +1,306 @@
+//! On-disk cache of the generated SPIR-V and the driver pipeline data.
+//!
+//! The directory contains:
+//!   - `pipelines.bin`: header identifying the device and the driver,
+//!     followed by the contents of `VkPipelineCache`.
+//!   - `spirv/<key>.spv`: SPIR-V of a shader entry point, keyed by the hash
+//!     of the shader source, entry point, constants, layouts, the naga version,
+//!     and the device header. The least recently used files are evicted
+//!     once the total size goes over `MAX_SPIRV_SIZE`.
+
+use ash::vk;
+use std::{
+    fs,
+    hash::{Hash as _, Hasher},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+const PIPELINES_FILE_NAME: &str = "pipelines.bin";
+const SPIRV_DIR_NAME: &str = "spirv";
+const MAGIC: [u8; 4] = *b"BPC1";
+const HEADER_SIZE: usize = 4 + 4 + 4 + 4 + vk::UUID_SIZE;
+/// Version of naga generating the cached SPIR-V.
+/// Needs to be bumped together with the `naga` dependency.
+const NAGA_VERSION: &str = "27.0.3";
+const MAX_SPIRV_SIZE: u64 = 64 << 20;
+
+/// FNV-1a, which unlike `DefaultHasher` produces the same keys
+/// across Rust releases.
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Directory with the SPIR-V files.
+struct SpirvStore {
+    directory: PathBuf,
+    max_size: u64,
+}
+
+impl SpirvStore {
+    fn path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.spv", key))
+    }
+
+    fn load(&self, key: u64) -> Option<Vec<u32>> {
+        let path = self.path(key);
+        let bytes = fs::read(&path).ok()?;
+        if bytes.is_empty() || bytes.len() % 4 != 0 {
+            return None;
+        }
+        // The modification time tracks the last use for the eviction
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect(),
+        )
+    }
+
+    fn store(&self, key: u64, spv: &[u32]) {
+        let bytes = spv
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        if let Err(e) = fs::write(self.path(key), bytes) {
+            log::warn!("Unable to save SPIR-V to the cache: {}", e);
+        }
+    }
+
+    /// Remove the least recently used files until the total size fits the limit.
+    fn evict(&self) {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let mut files = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((modified, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut total_size = files.iter().map(|&(_, size, _)| size).sum::<u64>();
+        if total_size <= self.max_size {
+            return;
+        }
+        files.sort_unstable();
+        let mut removed = 0;
+        for (_, size, path) in files {
+            if total_size <= self.max_size {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total_size -= size;
+                removed += 1;
+            }
+        }
+        log::info!("Evicted {} SPIR-V files from the cache", removed);
+    }
+}
+
+pub(super) struct PipelineCache {
+    pub(super) raw: vk::PipelineCache,
+    directory: PathBuf,
+    header: [u8; HEADER_SIZE],
+    spirv: SpirvStore,
+}
+
+fn make_header(properties: &vk::PhysicalDeviceProperties) -> [u8; HEADER_SIZE] {
+    let mut header = [0; HEADER_SIZE];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..8].copy_from_slice(&properties.vendor_id.to_le_bytes());
+    header[8..12].copy_from_slice(&properties.device_id.to_le_bytes());
+    header[12..16].copy_from_slice(&properties.driver_version.to_le_bytes());
+    header[16..].copy_from_slice(&properties.pipeline_cache_uuid);
+    header
+}
+
+impl PipelineCache {
+    pub(super) fn new(
+        device: &ash::Device,
+        properties: &vk::PhysicalDeviceProperties,
+        directory: PathBuf,
+    ) -> Option<Self> {
+        if let Err(e) = fs::create_dir_all(directory.join(SPIRV_DIR_NAME)) {
+            log::warn!(
+                "Unable to create pipeline cache at {}: {}",
+                directory.display(),
+                e
+            );
+            return None;
+        }
+        let header = make_header(properties);
+        let spirv = SpirvStore {
+            directory: directory.join(SPIRV_DIR_NAME),
+            max_size: MAX_SPIRV_SIZE,
+        };
+        spirv.evict();
+        // Drivers are not required to reject the data of another device,
+        // so it's only passed over if the header matches.
+        let initial_data = match fs::read(directory.join(PIPELINES_FILE_NAME)) {
+            Ok(data) if data.starts_with(&header) => data[HEADER_SIZE..].to_vec(),
+            Ok(_) => {
+                log::info!("Discarding the pipeline cache of a different device or driver");
+                Vec::new()
+            }
+            Err(_) => Vec::new(),
+        };
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&initial_data);
+        let raw = match unsafe { device.create_pipeline_cache(&create_info, None) } {
+            Ok(raw) => raw,
+            Err(e) if !initial_data.is_empty() => {
+                log::warn!("Pipeline cache data is rejected: {:?}", e);
+                let create_info = vk::PipelineCacheCreateInfo::default();
+                unsafe { device.create_pipeline_cache(&create_info, None) }.ok()?
+            }
+            Err(_) => return None,
+        };
+        log::info!(
+            "Using pipeline cache at {} with {} bytes of initial data",
+            directory.display(),
+            initial_data.len()
+        );
+        Some(Self {
+            raw,
+            directory,
+            header,
+            spirv,
+        })
+    }
+
+    /// Write the driver data to disk and destroy the cache.
+    pub(super) fn save(self, device: &ash::Device) {
+        match unsafe { device.get_pipeline_cache_data(self.raw) } {
+            Ok(data) => {
+                let mut contents = Vec::with_capacity(HEADER_SIZE + data.len());
+                contents.extend_from_slice(&self.header);
+                contents.extend_from_slice(&data);
+                if let Err(e) = fs::write(self.directory.join(PIPELINES_FILE_NAME), contents) {
+                    log::warn!("Unable to save the pipeline cache: {}", e);
+                }
+            }
+            Err(e) => log::warn!("Unable to get the pipeline cache data: {:?}", e),
+        }
+        unsafe { device.destroy_pipeline_cache(self.raw, None) };
+    }
+
+    pub(super) fn load_spirv(&self, key: u64) -> Option<Vec<u32>> {
+        self.spirv.load(key)
+    }
+
+    pub(super) fn store_spirv(&self, key: u64, spv: &[u32]) {
+        self.spirv.store(key, spv);
+    }
+
+    /// Compute the key of the SPIR-V generated for a shader function.
+    pub(super) fn spirv_key(
+        &self,
+        sf: &crate::ShaderFunction,
+        options: &naga::back::spv::Options,
+        group_layouts: &[&crate::ShaderDataLayout],
+        vertex_fetch_states: &[crate::VertexFetchState],
+    ) -> u64 {
+        let mut hasher = StableHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        NAGA_VERSION.hash(&mut hasher);
+        self.header.hash(&mut hasher);
+        options.lang_version.hash(&mut hasher);
+        options.flags.bits().hash(&mut hasher);
+        sf.shader.source.hash(&mut hasher);
+        sf.shader.source_kind.hash(&mut hasher);
+        sf.entry_point.hash(&mut hasher);
+        let mut constants = sf
+            .constants
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_bits()))
+            .collect::<Vec<_>>();
+        constants.sort_unstable();
+        constants.hash(&mut hasher);
+        group_layouts.hash(&mut hasher);
+        vertex_fetch_states.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpirvStore, StableHasher};
+    use std::{fs, hash::Hasher as _};
+
+    fn store(name: &str, max_size: u64) -> SpirvStore {
+        let directory = std::env::temp_dir().join("blade-cache-test").join(format!(
+            "{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        SpirvStore {
+            directory,
+            max_size,
+        }
+    }
+
+    #[test]
+    fn stable_hash() {
+        // Reference values of 64-bit FNV-1a
+        let mut hasher = StableHasher::new();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn spirv_round_trip() {
+        let store = store("round-trip", !0);
+        let spv = [0x0723_0203, 0x0001_0000, 28, 5, 0];
+        assert_eq!(store.load(1), None);
+        store.store(1, &spv);
+        assert_eq!(store.load(1).as_deref(), Some(&spv[..]));
+        // Truncated file is not valid SPIR-V
+        fs::write(store.path(2), [1, 2, 3]).unwrap();
+        assert_eq!(store.load(2), None);
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+
+    #[test]
+    fn spirv_eviction() {
+        let store = store("eviction", 8);
+        store.store(1, &[1, 1]);
+        store.store(2, &[2, 2]);
+        // Use the first file, so that the second one is the oldest
+        let past = std::time::SystemTime::now() - std::time::Duration::from_secs(10);
+        fs::File::options()
+            .write(true)
+            .open(store.path(2))
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        assert!(store.load(1).is_some());
+        store.evict();
+        assert!(store.load(1).is_some());
+        assert_eq!(store.load(2), None);
+        fs::remove_dir_all(&store.directory).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
-use ash::vk;
-use std::{ptr, str, time::Duration};
+use ash::vk::{self, Handle as _};
+use std::{ptr, str};
+
+use super::barrier::{ImageRange, Resource, Usage};
+use crate::trace::{self, record};
 
 impl super::CrashHandler {
     fn add_marker(&mut self, marker: &str) -> u32 {
@@ -48,11 +51,27 @@ impl super::PipelineContext<'_> {
             unsafe { ptr::write(base_ptr.add(i), value) };
         }
     }
+
+    #[inline]
+    fn capture(&mut self, index: u32, bound: impl FnOnce() -> trace::Bound) {
+        if let Some(ref mut captured) = self.captured {
+            captured.push((index, bound()));
+        }
+    }
+
+    #[inline]
+    fn track(&mut self, index: u32, resource: Resource) {
+        let usage = Usage::shader(self.shader_stages, self.binding_access[index as usize]);
+        self.barriers.use_resource(resource, usage);
+    }
 }
 
 impl<T: bytemuck::Pod> crate::ShaderBindable for T {
     fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
         ctx.write(index, *self);
+        ctx.capture(index, || {
+            trace::Bound::Plain(bytemuck::bytes_of(self).to_vec())
+        });
     }
 }
 impl crate::ShaderBindable for super::TextureView {
@@ -65,6 +84,8 @@ impl crate::ShaderBindable for super::TextureView {
                 image_layout: vk::ImageLayout::GENERAL,
             },
         );
+        ctx.track(index, Resource::Image(self.image));
+        ctx.capture(index, || trace::Bound::Texture(*self));
     }
 }
 impl<'a, const N: crate::ResourceIndex> crate::ShaderBindable for &'a crate::TextureArray<N> {
@@ -82,6 +103,13 @@ impl<'a, const N: crate::ResourceIndex> crate::ShaderBindable for &'a crate::Tex
                 .cycle()
                 .take(N as usize),
         );
+        for view in self.data.iter() {
+            ctx.track(index, Resource::Image(view.image));
+        }
+        ctx.capture(index, || trace::Bound::TextureArray {
+            views: self.data.clone(),
+            count: N,
+        });
     }
 }
 impl crate::ShaderBindable for super::Sampler {
@@ -94,6 +122,7 @@ impl crate::ShaderBindable for super::Sampler {
                 image_layout: vk::ImageLayout::UNDEFINED,
             },
         );
+        ctx.capture(index, || trace::Bound::Sampler(*self));
     }
 }
 impl crate::ShaderBindable for crate::BufferPiece {
@@ -106,6 +135,8 @@ impl crate::ShaderBindable for crate::BufferPiece {
                 range: vk::WHOLE_SIZE,
             },
         );
+        ctx.track(index, Resource::Buffer(self.buffer.raw));
+        ctx.capture(index, || trace::Bound::Buffer(*self));
     }
 }
 impl<'a, const N: crate::ResourceIndex> crate::ShaderBindable for &'a crate::BufferArray<N> {
@@ -123,15 +154,31 @@ impl<'a, const N: crate::ResourceIndex> crate::ShaderBindable for &'a crate::Buf
                 .cycle()
                 .take(N as usize),
         );
+        for piece in self.data.iter() {
+            ctx.track(index, Resource::Buffer(piece.buffer.raw));
+        }
+        ctx.capture(index, || trace::Bound::BufferArray {
+            pieces: self.data.clone(),
+            count: N,
+        });
     }
 }
 impl crate::ShaderBindable for super::AccelerationStructure {
     fn bind_to(&self, ctx: &mut super::PipelineContext, index: u32) {
         ctx.write(index, self.raw);
+        ctx.capture(index, || trace::Bound::AccelerationStructure(*self));
     }
 }
 
 impl crate::TexturePiece {
+    fn aspects(&self) -> vk::ImageAspectFlags {
+        super::map_aspects(self.texture.format.aspects())
+    }
+
+    fn range(&self) -> ImageRange {
+        ImageRange::single(self.mip_level, self.array_layer)
+    }
+
     fn subresource_layers(&self) -> vk::ImageSubresourceLayers {
         vk::ImageSubresourceLayers {
             aspect_mask: super::map_aspects(self.texture.format.aspects()),
@@ -168,10 +215,14 @@ fn make_buffer_image_copy(
     }
 }
 
-fn map_render_target(rt: &crate::RenderTarget) -> vk::RenderingAttachmentInfo<'static> {
+fn map_render_target(
+    rt: &crate::RenderTarget,
+    layout: vk::ImageLayout,
+    resolve_layout: vk::ImageLayout,
+) -> vk::RenderingAttachmentInfo<'static> {
     let mut vk_info = vk::RenderingAttachmentInfo::default()
         .image_view(rt.view.raw)
-        .image_layout(vk::ImageLayout::GENERAL);
+        .image_layout(layout);
 
     match rt.init_op {
         crate::InitOp::Load => vk_info = vk_info.load_op(vk::AttachmentLoadOp::LOAD),
@@ -206,7 +257,7 @@ fn map_render_target(rt: &crate::RenderTarget) -> vk::RenderingAttachmentInfo<'s
     if let crate::FinishOp::ResolveTo(resolve_view) = rt.finish_op {
         vk_info = vk_info
             .resolve_image_view(resolve_view.raw)
-            .resolve_image_layout(vk::ImageLayout::GENERAL)
+            .resolve_image_layout(resolve_layout)
             .resolve_mode(vk::ResolveModeFlags::AVERAGE);
     }
 
@@ -229,7 +280,71 @@ fn map_render_target(rt: &crate::RenderTarget) -> vk::RenderingAttachmentInfo<'s
     vk_info
 }
 
-fn end_pass(device: &super::Device, cmd_buf: vk::CommandBuffer) {
+impl super::TimestampQueries {
+    fn write(
+        &self,
+        device: &super::Device,
+        raw: vk::CommandBuffer,
+        query: u32,
+        stage: vk::PipelineStageFlags,
+    ) {
+        let pool = self.pools[(query / super::TIMESTAMP_POOL_SIZE) as usize];
+        let index = query % super::TIMESTAMP_POOL_SIZE;
+        unsafe { device.core.cmd_write_timestamp(raw, stage, pool, index) };
+    }
+
+    fn begin(&mut self, device: &super::Device, raw: vk::CommandBuffer, name: &str, pass: bool) {
+        if device.timing.is_none() {
+            return;
+        }
+        let query = if pass {
+            self.scopes.begin_pass(name, true)
+        } else {
+            self.scopes.begin(name, true)
+        };
+        if let Some(query) = query {
+            self.write(device, raw, query, vk::PipelineStageFlags::TOP_OF_PIPE);
+        }
+    }
+
+    fn end(&mut self, device: &super::Device, raw: vk::CommandBuffer) {
+        if device.timing.is_none() {
+            return;
+        }
+        match self.scopes.end() {
+            Ok(Some(query)) => {
+                self.write(device, raw, query, vk::PipelineStageFlags::BOTTOM_OF_PIPE)
+            }
+            Ok(None) => {}
+            Err(message) => log::error!("{}", message),
+        }
+    }
+
+    /// End the scopes that are still open at the end of a pass, or the whole recording.
+    fn end_all(&mut self, device: &super::Device, raw: vk::CommandBuffer, pass: bool) {
+        if device.timing.is_none() {
+            return;
+        }
+        let (queries, unended) = if pass {
+            self.scopes.end_pass()
+        } else {
+            self.scopes.finish()
+        };
+        for name in unended {
+            log::warn!("Timing scope '{}' is not ended", name);
+        }
+        for query in queries {
+            self.write(device, raw, query, vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+        }
+    }
+}
+
+fn end_pass(
+    device: &super::Device,
+    cmd_buf: vk::CommandBuffer,
+    timestamps: &mut super::TimestampQueries,
+) {
+    timestamps.end_all(device, cmd_buf, true);
     if device.command_scope.is_some() {
         unsafe {
             device.debug_utils.cmd_end_debug_utils_label(cmd_buf);
@@ -257,30 +372,21 @@ impl super::CommandEncoder {
         }
     }
 
-    fn add_timestamp(&mut self, label: &str) {
-        if let Some(_) = self.device.timing {
-            let cmd_buf = self.buffers.first_mut().unwrap();
-            if cmd_buf.timed_pass_names.len() == crate::limits::PASS_COUNT {
-                log::warn!("Reached the maximum for `limits::PASS_COUNT`, skipping the timer");
-                return;
-            }
-            let index = cmd_buf.timed_pass_names.len() as u32;
-            unsafe {
-                self.device.core.cmd_write_timestamp(
-                    cmd_buf.raw,
-                    vk::PipelineStageFlags::TOP_OF_PIPE,
-                    cmd_buf.query_pool,
-                    index,
-                );
-            }
-            cmd_buf.timed_pass_names.push(label.to_string());
-        }
-    }
-
-    fn begin_pass(&mut self, label: &str) {
-        self.barrier();
+    fn begin_pass(&mut self, label: &str, command_type: crate::CommandType) {
+        assert!(
+            self.command_type.supports(command_type),
+            "Pass '{}' can't be recorded into a {:?} command encoder",
+            label,
+            self.command_type
+        );
+        let barrier = self.barriers.begin_pass();
+        self.barriers
+            .emit(&self.device, self.buffers[0].raw, barrier);
         self.add_marker(label);
-        self.add_timestamp(label);
+        let cmd_buf = self.buffers.first_mut().unwrap();
+        cmd_buf
+            .timestamps
+            .begin(&self.device, cmd_buf.raw, label, true);
 
         if let Some(_) = self.device.command_scope {
             self.temp_label.clear();
@@ -299,51 +405,30 @@ impl super::CommandEncoder {
     }
 
     pub(super) fn finish(&mut self) -> vk::CommandBuffer {
-        self.barrier();
+        let barrier = self.barriers.finish();
+        self.barriers
+            .emit(&self.device, self.buffers[0].raw, barrier);
         self.add_marker("finish");
         let cmd_buf = self.buffers.first_mut().unwrap();
+        cmd_buf.timestamps.end_all(&self.device, cmd_buf.raw, false);
         unsafe {
-            if self.device.timing.is_some() {
-                let index = cmd_buf.timed_pass_names.len() as u32;
-                self.device.core.cmd_write_timestamp(
-                    cmd_buf.raw,
-                    vk::PipelineStageFlags::TOP_OF_PIPE,
-                    cmd_buf.query_pool,
-                    index,
-                );
-            }
             self.device.core.end_command_buffer(cmd_buf.raw).unwrap();
         }
         cmd_buf.raw
     }
 
-    fn barrier(&mut self) {
-        let wa = &self.device.workarounds;
-        let barrier = vk::MemoryBarrier {
-            src_access_mask: vk::AccessFlags::MEMORY_WRITE | wa.extra_sync_src_access,
-            dst_access_mask: vk::AccessFlags::MEMORY_READ
-                | vk::AccessFlags::MEMORY_WRITE
-                | wa.extra_sync_dst_access,
-            ..Default::default()
-        };
-        unsafe {
-            self.device.core.cmd_pipeline_barrier(
-                self.buffers[0].raw,
-                vk::PipelineStageFlags::ALL_COMMANDS,
-                vk::PipelineStageFlags::ALL_COMMANDS,
-                vk::DependencyFlags::empty(),
-                &[barrier],
-                &[],
-                &[],
-            );
-        }
-    }
-
     pub fn transfer(&mut self, label: &str) -> super::TransferCommandEncoder<'_> {
-        self.begin_pass(label);
+        self.begin_pass(label, crate::CommandType::Transfer);
+        record(&mut self.trace, |_| trace::Command::BeginTransfer {
+            label: label.to_string(),
+        });
+        let cmd_buf = self.buffers.first_mut().unwrap();
         super::TransferCommandEncoder {
-            raw: self.buffers[0].raw,
+            raw: cmd_buf.raw,
+            timestamps: &mut cmd_buf.timestamps,
             device: &self.device,
+            barriers: &mut self.barriers,
+            trace: &mut self.trace,
         }
     }
 
@@ -351,44 +436,102 @@ impl super::CommandEncoder {
         &mut self,
         label: &str,
     ) -> super::AccelerationStructureCommandEncoder<'_> {
-        self.begin_pass(label);
+        self.begin_pass(label, crate::CommandType::Compute);
+        record(&mut self.trace, |_| trace::Command::BeginAccelerationStructure {
+            label: label.to_string(),
+        });
+        // The builds access the buffers by device addresses,
+        // so they are synchronized with everything else.
+        let barrier = self.barriers.sync_all();
+        self.barriers
+            .emit(&self.device, self.buffers[0].raw, barrier);
+        self.barriers.add_untracked_writes();
+        let cmd_buf = self.buffers.first_mut().unwrap();
         super::AccelerationStructureCommandEncoder {
-            raw: self.buffers[0].raw,
+            raw: cmd_buf.raw,
+            timestamps: &mut cmd_buf.timestamps,
             device: &self.device,
+            trace: &mut self.trace,
         }
     }
 
     pub fn compute(&mut self, label: &str) -> super::ComputeCommandEncoder<'_> {
-        self.begin_pass(label);
+        self.begin_pass(label, crate::CommandType::Compute);
+        record(&mut self.trace, |_| trace::Command::BeginCompute {
+            label: label.to_string(),
+        });
         super::ComputeCommandEncoder {
             cmd_buf: self.buffers.first_mut().unwrap(),
             device: &self.device,
             update_data: &mut self.update_data,
+            barriers: &mut self.barriers,
+            trace: &mut self.trace,
         }
     }
 
-    pub fn render(
+    fn begin_render(
         &mut self,
         label: &str,
         targets: crate::RenderTargetSet,
-    ) -> super::RenderCommandEncoder<'_> {
-        self.begin_pass(label);
+        flags: vk::RenderingFlags,
+    ) {
+        self.begin_pass(label, crate::CommandType::General);
+        record(&mut self.trace, |ids| trace::Command::BeginRender {
+            label: label.to_string(),
+            colors: targets
+                .colors
+                .iter()
+                .map(|rt| ids.render_target(rt))
+                .collect(),
+            depth_stencil: targets.depth_stencil.as_ref().map(|rt| ids.render_target(rt)),
+        });
 
         let mut target_size = [0u16; 2];
         let mut color_attachments = Vec::with_capacity(targets.colors.len());
         let depth_stencil_attachment;
+        let mut attachments = Vec::with_capacity(targets.colors.len() * 2 + 1);
         for rt in targets.colors {
             target_size = rt.view.target_size;
-            color_attachments.push(map_render_target(rt));
+            let layout = self.barriers.use_image(
+                rt.view.image,
+                super::map_aspects(rt.view.aspects),
+                rt.view.range,
+                Usage::COLOR_ATTACHMENT,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            );
+            attachments.push((rt.view.image, rt.view.range));
+            let resolve_layout = match rt.finish_op {
+                crate::FinishOp::ResolveTo(resolve_view) => {
+                    attachments.push((resolve_view.image, resolve_view.range));
+                    self.barriers.use_image(
+                        resolve_view.image,
+                        super::map_aspects(resolve_view.aspects),
+                        resolve_view.range,
+                        Usage::RESOLVE_ATTACHMENT,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    )
+                }
+                _ => vk::ImageLayout::UNDEFINED,
+            };
+            color_attachments.push(map_render_target(rt, layout, resolve_layout));
         }
 
         let mut rendering_info = vk::RenderingInfoKHR::default()
+            .flags(flags)
             .layer_count(1)
             .color_attachments(&color_attachments);
 
         if let Some(rt) = targets.depth_stencil {
             target_size = rt.view.target_size;
-            depth_stencil_attachment = map_render_target(&rt);
+            let layout = self.barriers.use_image(
+                rt.view.image,
+                super::map_aspects(rt.view.aspects),
+                rt.view.range,
+                Usage::DEPTH_STENCIL_ATTACHMENT,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            );
+            attachments.push((rt.view.image, rt.view.range));
+            depth_stencil_attachment = map_render_target(&rt, layout, vk::ImageLayout::UNDEFINED);
             if rt.view.aspects.contains(crate::TexelAspects::DEPTH) {
                 rendering_info = rendering_info.depth_attachment(&depth_stencil_attachment);
             }
@@ -414,6 +557,15 @@ impl super::CommandEncoder {
         .to_vk();
         rendering_info.render_area = render_area;
 
+        // Barriers can't be recorded within the rendering, so the resources
+        // bound later are conservatively synchronized here, for both reads
+        // and writes by the shaders.
+        self.barriers.restore_layouts(&attachments, Usage::GRAPHICS);
+        let mut barrier = self.barriers.check_pending();
+        barrier.include(self.barriers.make_visible(Usage::GRAPHICS));
+        self.barriers
+            .emit(&self.device, self.buffers[0].raw, barrier);
+
         let cmd_buf = self.buffers.first_mut().unwrap();
         unsafe {
             self.device
@@ -426,11 +578,116 @@ impl super::CommandEncoder {
                 .dynamic_rendering
                 .cmd_begin_rendering(cmd_buf.raw, &rendering_info);
         };
+    }
 
+    pub fn render(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::RenderCommandEncoder<'_> {
+        self.begin_render(label, targets, vk::RenderingFlags::empty());
         super::RenderCommandEncoder {
-            cmd_buf,
+            cmd_buf: self.buffers.first_mut().unwrap(),
             device: &self.device,
             update_data: &mut self.update_data,
+            barriers: &mut self.barriers,
+            occlusion_queries: vk::QueryPool::null(),
+            trace: &mut self.trace,
+        }
+    }
+
+    /// Begin a render pass, which writes the occlusion queries into `queries`.
+    /// All the queries of the set are reset at the start of the pass.
+    pub fn render_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::RenderCommandEncoder<'_> {
+        assert_eq!(queries.ty, crate::QueryType::Occlusion);
+        unsafe {
+            self.device.core.cmd_reset_query_pool(
+                self.buffers[0].raw,
+                queries.raw,
+                0,
+                queries.count,
+            )
+        };
+        let mut pass = self.render(label, targets);
+        pass.occlusion_queries = queries.raw;
+        pass
+    }
+
+    /// Begin a parallel render pass, which writes the occlusion queries into `queries`.
+    /// All the queries of the set are reset at the start of the pass.
+    pub fn render_parallel_with_queries(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+        queries: super::QuerySet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        assert_eq!(queries.ty, crate::QueryType::Occlusion);
+        unsafe {
+            self.device.core.cmd_reset_query_pool(
+                self.buffers[0].raw,
+                queries.raw,
+                0,
+                queries.count,
+            )
+        };
+        let mut pass = self.render_parallel(label, targets);
+        pass.pass.occlusion_queries = queries.raw;
+        pass
+    }
+
+    pub fn render_parallel(
+        &mut self,
+        label: &str,
+        targets: crate::RenderTargetSet,
+    ) -> super::ParallelRenderCommandEncoder<'_> {
+        let mut color_formats = Vec::with_capacity(targets.colors.len());
+        let mut depth_format = vk::Format::UNDEFINED;
+        let mut stencil_format = vk::Format::UNDEFINED;
+        let mut samples = vk::SampleCountFlags::TYPE_1;
+        let mut target_size = [0u16; 2];
+        for rt in targets.colors {
+            color_formats.push(rt.view.format);
+            samples = rt.view.samples;
+            target_size = rt.view.target_size;
+        }
+        if let Some(ref rt) = targets.depth_stencil {
+            if rt.view.aspects.contains(crate::TexelAspects::DEPTH) {
+                depth_format = rt.view.format;
+            }
+            if rt.view.aspects.contains(crate::TexelAspects::STENCIL) {
+                stencil_format = rt.view.format;
+            }
+            samples = rt.view.samples;
+            target_size = rt.view.target_size;
+        }
+
+        self.begin_render(
+            label,
+            targets,
+            vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS,
+        );
+        super::ParallelRenderCommandEncoder {
+            pass: super::RenderCommandEncoder {
+                cmd_buf: self.buffers.first_mut().unwrap(),
+                device: &self.device,
+                update_data: &mut self.update_data,
+                barriers: &mut self.barriers,
+                occlusion_queries: vk::QueryPool::null(),
+                trace: &mut self.trace,
+            },
+            family_index: self.family_index,
+            free_chunks: &mut self.free_chunks,
+            color_formats,
+            depth_format,
+            stencil_format,
+            samples,
+            target_size,
+            chunks: Vec::new(),
         }
     }
 
@@ -464,12 +721,22 @@ impl super::CommandEncoder {
 impl crate::traits::CommandEncoder for super::CommandEncoder {
     type Texture = super::Texture;
     type Frame = super::Frame;
+    type SyncPoint = super::SyncPoint;
+    type QuerySet = super::QuerySet;
 
     fn start(&mut self) {
+        if let Some(ref mut trace) = self.trace {
+            trace.start();
+        }
         self.buffers.rotate_left(1);
+        self.stats = self.barriers.start();
         let cmd_buf = self.buffers.first_mut().unwrap();
         self.device
             .reset_descriptor_pool(&mut cmd_buf.descriptor_pool);
+        for mut chunk in cmd_buf.chunks.drain(..) {
+            self.device.reset_chunk_command_buffer(&mut chunk);
+            self.free_chunks.push(chunk);
+        }
 
         let vk_info = vk::CommandBufferBeginInfo {
             flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
@@ -483,43 +750,55 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
         }
 
         if let Some(ref timing) = self.device.timing {
-            self.timings.clear();
-            if !cmd_buf.timed_pass_names.is_empty() {
-                let mut timestamps = [0u64; super::QUERY_POOL_SIZE];
+            let timestamps = &mut cmd_buf.timestamps;
+            let mut values = vec![0u64; timestamps.scopes.query_count() as usize];
+            for (&pool, chunk) in timestamps
+                .pools
+                .iter()
+                .zip(values.chunks_mut(super::TIMESTAMP_POOL_SIZE as usize))
+            {
                 unsafe {
                     self.device
                         .core
-                        .get_query_pool_results(
-                            cmd_buf.query_pool,
-                            0,
-                            &mut timestamps[..cmd_buf.timed_pass_names.len() + 1],
-                            vk::QueryResultFlags::TYPE_64,
-                        )
+                        .get_query_pool_results(pool, 0, chunk, vk::QueryResultFlags::TYPE_64)
                         .unwrap();
                 }
-                let mut prev = timestamps[0];
-                for (name, &ts) in cmd_buf
-                    .timed_pass_names
-                    .drain(..)
-                    .zip(timestamps[1..].iter())
-                {
-                    let diff = (ts - prev) as f32 * timing.period;
-                    prev = ts;
-                    self.timings.push((name, Duration::from_nanos(diff as _)));
-                }
             }
-            unsafe {
-                self.device.core.cmd_reset_query_pool(
-                    cmd_buf.raw,
-                    cmd_buf.query_pool,
-                    0,
-                    super::QUERY_POOL_SIZE as u32,
-                );
+            for value in values.iter_mut() {
+                *value = (*value as f64 * timing.period as f64) as u64;
+            }
+            let required = timestamps.scopes.required_query_count();
+            self.timings = timestamps.scopes.resolve(&values);
+
+            // Add the pools for the scopes that didn't fit into the last recording
+            while (timestamps.pools.len() as u32) * super::TIMESTAMP_POOL_SIZE < required {
+                timestamps.pools.push(self.device.create_timestamp_pool());
+            }
+            timestamps
+                .scopes
+                .grow(timestamps.pools.len() as u32 * super::TIMESTAMP_POOL_SIZE);
+            for &pool in timestamps.pools.iter() {
+                unsafe {
+                    self.device.core.cmd_reset_query_pool(
+                        cmd_buf.raw,
+                        pool,
+                        0,
+                        super::TIMESTAMP_POOL_SIZE,
+                    );
+                }
             }
         }
     }
 
+    fn depend_on(&mut self, sync_point: &super::SyncPoint) {
+        self.waits.push(sync_point.clone());
+    }
+
     fn init_texture(&mut self, texture: super::Texture) {
+        record(&mut self.trace, |ids| trace::Command::InitTexture {
+            texture: ids.texture(texture),
+        });
+        self.barriers.forget_layout(texture.raw);
         let barrier = vk::ImageMemoryBarrier {
             old_layout: vk::ImageLayout::UNDEFINED,
             new_layout: vk::ImageLayout::GENERAL,
@@ -544,9 +823,37 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
                 &[barrier],
             );
         }
+        self.barriers.count_barrier();
+    }
+
+    fn begin_statistics_query(&mut self, set: super::QuerySet, index: u32) {
+        assert!(
+            matches!(set.ty, crate::QueryType::PipelineStatistics(_)),
+            "Query set is not for pipeline statistics"
+        );
+        let raw = self.buffers[0].raw;
+        unsafe {
+            self.device
+                .core
+                .cmd_reset_query_pool(raw, set.raw, index, 1);
+            self.device
+                .core
+                .cmd_begin_query(raw, set.raw, index, vk::QueryControlFlags::empty());
+        }
+    }
+
+    fn end_statistics_query(&mut self, set: super::QuerySet, index: u32) {
+        unsafe {
+            self.device
+                .core
+                .cmd_end_query(self.buffers[0].raw, set.raw, index)
+        };
     }
 
     fn present(&mut self, frame: super::Frame) {
+        record(&mut self.trace, |ids| trace::Command::Present {
+            texture: ids.texture(frame.texture()),
+        });
         let image_index = match frame.image_index {
             Some(index) => index,
             None => {
@@ -556,6 +863,11 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
         };
 
         assert_eq!(self.present, None);
+        assert_eq!(
+            self.command_type,
+            crate::CommandType::General,
+            "Presentation requires a general command encoder"
+        );
         let wa = &self.device.workarounds;
         self.present = Some(super::Presentation {
             swapchain: frame.swapchain.raw,
@@ -565,7 +877,7 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
         });
 
         let barrier = vk::ImageMemoryBarrier {
-            old_layout: vk::ImageLayout::GENERAL,
+            old_layout: self.barriers.forget_layout(frame.internal.image),
             new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
             image: frame.internal.image,
             subresource_range: vk::ImageSubresourceRange {
@@ -589,11 +901,79 @@ impl crate::traits::CommandEncoder for super::CommandEncoder {
                 &[barrier],
             );
         }
+        self.barriers.count_barrier();
     }
 
     fn timings(&self) -> &crate::Timings {
         &self.timings
     }
+
+    fn stats(&self) -> &crate::CommandStats {
+        &self.stats
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::CommandEncoder {
+    fn begin_scope(&mut self, name: &str) {
+        let cmd_buf = self.buffers.first_mut().unwrap();
+        cmd_buf
+            .timestamps
+            .begin(&self.device, cmd_buf.raw, name, false);
+    }
+
+    fn end_scope(&mut self) {
+        let cmd_buf = self.buffers.first_mut().unwrap();
+        cmd_buf.timestamps.end(&self.device, cmd_buf.raw);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::TransferCommandEncoder<'_> {
+    fn begin_scope(&mut self, name: &str) {
+        self.timestamps.begin(self.device, self.raw, name, false);
+    }
+
+    fn end_scope(&mut self) {
+        self.timestamps.end(self.device, self.raw);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::AccelerationStructureCommandEncoder<'_> {
+    fn begin_scope(&mut self, name: &str) {
+        self.timestamps.begin(self.device, self.raw, name, false);
+    }
+
+    fn end_scope(&mut self) {
+        self.timestamps.end(self.device, self.raw);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::ComputeCommandEncoder<'_> {
+    fn begin_scope(&mut self, name: &str) {
+        let raw = self.cmd_buf.raw;
+        self.cmd_buf.timestamps.begin(self.device, raw, name, false);
+    }
+
+    fn end_scope(&mut self) {
+        let raw = self.cmd_buf.raw;
+        self.cmd_buf.timestamps.end(self.device, raw);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::TimingEncoder for super::RenderCommandEncoder<'_> {
+    fn begin_scope(&mut self, name: &str) {
+        let raw = self.cmd_buf.raw;
+        self.cmd_buf.timestamps.begin(self.device, raw, name, false);
+    }
+
+    fn end_scope(&mut self) {
+        let raw = self.cmd_buf.raw;
+        self.cmd_buf.timestamps.end(self.device, raw);
+    }
 }
 
 #[hidden_trait::expose]
@@ -602,6 +982,14 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
     type TexturePiece = crate::TexturePiece;
 
     fn fill_buffer(&mut self, dst: crate::BufferPiece, size: u64, value: u8) {
+        record(self.trace, |ids| trace::Command::FillBuffer {
+            dst: ids.buffer(dst),
+            size,
+            value,
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(dst.buffer.raw), Usage::TRANSFER_WRITE);
+        self.barriers.flush(self.device, self.raw);
         let value_u32 = (value as u32) * 0x1010101;
         unsafe {
             self.device
@@ -616,6 +1004,16 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
         dst: crate::BufferPiece,
         size: u64,
     ) {
+        record(self.trace, |ids| trace::Command::CopyBufferToBuffer {
+            src: ids.buffer(src),
+            dst: ids.buffer(dst),
+            size,
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(src.buffer.raw), Usage::TRANSFER_READ);
+        self.barriers
+            .use_resource(Resource::Buffer(dst.buffer.raw), Usage::TRANSFER_WRITE);
+        self.barriers.flush(self.device, self.raw);
         let copy = vk::BufferCopy {
             src_offset: src.offset,
             dst_offset: dst.offset,
@@ -634,6 +1032,36 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
         dst: crate::TexturePiece,
         size: crate::Extent,
     ) {
+        record(self.trace, |ids| trace::Command::CopyTextureToTexture {
+            src: ids.texture_piece(src),
+            dst: ids.texture_piece(dst),
+            size,
+        });
+        // Copies within the same subresource can't use different layouts
+        let (src_layout, dst_layout) =
+            if src.texture.raw == dst.texture.raw && src.range() == dst.range() {
+                (vk::ImageLayout::GENERAL, vk::ImageLayout::GENERAL)
+            } else {
+                (
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                )
+            };
+        let src_layout = self.barriers.use_image(
+            src.texture.raw,
+            src.aspects(),
+            src.range(),
+            Usage::TRANSFER_READ,
+            src_layout,
+        );
+        let dst_layout = self.barriers.use_image(
+            dst.texture.raw,
+            dst.aspects(),
+            dst.range(),
+            Usage::TRANSFER_WRITE,
+            dst_layout,
+        );
+        self.barriers.flush(self.device, self.raw);
         let copy = vk::ImageCopy {
             src_subresource: src.subresource_layers(),
             src_offset: map_origin(&src.origin),
@@ -645,9 +1073,9 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
             self.device.core.cmd_copy_image(
                 self.raw,
                 src.texture.raw,
-                vk::ImageLayout::GENERAL,
+                src_layout,
                 dst.texture.raw,
-                vk::ImageLayout::GENERAL,
+                dst_layout,
                 &[copy],
             )
         };
@@ -660,13 +1088,29 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
         dst: crate::TexturePiece,
         size: crate::Extent,
     ) {
+        record(self.trace, |ids| trace::Command::CopyBufferToTexture {
+            src: ids.buffer(src),
+            bytes_per_row,
+            dst: ids.texture_piece(dst),
+            size,
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(src.buffer.raw), Usage::TRANSFER_READ);
+        let dst_layout = self.barriers.use_image(
+            dst.texture.raw,
+            dst.aspects(),
+            dst.range(),
+            Usage::TRANSFER_WRITE,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        self.barriers.flush(self.device, self.raw);
         let copy = make_buffer_image_copy(&src, bytes_per_row, &dst, &size);
         unsafe {
             self.device.core.cmd_copy_buffer_to_image(
                 self.raw,
                 src.buffer.raw,
                 dst.texture.raw,
-                vk::ImageLayout::GENERAL,
+                dst_layout,
                 &[copy],
             )
         };
@@ -679,12 +1123,28 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
         bytes_per_row: u32,
         size: crate::Extent,
     ) {
+        record(self.trace, |ids| trace::Command::CopyTextureToBuffer {
+            src: ids.texture_piece(src),
+            dst: ids.buffer(dst),
+            bytes_per_row,
+            size,
+        });
+        let src_layout = self.barriers.use_image(
+            src.texture.raw,
+            src.aspects(),
+            src.range(),
+            Usage::TRANSFER_READ,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        self.barriers
+            .use_resource(Resource::Buffer(dst.buffer.raw), Usage::TRANSFER_WRITE);
+        self.barriers.flush(self.device, self.raw);
         let copy = make_buffer_image_copy(&dst, bytes_per_row, &src, &size);
         unsafe {
             self.device.core.cmd_copy_image_to_buffer(
                 self.raw,
                 src.texture.raw,
-                vk::ImageLayout::GENERAL,
+                src_layout,
                 dst.buffer.raw,
                 &[copy],
             )
@@ -694,7 +1154,8 @@ impl crate::traits::TransferEncoder for super::TransferCommandEncoder<'_> {
 
 impl Drop for super::TransferCommandEncoder<'_> {
     fn drop(&mut self) {
-        end_pass(self.device, self.raw);
+        record(self.trace, |_| trace::Command::EndPass);
+        end_pass(self.device, self.raw, self.timestamps);
     }
 }
 
@@ -712,6 +1173,11 @@ impl crate::traits::AccelerationStructureEncoder
         meshes: &[crate::AccelerationStructureMesh],
         scratch_data: crate::BufferPiece,
     ) {
+        record(self.trace, |ids| trace::Command::BuildBottomLevel {
+            acceleration_structure: ids.acceleration_structure(acceleration_structure),
+            meshes: meshes.iter().map(|mesh| ids.mesh(mesh)).collect(),
+            scratch_data: ids.buffer(scratch_data),
+        });
         let rt = self.device.ray_tracing.as_ref().unwrap();
         let mut blas_input = self.device.map_acceleration_structure_meshes(meshes);
         blas_input.build_info.dst_acceleration_structure = acceleration_structure.raw;
@@ -737,11 +1203,21 @@ impl crate::traits::AccelerationStructureEncoder
     fn build_top_level(
         &mut self,
         acceleration_structure: super::AccelerationStructure,
-        _bottom_level: &[super::AccelerationStructure],
+        bottom_level: &[super::AccelerationStructure],
         instance_count: u32,
         instance_data: crate::BufferPiece,
         scratch_data: crate::BufferPiece,
     ) {
+        record(self.trace, |ids| trace::Command::BuildTopLevel {
+            acceleration_structure: ids.acceleration_structure(acceleration_structure),
+            bottom_level: bottom_level
+                .iter()
+                .map(|&blas| ids.acceleration_structure(blas))
+                .collect(),
+            instance_count,
+            instance_data: ids.buffer(instance_data),
+            scratch_data: ids.buffer(scratch_data),
+        });
         let build_range_info = vk::AccelerationStructureBuildRangeInfoKHR {
             primitive_count: instance_count,
             primitive_offset: 0,
@@ -785,7 +1261,8 @@ impl crate::traits::AccelerationStructureEncoder
 
 impl Drop for super::AccelerationStructureCommandEncoder<'_> {
     fn drop(&mut self) {
-        end_pass(self.device, self.raw);
+        record(self.trace, |_| trace::Command::EndPass);
+        end_pass(self.device, self.raw, self.timestamps);
     }
 }
 
@@ -794,6 +1271,9 @@ impl<'a> super::ComputeCommandEncoder<'a> {
         &'b mut self,
         pipeline: &'p super::ComputePipeline,
     ) -> super::PipelineEncoder<'b, 'p> {
+        record(self.trace, |ids| trace::Command::SetComputePipeline {
+            pipeline: ids.pipeline(pipeline.raw.as_raw()),
+        });
         let bind_point = vk::PipelineBindPoint::COMPUTE;
         unsafe {
             self.device
@@ -801,50 +1281,287 @@ impl<'a> super::ComputeCommandEncoder<'a> {
                 .cmd_bind_pipeline(self.cmd_buf.raw, bind_point, pipeline.raw)
         };
         super::PipelineEncoder {
-            cmd_buf: self.cmd_buf,
+            raw: self.cmd_buf.raw,
+            descriptor_pool: &mut self.cmd_buf.descriptor_pool,
             layout: &pipeline.layout,
             bind_point,
+            shader_stages: vk::PipelineStageFlags::COMPUTE_SHADER,
             device: self.device,
             update_data: self.update_data,
+            barriers: self.barriers,
+            occlusion_queries: vk::QueryPool::null(),
+            trace: self.trace,
         }
     }
 }
 
 impl Drop for super::ComputeCommandEncoder<'_> {
     fn drop(&mut self) {
-        end_pass(self.device, self.cmd_buf.raw);
+        record(self.trace, |_| trace::Command::EndPass);
+        end_pass(self.device, self.cmd_buf.raw, &mut self.cmd_buf.timestamps);
+    }
+}
+
+fn render_pipeline_encoder<'b, 'p>(
+    raw: vk::CommandBuffer,
+    descriptor_pool: &'b mut super::descriptor::DescriptorPool,
+    device: &'b super::Device,
+    update_data: &'b mut Vec<u8>,
+    barriers: &'b mut super::barrier::Tracker,
+    trace: &'b mut Option<trace::EncoderTrace>,
+    pipeline: &'p super::RenderPipeline,
+) -> super::PipelineEncoder<'b, 'p> {
+    record(trace, |ids| trace::Command::SetRenderPipeline {
+        pipeline: ids.pipeline(pipeline.raw.as_raw()),
+    });
+    let bind_point = vk::PipelineBindPoint::GRAPHICS;
+    unsafe { device.core.cmd_bind_pipeline(raw, bind_point, pipeline.raw) };
+    super::PipelineEncoder {
+        raw,
+        descriptor_pool,
+        layout: &pipeline.layout,
+        bind_point,
+        shader_stages: vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        device,
+        update_data,
+        barriers,
+        occlusion_queries: vk::QueryPool::null(),
+        trace,
     }
 }
 
+fn begin_occlusion_query(
+    device: &super::Device,
+    raw: vk::CommandBuffer,
+    pool: vk::QueryPool,
+    index: u32,
+    mode: crate::OcclusionMode,
+) {
+    assert_ne!(
+        pool,
+        vk::QueryPool::null(),
+        "Occlusion query {index} is begun in a pass without a query set"
+    );
+    let flags = match mode {
+        crate::OcclusionMode::Precise if device.precise_occlusion => vk::QueryControlFlags::PRECISE,
+        _ => vk::QueryControlFlags::empty(),
+    };
+    unsafe { device.core.cmd_begin_query(raw, pool, index, flags) };
+}
+
+fn end_occlusion_query(
+    device: &super::Device,
+    raw: vk::CommandBuffer,
+    pool: vk::QueryPool,
+    index: u32,
+) {
+    unsafe { device.core.cmd_end_query(raw, pool, index) };
+}
+
 impl<'a> super::RenderCommandEncoder<'a> {
     pub fn with<'b, 'p>(
         &'b mut self,
         pipeline: &'p super::RenderPipeline,
     ) -> super::PipelineEncoder<'b, 'p> {
-        let bind_point = vk::PipelineBindPoint::GRAPHICS;
-        unsafe {
-            self.device
-                .core
-                .cmd_bind_pipeline(self.cmd_buf.raw, bind_point, pipeline.raw)
-        };
-        super::PipelineEncoder {
-            cmd_buf: self.cmd_buf,
-            layout: &pipeline.layout,
-            bind_point,
-            device: self.device,
-            update_data: self.update_data,
-        }
+        let mut encoder = render_pipeline_encoder(
+            self.cmd_buf.raw,
+            &mut self.cmd_buf.descriptor_pool,
+            self.device,
+            self.update_data,
+            self.barriers,
+            self.trace,
+            pipeline,
+        );
+        encoder.occlusion_queries = self.occlusion_queries;
+        encoder
     }
 }
 
 impl Drop for super::RenderCommandEncoder<'_> {
     fn drop(&mut self) {
+        record(self.trace, |_| trace::Command::EndPass);
         unsafe {
             self.device
                 .dynamic_rendering
                 .cmd_end_rendering(self.cmd_buf.raw)
         };
-        end_pass(self.device, self.cmd_buf.raw);
+        end_pass(self.device, self.cmd_buf.raw, &mut self.cmd_buf.timestamps);
+    }
+}
+
+impl super::Device {
+    pub(super) fn create_timestamp_pool(&self) -> vk::QueryPool {
+        let query_pool_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(super::TIMESTAMP_POOL_SIZE);
+        unsafe { self.core.create_query_pool(&query_pool_info, None).unwrap() }
+    }
+
+    fn create_chunk_command_buffer(&self, family_index: u32) -> super::ChunkCommandBuffer {
+        let pool_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index: family_index,
+            ..Default::default()
+        };
+        let pool = unsafe { self.core.create_command_pool(&pool_info, None).unwrap() };
+        let cmd_buf_info = vk::CommandBufferAllocateInfo {
+            command_pool: pool,
+            level: vk::CommandBufferLevel::SECONDARY,
+            command_buffer_count: 1,
+            ..Default::default()
+        };
+        let raw = unsafe { self.core.allocate_command_buffers(&cmd_buf_info).unwrap()[0] };
+        super::ChunkCommandBuffer {
+            pool,
+            raw,
+            descriptor_pool: self.create_descriptor_pool(),
+        }
+    }
+
+    fn reset_chunk_command_buffer(&self, chunk: &mut super::ChunkCommandBuffer) {
+        unsafe {
+            self.core
+                .reset_command_pool(chunk.pool, vk::CommandPoolResetFlags::empty())
+                .unwrap()
+        };
+        self.reset_descriptor_pool(&mut chunk.descriptor_pool);
+    }
+
+    pub(super) fn destroy_chunk_command_buffer(&self, chunk: &mut super::ChunkCommandBuffer) {
+        unsafe { self.core.destroy_command_pool(chunk.pool, None) };
+        self.destroy_descriptor_pool(&mut chunk.descriptor_pool);
+    }
+}
+
+impl super::ParallelRenderCommandEncoder<'_> {
+    /// Begin a chunk of the pass, which can be recorded on any thread.
+    /// Chunks are executed in the order they are begun, after being merged back.
+    pub fn chunk(&mut self) -> super::RenderChunk {
+        let device = self.pass.device;
+        let cmd_buf = match self.free_chunks.pop() {
+            Some(cmd_buf) => cmd_buf,
+            None => device.create_chunk_command_buffer(self.family_index),
+        };
+        let mut rendering_info = vk::CommandBufferInheritanceRenderingInfo::default()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(self.depth_format)
+            .stencil_attachment_format(self.stencil_format)
+            .rasterization_samples(self.samples);
+        let inheritance_info =
+            vk::CommandBufferInheritanceInfo::default().push_next(&mut rendering_info);
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(
+                vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                    | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            )
+            .inheritance_info(&inheritance_info);
+        let [width, height] = self.target_size;
+        let render_area = crate::ScissorRect {
+            x: 0,
+            y: 0,
+            w: width as u32,
+            h: height as u32,
+        }
+        .to_vk();
+        let viewport = crate::Viewport {
+            x: 0.0,
+            y: 0.0,
+            w: width as f32,
+            h: height as f32,
+            depth: 0.0..1.0,
+        }
+        .to_vk();
+        unsafe {
+            device
+                .core
+                .begin_command_buffer(cmd_buf.raw, &begin_info)
+                .unwrap();
+            // The dynamic state isn't inherited from the primary command buffer
+            device.core.cmd_set_viewport(cmd_buf.raw, 0, &[viewport]);
+            device.core.cmd_set_scissor(cmd_buf.raw, 0, &[render_area]);
+        }
+
+        let index = self.chunks.len();
+        self.chunks.push(None);
+        super::RenderChunk {
+            index,
+            cmd_buf,
+            device: device.clone(),
+            update_data: Vec::new(),
+            barriers: Default::default(),
+            occlusion_queries: self.pass.occlusion_queries,
+            trace: self
+                .pass
+                .trace
+                .as_ref()
+                .map(|trace| trace.chunk(self.target_size)),
+        }
+    }
+
+    /// Return a recorded chunk into the pass.
+    pub fn merge(&mut self, chunk: super::RenderChunk) {
+        unsafe {
+            self.pass
+                .device
+                .core
+                .end_command_buffer(chunk.cmd_buf.raw)
+                .unwrap()
+        };
+        let slot = &mut self.chunks[chunk.index];
+        assert!(
+            slot.is_none(),
+            "Render chunk {} is merged twice",
+            chunk.index
+        );
+        *slot = Some(chunk);
+    }
+}
+
+impl Drop for super::ParallelRenderCommandEncoder<'_> {
+    fn drop(&mut self) {
+        let mut raw_buffers = Vec::with_capacity(self.chunks.len());
+        for (index, chunk) in self.chunks.drain(..).enumerate() {
+            let Some(mut chunk) = chunk else {
+                log::error!("Render chunk {} is not merged into the pass", index);
+                continue;
+            };
+            raw_buffers.push(chunk.cmd_buf.raw);
+            self.pass.barriers.absorb(&mut chunk.barriers);
+            if let Some(ref mut trace) = *self.pass.trace
+                && let Some(chunk_trace) = chunk.trace
+            {
+                trace.join(chunk_trace);
+            }
+            self.pass.cmd_buf.chunks.push(chunk.cmd_buf);
+        }
+        if !raw_buffers.is_empty() {
+            unsafe {
+                self.pass
+                    .device
+                    .core
+                    .cmd_execute_commands(self.pass.cmd_buf.raw, &raw_buffers)
+            };
+        }
+    }
+}
+
+impl super::RenderChunk {
+    pub fn with<'b, 'p>(
+        &'b mut self,
+        pipeline: &'p super::RenderPipeline,
+    ) -> super::PipelineEncoder<'b, 'p> {
+        let mut encoder = render_pipeline_encoder(
+            self.cmd_buf.raw,
+            &mut self.cmd_buf.descriptor_pool,
+            &self.device,
+            &mut self.update_data,
+            &mut self.barriers,
+            &mut self.trace,
+            pipeline,
+        );
+        encoder.occlusion_queries = self.occlusion_queries;
+        encoder
     }
 }
 
@@ -879,6 +1596,7 @@ impl crate::Viewport {
 #[hidden_trait::expose]
 impl crate::traits::RenderEncoder for super::RenderCommandEncoder<'_> {
     fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        record(self.trace, |_| trace::Command::SetScissorRect { rect: rect.clone() });
         let vk_scissor = rect.to_vk();
         unsafe {
             self.device
@@ -888,6 +1606,9 @@ impl crate::traits::RenderEncoder for super::RenderCommandEncoder<'_> {
     }
 
     fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        record(self.trace, |_| trace::Command::SetViewport {
+            viewport: viewport.clone(),
+        });
         let vk_viewport = viewport.to_vk();
         unsafe {
             self.device
@@ -897,6 +1618,7 @@ impl crate::traits::RenderEncoder for super::RenderCommandEncoder<'_> {
     }
 
     fn set_stencil_reference(&mut self, reference: u32) {
+        record(self.trace, |_| trace::Command::SetStencilReference { reference });
         unsafe {
             self.device.core.cmd_set_stencil_reference(
                 self.cmd_buf.raw,
@@ -905,26 +1627,143 @@ impl crate::traits::RenderEncoder for super::RenderCommandEncoder<'_> {
             )
         };
     }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        begin_occlusion_query(
+            self.device,
+            self.cmd_buf.raw,
+            self.occlusion_queries,
+            index,
+            mode,
+        );
+    }
+
+    fn end_occlusion_query(&mut self, index: u32) {
+        end_occlusion_query(self.device, self.cmd_buf.raw, self.occlusion_queries, index);
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::RenderEncoder for super::RenderChunk {
+    fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        record(&mut self.trace, |_| trace::Command::SetScissorRect {
+            rect: rect.clone(),
+        });
+        let vk_scissor = rect.to_vk();
+        unsafe {
+            self.device
+                .core
+                .cmd_set_scissor(self.cmd_buf.raw, 0, &[vk_scissor])
+        };
+    }
+
+    fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        record(&mut self.trace, |_| trace::Command::SetViewport {
+            viewport: viewport.clone(),
+        });
+        let vk_viewport = viewport.to_vk();
+        unsafe {
+            self.device
+                .core
+                .cmd_set_viewport(self.cmd_buf.raw, 0, &[vk_viewport])
+        };
+    }
+
+    fn set_stencil_reference(&mut self, reference: u32) {
+        record(&mut self.trace, |_| trace::Command::SetStencilReference {
+            reference,
+        });
+        unsafe {
+            self.device.core.cmd_set_stencil_reference(
+                self.cmd_buf.raw,
+                vk::StencilFaceFlags::FRONT_AND_BACK,
+                reference,
+            )
+        };
+    }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        begin_occlusion_query(
+            &self.device,
+            self.cmd_buf.raw,
+            self.occlusion_queries,
+            index,
+            mode,
+        );
+    }
+
+    fn end_occlusion_query(&mut self, index: u32) {
+        end_occlusion_query(
+            &self.device,
+            self.cmd_buf.raw,
+            self.occlusion_queries,
+            index,
+        );
+    }
 }
 
 #[hidden_trait::expose]
 impl crate::traits::PipelineEncoder for super::PipelineEncoder<'_, '_> {
+    type BindlessTable = super::BindlessTable;
+
     fn bind<D: crate::ShaderData>(&mut self, group: u32, data: &D) {
         let dsl = &self.layout.descriptor_set_layouts[group as usize];
+        if cfg!(debug_assertions)
+            && let Err(message) = crate::reflect::check_plain_data::<D>(&dsl.plain_structs)
+        {
+            panic!("{}", message);
+        }
+        let mut captured = self.trace.as_ref().map(|_| Vec::new());
         if !dsl.is_empty() {
             self.update_data.clear();
             self.update_data.resize(dsl.template_size as usize, 0);
             data.fill(super::PipelineContext {
                 update_data: self.update_data.as_mut_slice(),
                 template_offsets: &dsl.template_offsets,
+                captured: captured.as_mut(),
+                binding_access: &dsl.binding_access,
+                shader_stages: self.shader_stages,
+                barriers: self.barriers,
+            });
+            for spill in dsl.plain_spills.iter() {
+                let start = spill.data_offset as usize;
+                let data = &self.update_data[start..start + spill.size as usize];
+                match spill.target {
+                    super::SpillTarget::UniformBuffer { info_offset } => {
+                        let buffer_info =
+                            self.device.write_uniform_data(self.descriptor_pool, data);
+                        unsafe {
+                            ptr::write_unaligned(
+                                self.update_data.as_mut_ptr().add(info_offset as usize)
+                                    as *mut vk::DescriptorBufferInfo,
+                                buffer_info,
+                            )
+                        };
+                    }
+                    super::SpillTarget::PushConstants(stages) => unsafe {
+                        self.device.core.cmd_push_constants(
+                            self.raw,
+                            self.layout.raw,
+                            stages,
+                            0,
+                            data,
+                        );
+                    },
+                }
+            }
+        }
+        if let Some(captured) = captured {
+            record(self.trace, |ids| trace::Command::Bind {
+                group,
+                bindings: ids.bindings(captured),
             });
         }
 
         let vk_set = self
             .device
-            .allocate_descriptor_set(&mut self.cmd_buf.descriptor_pool, dsl);
+            .allocate_descriptor_set(self.descriptor_pool, dsl);
         unsafe {
-            if !dsl.is_empty() {
+            if dsl.update_template != vk::DescriptorUpdateTemplate::null() {
                 self.device.core.update_descriptor_set_with_template(
                     vk_set,
                     dsl.update_template,
@@ -932,7 +1771,7 @@ impl crate::traits::PipelineEncoder for super::PipelineEncoder<'_, '_> {
                 );
             }
             self.device.core.cmd_bind_descriptor_sets(
-                self.cmd_buf.raw,
+                self.raw,
                 self.bind_point,
                 self.layout.raw,
                 group,
@@ -941,6 +1780,23 @@ impl crate::traits::PipelineEncoder for super::PipelineEncoder<'_, '_> {
             );
         }
     }
+
+    fn bind_table(&mut self, group: u32, table: &super::BindlessTable) {
+        self.barriers.use_untracked(Usage::shader(
+            self.shader_stages,
+            crate::StorageAccess::empty(),
+        ));
+        unsafe {
+            self.device.core.cmd_bind_descriptor_sets(
+                self.raw,
+                self.bind_point,
+                self.layout.raw,
+                group,
+                &[table.raw],
+                &[],
+            );
+        }
+    }
 }
 
 #[hidden_trait::expose]
@@ -948,16 +1804,24 @@ impl crate::traits::ComputePipelineEncoder for super::PipelineEncoder<'_, '_> {
     type BufferPiece = crate::BufferPiece;
 
     fn dispatch(&mut self, groups: [u32; 3]) {
+        record(self.trace, |_| trace::Command::Dispatch { groups });
+        self.barriers.flush(self.device, self.raw);
         unsafe {
             self.device
                 .core
-                .cmd_dispatch(self.cmd_buf.raw, groups[0], groups[1], groups[2])
+                .cmd_dispatch(self.raw, groups[0], groups[1], groups[2])
         };
     }
     fn dispatch_indirect(&mut self, indirect_buf: crate::BufferPiece) {
+        record(self.trace, |ids| trace::Command::DispatchIndirect {
+            indirect_buf: ids.buffer(indirect_buf),
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(indirect_buf.buffer.raw), Usage::INDIRECT);
+        self.barriers.flush(self.device, self.raw);
         unsafe {
             self.device.core.cmd_dispatch_indirect(
-                self.cmd_buf.raw,
+                self.raw,
                 indirect_buf.buffer.raw,
                 indirect_buf.offset,
             )
@@ -968,32 +1832,41 @@ impl crate::traits::ComputePipelineEncoder for super::PipelineEncoder<'_, '_> {
 #[hidden_trait::expose]
 impl crate::traits::RenderEncoder for super::PipelineEncoder<'_, '_> {
     fn set_scissor_rect(&mut self, rect: &crate::ScissorRect) {
+        record(self.trace, |_| trace::Command::SetScissorRect { rect: rect.clone() });
         let vk_scissor = rect.to_vk();
-        unsafe {
-            self.device
-                .core
-                .cmd_set_scissor(self.cmd_buf.raw, 0, &[vk_scissor])
-        };
+        unsafe { self.device.core.cmd_set_scissor(self.raw, 0, &[vk_scissor]) };
     }
 
     fn set_viewport(&mut self, viewport: &crate::Viewport) {
+        record(self.trace, |_| trace::Command::SetViewport {
+            viewport: viewport.clone(),
+        });
         let vk_viewport = viewport.to_vk();
         unsafe {
             self.device
                 .core
-                .cmd_set_viewport(self.cmd_buf.raw, 0, &[vk_viewport])
+                .cmd_set_viewport(self.raw, 0, &[vk_viewport])
         };
     }
 
     fn set_stencil_reference(&mut self, reference: u32) {
+        record(self.trace, |_| trace::Command::SetStencilReference { reference });
         unsafe {
             self.device.core.cmd_set_stencil_reference(
-                self.cmd_buf.raw,
+                self.raw,
                 vk::StencilFaceFlags::FRONT_AND_BACK,
                 reference,
             )
         };
     }
+
+    fn begin_occlusion_query(&mut self, index: u32, mode: crate::OcclusionMode) {
+        begin_occlusion_query(self.device, self.raw, self.occlusion_queries, index, mode);
+    }
+
+    fn end_occlusion_query(&mut self, index: u32) {
+        end_occlusion_query(self.device, self.raw, self.occlusion_queries, index);
+    }
 }
 
 #[hidden_trait::expose]
@@ -1001,9 +1874,15 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_, '_> {
     type BufferPiece = crate::BufferPiece;
 
     fn bind_vertex(&mut self, index: u32, vertex_buf: crate::BufferPiece) {
+        record(self.trace, |ids| trace::Command::BindVertex {
+            index,
+            vertex_buf: ids.buffer(vertex_buf),
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(vertex_buf.buffer.raw), Usage::VERTEX);
         unsafe {
             self.device.core.cmd_bind_vertex_buffers(
-                self.cmd_buf.raw,
+                self.raw,
                 index,
                 &[vertex_buf.buffer.raw],
                 &[vertex_buf.offset],
@@ -1018,9 +1897,15 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_, '_> {
         start_instance: u32,
         instance_count: u32,
     ) {
+        record(self.trace, |_| trace::Command::Draw {
+            first_vertex: start_vertex,
+            vertex_count,
+            first_instance: start_instance,
+            instance_count,
+        });
         unsafe {
             self.device.core.cmd_draw(
-                self.cmd_buf.raw,
+                self.raw,
                 vertex_count,
                 instance_count,
                 start_vertex,
@@ -1038,16 +1923,26 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_, '_> {
         start_instance: u32,
         instance_count: u32,
     ) {
+        record(self.trace, |ids| trace::Command::DrawIndexed {
+            index_buf: ids.buffer(index_buf),
+            index_type,
+            index_count,
+            base_vertex,
+            start_instance,
+            instance_count,
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(index_buf.buffer.raw), Usage::INDEX);
         let raw_index_type = super::map_index_type(index_type);
         unsafe {
             self.device.core.cmd_bind_index_buffer(
-                self.cmd_buf.raw,
+                self.raw,
                 index_buf.buffer.raw,
                 index_buf.offset,
                 raw_index_type,
             );
             self.device.core.cmd_draw_indexed(
-                self.cmd_buf.raw,
+                self.raw,
                 index_count,
                 instance_count,
                 0,
@@ -1058,9 +1953,14 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_, '_> {
     }
 
     fn draw_indirect(&mut self, indirect_buf: crate::BufferPiece) {
+        record(self.trace, |ids| trace::Command::DrawIndirect {
+            indirect_buf: ids.buffer(indirect_buf),
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(indirect_buf.buffer.raw), Usage::INDIRECT);
         unsafe {
             self.device.core.cmd_draw_indirect(
-                self.cmd_buf.raw,
+                self.raw,
                 indirect_buf.buffer.raw,
                 indirect_buf.offset,
                 1,
@@ -1075,16 +1975,25 @@ impl crate::traits::RenderPipelineEncoder for super::PipelineEncoder<'_, '_> {
         index_type: crate::IndexType,
         indirect_buf: crate::BufferPiece,
     ) {
+        record(self.trace, |ids| trace::Command::DrawIndexedIndirect {
+            index_buf: ids.buffer(index_buf),
+            index_type,
+            indirect_buf: ids.buffer(indirect_buf),
+        });
+        self.barriers
+            .use_resource(Resource::Buffer(index_buf.buffer.raw), Usage::INDEX);
+        self.barriers
+            .use_resource(Resource::Buffer(indirect_buf.buffer.raw), Usage::INDIRECT);
         let raw_index_type = super::map_index_type(index_type);
         unsafe {
             self.device.core.cmd_bind_index_buffer(
-                self.cmd_buf.raw,
+                self.raw,
                 index_buf.buffer.raw,
                 index_buf.offset,
                 raw_index_type,
             );
             self.device.core.cmd_draw_indexed_indirect(
-                self.cmd_buf.raw,
+                self.raw,
                 indirect_buf.buffer.raw,
                 indirect_buf.offset,
                 1,
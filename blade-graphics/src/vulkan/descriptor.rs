@@ -1,13 +1,32 @@
 use ash::vk;
+use gpu_alloc_ash::AshMemoryDevice;
+use std::ptr;
 
 //TODO: replace by an abstraction in `gpu-descriptor`
 // https://github.com/zakarumych/gpu-descriptor/issues/42
 const COUNT_BASE: u32 = 16;
+/// Min size of a uniform buffer with the plain data that doesn't fit into the descriptors.
+const UNIFORM_CHUNK_SIZE: u64 = 1 << 16;
+
+/// Host-visible uniform buffer, filled linearly by the plain data.
+#[derive(Debug)]
+struct UniformChunk {
+    raw: vk::Buffer,
+    block: gpu_alloc::MemoryBlock<vk::DeviceMemory>,
+    data: *mut u8,
+    size: u64,
+}
+
+unsafe impl Send for UniformChunk {}
 
 #[derive(Debug)]
 pub struct DescriptorPool {
     sub_pools: Vec<vk::DescriptorPool>,
     growth_iter: usize,
+    /// Plain data that is larger than `limits::PLAIN_DATA_SIZE`. It lives
+    /// as long as the descriptor sets, and is reset together with them.
+    uniform_chunks: Vec<UniformChunk>,
+    uniform_offset: u64,
 }
 
 impl super::Device {
@@ -22,6 +41,10 @@ impl super::Device {
                 ty: vk::DescriptorType::STORAGE_BUFFER,
                 descriptor_count: max_sets,
             },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: max_sets,
+            },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::SAMPLED_IMAGE,
                 descriptor_count: 2 * max_sets,
@@ -65,6 +88,8 @@ impl super::Device {
         DescriptorPool {
             sub_pools: vec![vk_pool],
             growth_iter: 0,
+            uniform_chunks: Vec::new(),
+            uniform_offset: 0,
         }
     }
 
@@ -72,6 +97,98 @@ impl super::Device {
         for sub_pool in pool.sub_pools.drain(..) {
             unsafe { self.core.destroy_descriptor_pool(sub_pool, None) };
         }
+        for chunk in pool.uniform_chunks.drain(..) {
+            self.destroy_uniform_chunk(chunk);
+        }
+    }
+
+    fn create_uniform_chunk(&self, size: u64) -> UniformChunk {
+        log::info!("Creating a uniform buffer of {} bytes for plain data", size);
+        let buffer_info = vk::BufferCreateInfo {
+            size,
+            usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let raw = unsafe { self.core.create_buffer(&buffer_info, None).unwrap() };
+        let requirements = unsafe { self.core.get_buffer_memory_requirements(raw) };
+        let mut manager = self.memory.lock().unwrap();
+        let memory_types = requirements.memory_type_bits & manager.valid_ash_memory_types;
+        let mut block = unsafe {
+            manager
+                .allocator
+                .alloc(
+                    AshMemoryDevice::wrap(&self.core),
+                    gpu_alloc::Request {
+                        size: requirements.size,
+                        align_mask: requirements.alignment - 1,
+                        usage: gpu_alloc::UsageFlags::HOST_ACCESS | gpu_alloc::UsageFlags::UPLOAD,
+                        memory_types,
+                    },
+                )
+                .unwrap()
+        };
+        unsafe {
+            self.core
+                .bind_buffer_memory(raw, *block.memory(), block.offset())
+                .unwrap();
+            let data = block
+                .map(AshMemoryDevice::wrap(&self.core), 0, size as usize)
+                .unwrap();
+            UniformChunk {
+                raw,
+                block,
+                data: data.as_ptr(),
+                size,
+            }
+        }
+    }
+
+    fn destroy_uniform_chunk(&self, chunk: UniformChunk) {
+        let mut manager = self.memory.lock().unwrap();
+        unsafe {
+            self.core.destroy_buffer(chunk.raw, None);
+            manager
+                .allocator
+                .dealloc(AshMemoryDevice::wrap(&self.core), chunk.block);
+        }
+    }
+
+    /// Copy plain data into the uniform buffers of the pool.
+    pub(super) fn write_uniform_data(
+        &self,
+        pool: &mut DescriptorPool,
+        data: &[u8],
+    ) -> vk::DescriptorBufferInfo {
+        let size = data.len() as u64;
+        let alignment = self.min_uniform_buffer_alignment;
+        let offset = pool.uniform_offset.next_multiple_of(alignment);
+        let fits = pool
+            .uniform_chunks
+            .last()
+            .is_some_and(|chunk| offset + size <= chunk.size);
+        let offset = if fits {
+            offset
+        } else {
+            let chunk_size = pool
+                .uniform_chunks
+                .last()
+                .map_or(UNIFORM_CHUNK_SIZE, |chunk| 2 * chunk.size)
+                .max(size);
+            let chunk = self.create_uniform_chunk(chunk_size);
+            pool.uniform_chunks.push(chunk);
+            0
+        };
+        let chunk = pool.uniform_chunks.last().unwrap();
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), chunk.data.add(offset as usize), data.len());
+        }
+        pool.uniform_offset = offset + size;
+        vk::DescriptorBufferInfo {
+            buffer: chunk.raw,
+            offset,
+            range: size,
+        }
     }
 
     pub(super) fn allocate_descriptor_set(
@@ -105,6 +222,12 @@ impl super::Device {
                 self.core.destroy_descriptor_pool(vk_pool, None);
             }
         }
+        // keep only the largest uniform buffer for the next recordings
+        let last = pool.uniform_chunks.len().saturating_sub(1);
+        for chunk in pool.uniform_chunks.drain(..last) {
+            self.destroy_uniform_chunk(chunk);
+        }
+        pool.uniform_offset = 0;
 
         unsafe {
             self.core
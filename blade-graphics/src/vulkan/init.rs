@@ -1,6 +1,9 @@
 use ash::{amd, ext, khr, vk};
 use naga::back::spv;
-use std::{ffi, fs, sync::Mutex};
+use std::{
+    ffi, fs,
+    sync::{Arc, Mutex},
+};
 
 use crate::NotSupportedError;
 
@@ -43,6 +46,10 @@ struct AdapterCapabilities {
     properties: vk::PhysicalDeviceProperties,
     device_information: crate::DeviceInformation,
     queue_family_index: u32,
+    /// Family with compute but without graphics, for asynchronous compute.
+    compute_queue_family: Option<u32>,
+    /// Family with only transfer, for asynchronous uploads.
+    transfer_queue_family: Option<u32>,
     layered: bool,
     ray_tracing: Option<RayTracingCapabilities>,
     buffer_marker: bool,
@@ -51,6 +58,10 @@ struct AdapterCapabilities {
     external_memory: bool,
     timing: bool,
     dual_source_blending: bool,
+    precise_occlusion: bool,
+    pipeline_statistics: bool,
+    /// Max number of resources of each kind in a bindless table, zero if not supported.
+    max_bindless_resources: u32,
     bugs: SystemBugs,
 }
 
@@ -140,6 +151,40 @@ unsafe fn inspect_adapter(
     };
 
     let queue_family_index = 0; //TODO
+    let queue_families =
+        unsafe { instance.core.get_physical_device_queue_family_properties(phd) };
+    let find_dedicated_family = |required: vk::QueueFlags, excluded: vk::QueueFlags| {
+        queue_families
+            .iter()
+            .position(|family| {
+                family.queue_flags.contains(required)
+                    && !family.queue_flags.intersects(excluded)
+                    && (!desc.timing || family.timestamp_valid_bits != 0)
+            })
+            .map(|index| index as u32)
+            .filter(|&index| index != queue_family_index)
+    };
+    let compute_queue_family =
+        find_dedicated_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS);
+    // Transfer-only families may not be able to copy arbitrary image regions,
+    // in which case the copies go to the compute or the general queue.
+    let transfer_queue_family = find_dedicated_family(
+        vk::QueueFlags::TRANSFER,
+        vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+    )
+    .filter(|&index| {
+        let granularity = queue_families[index as usize].min_image_transfer_granularity;
+        let any_region =
+            granularity.width == 1 && granularity.height == 1 && granularity.depth == 1;
+        if !any_region {
+            log::info!(
+                "Skipping transfer queue family {} with image granularity {:?}",
+                index,
+                granularity
+            );
+        }
+        any_region
+    });
     if desc.presentation && bugs.intel_unable_to_present {
         log::warn!("Rejecting Intel for not presenting when Nvidia is present (on Linux)");
         return None;
@@ -171,6 +216,8 @@ unsafe fn inspect_adapter(
     }
 
     let dual_source_blending = features2_khr.features.dual_src_blend != 0;
+    let precise_occlusion = features2_khr.features.occlusion_query_precise != 0;
+    let pipeline_statistics = features2_khr.features.pipeline_statistics_query != 0;
 
     if inline_uniform_block_properties.max_inline_uniform_block_size
         < crate::limits::PLAIN_DATA_SIZE
@@ -262,6 +309,32 @@ unsafe fn inspect_adapter(
         })
     };
 
+    let max_bindless_resources = if descriptor_indexing_features.runtime_descriptor_array
+        == vk::FALSE
+        || descriptor_indexing_features.descriptor_binding_partially_bound == vk::FALSE
+        || descriptor_indexing_features.descriptor_binding_sampled_image_update_after_bind
+            == vk::FALSE
+        || descriptor_indexing_features.descriptor_binding_storage_buffer_update_after_bind
+            == vk::FALSE
+        || descriptor_indexing_features.descriptor_binding_update_unused_while_pending == vk::FALSE
+        || descriptor_indexing_features.shader_sampled_image_array_non_uniform_indexing == vk::FALSE
+        || descriptor_indexing_features.shader_storage_buffer_array_non_uniform_indexing
+            == vk::FALSE
+    {
+        log::info!(
+            "No bindless tables because of the descriptor indexing. Features = {:?}",
+            descriptor_indexing_features
+        );
+        0
+    } else {
+        let props = &descriptor_indexing_properties;
+        props
+            .max_descriptor_set_update_after_bind_sampled_images
+            .min(props.max_descriptor_set_update_after_bind_storage_buffers)
+            .min(props.max_descriptor_set_update_after_bind_samplers)
+            .min(props.max_per_stage_update_after_bind_resources)
+    };
+
     let buffer_marker = supported_extensions.contains(&vk::AMD_BUFFER_MARKER_NAME);
     let shader_info = supported_extensions.contains(&vk::AMD_SHADER_INFO_NAME);
     let full_screen_exclusive = supported_extensions.contains(&vk::EXT_FULL_SCREEN_EXCLUSIVE_NAME);
@@ -284,6 +357,8 @@ unsafe fn inspect_adapter(
         properties,
         device_information,
         queue_family_index,
+        compute_queue_family,
+        transfer_queue_family,
         layered: portability_subset_properties.min_vertex_input_binding_stride_alignment != 0,
         ray_tracing,
         buffer_marker,
@@ -292,6 +367,9 @@ unsafe fn inspect_adapter(
         external_memory,
         timing,
         dual_source_blending,
+        precise_occlusion,
+        pipeline_statistics,
+        max_bindless_resources,
         bugs,
     })
 }
@@ -451,11 +529,20 @@ impl super::Context {
             min_buffer_alignment = min_buffer_alignment.max(rt.min_scratch_buffer_alignment);
         }
 
+        let queue_families = [capabilities.queue_family_index]
+            .into_iter()
+            .chain(capabilities.compute_queue_family)
+            .chain(capabilities.transfer_queue_family)
+            .collect::<Vec<_>>();
         let device_core = {
-            let family_info = vk::DeviceQueueCreateInfo::default()
-                .queue_family_index(capabilities.queue_family_index)
-                .queue_priorities(&[1.0]);
-            let family_infos = [family_info];
+            let family_infos = queue_families
+                .iter()
+                .map(|&family_index| {
+                    vk::DeviceQueueCreateInfo::default()
+                        .queue_family_index(family_index)
+                        .queue_priorities(&[1.0])
+                })
+                .collect::<Vec<_>>();
 
             let mut device_extensions = REQUIRED_DEVICE_EXTENSIONS.to_vec();
             if desc.presentation {
@@ -465,9 +552,13 @@ impl super::Context {
                 log::info!("Enabling Vulkan Portability");
                 device_extensions.push(vk::KHR_PORTABILITY_SUBSET_NAME);
             }
+            let descriptor_indexing =
+                capabilities.ray_tracing.is_some() || capabilities.max_bindless_resources != 0;
+            if descriptor_indexing && capabilities.api_version < vk::API_VERSION_1_2 {
+                device_extensions.push(vk::EXT_DESCRIPTOR_INDEXING_NAME);
+            }
             if capabilities.ray_tracing.is_some() {
                 if capabilities.api_version < vk::API_VERSION_1_2 {
-                    device_extensions.push(vk::EXT_DESCRIPTOR_INDEXING_NAME);
                     device_extensions.push(vk::KHR_BUFFER_DEVICE_ADDRESS_NAME);
                     device_extensions.push(vk::KHR_SHADER_FLOAT_CONTROLS_NAME);
                     device_extensions.push(vk::KHR_SPIRV_1_4_NAME);
@@ -519,16 +610,29 @@ impl super::Context {
                 .push_next(&mut khr_dynamic_rendering);
 
             let mut ext_descriptor_indexing;
-            let mut khr_buffer_device_address;
-            let mut khr_acceleration_structure;
-            let mut khr_ray_query;
-            if capabilities.ray_tracing.is_some() {
+            if descriptor_indexing {
+                let bindless = if capabilities.max_bindless_resources != 0 {
+                    vk::TRUE
+                } else {
+                    vk::FALSE
+                };
                 ext_descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingFeaturesEXT {
                     shader_storage_buffer_array_non_uniform_indexing: vk::TRUE,
                     shader_sampled_image_array_non_uniform_indexing: vk::TRUE,
                     descriptor_binding_partially_bound: vk::TRUE,
+                    runtime_descriptor_array: bindless,
+                    descriptor_binding_sampled_image_update_after_bind: bindless,
+                    descriptor_binding_storage_buffer_update_after_bind: bindless,
+                    descriptor_binding_update_unused_while_pending: bindless,
                     ..Default::default()
                 };
+                device_create_info = device_create_info.push_next(&mut ext_descriptor_indexing);
+            }
+
+            let mut khr_buffer_device_address;
+            let mut khr_acceleration_structure;
+            let mut khr_ray_query;
+            if capabilities.ray_tracing.is_some() {
                 khr_buffer_device_address = vk::PhysicalDeviceBufferDeviceAddressFeaturesKHR {
                     buffer_device_address: vk::TRUE,
                     ..Default::default()
@@ -542,7 +646,6 @@ impl super::Context {
                     ..Default::default()
                 };
                 device_create_info = device_create_info
-                    .push_next(&mut ext_descriptor_indexing)
                     .push_next(&mut khr_buffer_device_address)
                     .push_next(&mut khr_acceleration_structure)
                     .push_next(&mut khr_ray_query);
@@ -552,6 +655,12 @@ impl super::Context {
             if capabilities.dual_source_blending {
                 core_features.dual_src_blend = vk::TRUE;
             }
+            if capabilities.precise_occlusion {
+                core_features.occlusion_query_precise = vk::TRUE;
+            }
+            if capabilities.pipeline_statistics {
+                core_features.pipeline_statistics_query = vk::TRUE;
+            }
 
             let mut device_features2 =
                 vk::PhysicalDeviceFeatures2::default().features(core_features);
@@ -564,6 +673,72 @@ impl super::Context {
                 .map_err(super::PlatformError::Init)? }
         };
 
+        let memory_manager = {
+            let mem_properties = unsafe { instance
+                .core
+                .get_physical_device_memory_properties(physical_device) };
+            let memory_types =
+                &mem_properties.memory_types[..mem_properties.memory_type_count as usize];
+            let limits = &capabilities.properties.limits;
+            let config = gpu_alloc::Config::i_am_prototyping(); //TODO?
+
+            let properties = gpu_alloc::DeviceProperties {
+                max_memory_allocation_count: limits.max_memory_allocation_count,
+                max_memory_allocation_size: u64::MAX, // TODO
+                non_coherent_atom_size: limits.non_coherent_atom_size,
+                memory_types: memory_types
+                    .iter()
+                    .map(|memory_type| gpu_alloc::MemoryType {
+                        props: gpu_alloc::MemoryPropertyFlags::from_bits_truncate(
+                            memory_type.property_flags.as_raw() as u8,
+                        ),
+                        heap: memory_type.heap_index,
+                    })
+                    .collect(),
+                memory_heaps: mem_properties.memory_heaps
+                    [..mem_properties.memory_heap_count as usize]
+                    .iter()
+                    .map(|&memory_heap| gpu_alloc::MemoryHeap {
+                        size: memory_heap.size,
+                    })
+                    .collect(),
+                buffer_device_address: capabilities.ray_tracing.is_some(),
+            };
+
+            let known_memory_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL
+                | vk::MemoryPropertyFlags::HOST_VISIBLE
+                | vk::MemoryPropertyFlags::HOST_COHERENT
+                | vk::MemoryPropertyFlags::HOST_CACHED
+                | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+            let valid_ash_memory_types = memory_types.iter().enumerate().fold(0, |u, (i, mem)| {
+                if !known_memory_flags.contains(mem.property_flags) {
+                    log::debug!(
+                        "Skipping memory type={} for having unknown flags: {:?}",
+                        i,
+                        mem.property_flags & !known_memory_flags
+                    );
+                    u
+                } else if mem
+                    .property_flags
+                    .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
+                    && !mem
+                        .property_flags
+                        .contains(vk::MemoryPropertyFlags::HOST_COHERENT)
+                {
+                    //TODO: see if and how we can support this
+                    log::debug!("Skipping memory type={} for lack of host coherency", i);
+                    u
+                } else {
+                    u | (1 << i)
+                }
+            });
+            super::MemoryManager {
+                allocator: gpu_alloc::GpuAllocator::new(config, properties),
+                slab: slab::Slab::new(),
+                valid_ash_memory_types,
+            }
+        };
+
         let device = super::Device {
             swapchain: if desc.presentation {
                 Some(khr::swapchain::Device::new(&instance.core, &device_core))
@@ -629,6 +804,30 @@ impl super::Context {
             } else {
                 None
             },
+            precise_occlusion: capabilities.precise_occlusion,
+            memory: Arc::new(Mutex::new(memory_manager)),
+            min_uniform_buffer_alignment: capabilities
+                .properties
+                .limits
+                .min_uniform_buffer_offset_alignment,
+            max_push_constants_size: capabilities.properties.limits.max_push_constants_size,
+            depth24_plus_stencil8: {
+                let properties = unsafe {
+                    instance.core.get_physical_device_format_properties(
+                        physical_device,
+                        vk::Format::D24_UNORM_S8_UINT,
+                    )
+                };
+                if properties
+                    .optimal_tiling_features
+                    .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+                {
+                    vk::Format::D24_UNORM_S8_UINT
+                } else {
+                    log::info!("Backing Depth24PlusStencil8 with D32_SFLOAT_S8_UINT");
+                    vk::Format::D32_SFLOAT_S8_UINT
+                }
+            },
             //TODO: detect GPU family
             workarounds: super::Workarounds {
                 extra_sync_src_access: vk::AccessFlags::TRANSFER_WRITE,
@@ -650,87 +849,36 @@ impl super::Context {
             },
         };
 
-        let memory_manager = {
-            let mem_properties = unsafe { instance
-                .core
-                .get_physical_device_memory_properties(physical_device) };
-            let memory_types =
-                &mem_properties.memory_types[..mem_properties.memory_type_count as usize];
-            let limits = &capabilities.properties.limits;
-            let config = gpu_alloc::Config::i_am_prototyping(); //TODO?
-
-            let properties = gpu_alloc::DeviceProperties {
-                max_memory_allocation_count: limits.max_memory_allocation_count,
-                max_memory_allocation_size: u64::MAX, // TODO
-                non_coherent_atom_size: limits.non_coherent_atom_size,
-                memory_types: memory_types
-                    .iter()
-                    .map(|memory_type| gpu_alloc::MemoryType {
-                        props: gpu_alloc::MemoryPropertyFlags::from_bits_truncate(
-                            memory_type.property_flags.as_raw() as u8,
-                        ),
-                        heap: memory_type.heap_index,
-                    })
-                    .collect(),
-                memory_heaps: mem_properties.memory_heaps
-                    [..mem_properties.memory_heap_count as usize]
-                    .iter()
-                    .map(|&memory_heap| gpu_alloc::MemoryHeap {
-                        size: memory_heap.size,
-                    })
-                    .collect(),
-                buffer_device_address: capabilities.ray_tracing.is_some(),
+        let create_queue = |family_index: u32| {
+            let raw = unsafe { device.core.get_device_queue(family_index, 0) };
+            let last_progress = 0;
+            let mut timeline_info = vk::SemaphoreTypeCreateInfo {
+                semaphore_type: vk::SemaphoreType::TIMELINE,
+                initial_value: last_progress,
+                ..Default::default()
             };
-
-            let known_memory_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL
-                | vk::MemoryPropertyFlags::HOST_VISIBLE
-                | vk::MemoryPropertyFlags::HOST_COHERENT
-                | vk::MemoryPropertyFlags::HOST_CACHED
-                | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
-            let valid_ash_memory_types = memory_types.iter().enumerate().fold(0, |u, (i, mem)| {
-                if !known_memory_flags.contains(mem.property_flags) {
-                    log::debug!(
-                        "Skipping memory type={} for having unknown flags: {:?}",
-                        i,
-                        mem.property_flags & !known_memory_flags
-                    );
-                    u
-                } else if mem
-                    .property_flags
-                    .contains(vk::MemoryPropertyFlags::HOST_VISIBLE)
-                    && !mem
-                        .property_flags
-                        .contains(vk::MemoryPropertyFlags::HOST_COHERENT)
-                {
-                    //TODO: see if and how we can support this
-                    log::debug!("Skipping memory type={} for lack of host coherency", i);
-                    u
-                } else {
-                    u | (1 << i)
-                }
-            });
-            super::MemoryManager {
-                allocator: gpu_alloc::GpuAllocator::new(config, properties),
-                slab: slab::Slab::new(),
-                valid_ash_memory_types,
-            }
-        };
-
-        let queue = unsafe { device
-            .core
-            .get_device_queue(capabilities.queue_family_index, 0) };
-        let last_progress = 0;
-        let mut timeline_info = vk::SemaphoreTypeCreateInfo {
-            semaphore_type: vk::SemaphoreType::TIMELINE,
-            initial_value: last_progress,
-            ..Default::default()
+            let timeline_semaphore_create_info =
+                vk::SemaphoreCreateInfo::default().push_next(&mut timeline_info);
+            let timeline_semaphore = unsafe { device
+                .core
+                .create_semaphore(&timeline_semaphore_create_info, None)
+                .unwrap() };
+            Mutex::new(super::Queue {
+                raw,
+                family_index,
+                timeline_semaphore,
+                last_progress,
+            })
         };
-        let timeline_semaphore_create_info =
-            vk::SemaphoreCreateInfo::default().push_next(&mut timeline_info);
-        let timeline_semaphore = unsafe { device
-            .core
-            .create_semaphore(&timeline_semaphore_create_info, None)
-            .unwrap() };
+        let queue = create_queue(capabilities.queue_family_index);
+        let compute_queue = capabilities.compute_queue_family.map(create_queue);
+        let transfer_queue = capabilities.transfer_queue_family.map(create_queue);
+        if let Some(family_index) = capabilities.compute_queue_family {
+            log::info!("Using queue family {} for compute", family_index);
+        }
+        if let Some(family_index) = capabilities.transfer_queue_family {
+            log::info!("Using queue family {} for transfers", family_index);
+        }
 
         let mut naga_flags = spv::WriterFlags::FORCE_POINT_SIZE;
         let shader_debug_path = if desc.validation || desc.capture {
@@ -743,18 +891,21 @@ impl super::Context {
             None
         };
 
+        let pipeline_cache = desc.pipeline_cache_path.clone().and_then(|path| {
+            super::cache::PipelineCache::new(&device.core, &capabilities.properties, path)
+        });
+
         Ok(super::Context {
-            memory: Mutex::new(memory_manager),
             device,
             queue_family_index: capabilities.queue_family_index,
-            queue: Mutex::new(super::Queue {
-                raw: queue,
-                timeline_semaphore,
-                last_progress,
-            }),
+            queue,
+            compute_queue,
+            transfer_queue,
+            queue_families,
             physical_device,
             naga_flags,
             shader_debug_path,
+            pipeline_cache,
             min_buffer_alignment,
             sample_count_flags: capabilities
                 .properties
@@ -765,6 +916,10 @@ impl super::Context {
                     .limits
                     .framebuffer_depth_sample_counts,
             dual_source_blending: capabilities.dual_source_blending,
+            pipeline_statistics: capabilities.pipeline_statistics,
+            max_bindless_resources: capabilities.max_bindless_resources,
+            trace: crate::trace::Recorder::from_desc(&desc),
+            tracker: crate::tracking::Tracker::from_desc(&desc),
             instance,
             entry,
         })
@@ -782,6 +937,37 @@ impl super::Context {
         };
     }
 
+    /// Sync points of the last submissions to all the queues.
+    pub(super) fn last_sync_points(&self) -> Vec<super::SyncPoint> {
+        [
+            Some(&self.queue),
+            self.compute_queue.as_ref(),
+            self.transfer_queue.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|queue| {
+            let queue = queue.lock().unwrap();
+            super::SyncPoint {
+                timeline_semaphore: queue.timeline_semaphore,
+                progress: queue.last_progress,
+            }
+        })
+        .collect()
+    }
+
+    /// Queue for the given type of work, falling back to the general one.
+    pub(super) fn queue(&self, command_type: crate::CommandType) -> &Mutex<super::Queue> {
+        let dedicated = match command_type {
+            crate::CommandType::Transfer => {
+                self.transfer_queue.as_ref().or(self.compute_queue.as_ref())
+            }
+            crate::CommandType::Compute => self.compute_queue.as_ref(),
+            crate::CommandType::General => None,
+        };
+        dedicated.unwrap_or(&self.queue)
+    }
+
     pub fn capabilities(&self) -> crate::Capabilities {
         crate::Capabilities {
             ray_query: match self.device.ray_tracing {
@@ -790,12 +976,62 @@ impl super::Context {
             },
             sample_count_mask: self.sample_count_flags.as_raw(),
             dual_source_blending: self.dual_source_blending,
+            precise_occlusion: self.device.precise_occlusion,
+            pipeline_statistics: self.pipeline_statistics,
+            max_bindless_resources: self.max_bindless_resources,
         }
     }
 
     pub fn device_information(&self) -> &crate::DeviceInformation {
         &self.device.device_information
     }
+
+    /// Usages supported by textures of the given format on this device.
+    pub fn texture_format_usage(&self, format: crate::TextureFormat) -> crate::TextureUsage {
+        let properties = unsafe {
+            self.instance.core.get_physical_device_format_properties(
+                self.physical_device,
+                self.device.map_texture_format(format),
+            )
+        };
+        let features = properties.optimal_tiling_features;
+        let mut usage = crate::TextureUsage::empty();
+        usage.set(
+            crate::TextureUsage::COPY,
+            features.contains(
+                vk::FormatFeatureFlags::TRANSFER_SRC | vk::FormatFeatureFlags::TRANSFER_DST,
+            ),
+        );
+        usage.set(
+            crate::TextureUsage::TARGET,
+            features.intersects(
+                vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                    | vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+            ),
+        );
+        usage.set(
+            crate::TextureUsage::RESOURCE,
+            features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE),
+        );
+        usage.set(
+            crate::TextureUsage::STORAGE,
+            features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE),
+        );
+        if format.to_linear() != format {
+            // Written through views of the linear format
+            let linear = self.texture_format_usage(format.to_linear());
+            usage |= linear & crate::TextureUsage::STORAGE;
+        }
+        usage
+    }
+
+    /// Return the resources that are currently alive, in the order of creation.
+    /// Empty unless `ContextDesc::track_resources` is enabled.
+    pub fn live_resources(&self) -> Vec<crate::LiveResource> {
+        self.tracker
+            .as_ref()
+            .map_or_else(Vec::new, crate::tracking::Tracker::snapshot)
+    }
 }
 
 impl Drop for super::Context {
@@ -803,12 +1039,25 @@ impl Drop for super::Context {
         if std::thread::panicking() {
             return;
         }
+        if let Some(ref tracker) = self.tracker {
+            tracker.report_leaks();
+        }
         unsafe {
-            if let Ok(queue) = self.queue.lock() {
-                let _ = self.device.core.queue_wait_idle(queue.raw);
-                self.device
-                    .core
-                    .destroy_semaphore(queue.timeline_semaphore, None);
+            let queues = [
+                Some(&self.queue),
+                self.compute_queue.as_ref(),
+                self.transfer_queue.as_ref(),
+            ];
+            for queue in queues.into_iter().flatten() {
+                if let Ok(queue) = queue.lock() {
+                    let _ = self.device.core.queue_wait_idle(queue.raw);
+                    self.device
+                        .core
+                        .destroy_semaphore(queue.timeline_semaphore, None);
+                }
+            }
+            if let Some(cache) = self.pipeline_cache.take() {
+                cache.save(&self.device.core);
             }
             self.device.core.destroy_device(None);
             self.instance.core.destroy_instance(None);
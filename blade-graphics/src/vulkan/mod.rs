@@ -2,8 +2,16 @@ use ash::{
     khr,
     vk::{self},
 };
-use std::{mem, num::NonZeroU32, path::PathBuf, ptr, sync::Mutex};
+use std::{
+    mem,
+    num::NonZeroU32,
+    path::PathBuf,
+    ptr,
+    sync::{Arc, Mutex},
+};
 
+mod barrier;
+mod cache;
 mod command;
 mod descriptor;
 mod init;
@@ -11,7 +19,8 @@ mod pipeline;
 mod resource;
 mod surface;
 
-const QUERY_POOL_SIZE: usize = crate::limits::PASS_COUNT + 1;
+/// Number of timestamps in each query pool of a command buffer.
+const TIMESTAMP_POOL_SIZE: u32 = 128;
 
 #[derive(Debug)]
 pub enum PlatformError {
@@ -65,6 +74,13 @@ struct Device {
     external_memory: Option<ash::khr::external_memory_fd::Device>,
     command_scope: Option<CommandScopeDevice>,
     timing: Option<TimingDevice>,
+    precise_occlusion: bool,
+    /// Shared with the command encoders for the uniform buffers with the plain data.
+    memory: Arc<Mutex<MemoryManager>>,
+    min_uniform_buffer_alignment: u64,
+    max_push_constants_size: u32,
+    /// Backing of `Depth24PlusStencil8`, which isn't renderable everywhere.
+    depth24_plus_stencil8: vk::Format,
     workarounds: Workarounds,
 }
 
@@ -76,6 +92,7 @@ struct MemoryManager {
 
 struct Queue {
     raw: vk::Queue,
+    family_index: u32,
     timeline_semaphore: vk::Semaphore,
     last_progress: u64,
 }
@@ -103,6 +120,7 @@ pub struct Surface {
     next_semaphore: vk::Semaphore,
     swapchain: Swapchain,
     full_screen_exclusive: bool,
+    trace: Option<Arc<crate::trace::Recorder>>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -127,6 +145,8 @@ impl Frame {
             memory_handle: !0,
             target_size: self.swapchain.target_size,
             format: self.swapchain.format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            usage: vk::ImageUsageFlags::empty(),
             external: None,
         }
     }
@@ -134,12 +154,25 @@ impl Frame {
     pub fn texture_view(&self) -> TextureView {
         TextureView {
             raw: self.internal.view,
+            image: self.internal.image,
+            format: map_texture_format(self.swapchain.format),
+            samples: vk::SampleCountFlags::TYPE_1,
             target_size: self.swapchain.target_size,
             aspects: crate::TexelAspects::COLOR,
+            range: barrier::ImageRange::single(0, 0),
         }
     }
 }
 
+fn map_error(result: vk::Result) -> crate::Error {
+    match result {
+        vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => {
+            crate::Error::OutOfMemory
+        }
+        other => crate::Error::Device(format!("{:?}", other)),
+    }
+}
+
 fn map_timeout(millis: u32) -> u64 {
     if millis == !0 {
         !0
@@ -149,16 +182,24 @@ fn map_timeout(millis: u32) -> u64 {
 }
 
 pub struct Context {
-    memory: Mutex<MemoryManager>,
     device: Device,
     queue_family_index: u32,
     queue: Mutex<Queue>,
+    compute_queue: Option<Mutex<Queue>>,
+    transfer_queue: Option<Mutex<Queue>>,
+    /// Distinct families of all the queues, for sharing the resources.
+    queue_families: Vec<u32>,
     physical_device: vk::PhysicalDevice,
     naga_flags: naga::back::spv::WriterFlags,
     shader_debug_path: Option<PathBuf>,
+    pipeline_cache: Option<cache::PipelineCache>,
     min_buffer_alignment: u64,
     sample_count_flags: vk::SampleCountFlags,
     dual_source_blending: bool,
+    pipeline_statistics: bool,
+    max_bindless_resources: u32,
+    trace: Option<Arc<crate::trace::Recorder>>,
+    tracker: Option<crate::tracking::Tracker>,
     instance: Instance,
     entry: ash::Entry,
 }
@@ -197,6 +238,9 @@ pub struct Texture {
     memory_handle: usize,
     target_size: [u16; 2],
     format: crate::TextureFormat,
+    samples: vk::SampleCountFlags,
+    /// Usage of the image, left empty for the swapchain images.
+    usage: vk::ImageUsageFlags,
     external: Option<crate::ExternalMemorySource>,
 }
 
@@ -207,6 +251,8 @@ impl Default for Texture {
             memory_handle: !0,
             target_size: [0; 2],
             format: crate::TextureFormat::Rgba8Unorm,
+            samples: vk::SampleCountFlags::TYPE_1,
+            usage: vk::ImageUsageFlags::empty(),
             external: None,
         }
     }
@@ -215,8 +261,12 @@ impl Default for Texture {
 #[derive(Clone, Copy, Debug, Default, Hash, PartialEq)]
 pub struct TextureView {
     raw: vk::ImageView,
+    image: vk::Image,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
     target_size: [u16; 2],
     aspects: crate::TexelAspects,
+    range: barrier::ImageRange,
 }
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq)]
@@ -231,12 +281,57 @@ pub struct AccelerationStructure {
     memory_handle: usize,
 }
 
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub struct QuerySet {
+    raw: vk::QueryPool,
+    ty: crate::QueryType,
+    count: u32,
+}
+
+#[derive(Debug)]
+pub struct BindlessTable {
+    raw: vk::DescriptorSet,
+    pool: vk::DescriptorPool,
+    layout: DescriptorSetLayout,
+    /// Freed slots are retired by the last submissions of all the queues.
+    slots: crate::bindless::BindlessSlots<Vec<SyncPoint>>,
+}
+
+impl BindlessTable {
+    /// Check that the handle refers to a resource that is still in the table.
+    pub fn contains(&self, handle: crate::BindlessHandle) -> bool {
+        self.slots.contains(handle)
+    }
+}
+
+/// Destination of the plain data that is too large for an inline uniform block.
+#[derive(Clone, Copy, Debug)]
+enum SpillTarget {
+    /// Uniform buffer, described by the `vk::DescriptorBufferInfo`
+    /// at this offset in the update data.
+    UniformBuffer { info_offset: u32 },
+    /// Push constants of the pipeline, visible to these stages.
+    PushConstants(vk::ShaderStageFlags),
+}
+
+/// Plain data that is too large for an inline uniform block.
+#[derive(Clone, Copy, Debug)]
+struct PlainSpill {
+    target: SpillTarget,
+    /// Offset of the data itself, which is filled by the shader data.
+    data_offset: u32,
+    size: u32,
+}
+
 #[derive(Debug, Default)]
 struct DescriptorSetLayout {
     raw: vk::DescriptorSetLayout,
     update_template: vk::DescriptorUpdateTemplate,
     template_size: u32,
     template_offsets: Box<[u32]>,
+    plain_spills: Box<[PlainSpill]>,
+    plain_structs: Box<[Option<crate::StructReflection>]>,
+    binding_access: Box<[crate::StorageAccess]>,
 }
 
 impl DescriptorSetLayout {
@@ -254,6 +349,10 @@ struct PipelineLayout {
 pub struct PipelineContext<'a> {
     update_data: &'a mut [u8],
     template_offsets: &'a [u32],
+    captured: Option<&'a mut Vec<(u32, crate::trace::Bound)>>,
+    binding_access: &'a [crate::StorageAccess],
+    shader_stages: vk::PipelineStageFlags,
+    barriers: &'a mut barrier::Tracker,
 }
 
 #[derive(Debug)]
@@ -279,8 +378,25 @@ pub struct RenderPipeline {
 struct CommandBuffer {
     raw: vk::CommandBuffer,
     descriptor_pool: descriptor::DescriptorPool,
-    query_pool: vk::QueryPool,
-    timed_pass_names: Vec<String>,
+    timestamps: TimestampQueries,
+    /// Secondary command buffers of the render chunks executed by this one.
+    chunks: Vec<ChunkCommandBuffer>,
+}
+
+/// Timestamps of the timing scopes of a command buffer, in query pools
+/// that are added when a recording needs more of them.
+#[derive(Debug, Default)]
+struct TimestampQueries {
+    pools: Vec<vk::QueryPool>,
+    scopes: crate::timing::ScopeRecorder,
+}
+
+/// Secondary command buffer with its own pools, so that it can be recorded on any thread.
+#[derive(Debug)]
+struct ChunkCommandBuffer {
+    pool: vk::CommandPool,
+    raw: vk::CommandBuffer,
+    descriptor_pool: descriptor::DescriptorPool,
 }
 
 struct CrashHandler {
@@ -292,26 +408,42 @@ struct CrashHandler {
 
 pub struct CommandEncoder {
     pool: vk::CommandPool,
+    family_index: u32,
+    command_type: crate::CommandType,
     buffers: Box<[CommandBuffer]>,
+    /// Chunk command buffers that aren't used by any submission.
+    free_chunks: Vec<ChunkCommandBuffer>,
     device: Device,
     update_data: Vec<u8>,
     present: Option<Presentation>,
+    /// Submissions that have to finish on the GPU before this one starts.
+    waits: Vec<SyncPoint>,
     crash_handler: Option<CrashHandler>,
     temp_label: Vec<u8>,
     timings: crate::Timings,
+    barriers: barrier::Tracker,
+    stats: crate::CommandStats,
+    trace: Option<crate::trace::EncoderTrace>,
 }
 pub struct TransferCommandEncoder<'a> {
     raw: vk::CommandBuffer,
+    timestamps: &'a mut TimestampQueries,
     device: &'a Device,
+    barriers: &'a mut barrier::Tracker,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
 }
 pub struct AccelerationStructureCommandEncoder<'a> {
     raw: vk::CommandBuffer,
+    timestamps: &'a mut TimestampQueries,
     device: &'a Device,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
 }
 pub struct ComputeCommandEncoder<'a> {
     cmd_buf: &'a mut CommandBuffer,
     device: &'a Device,
     update_data: &'a mut Vec<u8>,
+    barriers: &'a mut barrier::Tracker,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
 }
 //Note: we aren't merging this with `ComputeCommandEncoder`
 // because the destructors are different, and they can't be specialized
@@ -320,18 +452,51 @@ pub struct RenderCommandEncoder<'a> {
     cmd_buf: &'a mut CommandBuffer,
     device: &'a Device,
     update_data: &'a mut Vec<u8>,
+    barriers: &'a mut barrier::Tracker,
+    /// Pool of the occlusion queries, if the pass has any.
+    occlusion_queries: vk::QueryPool,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
+}
+
+pub struct ParallelRenderCommandEncoder<'a> {
+    pass: RenderCommandEncoder<'a>,
+    family_index: u32,
+    free_chunks: &'a mut Vec<ChunkCommandBuffer>,
+    color_formats: Vec<vk::Format>,
+    depth_format: vk::Format,
+    stencil_format: vk::Format,
+    samples: vk::SampleCountFlags,
+    target_size: [u16; 2],
+    chunks: Vec<Option<RenderChunk>>,
+}
+
+pub struct RenderChunk {
+    index: usize,
+    cmd_buf: ChunkCommandBuffer,
+    device: Device,
+    update_data: Vec<u8>,
+    barriers: barrier::Tracker,
+    /// Pool of the occlusion queries, inherited from the pass.
+    occlusion_queries: vk::QueryPool,
+    trace: Option<crate::trace::EncoderTrace>,
 }
 
 pub struct PipelineEncoder<'a, 'p> {
-    cmd_buf: &'a mut CommandBuffer,
+    raw: vk::CommandBuffer,
+    descriptor_pool: &'a mut descriptor::DescriptorPool,
     layout: &'p PipelineLayout,
     bind_point: vk::PipelineBindPoint,
+    shader_stages: vk::PipelineStageFlags,
     device: &'a Device,
     update_data: &'a mut Vec<u8>,
+    barriers: &'a mut barrier::Tracker,
+    occlusion_queries: vk::QueryPool,
+    trace: &'a mut Option<crate::trace::EncoderTrace>,
 }
 
 #[derive(Clone, Debug)]
 pub struct SyncPoint {
+    timeline_semaphore: vk::Semaphore,
     progress: u64,
 }
 
@@ -373,8 +538,10 @@ impl crate::traits::CommandDevice for Context {
             });
         }
 
+        let family_index = self.queue(desc.command_type).lock().unwrap().family_index;
         let pool_info = vk::CommandPoolCreateInfo {
             flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index: family_index,
             ..Default::default()
         };
         let pool = unsafe {
@@ -402,24 +569,16 @@ impl crate::traits::CommandDevice for Context {
                     self.set_object_name(raw, desc.name);
                 };
                 let descriptor_pool = self.device.create_descriptor_pool();
-                let query_pool = if self.device.timing.is_some() {
-                    let query_pool_info = vk::QueryPoolCreateInfo::default()
-                        .query_type(vk::QueryType::TIMESTAMP)
-                        .query_count(QUERY_POOL_SIZE as u32);
-                    unsafe {
-                        self.device
-                            .core
-                            .create_query_pool(&query_pool_info, None)
-                            .unwrap()
-                    }
-                } else {
-                    vk::QueryPool::null()
-                };
+                let mut timestamps = TimestampQueries::default();
+                if self.device.timing.is_some() {
+                    timestamps.pools.push(self.device.create_timestamp_pool());
+                    timestamps.scopes.grow(TIMESTAMP_POOL_SIZE);
+                }
                 CommandBuffer {
                     raw,
                     descriptor_pool,
-                    query_pool,
-                    timed_pass_names: Vec::new(),
+                    timestamps,
+                    chunks: Vec::new(),
                 }
             })
             .collect();
@@ -441,13 +600,20 @@ impl crate::traits::CommandDevice for Context {
 
         CommandEncoder {
             pool,
+            family_index,
+            command_type: desc.command_type,
             buffers,
+            free_chunks: Vec::new(),
             device: self.device.clone(),
             update_data: Vec::new(),
             present: None,
+            waits: Vec::new(),
             crash_handler,
             temp_label: Vec::new(),
             timings: Default::default(),
+            barriers: Default::default(),
+            stats: Default::default(),
+            trace: self.trace.as_ref().map(|trace| trace.encoder(desc.name)),
         }
     }
 
@@ -461,14 +627,16 @@ impl crate::traits::CommandDevice for Context {
             }
             self.device
                 .destroy_descriptor_pool(&mut cmd_buf.descriptor_pool);
-            if self.device.timing.is_some() {
-                unsafe {
-                    self.device
-                        .core
-                        .destroy_query_pool(cmd_buf.query_pool, None);
-                }
+            for mut chunk in cmd_buf.chunks.drain(..) {
+                self.device.destroy_chunk_command_buffer(&mut chunk);
+            }
+            for pool in cmd_buf.timestamps.pools.drain(..) {
+                unsafe { self.device.core.destroy_query_pool(pool, None) };
             }
         }
+        for mut chunk in command_encoder.free_chunks.drain(..) {
+            self.device.destroy_chunk_command_buffer(&mut chunk);
+        }
         unsafe {
             self.device
                 .core
@@ -480,32 +648,37 @@ impl crate::traits::CommandDevice for Context {
     }
 
     fn submit(&self, encoder: &mut CommandEncoder) -> SyncPoint {
+        if let Some(ref mut trace) = encoder.trace {
+            trace.submit();
+        }
         let raw_cmd_buf = encoder.finish();
-        let mut queue = self.queue.lock().unwrap();
+        let mut queue = self.queue(encoder.command_type).lock().unwrap();
         queue.last_progress += 1;
         let progress = queue.last_progress;
         let command_buffers = [raw_cmd_buf];
-        let wait_values_all = [0];
-        let mut wait_semaphores_all = [vk::Semaphore::null()];
-        let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
-        let mut signal_semaphores_all = [queue.timeline_semaphore, vk::Semaphore::null()];
-        let signal_values_all = [progress, 0];
-        let (num_wait_semaphores, num_signal_sepahores) = match encoder.present {
-            Some(ref presentation) => {
-                wait_semaphores_all[0] = presentation.acquire_semaphore;
-                signal_semaphores_all[1] = presentation.present_semaphore;
-                (1, 2)
-            }
-            None => (0, 1),
-        };
+        let mut wait_semaphores = Vec::with_capacity(encoder.waits.len() + 1);
+        let mut wait_values = Vec::with_capacity(encoder.waits.len() + 1);
+        for sp in encoder.waits.drain(..) {
+            wait_semaphores.push(sp.timeline_semaphore);
+            wait_values.push(sp.progress);
+        }
+        let mut signal_semaphores = vec![queue.timeline_semaphore];
+        let mut signal_values = vec![progress];
+        if let Some(ref presentation) = encoder.present {
+            wait_semaphores.push(presentation.acquire_semaphore);
+            wait_values.push(0);
+            signal_semaphores.push(presentation.present_semaphore);
+            signal_values.push(0);
+        }
+        let wait_stages = vec![vk::PipelineStageFlags::ALL_COMMANDS; wait_semaphores.len()];
         let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
-            .wait_semaphore_values(&wait_values_all[..num_wait_semaphores])
-            .signal_semaphore_values(&signal_values_all[..num_signal_sepahores]);
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
         let vk_info = vk::SubmitInfo::default()
             .command_buffers(&command_buffers)
-            .wait_semaphores(&wait_semaphores_all[..num_wait_semaphores])
-            .wait_dst_stage_mask(&wait_stages[..num_wait_semaphores])
-            .signal_semaphores(&signal_semaphores_all[..num_signal_sepahores])
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .signal_semaphores(&signal_semaphores)
             .push_next(&mut timeline_info);
         let ret = unsafe {
             self.device
@@ -527,14 +700,14 @@ impl crate::traits::CommandDevice for Context {
             let _ = encoder.check_gpu_crash(ret);
         }
 
-        SyncPoint { progress }
+        SyncPoint {
+            timeline_semaphore: queue.timeline_semaphore,
+            progress,
+        }
     }
 
     fn wait_for(&self, sp: &SyncPoint, timeout_ms: u32) -> bool {
-        //Note: technically we could get away without locking the queue,
-        // but also this isn't time-sensitive, so it's fine.
-        let timeline_semaphore = self.queue.lock().unwrap().timeline_semaphore;
-        let semaphores = [timeline_semaphore];
+        let semaphores = [sp.timeline_semaphore];
         let semaphore_values = [sp.progress];
         let wait_info = vk::SemaphoreWaitInfoKHR::default()
             .semaphores(&semaphores)
@@ -569,9 +742,16 @@ fn map_texture_format(format: crate::TextureFormat) -> vk::Format {
         Tf::R32Uint => vk::Format::R32_UINT,
         Tf::Rg32Uint => vk::Format::R32G32_UINT,
         Tf::Rgba32Uint => vk::Format::R32G32B32A32_UINT,
+        Tf::R16Unorm => vk::Format::R16_UNORM,
+        Tf::Rgba16Unorm => vk::Format::R16G16B16A16_UNORM,
+        Tf::R8Uint => vk::Format::R8_UINT,
+        Tf::Rg16Uint => vk::Format::R16G16_UINT,
+        Tf::R32Sint => vk::Format::R32_SINT,
         Tf::Depth32Float => vk::Format::D32_SFLOAT,
         Tf::Depth32FloatStencil8Uint => vk::Format::D32_SFLOAT_S8_UINT,
         Tf::Stencil8Uint => vk::Format::S8_UINT,
+        Tf::Depth16Unorm => vk::Format::D16_UNORM,
+        Tf::Depth24PlusStencil8 => vk::Format::D24_UNORM_S8_UINT,
         Tf::Bc1Unorm => vk::Format::BC1_RGBA_SRGB_BLOCK,
         Tf::Bc1UnormSrgb => vk::Format::BC1_RGBA_UNORM_BLOCK,
         Tf::Bc2Unorm => vk::Format::BC2_UNORM_BLOCK,
@@ -589,6 +769,44 @@ fn map_texture_format(format: crate::TextureFormat) -> vk::Format {
         Tf::Rgb10a2Unorm => vk::Format::A2B10G10R10_UNORM_PACK32,
         Tf::Rg11b10Ufloat => vk::Format::B10G11R11_UFLOAT_PACK32,
         Tf::Rgb9e5Ufloat => vk::Format::E5B9G9R9_UFLOAT_PACK32,
+        Tf::Etc2Rgb8Unorm => vk::Format::ETC2_R8G8B8_UNORM_BLOCK,
+        Tf::Etc2Rgb8UnormSrgb => vk::Format::ETC2_R8G8B8_SRGB_BLOCK,
+        Tf::Etc2Rgb8A1Unorm => vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK,
+        Tf::Etc2Rgb8A1UnormSrgb => vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK,
+        Tf::Etc2Rgba8Unorm => vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+        Tf::Etc2Rgba8UnormSrgb => vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+        Tf::EacR11Unorm => vk::Format::EAC_R11_UNORM_BLOCK,
+        Tf::EacR11Snorm => vk::Format::EAC_R11_SNORM_BLOCK,
+        Tf::EacRg11Unorm => vk::Format::EAC_R11G11_UNORM_BLOCK,
+        Tf::EacRg11Snorm => vk::Format::EAC_R11G11_SNORM_BLOCK,
+        Tf::Astc4x4Unorm => vk::Format::ASTC_4X4_UNORM_BLOCK,
+        Tf::Astc4x4UnormSrgb => vk::Format::ASTC_4X4_SRGB_BLOCK,
+        Tf::Astc5x4Unorm => vk::Format::ASTC_5X4_UNORM_BLOCK,
+        Tf::Astc5x4UnormSrgb => vk::Format::ASTC_5X4_SRGB_BLOCK,
+        Tf::Astc5x5Unorm => vk::Format::ASTC_5X5_UNORM_BLOCK,
+        Tf::Astc5x5UnormSrgb => vk::Format::ASTC_5X5_SRGB_BLOCK,
+        Tf::Astc6x5Unorm => vk::Format::ASTC_6X5_UNORM_BLOCK,
+        Tf::Astc6x5UnormSrgb => vk::Format::ASTC_6X5_SRGB_BLOCK,
+        Tf::Astc6x6Unorm => vk::Format::ASTC_6X6_UNORM_BLOCK,
+        Tf::Astc6x6UnormSrgb => vk::Format::ASTC_6X6_SRGB_BLOCK,
+        Tf::Astc8x5Unorm => vk::Format::ASTC_8X5_UNORM_BLOCK,
+        Tf::Astc8x5UnormSrgb => vk::Format::ASTC_8X5_SRGB_BLOCK,
+        Tf::Astc8x6Unorm => vk::Format::ASTC_8X6_UNORM_BLOCK,
+        Tf::Astc8x6UnormSrgb => vk::Format::ASTC_8X6_SRGB_BLOCK,
+        Tf::Astc8x8Unorm => vk::Format::ASTC_8X8_UNORM_BLOCK,
+        Tf::Astc8x8UnormSrgb => vk::Format::ASTC_8X8_SRGB_BLOCK,
+        Tf::Astc10x5Unorm => vk::Format::ASTC_10X5_UNORM_BLOCK,
+        Tf::Astc10x5UnormSrgb => vk::Format::ASTC_10X5_SRGB_BLOCK,
+        Tf::Astc10x6Unorm => vk::Format::ASTC_10X6_UNORM_BLOCK,
+        Tf::Astc10x6UnormSrgb => vk::Format::ASTC_10X6_SRGB_BLOCK,
+        Tf::Astc10x8Unorm => vk::Format::ASTC_10X8_UNORM_BLOCK,
+        Tf::Astc10x8UnormSrgb => vk::Format::ASTC_10X8_SRGB_BLOCK,
+        Tf::Astc10x10Unorm => vk::Format::ASTC_10X10_UNORM_BLOCK,
+        Tf::Astc10x10UnormSrgb => vk::Format::ASTC_10X10_SRGB_BLOCK,
+        Tf::Astc12x10Unorm => vk::Format::ASTC_12X10_UNORM_BLOCK,
+        Tf::Astc12x10UnormSrgb => vk::Format::ASTC_12X10_SRGB_BLOCK,
+        Tf::Astc12x12Unorm => vk::Format::ASTC_12X12_UNORM_BLOCK,
+        Tf::Astc12x12UnormSrgb => vk::Format::ASTC_12X12_SRGB_BLOCK,
     }
 }
 
@@ -667,6 +885,16 @@ fn map_vertex_format(vertex_format: crate::VertexFormat) -> vk::Format {
         Vf::I32Vec2 => vk::Format::R32G32_SINT,
         Vf::I32Vec3 => vk::Format::R32G32B32_SINT,
         Vf::I32Vec4 => vk::Format::R32G32B32A32_SINT,
+        Vf::Unorm8x4 => vk::Format::R8G8B8A8_UNORM,
+        Vf::Snorm8x4 => vk::Format::R8G8B8A8_SNORM,
+        Vf::Uint8x4 => vk::Format::R8G8B8A8_UINT,
+        Vf::Unorm16x2 => vk::Format::R16G16_UNORM,
+        Vf::Unorm16x4 => vk::Format::R16G16B16A16_UNORM,
+        Vf::Snorm16x2 => vk::Format::R16G16_SNORM,
+        Vf::Snorm16x4 => vk::Format::R16G16B16A16_SNORM,
+        Vf::Float16x2 => vk::Format::R16G16_SFLOAT,
+        Vf::Float16x4 => vk::Format::R16G16B16A16_SFLOAT,
+        Vf::Rgb10a2Unorm => vk::Format::A2B10G10R10_UNORM_PACK32,
     }
 }
 
@@ -678,6 +906,14 @@ struct BottomLevelAccelerationStructureInput<'a> {
 }
 
 impl Device {
+    /// Like `map_texture_format`, but aware of the formats picked at init.
+    fn map_texture_format(&self, format: crate::TextureFormat) -> vk::Format {
+        match format {
+            crate::TextureFormat::Depth24PlusStencil8 => self.depth24_plus_stencil8,
+            _ => map_texture_format(format),
+        }
+    }
+
     fn get_device_address(&self, piece: &crate::BufferPiece) -> u64 {
         let vk_info = vk::BufferDeviceAddressInfo {
             buffer: piece.buffer.raw,
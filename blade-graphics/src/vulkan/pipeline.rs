@@ -1,6 +1,6 @@
-use ash::vk;
+use ash::vk::{self, Handle as _};
 use naga::back::spv;
-use std::{ffi, mem, str};
+use std::{borrow::Cow, ffi, mem, str};
 
 const DUMP_PREFIX: Option<&str> = None;
 
@@ -20,7 +20,8 @@ impl super::Context {
             for (binding_index, &(_, binding)) in layout.bindings.iter().enumerate() {
                 let binding_array_size = match binding {
                     crate::ShaderBinding::TextureArray { count }
-                    | crate::ShaderBinding::BufferArray { count } => Some(count),
+                    | crate::ShaderBinding::BufferArray { count }
+                    | crate::ShaderBinding::Bindless { count, .. } => Some(count),
                     _ => None,
                 };
                 let rb = naga::ResourceBinding {
@@ -56,6 +57,12 @@ impl super::Context {
         }
     }
 
+    fn raw_pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache
+            .as_ref()
+            .map_or(vk::PipelineCache::null(), |cache| cache.raw)
+    }
+
     fn load_shader(
         &self,
         sf: crate::ShaderFunction,
@@ -63,21 +70,26 @@ impl super::Context {
         group_layouts: &[&crate::ShaderDataLayout],
         group_infos: &mut [crate::ShaderDataInfo],
         vertex_fetch_states: &[crate::VertexFetchState],
-    ) -> CompiledShader<'_> {
-        let ep_index = sf.entry_point_index();
+    ) -> Result<CompiledShader<'_>, crate::Error> {
+        let ep_index = sf.entry_point_index()?;
         let ep = &sf.shader.module.entry_points[ep_index];
         let ep_info = sf.shader.info.get_entry_point(ep_index);
 
-        let (mut module, module_info) = sf.shader.resolve_constants(sf.constants);
+        let (mut module, mut module_info) = sf.shader.try_resolve_constants(sf.constants)?;
         crate::Shader::fill_resource_bindings(
             &mut module,
             group_infos,
             ep.stage,
             ep_info,
             group_layouts,
-        );
+        )?;
+        if let Some((group, binding)) = self.find_push_constant_binding(group_layouts)
+            && let Some(info) = move_to_push_constants(&mut module, group, binding)?
+        {
+            module_info = Cow::Owned(info);
+        }
         let attribute_mappings =
-            crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states);
+            crate::Shader::fill_vertex_locations(&mut module, ep_index, vertex_fetch_states)?;
 
         let pipeline_options = spv::PipelineOptions {
             shader_stage: ep.stage,
@@ -116,8 +128,26 @@ impl super::Context {
             naga_options_base
         };
 
-        let spv =
-            spv::write_vec(&module, &module_info, naga_options, Some(&pipeline_options)).unwrap();
+        // Debug info refers to the dumped files, so it's not cached
+        let cache_key = match self.pipeline_cache {
+            Some(ref cache) if self.shader_debug_path.is_none() => Some((
+                cache,
+                cache.spirv_key(&sf, naga_options, group_layouts, vertex_fetch_states),
+            )),
+            _ => None,
+        };
+        let spv = match cache_key.and_then(|(cache, key)| cache.load_spirv(key)) {
+            Some(spv) => spv,
+            None => {
+                let spv =
+                    spv::write_vec(&module, &module_info, naga_options, Some(&pipeline_options))
+                        .map_err(|e| crate::Error::InvalidShader(e.to_string()))?;
+                if let Some((cache, key)) = cache_key {
+                    cache.store_spirv(key, &spv);
+                }
+                spv
+            }
+        };
 
         if let Some(dump_prefix) = DUMP_PREFIX {
             let mut file_name = String::new();
@@ -135,12 +165,8 @@ impl super::Context {
 
         let vk_info = vk::ShaderModuleCreateInfo::default().code(&spv);
 
-        let vk_module = unsafe {
-            self.device
-                .core
-                .create_shader_module(&vk_info, None)
-                .unwrap()
-        };
+        let vk_module = unsafe { self.device.core.create_shader_module(&vk_info, None) }
+            .map_err(super::map_error)?;
 
         let vk_stage = match ep.stage {
             naga::ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
@@ -157,21 +183,46 @@ impl super::Context {
             ..Default::default()
         };
 
-        CompiledShader {
+        Ok(CompiledShader {
             vk_module,
             _entry_point: entry_point,
             create_info,
             attribute_mappings,
             wg_size: ep.workgroup_size,
-        }
+        })
     }
 
-    fn create_descriptor_set_layout(
+    /// Find the plain binding that goes into the push constants instead of
+    /// a uniform buffer. Only one fits into a pipeline, and only if it's too
+    /// large for an inline uniform block.
+    fn find_push_constant_binding(
+        &self,
+        group_layouts: &[&crate::ShaderDataLayout],
+    ) -> Option<(u32, u32)> {
+        let max_size = self.device.max_push_constants_size;
+        group_layouts
+            .iter()
+            .enumerate()
+            .find_map(|(group_index, layout)| {
+                let binding_index = layout.bindings.iter().position(|&(_, binding)| {
+                    matches!(binding, crate::ShaderBinding::Plain { size }
+                        if size > crate::limits::PLAIN_DATA_SIZE && size <= max_size)
+                })?;
+                Some((group_index as u32, binding_index as u32))
+            })
+    }
+
+    pub(super) fn create_descriptor_set_layout(
         &self,
         layout: &crate::ShaderDataLayout,
         info: &crate::ShaderDataInfo,
+        push_constant_binding: Option<u32>,
     ) -> super::DescriptorSetLayout {
-        if info.visibility.is_empty() {
+        let bindless = layout
+            .bindings
+            .iter()
+            .any(|&(_, binding)| matches!(binding, crate::ShaderBinding::Bindless { .. }));
+        if info.visibility.is_empty() && !bindless {
             // we need to have a valid `VkDescriptorSetLayout` regardless
             return super::DescriptorSetLayout {
                 raw: unsafe {
@@ -184,18 +235,46 @@ impl super::Context {
             };
         }
 
-        let stage_flags = map_shader_visibility(info.visibility);
+        // bindless layouts have to be compatible with the one of the table
+        let stage_flags = map_shader_visibility(if bindless {
+            crate::ShaderVisibility::all()
+        } else {
+            info.visibility
+        });
         let mut vk_bindings = Vec::with_capacity(layout.bindings.len());
         let mut template_entries = Vec::with_capacity(layout.bindings.len());
         let mut template_offsets = Vec::with_capacity(layout.bindings.len());
         let mut binding_flags = Vec::with_capacity(layout.bindings.len());
+        let mut plain_spills = Vec::new();
         let mut update_offset = 0;
+        let mut layout_flags = vk::DescriptorSetLayoutCreateFlags::empty();
         for (binding_index, (&(_, binding), &access)) in layout
             .bindings
             .iter()
             .zip(info.binding_access.iter())
             .enumerate()
         {
+            if push_constant_binding == Some(binding_index as u32) {
+                // the data stays in the update data, and goes to `vkCmdPushConstants`
+                let size = match binding {
+                    crate::ShaderBinding::Plain { size } => size,
+                    _ => unreachable!(),
+                };
+                plain_spills.push(super::PlainSpill {
+                    target: super::SpillTarget::PushConstants(stage_flags),
+                    data_offset: update_offset as u32,
+                    size,
+                });
+                template_offsets.push(update_offset as u32);
+                update_offset += size as usize;
+                continue;
+            }
+            let spill_size = match binding {
+                crate::ShaderBinding::Plain { size } if size > crate::limits::PLAIN_DATA_SIZE => {
+                    size
+                }
+                _ => 0,
+            };
             let (descriptor_type, descriptor_size, descriptor_count, flag) = match binding {
                 crate::ShaderBinding::Texture => (
                     if access.is_empty() {
@@ -241,12 +320,30 @@ impl super::Context {
                     1u32,
                     vk::DescriptorBindingFlags::empty(),
                 ),
+                crate::ShaderBinding::Plain { .. } if spill_size != 0 => (
+                    vk::DescriptorType::UNIFORM_BUFFER,
+                    mem::size_of::<vk::DescriptorBufferInfo>(),
+                    1u32,
+                    vk::DescriptorBindingFlags::empty(),
+                ),
                 crate::ShaderBinding::Plain { size } => (
                     vk::DescriptorType::INLINE_UNIFORM_BLOCK_EXT,
                     1,
                     size,
                     vk::DescriptorBindingFlags::empty(),
                 ),
+                crate::ShaderBinding::Bindless { kind, count } => (
+                    match kind {
+                        crate::BindlessKind::Texture => vk::DescriptorType::SAMPLED_IMAGE,
+                        crate::BindlessKind::Buffer => vk::DescriptorType::STORAGE_BUFFER,
+                        crate::BindlessKind::Sampler => vk::DescriptorType::SAMPLER,
+                    },
+                    0,
+                    count,
+                    vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                        | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                        | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING,
+                ),
             };
 
             vk_bindings.push(vk::DescriptorSetLayoutBinding {
@@ -256,6 +353,27 @@ impl super::Context {
                 stage_flags,
                 ..Default::default()
             });
+            binding_flags.push(flag);
+            if spill_size != 0 {
+                // the data is written after the buffer info, and it's moved
+                // into a uniform buffer when the group is bound
+                let data_offset = update_offset + descriptor_size;
+                plain_spills.push(super::PlainSpill {
+                    target: super::SpillTarget::UniformBuffer {
+                        info_offset: update_offset as u32,
+                    },
+                    data_offset: data_offset as u32,
+                    size: spill_size,
+                });
+                template_offsets.push(data_offset as u32);
+            } else {
+                template_offsets.push(update_offset as u32);
+            }
+            if descriptor_size == 0 {
+                // bindless arrays are written by the table, not by the template
+                layout_flags |= vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL;
+                continue;
+            }
             template_entries.push(vk::DescriptorUpdateTemplateEntryKHR {
                 dst_binding: binding_index as u32,
                 dst_array_element: 0,
@@ -264,14 +382,13 @@ impl super::Context {
                 offset: update_offset,
                 stride: descriptor_size,
             });
-            binding_flags.push(flag);
-            template_offsets.push(update_offset as u32);
-            update_offset += descriptor_size * descriptor_count as usize;
+            update_offset += descriptor_size * descriptor_count as usize + spill_size as usize;
         }
 
         let mut binding_flags_info =
             vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&binding_flags);
         let set_layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(layout_flags)
             .bindings(&vk_bindings)
             .push_next(&mut binding_flags_info);
         let raw = unsafe {
@@ -281,15 +398,19 @@ impl super::Context {
                 .unwrap()
         };
 
-        let template_create_info = vk::DescriptorUpdateTemplateCreateInfo::default()
-            .descriptor_update_entries(&template_entries)
-            .template_type(vk::DescriptorUpdateTemplateTypeKHR::DESCRIPTOR_SET)
-            .descriptor_set_layout(raw);
-        let update_template = unsafe {
-            self.device
-                .core
-                .create_descriptor_update_template(&template_create_info, None)
-                .unwrap()
+        let update_template = if template_entries.is_empty() {
+            vk::DescriptorUpdateTemplate::null()
+        } else {
+            let template_create_info = vk::DescriptorUpdateTemplateCreateInfo::default()
+                .descriptor_update_entries(&template_entries)
+                .template_type(vk::DescriptorUpdateTemplateTypeKHR::DESCRIPTOR_SET)
+                .descriptor_set_layout(raw);
+            unsafe {
+                self.device
+                    .core
+                    .create_descriptor_update_template(&template_create_info, None)
+                    .unwrap()
+            }
         };
 
         super::DescriptorSetLayout {
@@ -297,6 +418,9 @@ impl super::Context {
             update_template,
             template_size: update_offset as u32,
             template_offsets: template_offsets.into_boxed_slice(),
+            plain_spills: plain_spills.into_boxed_slice(),
+            plain_structs: info.plain_structs.clone(),
+            binding_access: info.binding_access.clone(),
         }
     }
 
@@ -305,15 +429,31 @@ impl super::Context {
         group_layouts: &[&crate::ShaderDataLayout],
         group_infos: &[crate::ShaderDataInfo],
     ) -> super::PipelineLayout {
+        let push_constants = self.find_push_constant_binding(group_layouts);
         let mut descriptor_set_layouts = Vec::with_capacity(group_layouts.len());
         let mut vk_set_layouts = Vec::with_capacity(group_layouts.len());
-        for (&layout, info) in group_layouts.iter().zip(group_infos) {
-            let dsl = self.create_descriptor_set_layout(layout, info);
+        let mut push_constant_ranges = Vec::new();
+        for (group_index, (&layout, info)) in group_layouts.iter().zip(group_infos).enumerate() {
+            let push_constant_binding = push_constants
+                .filter(|&(group, _)| group == group_index as u32)
+                .map(|(_, binding)| binding);
+            let dsl = self.create_descriptor_set_layout(layout, info, push_constant_binding);
+            for spill in dsl.plain_spills.iter() {
+                if let super::SpillTarget::PushConstants(stage_flags) = spill.target {
+                    push_constant_ranges.push(vk::PushConstantRange {
+                        stage_flags,
+                        offset: 0,
+                        size: spill.size,
+                    });
+                }
+            }
             vk_set_layouts.push(dsl.raw);
             descriptor_set_layouts.push(dsl);
         }
 
-        let vk_info = vk::PipelineLayoutCreateInfo::default().set_layouts(&vk_set_layouts);
+        let vk_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&vk_set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
         let raw = unsafe {
             self.device
                 .core
@@ -339,7 +479,7 @@ impl super::Context {
                     .core
                     .destroy_descriptor_set_layout(dsl.raw, None);
             }
-            if !dsl.is_empty() {
+            if dsl.update_template != vk::DescriptorUpdateTemplate::null() {
                 unsafe {
                     self.device
                         .core
@@ -355,7 +495,10 @@ impl crate::traits::ShaderDevice for super::Context {
     type ComputePipeline = super::ComputePipeline;
     type RenderPipeline = super::RenderPipeline;
 
-    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
+    fn try_create_compute_pipeline(
+        &self,
+        desc: crate::ComputePipelineDesc,
+    ) -> Result<super::ComputePipeline, crate::Error> {
         let mut group_infos = desc
             .data_layouts
             .iter()
@@ -369,23 +512,29 @@ impl crate::traits::ShaderDevice for super::Context {
             desc.data_layouts,
             &mut group_infos,
             &[],
-        );
+        )?;
 
-        let layout = self.create_pipeline_layout(desc.data_layouts, &group_infos);
+        let mut layout = self.create_pipeline_layout(desc.data_layouts, &group_infos);
 
         let create_info = vk::ComputePipelineCreateInfo::default()
             .layout(layout.raw)
             .stage(cs.create_info);
 
-        let mut raw_vec = unsafe {
-            self.device
-                .core
-                .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
-                .unwrap()
+        let result = unsafe {
+            self.device.core.create_compute_pipelines(
+                self.raw_pipeline_cache(),
+                &[create_info],
+                None,
+            )
         };
-        let raw = raw_vec.pop().unwrap();
-
         unsafe { self.device.core.destroy_shader_module(cs.vk_module, None) };
+        let raw = match result {
+            Ok(mut raw_vec) => raw_vec.pop().unwrap(),
+            Err((_, e)) => {
+                self.destroy_pipeline_layout(&mut layout);
+                return Err(super::map_error(e));
+            }
+        };
 
         if let Some(ref ext) = self.device.shader_info
             && let Ok(statistics) =
@@ -403,21 +552,48 @@ impl crate::traits::ShaderDevice for super::Context {
         if !desc.name.is_empty() {
             self.set_object_name(raw, desc.name);
         }
-        super::ComputePipeline {
+        if let Some(ref trace) = self.trace {
+            trace.create_compute_pipeline(&desc, raw.as_raw());
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(
+                crate::tracking::Handle::ComputePipeline(raw.as_raw()),
+                desc.name,
+                0,
+            );
+        }
+        Ok(super::ComputePipeline {
             raw,
             layout,
             wg_size: cs.wg_size,
-        }
+        })
+    }
+
+    fn create_compute_pipeline(&self, desc: crate::ComputePipelineDesc) -> super::ComputePipeline {
+        let name = desc.name;
+        self.try_create_compute_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create compute pipeline '{name}': {e}"))
     }
 
     fn destroy_compute_pipeline(&self, pipeline: &mut super::ComputePipeline) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_compute_pipeline(pipeline.raw.as_raw());
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(crate::tracking::Handle::ComputePipeline(
+                pipeline.raw.as_raw(),
+            ));
+        }
         self.destroy_pipeline_layout(&mut pipeline.layout);
         unsafe {
             self.device.core.destroy_pipeline(pipeline.raw, None);
         }
     }
 
-    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
+    fn try_create_render_pipeline(
+        &self,
+        desc: crate::RenderPipelineDesc,
+    ) -> Result<super::RenderPipeline, crate::Error> {
         let mut group_infos = desc
             .data_layouts
             .iter()
@@ -431,16 +607,26 @@ impl crate::traits::ShaderDevice for super::Context {
             desc.data_layouts,
             &mut group_infos,
             desc.vertex_fetches,
-        );
-        let fs = desc.fragment.map(|desc_fragment| {
-            self.load_shader(
-                desc_fragment,
-                &options,
-                desc.data_layouts,
-                &mut group_infos,
-                &[],
-            )
-        });
+        )?;
+        let fs = desc
+            .fragment
+            .map(|desc_fragment| {
+                self.load_shader(
+                    desc_fragment,
+                    &options,
+                    desc.data_layouts,
+                    &mut group_infos,
+                    &[],
+                )
+            })
+            .transpose();
+        let fs = match fs {
+            Ok(fs) => fs,
+            Err(e) => {
+                unsafe { self.device.core.destroy_shader_module(vs.vk_module, None) };
+                return Err(e);
+            }
+        };
 
         let mut stages = [vs.create_info, vk::PipelineShaderStageCreateInfo::default()];
         let mut stage_count = 1;
@@ -450,7 +636,7 @@ impl crate::traits::ShaderDevice for super::Context {
         }
         let stages = &stages[..stage_count]; // 'dynamic' stack allocated array
 
-        let layout = self.create_pipeline_layout(desc.data_layouts, &group_infos);
+        let mut layout = self.create_pipeline_layout(desc.data_layouts, &group_infos);
 
         let vertex_buffers = desc
             .vertex_fetches
@@ -539,7 +725,7 @@ impl crate::traits::ShaderDevice for super::Context {
         let mut s_format = vk::Format::UNDEFINED;
         let mut vk_depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default();
         if let Some(ref ds) = desc.depth_stencil {
-            let ds_format = super::map_texture_format(ds.format);
+            let ds_format = self.device.map_texture_format(ds.format);
             if ds.format.aspects().contains(crate::TexelAspects::DEPTH) {
                 d_format = ds_format;
             }
@@ -619,26 +805,56 @@ impl crate::traits::ShaderDevice for super::Context {
             .dynamic_state(&vk_dynamic_state)
             .push_next(&mut rendering_info);
 
-        let mut raw_vec = unsafe {
-            self.device
-                .core
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
-                .unwrap()
+        let result = unsafe {
+            self.device.core.create_graphics_pipelines(
+                self.raw_pipeline_cache(),
+                &[create_info],
+                None,
+            )
         };
-        let raw = raw_vec.pop().unwrap();
-
         unsafe { self.device.core.destroy_shader_module(vs.vk_module, None) };
         if let Some(fs) = fs {
             unsafe { self.device.core.destroy_shader_module(fs.vk_module, None) };
         }
+        let raw = match result {
+            Ok(mut raw_vec) => raw_vec.pop().unwrap(),
+            Err((_, e)) => {
+                self.destroy_pipeline_layout(&mut layout);
+                return Err(super::map_error(e));
+            }
+        };
 
         if !desc.name.is_empty() {
             self.set_object_name(raw, desc.name);
         }
-        super::RenderPipeline { raw, layout }
+        if let Some(ref trace) = self.trace {
+            trace.create_render_pipeline(&desc, raw.as_raw());
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(
+                crate::tracking::Handle::RenderPipeline(raw.as_raw()),
+                desc.name,
+                0,
+            );
+        }
+        Ok(super::RenderPipeline { raw, layout })
+    }
+
+    fn create_render_pipeline(&self, desc: crate::RenderPipelineDesc) -> super::RenderPipeline {
+        let name = desc.name;
+        self.try_create_render_pipeline(desc)
+            .unwrap_or_else(|e| panic!("Unable to create render pipeline '{name}': {e}"))
     }
 
     fn destroy_render_pipeline(&self, pipeline: &mut super::RenderPipeline) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_render_pipeline(pipeline.raw.as_raw());
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(crate::tracking::Handle::RenderPipeline(
+                pipeline.raw.as_raw(),
+            ));
+        }
         self.destroy_pipeline_layout(&mut pipeline.layout);
         unsafe {
             self.device.core.destroy_pipeline(pipeline.raw, None);
@@ -646,6 +862,31 @@ impl crate::traits::ShaderDevice for super::Context {
     }
 }
 
+/// Move a plain binding into the push constants, if the module uses it.
+/// Returns the new module info, since the pointer types of the expressions
+/// depend on the address space.
+fn move_to_push_constants(
+    module: &mut naga::Module,
+    group: u32,
+    binding: u32,
+) -> Result<Option<naga::valid::ModuleInfo>, crate::Error> {
+    let target = Some(naga::ResourceBinding { group, binding });
+    let Some((_, var)) = module
+        .global_variables
+        .iter_mut()
+        .find(|entry| entry.1.binding == target)
+    else {
+        return Ok(None);
+    };
+    var.space = naga::AddressSpace::PushConstant;
+    var.binding = None;
+    let flags = naga::valid::ValidationFlags::all() ^ naga::valid::ValidationFlags::BINDINGS;
+    naga::valid::Validator::new(flags, naga::valid::Capabilities::all())
+        .validate(module)
+        .map(Some)
+        .map_err(|e| crate::Error::InvalidShader(e.to_string()))
+}
+
 fn map_shader_visibility(visibility: crate::ShaderVisibility) -> vk::ShaderStageFlags {
     use crate::ShaderVisibility as Sv;
     use vk::ShaderStageFlags as Flags;
@@ -775,3 +1016,53 @@ fn map_blend_component(
     let dst = map_blend_factor(component.dst_factor);
     (op, src, dst)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::move_to_push_constants;
+    use naga::back::spv;
+
+    const SHADER: &str = "
+struct Params {
+    weights: array<vec4<f32>, 32>,
+}
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(32)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= params.weights[id.x].x;
+}
+";
+
+    #[test]
+    fn plain_binding_moves_to_push_constants() {
+        let mut module = naga::front::wgsl::parse_str(SHADER).unwrap();
+        assert!(move_to_push_constants(&mut module, 1, 0).unwrap().is_none());
+
+        let info = move_to_push_constants(&mut module, 0, 0).unwrap().unwrap();
+        let (_, var) = module
+            .global_variables
+            .iter()
+            .find(|entry| entry.1.name.as_deref() == Some("params"))
+            .unwrap();
+        assert_eq!(var.space, naga::AddressSpace::PushConstant);
+        assert_eq!(var.binding, None);
+
+        let options = spv::Options {
+            lang_version: (1, 3),
+            ..Default::default()
+        };
+        let words = spv::write_vec(&module, &info, &options, None).unwrap();
+        // walk the instructions after the header, looking for
+        // `OpVariable` (59) in the `PushConstant` storage class (9)
+        let mut offset = 5;
+        let mut found = false;
+        while offset < words.len() {
+            let count = (words[offset] >> 16) as usize;
+            found |= words[offset] & 0xFFFF == 59 && words[offset + 3] == 9;
+            offset += count;
+        }
+        assert!(found);
+    }
+}
@@ -1,6 +1,14 @@
 use ash::vk;
 use gpu_alloc_ash::AshMemoryDevice;
-use std::{mem, ptr};
+use std::{mem, ptr, slice};
+
+fn map_allocation_error(error: gpu_alloc::AllocationError) -> crate::Error {
+    match error {
+        gpu_alloc::AllocationError::OutOfDeviceMemory
+        | gpu_alloc::AllocationError::OutOfHostMemory => crate::Error::OutOfMemory,
+        other => crate::Error::Device(other.to_string()),
+    }
+}
 
 struct Allocation {
     memory: vk::DeviceMemory,
@@ -15,8 +23,8 @@ impl super::Context {
         &self,
         requirements: vk::MemoryRequirements,
         memory: crate::Memory,
-    ) -> Allocation {
-        let mut manager = self.memory.lock().unwrap();
+    ) -> Result<Allocation, crate::Error> {
+        let mut manager = self.device.memory.lock().unwrap();
         let device_address_usage = if self.device.ray_tracing.is_some() {
             gpu_alloc::UsageFlags::DEVICE_ADDRESS
         } else {
@@ -97,7 +105,7 @@ impl super::Context {
                     self.device
                         .core
                         .allocate_memory(&allocation_info, None)
-                        .unwrap()
+                        .map_err(super::map_error)?
                 };
 
                 unsafe {
@@ -122,7 +130,7 @@ impl super::Context {
                             memory_types,
                         },
                     )
-                    .unwrap()
+                    .map_err(map_allocation_error)?
             },
         };
 
@@ -132,27 +140,31 @@ impl super::Context {
             }
             crate::Memory::Device | crate::Memory::External(_) => ptr::null_mut(),
             crate::Memory::Shared | crate::Memory::Upload => unsafe {
-                block
-                    .map(
-                        AshMemoryDevice::wrap(&self.device.core),
-                        0,
-                        requirements.size as usize,
-                    )
-                    .unwrap()
-                    .as_ptr()
+                let device = AshMemoryDevice::wrap(&self.device.core);
+                match block.map(device, 0, requirements.size as usize) {
+                    Ok(data) => data.as_ptr(),
+                    Err(e) => {
+                        manager.allocator.dealloc(device, block);
+                        return Err(match e {
+                            gpu_alloc::MapError::OutOfDeviceMemory
+                            | gpu_alloc::MapError::OutOfHostMemory => crate::Error::OutOfMemory,
+                            other => crate::Error::Device(other.to_string()),
+                        });
+                    }
+                }
             },
         };
-        Allocation {
+        Ok(Allocation {
             memory: *block.memory(),
             offset: block.offset(),
             data,
             handle: manager.slab.insert(block),
             memory_type: memory,
-        }
+        })
     }
 
     fn free_memory(&self, handle: usize) {
-        let mut manager = self.memory.lock().unwrap();
+        let mut manager = self.device.memory.lock().unwrap();
         let block = manager.slab.remove(handle);
         unsafe {
             manager
@@ -282,7 +294,7 @@ impl crate::traits::ResourceDevice for super::Context {
     type Sampler = super::Sampler;
     type AccelerationStructure = super::AccelerationStructure;
 
-    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+    fn try_create_buffer(&self, desc: crate::BufferDesc) -> Result<super::Buffer, crate::Error> {
         use vk::BufferUsageFlags as Buf;
         let external_source = match desc.memory {
             crate::Memory::External(e) => Some(e),
@@ -303,6 +315,12 @@ impl crate::traits::ResourceDevice for super::Context {
             sharing_mode: vk::SharingMode::EXCLUSIVE,
             ..Default::default()
         };
+        if self.queue_families.len() > 1 {
+            // Shared with the dedicated queues without ownership transfers
+            vk_info = vk_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&self.queue_families);
+        }
         if let Some(external_next) = external_next.as_mut() {
             vk_info = vk_info.push_next(external_next);
         }
@@ -311,10 +329,17 @@ impl crate::traits::ResourceDevice for super::Context {
                 Buf::SHADER_DEVICE_ADDRESS | Buf::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR;
         }
 
-        let raw = unsafe { self.device.core.create_buffer(&vk_info, None).unwrap() };
+        let raw =
+            unsafe { self.device.core.create_buffer(&vk_info, None) }.map_err(super::map_error)?;
         let mut requirements = unsafe { self.device.core.get_buffer_memory_requirements(raw) };
         requirements.alignment = requirements.alignment.max(self.min_buffer_alignment);
-        let allocation = self.allocate_memory(requirements, desc.memory);
+        let allocation = match self.allocate_memory(requirements, desc.memory) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { self.device.core.destroy_buffer(raw, None) };
+                return Err(e);
+            }
+        };
 
         log::info!(
             "Creating buffer {:?} of size {}, name '{}', handle {:?}",
@@ -333,17 +358,40 @@ impl crate::traits::ResourceDevice for super::Context {
             self.set_object_name(raw, desc.name);
         }
 
-        super::Buffer {
+        let buffer = super::Buffer {
             raw,
             memory_handle: allocation.handle,
             mapped_data: allocation.data,
             external: fetch_external_source(&self.device, allocation),
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_buffer(&desc, buffer);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(buffer, desc.name, desc.size);
         }
+        Ok(buffer)
     }
 
-    fn sync_buffer(&self, _buffer: super::Buffer) {}
+    fn create_buffer(&self, desc: crate::BufferDesc) -> super::Buffer {
+        let name = desc.name;
+        self.try_create_buffer(desc)
+            .unwrap_or_else(|e| panic!("Unable to create buffer '{name}': {e}"))
+    }
+
+    fn sync_buffer(&self, buffer: super::Buffer) {
+        if let Some(ref trace) = self.trace {
+            trace.sync_buffer(buffer);
+        }
+    }
 
     fn destroy_buffer(&self, buffer: super::Buffer) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_buffer(buffer);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(buffer);
+        }
         log::info!(
             "Destroying buffer {:?}, handle {:?}",
             buffer.raw,
@@ -353,7 +401,7 @@ impl crate::traits::ResourceDevice for super::Context {
         self.free_memory(buffer.memory_handle);
     }
 
-    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+    fn try_create_texture(&self, desc: crate::TextureDesc) -> Result<super::Texture, crate::Error> {
         let mut create_flags = vk::ImageCreateFlags::empty();
         if desc.dimension == crate::TextureDimension::D2
             && desc.size.depth.is_multiple_of(6)
@@ -362,6 +410,13 @@ impl crate::traits::ResourceDevice for super::Context {
         {
             create_flags |= vk::ImageCreateFlags::CUBE_COMPATIBLE;
         }
+        if desc.usage.contains(crate::TextureUsage::STORAGE)
+            && desc.format.to_linear() != desc.format
+        {
+            // sRGB formats are written through views of the linear format
+            create_flags |=
+                vk::ImageCreateFlags::MUTABLE_FORMAT | vk::ImageCreateFlags::EXTENDED_USAGE;
+        }
 
         let mut external_next = desc.external.map(|e| vk::ExternalMemoryImageCreateInfo {
             handle_types: external_source_handle_type(e),
@@ -371,7 +426,7 @@ impl crate::traits::ResourceDevice for super::Context {
         let mut vk_info = vk::ImageCreateInfo {
             flags: create_flags,
             image_type: map_texture_dimension(desc.dimension),
-            format: super::map_texture_format(desc.format),
+            format: self.device.map_texture_format(desc.format),
             extent: super::map_extent_3d(&desc.size),
             mip_levels: desc.mip_level_count,
             array_layers: desc.array_layer_count,
@@ -381,6 +436,11 @@ impl crate::traits::ResourceDevice for super::Context {
             sharing_mode: vk::SharingMode::EXCLUSIVE,
             ..Default::default()
         };
+        if self.queue_families.len() > 1 {
+            vk_info = vk_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&self.queue_families);
+        }
 
         if let Some(external_next) = external_next.as_mut() {
             vk_info = vk_info.push_next(external_next);
@@ -390,13 +450,40 @@ impl crate::traits::ResourceDevice for super::Context {
             TODO(ErikWDev): Support lazily allocated texture with transient allocation for efficient msaa?
                             Measure bandwidth usage!
         */
-        let raw = unsafe { self.device.core.create_image(&vk_info, None).unwrap() };
+        let format_properties = unsafe {
+            self.instance
+                .core
+                .get_physical_device_image_format_properties(
+                    self.physical_device,
+                    vk_info.format,
+                    vk_info.image_type,
+                    vk_info.tiling,
+                    vk_info.usage,
+                    vk_info.flags,
+                )
+        };
+        match format_properties {
+            Ok(properties) if properties.sample_counts.contains(vk_info.samples) => {}
+            Ok(_) | Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED) => {
+                return Err(crate::Error::UnsupportedFormat(desc.format));
+            }
+            Err(e) => return Err(super::map_error(e)),
+        }
+
+        let raw =
+            unsafe { self.device.core.create_image(&vk_info, None) }.map_err(super::map_error)?;
         let requirements = unsafe { self.device.core.get_image_memory_requirements(raw) };
-        let allocation = self.allocate_memory(
+        let allocation = match self.allocate_memory(
             requirements,
             desc.external
                 .map_or(crate::Memory::Device, crate::Memory::External),
-        );
+        ) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { self.device.core.destroy_image(raw, None) };
+                return Err(e);
+            }
+        };
 
         log::info!(
             "Creating texture {:?} of size {} and format {:?}, name '{}', handle {:?}",
@@ -416,16 +503,37 @@ impl crate::traits::ResourceDevice for super::Context {
             self.set_object_name(raw, desc.name);
         }
 
-        super::Texture {
+        let texture = super::Texture {
             raw,
             memory_handle: allocation.handle,
             target_size: [desc.size.width as u16, desc.size.height as u16],
             format: desc.format,
+            samples: vk::SampleCountFlags::from_raw(desc.sample_count),
+            usage: vk_info.usage,
             external: fetch_external_source(&self.device, allocation),
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_texture(&desc, texture);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(texture, desc.name, crate::tracking::texture_size(&desc));
         }
+        Ok(texture)
+    }
+
+    fn create_texture(&self, desc: crate::TextureDesc) -> super::Texture {
+        let name = desc.name;
+        self.try_create_texture(desc)
+            .unwrap_or_else(|e| panic!("Unable to create texture '{name}': {e}"))
     }
 
     fn destroy_texture(&self, texture: super::Texture) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_texture(texture);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(texture);
+        }
         log::info!(
             "Destroying texture {:?}, handle {:?}",
             texture.raw,
@@ -442,30 +550,57 @@ impl crate::traits::ResourceDevice for super::Context {
     ) -> super::TextureView {
         let aspects = desc.format.aspects();
         let subresource_range = super::map_subresource_range(desc.subresources, aspects);
-        let vk_info = vk::ImageViewCreateInfo {
+        let mut vk_info = vk::ImageViewCreateInfo {
             image: texture.raw,
             view_type: map_view_dimension(desc.dimension),
-            format: super::map_texture_format(desc.format),
+            format: self.device.map_texture_format(desc.format),
             subresource_range,
             ..Default::default()
         };
+        // Views of sRGB formats can't be used for storage
+        let mut usage_info = vk::ImageViewUsageCreateInfo {
+            usage: texture.usage & !vk::ImageUsageFlags::STORAGE,
+            ..Default::default()
+        };
+        if texture.usage.contains(vk::ImageUsageFlags::STORAGE)
+            && desc.format.to_linear() != desc.format
+        {
+            vk_info = vk_info.push_next(&mut usage_info);
+        }
 
         let raw = unsafe { self.device.core.create_image_view(&vk_info, None).unwrap() };
         if !desc.name.is_empty() {
             self.set_object_name(raw, desc.name);
         }
 
-        super::TextureView {
+        let view = super::TextureView {
             raw,
+            image: texture.raw,
+            format: vk_info.format,
+            samples: texture.samples,
             target_size: [
                 (texture.target_size[0] >> desc.subresources.base_mip_level).max(1),
                 (texture.target_size[1] >> desc.subresources.base_mip_level).max(1),
             ],
             aspects,
+            range: super::barrier::ImageRange::from_vk(&subresource_range),
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_texture_view(texture, &desc, view);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(view, desc.name, 0);
         }
+        view
     }
 
     fn destroy_texture_view(&self, view: super::TextureView) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_texture_view(view);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(view);
+        }
         unsafe { self.device.core.destroy_image_view(view.raw, None) };
     }
 
@@ -499,10 +634,23 @@ impl crate::traits::ResourceDevice for super::Context {
             self.set_object_name(raw, desc.name);
         }
 
-        super::Sampler { raw }
+        let sampler = super::Sampler { raw };
+        if let Some(ref trace) = self.trace {
+            trace.create_sampler(&desc, sampler);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(sampler, desc.name, 0);
+        }
+        sampler
     }
 
     fn destroy_sampler(&self, sampler: super::Sampler) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_sampler(sampler);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(sampler);
+        }
         unsafe { self.device.core.destroy_sampler(sampler.raw, None) };
     }
 
@@ -510,17 +658,24 @@ impl crate::traits::ResourceDevice for super::Context {
         &self,
         desc: crate::AccelerationStructureDesc,
     ) -> super::AccelerationStructure {
-        let buffer_info = vk::BufferCreateInfo {
+        let mut buffer_info = vk::BufferCreateInfo {
             size: desc.size,
             usage: vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
                 | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
             sharing_mode: vk::SharingMode::EXCLUSIVE,
             ..Default::default()
         };
+        if self.queue_families.len() > 1 {
+            buffer_info = buffer_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&self.queue_families);
+        }
 
         let buffer = unsafe { self.device.core.create_buffer(&buffer_info, None).unwrap() };
         let requirements = unsafe { self.device.core.get_buffer_memory_requirements(buffer) };
-        let allocation = self.allocate_memory(requirements, crate::Memory::Device);
+        let allocation = self
+            .allocate_memory(requirements, crate::Memory::Device)
+            .unwrap();
 
         unsafe {
             self.device
@@ -555,14 +710,27 @@ impl crate::traits::ResourceDevice for super::Context {
             self.set_object_name(buffer, desc.name);
             self.set_object_name(raw, desc.name);
         }
-        super::AccelerationStructure {
+        let acceleration_structure = super::AccelerationStructure {
             raw,
             buffer,
             memory_handle: allocation.handle,
+        };
+        if let Some(ref trace) = self.trace {
+            trace.create_acceleration_structure(&desc, acceleration_structure);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(acceleration_structure, desc.name, desc.size);
         }
+        acceleration_structure
     }
 
     fn destroy_acceleration_structure(&self, acceleration_structure: super::AccelerationStructure) {
+        if let Some(ref trace) = self.trace {
+            trace.destroy_acceleration_structure(acceleration_structure);
+        }
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(acceleration_structure);
+        }
         let rt = self.device.ray_tracing.as_ref().unwrap();
         unsafe {
             rt.acceleration_structure
@@ -575,6 +743,278 @@ impl crate::traits::ResourceDevice for super::Context {
     }
 }
 
+#[hidden_trait::expose]
+impl crate::traits::QueryDevice for super::Context {
+    type QuerySet = super::QuerySet;
+    type SyncPoint = super::SyncPoint;
+
+    fn create_query_set(&self, desc: crate::QuerySetDesc) -> super::QuerySet {
+        let (query_type, pipeline_statistics) = match desc.ty {
+            crate::QueryType::Occlusion => (
+                vk::QueryType::OCCLUSION,
+                vk::QueryPipelineStatisticFlags::empty(),
+            ),
+            crate::QueryType::PipelineStatistics(statistics) => {
+                assert!(
+                    self.pipeline_statistics,
+                    "Pipeline statistics are not supported"
+                );
+                (
+                    vk::QueryType::PIPELINE_STATISTICS,
+                    map_pipeline_statistics(statistics),
+                )
+            }
+        };
+        let vk_info = vk::QueryPoolCreateInfo {
+            query_type,
+            query_count: desc.count,
+            pipeline_statistics,
+            ..Default::default()
+        };
+        let raw = unsafe { self.device.core.create_query_pool(&vk_info, None).unwrap() };
+        if !desc.name.is_empty() {
+            self.set_object_name(raw, desc.name);
+        }
+
+        let set = super::QuerySet {
+            raw,
+            ty: desc.ty,
+            count: desc.count,
+        };
+        if let Some(ref tracker) = self.tracker {
+            tracker.add(set, desc.name, crate::tracking::query_set_size(&desc));
+        }
+        set
+    }
+
+    fn destroy_query_set(&self, set: super::QuerySet) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.remove(set);
+        }
+        unsafe { self.device.core.destroy_query_pool(set.raw, None) };
+    }
+
+    fn read_query_results(
+        &self,
+        set: super::QuerySet,
+        sync_point: &super::SyncPoint,
+    ) -> Option<Vec<u64>> {
+        if !self.wait_for(sync_point, 0) {
+            return None;
+        }
+        // Each query is followed by its availability value
+        let value_count = set.ty.value_count();
+        let stride = value_count + 1;
+        let mut data = vec![0u64; set.count as usize * stride];
+        let result = unsafe {
+            (self.device.core.fp_v1_0().get_query_pool_results)(
+                self.device.core.handle(),
+                set.raw,
+                0,
+                set.count,
+                mem::size_of_val(data.as_slice()),
+                data.as_mut_ptr().cast(),
+                (stride * mem::size_of::<u64>()) as vk::DeviceSize,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+        match result {
+            vk::Result::SUCCESS | vk::Result::NOT_READY => {}
+            other => panic!("Unable to read query results: {other:?}"),
+        }
+        let mut values = Vec::with_capacity(set.count as usize * value_count);
+        for chunk in data.chunks(stride) {
+            if chunk[value_count] != 0 {
+                values.extend_from_slice(&chunk[..value_count]);
+            } else {
+                values.extend(std::iter::repeat_n(0, value_count));
+            }
+        }
+        Some(values)
+    }
+}
+
+impl super::Context {
+    fn write_bindless(
+        &self,
+        table: &mut super::BindlessTable,
+        kind: crate::BindlessKind,
+        write: vk::WriteDescriptorSet,
+    ) -> crate::BindlessHandle {
+        let handle = table.slots.insert(kind, |sync_points, timeout_ms| {
+            sync_points.iter().all(|sp| self.wait_for(sp, timeout_ms))
+        });
+        let write = vk::WriteDescriptorSet {
+            dst_set: table.raw,
+            dst_binding: kind as u32,
+            dst_array_element: handle.index(),
+            ..write
+        };
+        unsafe { self.device.core.update_descriptor_sets(&[write], &[]) };
+        handle
+    }
+}
+
+#[hidden_trait::expose]
+impl crate::traits::BindlessDevice for super::Context {
+    type BindlessTable = super::BindlessTable;
+    type TextureView = super::TextureView;
+    type Sampler = super::Sampler;
+
+    fn create_bindless_table(&self, desc: crate::BindlessTableDesc) -> super::BindlessTable {
+        for &(_, kind) in crate::BindlessTableDesc::BINDINGS.iter() {
+            assert!(
+                desc.count(kind) <= self.max_bindless_resources,
+                "Bindless table {:?} count {} is over the limit of {}",
+                kind,
+                desc.count(kind),
+                self.max_bindless_resources
+            );
+        }
+        let info = crate::ShaderDataInfo {
+            visibility: crate::ShaderVisibility::all(),
+            binding_access: vec![
+                crate::StorageAccess::empty();
+                crate::BindlessTableDesc::BINDINGS.len()
+            ]
+            .into_boxed_slice(),
+            plain_structs: vec![None; crate::BindlessTableDesc::BINDINGS.len()].into_boxed_slice(),
+        };
+        let layout = self.create_descriptor_set_layout(&desc.layout(), &info, None);
+
+        let pool_sizes = [
+            (vk::DescriptorType::SAMPLED_IMAGE, desc.texture_count),
+            (vk::DescriptorType::STORAGE_BUFFER, desc.buffer_count),
+            (vk::DescriptorType::SAMPLER, desc.sampler_count),
+        ]
+        .into_iter()
+        .filter(|&(_, descriptor_count)| descriptor_count != 0)
+        .map(|(ty, descriptor_count)| vk::DescriptorPoolSize {
+            ty,
+            descriptor_count,
+        })
+        .collect::<Vec<_>>();
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(1)
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .pool_sizes(&pool_sizes);
+        let pool = unsafe {
+            self.device
+                .core
+                .create_descriptor_pool(&pool_info, None)
+                .unwrap()
+        };
+        let set_layouts = [layout.raw];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        let raw = unsafe {
+            self.device
+                .core
+                .allocate_descriptor_sets(&allocate_info)
+                .unwrap()[0]
+        };
+        if !desc.name.is_empty() {
+            self.set_object_name(raw, desc.name);
+        }
+
+        super::BindlessTable {
+            raw,
+            pool,
+            layout,
+            slots: crate::bindless::BindlessSlots::new(&desc),
+        }
+    }
+
+    fn destroy_bindless_table(&self, table: &mut super::BindlessTable) {
+        unsafe {
+            self.device
+                .core
+                .destroy_descriptor_pool(mem::take(&mut table.pool), None);
+            self.device
+                .core
+                .destroy_descriptor_set_layout(mem::take(&mut table.layout.raw), None);
+        }
+        table.raw = vk::DescriptorSet::null();
+    }
+
+    fn insert_bindless_texture(
+        &self,
+        table: &mut super::BindlessTable,
+        view: super::TextureView,
+    ) -> crate::BindlessHandle {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: view.raw,
+            image_layout: vk::ImageLayout::GENERAL,
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(slice::from_ref(&image_info));
+        self.write_bindless(table, crate::BindlessKind::Texture, write)
+    }
+
+    fn insert_bindless_buffer(
+        &self,
+        table: &mut super::BindlessTable,
+        piece: crate::BufferPiece,
+    ) -> crate::BindlessHandle {
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer: piece.buffer.raw,
+            offset: piece.offset,
+            range: vk::WHOLE_SIZE,
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(slice::from_ref(&buffer_info));
+        self.write_bindless(table, crate::BindlessKind::Buffer, write)
+    }
+
+    fn insert_bindless_sampler(
+        &self,
+        table: &mut super::BindlessTable,
+        sampler: super::Sampler,
+    ) -> crate::BindlessHandle {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: sampler.raw,
+            image_view: vk::ImageView::null(),
+            image_layout: vk::ImageLayout::UNDEFINED,
+        };
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(slice::from_ref(&image_info));
+        self.write_bindless(table, crate::BindlessKind::Sampler, write)
+    }
+
+    fn remove_bindless(&self, table: &mut super::BindlessTable, handle: crate::BindlessHandle) {
+        // The descriptor stays in place, since the arrays are partially bound.
+        // It can't be overwritten while the submitted work may still use it,
+        // so the slot is only reused after that work is done.
+        if let Err(message) = table.slots.remove(handle, self.last_sync_points()) {
+            panic!("{}", message);
+        }
+    }
+}
+
+fn map_pipeline_statistics(
+    statistics: crate::PipelineStatistics,
+) -> vk::QueryPipelineStatisticFlags {
+    let mut flags = vk::QueryPipelineStatisticFlags::empty();
+    if statistics.contains(crate::PipelineStatistics::PRIMITIVES) {
+        flags |= vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES;
+    }
+    if statistics.contains(crate::PipelineStatistics::VERTEX_INVOCATIONS) {
+        flags |= vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS;
+    }
+    if statistics.contains(crate::PipelineStatistics::FRAGMENT_INVOCATIONS) {
+        flags |= vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS;
+    }
+    if statistics.contains(crate::PipelineStatistics::COMPUTE_INVOCATIONS) {
+        flags |= vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS;
+    }
+    flags
+}
+
 fn map_texture_dimension(dimension: crate::TextureDimension) -> vk::ImageType {
     match dimension {
         crate::TextureDimension::D1 => vk::ImageType::TYPE_1D,
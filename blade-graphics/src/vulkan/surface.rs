@@ -37,11 +37,25 @@ impl super::Surface {
                     &mut self.frames[index as usize].acquire_semaphore,
                     acquire_semaphore,
                 );
-                super::Frame {
+                let frame = super::Frame {
                     internal: self.frames[index as usize],
                     swapchain: self.swapchain,
                     image_index: Some(index),
+                };
+                if let Some(ref trace) = self.trace {
+                    let [width, height] = self.swapchain.target_size;
+                    trace.acquire_frame(
+                        frame.texture(),
+                        frame.texture_view(),
+                        self.swapchain.format,
+                        crate::Extent {
+                            width: width as u32,
+                            height: height as u32,
+                            depth: 1,
+                        },
+                    );
                 }
+                frame
             }
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
                 log::warn!("Acquire failed because the surface is out of date");
@@ -138,6 +152,7 @@ impl super::Context {
                 target_size: [0; 2],
             },
             full_screen_exclusive: fullscreen_exclusive_ext.full_screen_exclusive_supported != 0,
+            trace: self.trace.clone(),
         })
     }
 
@@ -0,0 +1,1549 @@
+//! Проверки бэкенда без GPU: `cargo test -p blade-graphics --features null`.
+
+use blade_graphics as gpu;
+use gpu::ShaderBindable as _;
+
+const SHADER: &str = "
+var<uniform> scale: vec4<f32>;
+var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= scale.x;
+}
+";
+
+struct Data {
+    scale: [f32; 4],
+    values: gpu::BufferPiece,
+}
+
+impl gpu::ShaderData for Data {
+    fn layout() -> gpu::ShaderDataLayout {
+        gpu::ShaderDataLayout {
+            bindings: vec![
+                ("scale", gpu::ShaderBinding::Plain { size: 16 }),
+                ("values", gpu::ShaderBinding::Buffer),
+            ],
+        }
+    }
+    fn fill(&self, mut context: gpu::PipelineContext) {
+        self.scale.bind_to(&mut context, 0);
+        self.values.bind_to(&mut context, 1);
+    }
+}
+
+fn init() -> gpu::Context {
+    gpu::Context::init(gpu::ContextDesc::default()).unwrap()
+}
+
+fn create_buffer(context: &gpu::Context, size: u64) -> gpu::Buffer {
+    context.create_buffer(gpu::BufferDesc {
+        name: "test",
+        size,
+        memory: gpu::Memory::Shared,
+    })
+}
+
+#[test]
+fn transfers_execute_on_submit() {
+    let context = init();
+    let src = create_buffer(&context, 16);
+    let dst = create_buffer(&context, 16);
+    unsafe { std::ptr::write_bytes(src.data(), 7, 16) };
+
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    {
+        let mut transfer = encoder.transfer("copy");
+        transfer.copy_buffer_to_buffer(src.into(), dst.into(), 8);
+        transfer.fill_buffer(dst.at(8), 8, 1);
+    }
+    let sync_point = context.submit(&mut encoder);
+    assert!(context.wait_for(&sync_point, !0));
+
+    let result = unsafe { std::slice::from_raw_parts(dst.data(), 16) };
+    assert_eq!(result, [7, 7, 7, 7, 7, 7, 7, 7, 1, 1, 1, 1, 1, 1, 1, 1]);
+    assert!(context.take_validation_errors().is_empty());
+    // Проходы учитываются при отправке, барьеров у null-бэкенда нет
+    assert_eq!(
+        encoder.stats(),
+        &gpu::CommandStats {
+            passes: 1,
+            ..Default::default()
+        }
+    );
+
+    context.destroy_buffer(src);
+    context.destroy_buffer(dst);
+    context.destroy_command_encoder(&mut encoder);
+}
+
+#[test]
+fn misuse_is_reported() {
+    let context = init();
+    let buffer = create_buffer(&context, 16);
+    context.destroy_buffer(buffer);
+    let texture = context.create_texture(gpu::TextureDesc {
+        name: "target",
+        format: gpu::TextureFormat::Rgba8Unorm,
+        size: gpu::Extent::default(),
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::TARGET,
+        external: None,
+    });
+    let view = context.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: "target",
+            format: gpu::TextureFormat::Rgba8Unorm,
+            dimension: gpu::ViewDimension::D2,
+            subresources: &Default::default(),
+        },
+    );
+
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    encoder.transfer("fill").fill_buffer(buffer.into(), 4, 0);
+    // Текстура не инициализирована через `init_texture`
+    let _ = encoder.render(
+        "draw",
+        gpu::RenderTargetSet {
+            colors: &[gpu::RenderTarget {
+                view,
+                init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                finish_op: gpu::FinishOp::Store,
+            }],
+            depth_stencil: None,
+        },
+    );
+    context.submit(&mut encoder);
+
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 2, "{errors:?}");
+    assert!(errors[0].contains("after being destroyed"));
+    assert!(errors[1].contains("init_texture"));
+
+    context.destroy_texture_view(view);
+    context.destroy_texture(texture);
+    context.destroy_texture(texture);
+    assert_eq!(context.take_validation_errors().len(), 1);
+}
+
+#[test]
+fn command_types_are_checked() {
+    let context = init();
+    let buffer = create_buffer(&context, 16);
+    let mut uploader = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "upload",
+        buffer_count: 1,
+        command_type: gpu::CommandType::Transfer,
+    });
+    uploader.start();
+    uploader.transfer("fill").fill_buffer(buffer.into(), 16, 1);
+    let upload = context.submit(&mut uploader);
+
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "work",
+        buffer_count: 1,
+        command_type: gpu::CommandType::Compute,
+    });
+    encoder.start();
+    encoder.depend_on(&upload);
+    encoder.transfer("copy").fill_buffer(buffer.into(), 4, 0);
+    let _ = encoder.compute("compute");
+    assert!(context.take_validation_errors().is_empty());
+
+    // Вычислительный проход не помещается в кодировщик копирования
+    uploader.start();
+    let _ = uploader.compute("compute");
+    context.submit(&mut uploader);
+    context.submit(&mut encoder);
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("Transfer"));
+
+    context.destroy_buffer(buffer);
+    context.destroy_command_encoder(&mut uploader);
+    context.destroy_command_encoder(&mut encoder);
+}
+
+#[test]
+fn render_chunks_are_merged() {
+    let context = init();
+    let texture = context.create_texture(gpu::TextureDesc {
+        name: "target",
+        format: gpu::TextureFormat::Rgba8Unorm,
+        size: gpu::Extent::default(),
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::TARGET,
+        external: None,
+    });
+    let view = context.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: "target",
+            format: gpu::TextureFormat::Rgba8Unorm,
+            dimension: gpu::ViewDimension::D2,
+            subresources: &Default::default(),
+        },
+    );
+
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    encoder.init_texture(texture);
+    let colors = [gpu::RenderTarget {
+        view,
+        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+        finish_op: gpu::FinishOp::Store,
+    }];
+    let targets = || gpu::RenderTargetSet {
+        colors: &colors,
+        depth_stencil: None,
+    };
+    {
+        let mut pass = encoder.render_parallel("draw", targets());
+        // Куски записываются в других потоках и возвращаются в любом порядке
+        let threads = (0..2)
+            .map(|_| {
+                let mut chunk = pass.chunk();
+                std::thread::spawn(move || {
+                    chunk.set_stencil_reference(1);
+                    chunk
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads.into_iter().rev() {
+            pass.merge(thread.join().unwrap());
+        }
+    }
+    assert!(context.take_validation_errors().is_empty());
+
+    // Забытый кусок прохода
+    {
+        let mut pass = encoder.render_parallel("draw", targets());
+        let _ = pass.chunk();
+    }
+    context.submit(&mut encoder);
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("not merged"));
+
+    context.destroy_command_encoder(&mut encoder);
+    context.destroy_texture_view(view);
+    context.destroy_texture(texture);
+}
+
+#[test]
+fn timing_scopes_form_a_tree() {
+    let context = gpu::Context::init(gpu::ContextDesc {
+        timing: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let buffer = create_buffer(&context, 16);
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    let record = |encoder: &mut gpu::CommandEncoder| {
+        encoder.start();
+        encoder.begin_scope("frame");
+        {
+            let mut pass = encoder.transfer("upload");
+            pass.begin_scope("fill \"all\"");
+            pass.fill_buffer(buffer.into(), 16, 0);
+            pass.end_scope();
+        }
+        let _ = encoder.compute("compute");
+        encoder.end_scope();
+        let _ = encoder.transfer("readback");
+        context.submit(encoder);
+    };
+
+    // Запросы под метки времени появляются только к следующей записи
+    record(&mut encoder);
+    assert!(context.take_validation_errors().is_empty());
+    let timings = encoder.timings().clone();
+    let names = timings
+        .iter()
+        .map(|(depth, scope)| (depth, scope.name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            (0, "frame"),
+            (1, "upload"),
+            (2, "fill \"all\""),
+            (1, "compute"),
+            (0, "readback"),
+        ]
+    );
+    assert!(timings.iter().all(|(_, scope)| scope.gpu.is_none()));
+
+    record(&mut encoder);
+    let timings = encoder.timings();
+    assert!(timings.iter().all(|(_, scope)| scope.gpu.is_some()));
+    assert_eq!(timings.gpu_total(), std::time::Duration::ZERO);
+    let trace = timings.to_chrome_trace();
+    assert!(trace.starts_with('[') && trace.trim_end().ends_with(']'));
+    assert!(trace.contains(r#""name":"fill \"all\"""#));
+
+    // Метка, не закрытая в проходе, и лишнее закрытие
+    encoder.start();
+    {
+        let mut pass = encoder.transfer("upload");
+        pass.begin_scope("unended");
+    }
+    encoder.end_scope();
+    encoder.begin_scope("frame");
+    context.submit(&mut encoder);
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 3, "{errors:?}");
+    assert!(errors[0].contains("'unended' is not ended in the pass"));
+    assert!(errors[1].contains("without being begun"));
+    assert!(errors[2].contains("'frame' is not ended in command encoder"));
+
+    context.destroy_command_encoder(&mut encoder);
+    context.destroy_buffer(buffer);
+}
+
+#[test]
+fn queries_are_checked() {
+    let context = init();
+    let texture = context.create_texture(gpu::TextureDesc {
+        name: "target",
+        format: gpu::TextureFormat::Rgba8Unorm,
+        size: gpu::Extent::default(),
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::TARGET,
+        external: None,
+    });
+    let view = context.create_texture_view(
+        texture,
+        gpu::TextureViewDesc {
+            name: "target",
+            format: gpu::TextureFormat::Rgba8Unorm,
+            dimension: gpu::ViewDimension::D2,
+            subresources: &Default::default(),
+        },
+    );
+    let occlusion = context.create_query_set(gpu::QuerySetDesc {
+        name: "occlusion",
+        ty: gpu::QueryType::Occlusion,
+        count: 2,
+    });
+    let statistics =
+        gpu::PipelineStatistics::VERTEX_INVOCATIONS | gpu::PipelineStatistics::FRAGMENT_INVOCATIONS;
+    let stats = context.create_query_set(gpu::QuerySetDesc {
+        name: "stats",
+        ty: gpu::QueryType::PipelineStatistics(statistics),
+        count: 1,
+    });
+
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    encoder.init_texture(texture);
+    let colors = [gpu::RenderTarget {
+        view,
+        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+        finish_op: gpu::FinishOp::Store,
+    }];
+    let targets = || gpu::RenderTargetSet {
+        colors: &colors,
+        depth_stencil: None,
+    };
+    encoder.begin_statistics_query(stats, 0);
+    {
+        let mut pass = encoder.render_with_queries("draw", targets(), occlusion);
+        pass.begin_occlusion_query(0, gpu::OcclusionMode::Precise);
+        pass.end_occlusion_query(0);
+        pass.begin_occlusion_query(1, gpu::OcclusionMode::Binary);
+        pass.end_occlusion_query(1);
+    }
+    encoder.end_statistics_query(stats, 0);
+    {
+        // Части параллельного прохода пишут в набор прохода
+        let mut pass = encoder.render_parallel_with_queries("draw", targets(), occlusion);
+        let mut chunk = pass.chunk();
+        chunk.begin_occlusion_query(1, gpu::OcclusionMode::Binary);
+        chunk.end_occlusion_query(1);
+        pass.merge(chunk);
+    }
+    let sync_point = context.submit(&mut encoder);
+    assert!(context.take_validation_errors().is_empty());
+    // По одному значению на каждый счётчик статистики
+    assert_eq!(
+        context.read_query_results(occlusion, &sync_point),
+        Some(vec![0; 2])
+    );
+    assert_eq!(
+        context.read_query_results(stats, &sync_point),
+        Some(vec![0; 2])
+    );
+
+    encoder.start();
+    {
+        // Запрос за пределами набора и проход без набора
+        let mut pass = encoder.render_with_queries("draw", targets(), occlusion);
+        pass.begin_occlusion_query(2, gpu::OcclusionMode::Binary);
+        pass.end_occlusion_query(2);
+        pass.begin_occlusion_query(0, gpu::OcclusionMode::Binary);
+    }
+    {
+        let mut pass = encoder.render("draw", targets());
+        pass.begin_occlusion_query(0, gpu::OcclusionMode::Binary);
+        pass.end_occlusion_query(0);
+    }
+    encoder.begin_statistics_query(stats, 0);
+    context.submit(&mut encoder);
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 4, "{errors:?}");
+    assert!(errors[0].contains("beyond the 2 queries"));
+    assert!(errors[1].contains("not ended in the pass"));
+    assert!(errors[2].contains("without a query set"));
+    assert!(errors[3].contains("not ended in command encoder"));
+
+    // Статистика отрисовки недоступна кодировщику копирования
+    let mut uploader = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "upload",
+        buffer_count: 1,
+        command_type: gpu::CommandType::Transfer,
+    });
+    uploader.start();
+    uploader.begin_statistics_query(stats, 0);
+    uploader.end_statistics_query(stats, 0);
+    context.submit(&mut uploader);
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("Transfer"));
+
+    context.destroy_command_encoder(&mut uploader);
+    context.destroy_command_encoder(&mut encoder);
+    context.destroy_query_set(stats);
+    context.destroy_query_set(occlusion);
+    context.destroy_texture_view(view);
+    context.destroy_texture(texture);
+}
+
+#[test]
+fn pipeline_bindings_are_checked() {
+    let context = init();
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: SHADER,
+        ..Default::default()
+    });
+    let data_layout = <Data as gpu::ShaderData>::layout();
+    let mut pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "scale",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+    assert_eq!(pipeline.get_workgroup_size(), [64, 1, 1]);
+
+    let values = create_buffer(&context, 256);
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    {
+        let mut pass = encoder.compute("scale");
+        let mut pc = pass.with(&pipeline);
+        pc.dispatch([1, 1, 1]);
+        pc.bind(
+            0,
+            &Data {
+                scale: [2.0; 4],
+                values: values.into(),
+            },
+        );
+        pc.dispatch([1, 1, 1]);
+    }
+    context.submit(&mut encoder);
+
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("Group 0 of pipeline 'scale' is not bound"));
+
+    context.destroy_buffer(values);
+    context.destroy_compute_pipeline(&mut pipeline);
+}
+
+#[test]
+#[should_panic(expected = "Unable to resolve binding for 'values'")]
+fn pipeline_layout_mismatch_panics() {
+    let context = init();
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: SHADER,
+        ..Default::default()
+    });
+    let data_layout = gpu::ShaderDataLayout {
+        bindings: vec![("scale", gpu::ShaderBinding::Plain { size: 16 })],
+    };
+    context.create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "scale",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+}
+
+#[test]
+fn pipeline_errors_are_returned() {
+    let context = init();
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: SHADER,
+        ..Default::default()
+    });
+    // Буфер объявлен как простые данные
+    let data_layout = gpu::ShaderDataLayout {
+        bindings: vec![
+            ("scale", gpu::ShaderBinding::Plain { size: 16 }),
+            ("values", gpu::ShaderBinding::Plain { size: 16 }),
+        ],
+    };
+    let result = context.try_create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "scale",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+    match result {
+        Err(gpu::Error::BindingMismatch { ref name, .. }) => assert_eq!(name, "values"),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    let result = context.try_create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "missing",
+        data_layouts: &[&data_layout],
+        compute: shader.at("other"),
+    });
+    assert_eq!(
+        result.map(|_| ()),
+        Err(gpu::Error::MissingEntryPoint("other".to_string()))
+    );
+
+    // Ошибки создания возвращаются, а не попадают в журнал проверок
+    assert!(context.take_validation_errors().is_empty());
+}
+
+#[test]
+fn shader_errors_are_returned() {
+    let context = init();
+
+    let error = context
+        .try_create_shader(gpu::ShaderDesc {
+            source: "fn main() {\n    let x = ;\n}\n",
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+    assert_eq!(error.stage, gpu::ShaderErrorStage::Parse);
+    assert_eq!(error.labels[0].line, 2);
+    assert_eq!(error.labels[0].column, 13);
+    assert!(error.rendered.contains("let x = ;"), "{}", error.rendered);
+
+    // Разбор проходит, но тип возвращаемого значения не совпадает
+    let error = context
+        .try_create_shader(gpu::ShaderDesc {
+            source: "fn value() -> f32 {\n    return 1u;\n}\n",
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+    assert_eq!(error.stage, gpu::ShaderErrorStage::Validation);
+    assert!(!error.labels.is_empty());
+    assert!(
+        error.labels.iter().any(|label| label.line == 2),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn shaders_are_composed() {
+    let loader = |path: &str| -> Result<String, String> {
+        match path {
+            "main.wgsl" => Ok("#import \"lib/common.wgsl\"\n#import \"lib/light.wgsl\"\n\
+                @compute @workgroup_size(1)\nfn main() {\n    let v = light();\n}\n"
+                .to_string()),
+            "lib/common.wgsl" => Ok("const SCALE: f32 = 2.0;\n".to_string()),
+            // Повторный импорт общего файла пропускается
+            "lib/light.wgsl" => Ok("#import \"common.wgsl\"\n#ifdef SHADOWS\n\
+                fn light() -> f32 { return SCALE * 0.5; }\n#else\n\
+                fn light() -> f32 { return SCALE + true; }\n#endif\n"
+                .to_string()),
+            _ => Err("not found".to_string()),
+        }
+    };
+    let compose = |defines: &[&str]| {
+        gpu::ComposedShader::new(gpu::ComposeDesc {
+            path: "main.wgsl",
+            defines,
+            loader: &loader,
+        })
+        .unwrap()
+    };
+
+    let context = init();
+    let composed = compose(&["SHADOWS"]);
+    assert_eq!(
+        composed.dependencies().collect::<Vec<_>>(),
+        ["main.wgsl", "lib/common.wgsl", "lib/light.wgsl"]
+    );
+    assert_eq!(composed.source.matches("const SCALE").count(), 1);
+    assert_eq!(composed.source_map.locate(2), Some(("lib/light.wgsl", 3)));
+    assert!(context.try_create_shader(composed.desc()).is_ok());
+
+    // Ошибка указывает на исходный файл и строку, а не на собранный текст
+    let composed = compose(&[]);
+    let error = context.try_create_shader(composed.desc()).err().unwrap();
+    let label = &error.labels[0];
+    assert_eq!(label.file.as_deref(), Some("lib/light.wgsl"));
+    assert_eq!(label.line, 5);
+    assert!(
+        error.rendered.contains("lib/light.wgsl:5:"),
+        "{}",
+        error.rendered
+    );
+
+    let error = gpu::ComposedShader::new(gpu::ComposeDesc {
+        path: "lib/missing.wgsl",
+        defines: &[],
+        loader: &loader,
+    })
+    .unwrap_err();
+    assert!(error.message.contains("not found"), "{error}");
+}
+
+#[test]
+fn glsl_and_spirv_shaders_are_bound_by_name() {
+    // Явные номера привязок отбрасываются, ресурсы связываются по именам
+    const GLSL: &str = "#version 450
+layout(local_size_x = 64) in;
+layout(set = 3, binding = 7) uniform Scale { vec4 factor; } scale;
+layout(set = 2, binding = 0) buffer Values { float data[]; } values;
+void main() {
+    values.data[gl_GlobalInvocationID.x] *= scale.factor.x;
+}
+";
+    const WGSL: &str = "
+@group(1) @binding(2) var<uniform> scale: vec4<f32>;
+@group(0) @binding(5) var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(32)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= scale.x;
+}
+";
+    let module = naga::front::wgsl::parse_str(WGSL).unwrap();
+    let info = naga::valid::Validator::new(Default::default(), Default::default())
+        .validate(&module)
+        .unwrap();
+    let options = naga::back::spv::Options {
+        flags: naga::back::spv::WriterFlags::DEBUG,
+        ..Default::default()
+    };
+    let words = naga::back::spv::write_vec(&module, &info, &options, None).unwrap();
+    let spirv = bytemuck::cast_slice::<u32, u8>(&words);
+
+    let context = init();
+    let glsl_shader = context.create_shader(gpu::ShaderDesc {
+        source: GLSL,
+        language: gpu::ShaderLanguage::Glsl {
+            stage: gpu::ShaderStage::Compute,
+        },
+        ..Default::default()
+    });
+    let spirv_shader = context.create_shader(gpu::ShaderDesc {
+        language: gpu::ShaderLanguage::SpirV(spirv),
+        ..Default::default()
+    });
+
+    let data_layout = <Data as gpu::ShaderData>::layout();
+    let values = create_buffer(&context, 256);
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    let mut pipelines = Vec::new();
+    for (shader, wg_size) in [(&glsl_shader, 64), (&spirv_shader, 32)] {
+        let pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+            name: "scale",
+            data_layouts: &[&data_layout],
+            compute: shader.at("main"),
+        });
+        assert_eq!(pipeline.get_workgroup_size(), [wg_size, 1, 1]);
+        let mut pass = encoder.compute("scale");
+        let mut pc = pass.with(&pipeline);
+        pc.bind(
+            0,
+            &Data {
+                scale: [2.0; 4],
+                values: values.into(),
+            },
+        );
+        pc.dispatch([1, 1, 1]);
+        drop(pass);
+        pipelines.push(pipeline);
+    }
+    context.submit(&mut encoder);
+    assert_eq!(context.take_validation_errors(), Vec::<String>::new());
+
+    // Входы вершин тоже сопоставляются с атрибутами по имени
+    let vertex_shader = context.create_shader(gpu::ShaderDesc {
+        source: "#version 450
+layout(location = 3) in vec2 position;
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+",
+        language: gpu::ShaderLanguage::Glsl {
+            stage: gpu::ShaderStage::Vertex,
+        },
+        ..Default::default()
+    });
+    let vertex_layout = gpu::VertexLayout {
+        attributes: vec![(
+            "position",
+            gpu::VertexAttribute {
+                offset: 0,
+                format: gpu::VertexFormat::F32Vec2,
+            },
+        )],
+        stride: 8,
+    };
+    let mut render_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+        name: "glsl",
+        data_layouts: &[],
+        vertex: vertex_shader.at("main"),
+        vertex_fetches: &[gpu::VertexFetchState {
+            layout: &vertex_layout,
+            instanced: false,
+        }],
+        primitive: gpu::PrimitiveState::default(),
+        depth_stencil: None,
+        fragment: None,
+        color_targets: &[],
+        multisample_state: gpu::MultisampleState::default(),
+    });
+
+    context.destroy_render_pipeline(&mut render_pipeline);
+    for mut pipeline in pipelines {
+        context.destroy_compute_pipeline(&mut pipeline);
+    }
+    context.destroy_buffer(values);
+}
+
+#[test]
+fn shader_is_reflected() {
+    const REFLECTED: &str = "
+struct Params {
+    scale: vec4<f32>,
+    count: u32,
+}
+var<uniform> params: Params;
+var<storage, read_write> values: array<f32>;
+var input: texture_2d<f32>;
+var input_sampler: sampler;
+
+struct VertexInput {
+    position: vec3<f32>,
+    @builtin(vertex_index) index: u32,
+    color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(input: VertexInput) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(input.position, 1.0) * input.color;
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return textureSample(input, input_sampler, vec2<f32>(0.5)) * params.scale;
+}
+
+@compute @workgroup_size(8, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= f32(params.count);
+}
+";
+    let context = init();
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: REFLECTED,
+        ..Default::default()
+    });
+    let reflection = shader.reflect();
+
+    let bindings = reflection
+        .globals
+        .iter()
+        .map(|global| (global.name.as_str(), global.binding))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bindings,
+        [
+            ("params", gpu::ShaderBinding::Plain { size: 32 }),
+            ("values", gpu::ShaderBinding::Buffer),
+            ("input", gpu::ShaderBinding::Texture),
+            ("input_sampler", gpu::ShaderBinding::Sampler),
+        ]
+    );
+    assert_eq!(reflection.globals[0].type_name.as_deref(), Some("Params"));
+
+    let params = reflection.find_struct("Params").unwrap();
+    assert_eq!(params.size, 32);
+    let members = params
+        .members
+        .iter()
+        .map(|member| (member.name.as_str(), member.offset, member.size))
+        .collect::<Vec<_>>();
+    assert_eq!(members, [("scale", 0, 16), ("count", 16, 4)]);
+
+    let cs = reflection.entry_point("cs_main").unwrap();
+    assert_eq!(cs.stage, gpu::ShaderStage::Compute);
+    assert_eq!(cs.workgroup_size, [8, 4, 1]);
+    assert_eq!(cs.globals, [0, 1]);
+
+    // Встроенные входы не считаются атрибутами вершин
+    let vs = reflection.entry_point("vs_main").unwrap();
+    let vertex_layout = vs.vertex_layout().unwrap();
+    assert_eq!(
+        vertex_layout.attributes,
+        [
+            (
+                "position",
+                gpu::VertexAttribute {
+                    offset: 0,
+                    format: gpu::VertexFormat::F32Vec3,
+                },
+            ),
+            (
+                "color",
+                gpu::VertexAttribute {
+                    offset: 12,
+                    format: gpu::VertexFormat::F32Vec4,
+                },
+            ),
+        ]
+    );
+    assert_eq!(vertex_layout.stride, 28);
+
+    // Раскладка, построенная по отражению, принимается конвейером
+    let data_layout = reflection.data_layout(&["vs_main", "fs_main"]);
+    assert_eq!(data_layout.bindings.len(), 3);
+    assert_eq!(reflection.check_data_layout(&data_layout), Ok(()));
+    let mut pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+        name: "reflected",
+        data_layouts: &[&data_layout],
+        vertex: shader.at("vs_main"),
+        vertex_fetches: &[gpu::VertexFetchState {
+            layout: &vertex_layout,
+            instanced: false,
+        }],
+        primitive: gpu::PrimitiveState::default(),
+        depth_stencil: None,
+        fragment: Some(shader.at("fs_main")),
+        color_targets: &[gpu::TextureFormat::Rgba8Unorm.into()],
+        multisample_state: gpu::MultisampleState::default(),
+    });
+    context.destroy_render_pipeline(&mut pipeline);
+
+    let wrong_layout = gpu::ShaderDataLayout {
+        bindings: vec![("input", gpu::ShaderBinding::Sampler)],
+    };
+    assert_eq!(
+        reflection.check_data_layout(&wrong_layout),
+        Err(gpu::Error::BindingMismatch {
+            name: "input".to_string(),
+            expected: gpu::ShaderBinding::Texture,
+            provided: gpu::ShaderBinding::Sampler,
+        })
+    );
+}
+
+#[test]
+fn plain_structs_are_checked_on_bind() {
+    const PARAMS_SHADER: &str = "
+struct Params {
+    scale: vec4<f32>,
+    count: u32,
+}
+var<uniform> params: Params;
+var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= params.scale.x * f32(params.count);
+}
+";
+    // Поля переставлены относительно шейдера
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Params {
+        count: i32,
+        _pad: [u32; 3],
+        scale: [f32; 4],
+    }
+    unsafe impl bytemuck::Zeroable for Params {}
+    unsafe impl bytemuck::Pod for Params {}
+    impl gpu::ShaderStruct for Params {
+        fn struct_layout() -> gpu::StructLayout {
+            gpu::StructLayout {
+                size: 32,
+                fields: vec![
+                    gpu::StructField {
+                        name: "count",
+                        offset: 0,
+                        size: 4,
+                        kind: Some(gpu::ScalarKind::Sint),
+                    },
+                    gpu::StructField {
+                        name: "_pad",
+                        offset: 4,
+                        size: 12,
+                        kind: Some(gpu::ScalarKind::Uint),
+                    },
+                    gpu::StructField {
+                        name: "scale",
+                        offset: 16,
+                        size: 16,
+                        kind: Some(gpu::ScalarKind::Float),
+                    },
+                ],
+            }
+        }
+    }
+    struct ParamsData {
+        params: Params,
+        values: gpu::BufferPiece,
+    }
+    impl gpu::ShaderData for ParamsData {
+        fn layout() -> gpu::ShaderDataLayout {
+            gpu::ShaderDataLayout {
+                bindings: vec![
+                    ("params", gpu::ShaderBinding::Plain { size: 32 }),
+                    ("values", gpu::ShaderBinding::Buffer),
+                ],
+            }
+        }
+        fn fill(&self, mut context: gpu::PipelineContext) {
+            self.params.bind_to(&mut context, 0);
+            self.values.bind_to(&mut context, 1);
+        }
+        fn plain_layouts() -> Vec<(u32, gpu::StructLayout)> {
+            vec![(0, <Params as gpu::ShaderStruct>::struct_layout())]
+        }
+    }
+
+    let context = init();
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: PARAMS_SHADER,
+        ..Default::default()
+    });
+    assert_eq!(
+        shader.check_struct_layout::<Params>("Params"),
+        Err(vec![
+            gpu::FieldMismatch::Offset {
+                name: "scale".to_string(),
+                host: 16,
+                shader: 0,
+            },
+            gpu::FieldMismatch::Offset {
+                name: "count".to_string(),
+                host: 0,
+                shader: 16,
+            },
+            gpu::FieldMismatch::Kind {
+                name: "count".to_string(),
+                host: Some(gpu::ScalarKind::Sint),
+                shader: Some(gpu::ScalarKind::Uint),
+            },
+        ])
+    );
+    // Отсутствующая структура — ошибка, а не паника
+    assert_eq!(
+        shader.check_struct_layout::<Params>("Missing"),
+        Err(vec![gpu::FieldMismatch::MissingStruct {
+            name: "Missing".to_string(),
+        }])
+    );
+
+    let data_layout = <ParamsData as gpu::ShaderData>::layout();
+    let mut pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "params",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+    let values = create_buffer(&context, 256);
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    {
+        let mut pass = encoder.compute("params");
+        let mut pc = pass.with(&pipeline);
+        pc.bind(
+            0,
+            &ParamsData {
+                params: Params {
+                    count: 1,
+                    _pad: [0; 3],
+                    scale: [1.0; 4],
+                },
+                values: values.into(),
+            },
+        );
+    }
+    context.submit(&mut encoder);
+
+    // Каждое расхождение названо по имени поля
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("struct 'Params'"), "{}", errors[0]);
+    assert!(errors[0].contains("field 'scale' is at offset 16, but the shader expects 0"));
+    assert!(
+        errors[0].contains("field 'count' has Sint scalars, but the shader expects Uint scalars")
+    );
+
+    context.destroy_buffer(values);
+    context.destroy_compute_pipeline(&mut pipeline);
+    context.destroy_command_encoder(&mut encoder);
+}
+
+#[test]
+fn large_plain_data_is_bound() {
+    const LARGE_SHADER: &str = "
+struct Params {
+    weights: array<vec4<f32>, 32>,
+}
+var<uniform> params: Params;
+var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(32)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= params.weights[id.x].x;
+}
+";
+    const HUGE_SHADER: &str = "
+struct Params {
+    weights: array<vec4<f32>, 2048>,
+}
+var<uniform> params: Params;
+var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(32)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] *= params.weights[id.x].x;
+}
+";
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Params {
+        weights: [[f32; 4]; 32],
+    }
+    unsafe impl bytemuck::Zeroable for Params {}
+    unsafe impl bytemuck::Pod for Params {}
+    struct ParamsData {
+        params: Params,
+        values: gpu::BufferPiece,
+    }
+    impl gpu::ShaderData for ParamsData {
+        fn layout() -> gpu::ShaderDataLayout {
+            gpu::ShaderDataLayout {
+                bindings: vec![
+                    ("params", gpu::ShaderBinding::Plain { size: 512 }),
+                    ("values", gpu::ShaderBinding::Buffer),
+                ],
+            }
+        }
+        fn fill(&self, mut context: gpu::PipelineContext) {
+            self.params.bind_to(&mut context, 0);
+            self.values.bind_to(&mut context, 1);
+        }
+    }
+
+    let context = init();
+    // Данные больше `PLAIN_DATA_SIZE` привязываются так же, как и маленькие
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: LARGE_SHADER,
+        ..Default::default()
+    });
+    let data_layout = <ParamsData as gpu::ShaderData>::layout();
+    let mut pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "large",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+    let values = create_buffer(&context, 128);
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    encoder.start();
+    {
+        let mut pass = encoder.compute("large");
+        let mut pc = pass.with(&pipeline);
+        pc.bind(
+            0,
+            &ParamsData {
+                params: Params {
+                    weights: [[2.0; 4]; 32],
+                },
+                values: values.into(),
+            },
+        );
+        pc.dispatch([1, 1, 1]);
+    }
+    context.submit(&mut encoder);
+    let errors = context.take_validation_errors();
+    assert!(errors.is_empty(), "{errors:?}");
+
+    // Данные больше `MAX_PLAIN_DATA_SIZE` не помещаются ни в один бэкенд
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: HUGE_SHADER,
+        ..Default::default()
+    });
+    let huge_layout = gpu::ShaderDataLayout {
+        bindings: vec![
+            ("params", gpu::ShaderBinding::Plain { size: 32768 }),
+            ("values", gpu::ShaderBinding::Buffer),
+        ],
+    };
+    let result = context.try_create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "huge",
+        data_layouts: &[&huge_layout],
+        compute: shader.at("main"),
+    });
+    match result {
+        Err(gpu::Error::UnsupportedBinding { ref name, binding }) => {
+            assert_eq!(name, "params");
+            assert_eq!(binding, gpu::ShaderBinding::Plain { size: 32768 });
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    context.destroy_buffer(values);
+    context.destroy_compute_pipeline(&mut pipeline);
+    context.destroy_command_encoder(&mut encoder);
+}
+
+#[test]
+fn trace_is_replayed() {
+    let dir = std::env::temp_dir().join(format!("blade-trace-{}", std::process::id()));
+    {
+        let context = gpu::Context::init(gpu::ContextDesc {
+            trace_path: Some(dir.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        let shader = context.create_shader(gpu::ShaderDesc {
+            source: SHADER,
+            ..Default::default()
+        });
+        let data_layout = <Data as gpu::ShaderData>::layout();
+        let pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+            name: "scale",
+            data_layouts: &[&data_layout],
+            compute: shader.at("main"),
+        });
+        let src = context.create_buffer(gpu::BufferDesc {
+            name: "src",
+            size: 16,
+            memory: gpu::Memory::Shared,
+        });
+        let dst = context.create_buffer(gpu::BufferDesc {
+            name: "dst",
+            size: 16,
+            memory: gpu::Memory::Shared,
+        });
+        unsafe { std::ptr::write_bytes(src.data(), 7, 16) };
+
+        let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+            name: "test",
+            buffer_count: 1,
+            ..Default::default()
+        });
+        encoder.start();
+        {
+            let mut transfer = encoder.transfer("copy");
+            transfer.copy_buffer_to_buffer(src.into(), dst.into(), 8);
+            transfer.fill_buffer(dst.at(8), 8, 1);
+        }
+        {
+            let mut pass = encoder.compute("scale");
+            let mut pc = pass.with(&pipeline);
+            pc.bind(
+                0,
+                &Data {
+                    scale: [2.0; 4],
+                    values: dst.into(),
+                },
+            );
+            pc.dispatch([1, 1, 1]);
+        }
+        context.submit(&mut encoder);
+        assert!(context.take_validation_errors().is_empty());
+    }
+
+    // Воспроизведение на новом контексте, без исходного кода приложения
+    let context = init();
+    let mut replayer = gpu::trace::Replayer::new(&context, &dir).unwrap();
+    replayer.run().unwrap();
+    let dst = replayer.buffer("dst").unwrap();
+    let result = unsafe { std::slice::from_raw_parts(dst.data(), 16) };
+    assert_eq!(result, [7, 7, 7, 7, 7, 7, 7, 7, 1, 1, 1, 1, 1, 1, 1, 1]);
+    let errors = context.take_validation_errors();
+    assert!(errors.is_empty(), "{errors:?}");
+
+    drop(replayer);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn live_resources_are_tracked() {
+    let context = gpu::Context::init(gpu::ContextDesc {
+        track_resources: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let buffer = create_buffer(&context, 16);
+    let texture = context.create_texture(gpu::TextureDesc {
+        name: "mips",
+        format: gpu::TextureFormat::Rgba8Unorm,
+        size: gpu::Extent {
+            width: 4,
+            height: 4,
+            depth: 1,
+        },
+        array_layer_count: 2,
+        mip_level_count: 3,
+        sample_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::RESOURCE,
+        external: None,
+    });
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: SHADER,
+        ..Default::default()
+    });
+    let data_layout = <Data as gpu::ShaderData>::layout();
+    let mut pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "scale",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+
+    let live = context.live_resources();
+    let summary = live
+        .iter()
+        .map(|resource| (resource.ty, resource.name.as_str(), resource.size))
+        .collect::<Vec<_>>();
+    // Мипы 4x4, 2x2 и 1x1 в двух слоях, по 4 байта на тексель
+    assert_eq!(
+        summary,
+        [
+            (gpu::ResourceType::Buffer, "test", 16),
+            (gpu::ResourceType::Texture, "mips", 2 * (16 + 4 + 1) * 4),
+            (gpu::ResourceType::ComputePipeline, "scale", 0),
+        ]
+    );
+
+    context.destroy_buffer(buffer);
+    context.destroy_texture(texture);
+    let live = context.live_resources();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].name, "scale");
+    context.destroy_compute_pipeline(&mut pipeline);
+    assert!(context.live_resources().is_empty());
+}
+
+const BINDLESS_SHADER: &str = "
+struct Values {
+    data: array<f32>,
+}
+var textures: binding_array<texture_2d<f32>>;
+var<storage, read_write> buffers: binding_array<Values>;
+var samplers: binding_array<sampler>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let color = textureSampleLevel(textures[id.y], samplers[id.z], vec2<f32>(0.5), 0.0);
+    buffers[id.x].data[0] = color.x;
+}
+";
+
+#[test]
+fn bindless_handles_are_generational() {
+    let context = init();
+    assert_ne!(context.capabilities().max_bindless_resources, 0);
+    let table_desc = gpu::BindlessTableDesc {
+        name: "bindless",
+        texture_count: 4,
+        buffer_count: 2,
+        sampler_count: 1,
+    };
+    let data_layout = table_desc.layout();
+    let mut table = context.create_bindless_table(table_desc);
+    let first = create_buffer(&context, 16);
+    let second = create_buffer(&context, 16);
+
+    let a = context.insert_bindless_buffer(&mut table, first.into());
+    let b = context.insert_bindless_buffer(&mut table, second.into());
+    assert_eq!((a.index(), b.index()), (0, 1));
+    context.remove_bindless(&mut table, a);
+    assert!(!table.contains(a));
+    // Слот переиспользуется, но старый дескриптор остаётся недействительным
+    let c = context.insert_bindless_buffer(&mut table, first.into());
+    assert_eq!(c.index(), a.index());
+    assert_ne!(c, a);
+    assert!(table.contains(c) && !table.contains(a));
+    context.remove_bindless(&mut table, a);
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("is removed, but it's not in the table"));
+
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: BINDLESS_SHADER,
+        ..Default::default()
+    });
+    let mut pipeline = context.create_compute_pipeline(gpu::ComputePipelineDesc {
+        name: "bindless",
+        data_layouts: &[&data_layout],
+        compute: shader.at("main"),
+    });
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "test",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    for step in 0..2 {
+        encoder.start();
+        {
+            let mut pass = encoder.compute("bindless");
+            let mut pc = pass.with(&pipeline);
+            pc.bind_table(0, &table);
+            pc.dispatch([1, 1, 1]);
+        }
+        context.submit(&mut encoder);
+        if step == 0 {
+            assert!(context.take_validation_errors().is_empty());
+            // Ресурсы таблицы должны жить, пока она используется
+            context.destroy_buffer(second);
+        }
+    }
+    let errors = context.take_validation_errors();
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(errors[0].contains("Buffer 'test' is used by BindlessTable 'bindless'"));
+
+    context.destroy_buffer(first);
+    context.destroy_bindless_table(&mut table);
+    context.destroy_compute_pipeline(&mut pipeline);
+}
+
+#[test]
+fn packed_vertex_formats_are_checked() {
+    use gpu::derive::HasVertexAttribute;
+
+    const PACKED: &str = "
+struct VertexInput {
+    position: vec4<f32>,
+    color: vec4<f32>,
+    uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(input: VertexInput) -> @builtin(position) vec4<f32> {
+    return input.position * input.color + vec4<f32>(input.uv, 0.0, 0.0);
+}
+";
+    let context = init();
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: PACKED,
+        ..Default::default()
+    });
+    let make_layout = |color: gpu::VertexFormat| gpu::VertexLayout {
+        attributes: vec![
+            (
+                "position",
+                gpu::VertexAttribute {
+                    offset: 0,
+                    format: gpu::Float16x4::FORMAT,
+                },
+            ),
+            (
+                "color",
+                gpu::VertexAttribute {
+                    offset: 8,
+                    format: color,
+                },
+            ),
+            (
+                "uv",
+                gpu::VertexAttribute {
+                    offset: 12,
+                    format: gpu::Unorm16x2::FORMAT,
+                },
+            ),
+        ],
+        stride: 16,
+    };
+    let create = |layout: &gpu::VertexLayout| {
+        context.try_create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "packed",
+            data_layouts: &[],
+            vertex: shader.at("vs_main"),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout,
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState::default(),
+            depth_stencil: None,
+            fragment: None,
+            color_targets: &[],
+            multisample_state: gpu::MultisampleState::default(),
+        })
+    };
+
+    // Нормализованные форматы читаются шейдером как числа с плавающей точкой
+    for format in [
+        gpu::Unorm8x4::FORMAT,
+        gpu::Snorm8x4::FORMAT,
+        gpu::Rgb10a2Unorm::FORMAT,
+    ] {
+        assert_eq!(format.size(), 4);
+        let mut pipeline = create(&make_layout(format)).unwrap();
+        context.destroy_render_pipeline(&mut pipeline);
+    }
+
+    // Число компонент может отличаться: недостающие дополняются при выборке
+    let mut layout = make_layout(<[f32; 3]>::FORMAT);
+    layout.attributes[2].1.offset = 20;
+    layout.stride = 24;
+    let mut pipeline = create(&layout).unwrap();
+    context.destroy_render_pipeline(&mut pipeline);
+
+    // Целочисленный формат не подходит для `vec4<f32>`
+    match create(&make_layout(gpu::Uint8x4::FORMAT)) {
+        Err(gpu::Error::VertexFormatMismatch { ref name, format }) => {
+            assert_eq!(name, "color");
+            assert_eq!(format, gpu::VertexFormat::Uint8x4);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    // Входы, объявленные по одному, проверяются так же
+    let shader = context.create_shader(gpu::ShaderDesc {
+        source: "
+@vertex
+fn vs_main(position: vec4<f32>, color: vec4<f32>, uv: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return position * color + vec4<f32>(uv, 0.0, 0.0);
+}
+",
+        ..Default::default()
+    });
+    let layout = make_layout(gpu::Uint8x4::FORMAT);
+    let result = context.try_create_render_pipeline(gpu::RenderPipelineDesc {
+        name: "separate",
+        data_layouts: &[],
+        vertex: shader.at("vs_main"),
+        vertex_fetches: &[gpu::VertexFetchState {
+            layout: &layout,
+            instanced: false,
+        }],
+        primitive: gpu::PrimitiveState::default(),
+        depth_stencil: None,
+        fragment: None,
+        color_targets: &[],
+        multisample_state: gpu::MultisampleState::default(),
+    });
+    match result {
+        Err(gpu::Error::VertexFormatMismatch { ref name, .. }) => assert_eq!(name, "color"),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    assert_eq!(gpu::Rgb10a2Unorm::new(1.0, 0.0, 0.5, 1.0).0, 0xE000_03FF);
+}
+
+#[test]
+fn texture_format_usage_is_reported() {
+    let context = init();
+    let astc = gpu::TextureFormat::Astc8x5UnormSrgb;
+    assert_eq!(astc.block_info().dimensions, (8, 5));
+    assert_eq!(
+        context.texture_format_usage(astc),
+        gpu::TextureUsage::COPY | gpu::TextureUsage::RESOURCE
+    );
+    assert!(
+        context
+            .texture_format_usage(gpu::TextureFormat::Depth24PlusStencil8)
+            .contains(gpu::TextureUsage::TARGET)
+    );
+    // Запись в sRGB возможна только через линейные виды, которых здесь нет
+    assert_eq!(
+        context.texture_format_usage(gpu::TextureFormat::Rgba8UnormSrgb),
+        gpu::TextureUsage::COPY | gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE
+    );
+    // Размер блока учитывает подмену на 32-битную глубину
+    assert_eq!(
+        gpu::TextureFormat::Depth24PlusStencil8.block_info().size,
+        gpu::TextureFormat::Depth32FloatStencil8Uint
+            .block_info()
+            .size
+    );
+
+    let desc = |format, usage| gpu::TextureDesc {
+        name: "texture",
+        format,
+        size: gpu::Extent {
+            width: 16,
+            height: 10,
+            depth: 1,
+        },
+        array_layer_count: 1,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage,
+        external: None,
+    };
+    let texture = context
+        .try_create_texture(desc(astc, gpu::TextureUsage::RESOURCE))
+        .unwrap();
+    context.destroy_texture(texture);
+
+    // Сжатые текстуры нельзя использовать для записи
+    let result = context.try_create_texture(desc(astc, gpu::TextureUsage::STORAGE));
+    assert_eq!(result.map(|_| ()), Err(gpu::Error::UnsupportedFormat(astc)));
+    assert!(context.take_validation_errors().is_empty());
+}
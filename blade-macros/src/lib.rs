@@ -1,6 +1,7 @@
 mod as_primitive;
 mod flat;
 mod shader_data;
+mod shader_struct;
 mod vertex;
 
 use proc_macro::TokenStream;
@@ -24,6 +25,32 @@ pub fn shader_data_derive(input: TokenStream) -> TokenStream {
     stream.into()
 }
 
+/// Derive the `ShaderStruct` trait for a plain data struct.
+///
+/// The layout is checked against the shader struct on `bind` in debug builds,
+/// when the struct is a field of a `ShaderData` struct. Fields with names
+/// starting with `_` are considered padding.
+///
+/// ## Example
+///
+/// ```rust
+/// #[repr(C)]
+/// #[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod, blade_macros::ShaderStruct)]
+/// struct Globals {
+///   mvp_transform: [[f32; 4]; 4],
+///   time: f32,
+///   _pad: [u32; 3],
+/// }
+/// ```
+#[proc_macro_derive(ShaderStruct)]
+pub fn shader_struct_derive(input: TokenStream) -> TokenStream {
+    let stream = match shader_struct::generate(input) {
+        Ok(tokens) => tokens,
+        Err(err) => err.into_compile_error(),
+    };
+    stream.into()
+}
+
 /// Derive the `Vertex` trait for a struct.
 ///
 /// ## Example
@@ -15,6 +15,7 @@ pub fn generate(input_stream: TokenStream) -> syn::Result<proc_macro2::TokenStre
 
     let mut bindings = Vec::new();
     let mut assignments = Vec::new();
+    let mut plain_layouts = Vec::new();
     for (index_usize, field) in fields.named.iter().enumerate() {
         let index = index_usize as u32;
         let name = field.ident.as_ref().unwrap();
@@ -25,6 +26,11 @@ pub fn generate(input_stream: TokenStream) -> syn::Result<proc_macro2::TokenStre
         assignments.push(quote! {
             self.#name.bind_to(&mut ctx, #index);
         });
+        plain_layouts.push(quote! {
+            (&blade_graphics::derive::FieldProbe::<#ty>::new())
+                .struct_layout()
+                .map(|layout| (#index, layout))
+        });
     }
 
     let mut generics = Vec::new();
@@ -43,6 +49,7 @@ pub fn generate(input_stream: TokenStream) -> syn::Result<proc_macro2::TokenStre
     }
 
     let struct_name = item_struct.ident;
+    let field_count = plain_layouts.len();
     Ok(quote! {
         impl<#(#generics),*> blade_graphics::ShaderData for #struct_name<#(#generics),*> {
             fn layout() -> blade_graphics::ShaderDataLayout {
@@ -54,6 +61,12 @@ pub fn generate(input_stream: TokenStream) -> syn::Result<proc_macro2::TokenStre
                 use blade_graphics::ShaderBindable as _;
                 #(#assignments)*
             }
+            fn plain_layouts() -> Vec<(u32, blade_graphics::StructLayout)> {
+                use blade_graphics::derive::{OtherFieldProbe as _, StructFieldProbe as _};
+                let layouts: [Option<(u32, blade_graphics::StructLayout)>; #field_count] =
+                    [#(#plain_layouts),*];
+                layouts.into_iter().flatten().collect()
+            }
         }
     })
 }
@@ -0,0 +1,50 @@
+use proc_macro::TokenStream;
+use quote::quote;
+
+pub fn generate(input_stream: TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let item_struct = syn::parse::<syn::ItemStruct>(input_stream)?;
+    let fields = match item_struct.fields {
+        syn::Fields::Named(ref fields) => fields,
+        _ => {
+            return Err(syn::Error::new(
+                item_struct.struct_token.span,
+                "Structure fields must be named",
+            ))
+        }
+    };
+    if !item_struct.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item_struct.struct_token.span,
+            "Unsupported generic parameters",
+        ));
+    }
+
+    let struct_name = item_struct.ident;
+    let mut struct_fields = Vec::new();
+    for field in fields.named.iter() {
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        struct_fields.push(quote! {
+            blade_graphics::StructField {
+                name: stringify!(#name),
+                offset: core::mem::offset_of!(#struct_name, #name) as u32,
+                size: core::mem::size_of::<#ty>() as u32,
+                kind: <#ty as blade_graphics::derive::HasScalarKind>::KIND,
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl blade_graphics::ShaderStruct for #struct_name {
+            fn struct_layout() -> blade_graphics::StructLayout {
+                blade_graphics::StructLayout {
+                    size: core::mem::size_of::<Self>() as u32,
+                    fields: vec![#(#struct_fields),*],
+                }
+            }
+        }
+        impl blade_graphics::derive::HasScalarKind for #struct_name {
+            const KIND: Option<blade_graphics::ScalarKind> = None;
+        }
+    })
+}
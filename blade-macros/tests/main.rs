@@ -1,7 +1,9 @@
 #[repr(C)]
-#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
+#[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod, blade_macros::ShaderStruct)]
 struct Globals {
     mvp_transform: [[f32; 4]; 4],
+    frame_index: u32,
+    _pad: [u32; 3],
 }
 
 
@@ -13,6 +15,38 @@ struct ShaderParams {
     sprite_sampler: blade_graphics::Sampler,
 }
 
+#[test]
+fn test_shader_struct() {
+    use blade_graphics::{ScalarKind, ShaderData as _, ShaderStruct as _, StructField};
+
+    let layout = Globals::struct_layout();
+    assert_eq!(layout.size, 80);
+    assert_eq!(
+        layout.fields,
+        [
+            StructField {
+                name: "mvp_transform",
+                offset: 0,
+                size: 64,
+                kind: Some(ScalarKind::Float),
+            },
+            StructField {
+                name: "frame_index",
+                offset: 64,
+                size: 4,
+                kind: Some(ScalarKind::Uint),
+            },
+            StructField {
+                name: "_pad",
+                offset: 68,
+                size: 12,
+                kind: Some(ScalarKind::Uint),
+            },
+        ]
+    );
+    assert_eq!(ShaderParams::plain_layouts(), [(0, layout)]);
+}
+
 #[derive(blade_macros::Flat, PartialEq, Debug)]
 struct FlatData<'a> {
     array: [u32; 2],
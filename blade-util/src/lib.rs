@@ -1,4 +1,8 @@
 mod belt;
+mod mip;
+mod parallel;
 
 pub use belt::{BufferBelt, BufferBeltDescriptor,};
-pub use belt::create_static_buffer;
\ No newline at end of file
+pub use belt::create_static_buffer;
+pub use mip::{MipError, MipFilter, MipGenerator};
+pub use parallel::render_parallel;
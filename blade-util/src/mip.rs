@@ -0,0 +1,272 @@
+use blade_graphics as gpu;
+use std::{collections::HashMap, fmt, num::NonZeroU32};
+
+const SHADER: &str = include_str!("mip.wgsl");
+
+/// Filter used to compute each mip level from the previous one.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum MipFilter {
+    /// Average of the source texels covered by the target texel.
+    #[default]
+    Box,
+    /// Kaiser-windowed sinc. Sharper than the box, at the cost of more samples.
+    Kaiser,
+}
+
+/// Reason the mips of a texture can't be generated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MipError {
+    /// The format can't be written by a compute shader on this device.
+    UnsupportedFormat(gpu::TextureFormat),
+    /// Only 2D textures and their arrays are supported.
+    UnsupportedDimension(gpu::TextureDimension),
+    /// Multisampled textures have no mips, the sample count is attached.
+    Multisampled(u32),
+    /// The texture lacks some of the usage needed by the downsample pass.
+    MissingUsage(gpu::TextureUsage),
+    /// The downsample shader failed to compile.
+    Shader(gpu::ShaderError),
+    /// The downsample pipeline failed to create.
+    Pipeline(gpu::Error),
+}
+
+impl fmt::Display for MipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::UnsupportedFormat(format) => {
+                write!(f, "Mip generation is not supported for {format:?}")
+            }
+            Self::UnsupportedDimension(dimension) => {
+                write!(
+                    f,
+                    "Mip generation is not supported for {dimension:?} textures"
+                )
+            }
+            Self::Multisampled(count) => {
+                write!(f, "Mip generation is not supported for {count} samples")
+            }
+            Self::MissingUsage(usage) => write!(f, "Mip generation needs {usage:?} usage"),
+            Self::Shader(ref e) => write!(f, "Mip shader error: {e}"),
+            Self::Pipeline(ref e) => write!(f, "Mip pipeline error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MipError {}
+
+#[derive(blade_macros::ShaderData)]
+struct MipData {
+    source_mip: gpu::TextureView,
+    target_mip: gpu::TextureView,
+}
+
+/// WGSL storage format to write the mips with.
+fn storage_format(format: gpu::TextureFormat) -> Option<&'static str> {
+    use gpu::TextureFormat as Tf;
+    Some(match format {
+        Tf::R8Unorm => "r8unorm",
+        Tf::Rg8Unorm => "rg8unorm",
+        Tf::Rgba8Unorm => "rgba8unorm",
+        Tf::Rgba8Snorm => "rgba8snorm",
+        Tf::R16Unorm => "r16unorm",
+        Tf::Rgba16Unorm => "rgba16unorm",
+        Tf::R16Float => "r16float",
+        Tf::Rg16Float => "rg16float",
+        Tf::Rgba16Float => "rgba16float",
+        Tf::R32Float => "r32float",
+        Tf::Rg32Float => "rg32float",
+        Tf::Rgba32Float => "rgba32float",
+        Tf::Rgb10a2Unorm => "rgb10a2unorm",
+        Tf::Rg11b10Ufloat => "rg11b10ufloat",
+        _ => return None,
+    })
+}
+
+/// Generator of the mip chains with a compute downsample.
+///
+/// Works on 2D textures, including arrays and cubes, of any size.
+/// sRGB textures are filtered in linear space.
+pub struct MipGenerator {
+    pipelines: HashMap<(gpu::TextureFormat, MipFilter), gpu::ComputePipeline>,
+    active: Vec<gpu::TextureView>,
+    retired: Vec<(Vec<gpu::TextureView>, gpu::SyncPoint)>,
+}
+
+impl Default for MipGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MipGenerator {
+    /// Create a new generator. Pipelines are created on demand.
+    pub fn new() -> Self {
+        Self {
+            pipelines: HashMap::new(),
+            active: Vec::new(),
+            retired: Vec::new(),
+        }
+    }
+
+    /// Destroy this generator.
+    pub fn destroy(&mut self, gpu: &gpu::Context) {
+        for (_, mut pipeline) in self.pipelines.drain() {
+            gpu.destroy_compute_pipeline(&mut pipeline);
+        }
+        for view in self.active.drain(..) {
+            gpu.destroy_texture_view(view);
+        }
+        for (views, _) in self.retired.drain(..) {
+            for view in views {
+                gpu.destroy_texture_view(view);
+            }
+        }
+    }
+
+    fn pipeline(
+        &mut self,
+        format: gpu::TextureFormat,
+        filter: MipFilter,
+        gpu: &gpu::Context,
+    ) -> Result<&gpu::ComputePipeline, MipError> {
+        use std::collections::hash_map::Entry;
+        let entry = match self.pipelines.entry((format, filter)) {
+            Entry::Occupied(entry) => return Ok(entry.into_mut()),
+            Entry::Vacant(entry) => entry,
+        };
+        let linear = format.to_linear();
+        let storage = storage_format(linear).ok_or(MipError::UnsupportedFormat(format))?;
+        let source = format!(
+            "const SRGB: bool = {};\nconst KAISER: bool = {};\n{}",
+            linear != format,
+            filter == MipFilter::Kaiser,
+            SHADER.replace("STORAGE_FORMAT", storage),
+        );
+        let shader = gpu
+            .try_create_shader(gpu::ShaderDesc {
+                source: &source,
+                ..Default::default()
+            })
+            .map_err(MipError::Shader)?;
+        let pipeline = gpu
+            .try_create_compute_pipeline(gpu::ComputePipelineDesc {
+                name: &format!("mip-{format:?}-{filter:?}"),
+                data_layouts: &[&<MipData as gpu::ShaderData>::layout()],
+                compute: shader.at("main"),
+            })
+            .map_err(MipError::Pipeline)?;
+        Ok(entry.insert(pipeline))
+    }
+
+    /// Record the generation of all the mips of `texture` after the first one,
+    /// which needs to be filled already.
+    ///
+    /// `desc` is the descriptor the texture was created with. The texture needs
+    /// to be single-sampled 2D with `RESOURCE` and `STORAGE` usages, otherwise
+    /// an error is returned. Every mip level is computed in a separate pass.
+    /// Formats that the device can't write in a compute shader are rejected,
+    /// which includes sRGB on backends without linear views of them.
+    #[profiling::function]
+    pub fn generate(
+        &mut self,
+        encoder: &mut gpu::CommandEncoder,
+        texture: gpu::Texture,
+        desc: &gpu::TextureDesc,
+        filter: MipFilter,
+        gpu: &gpu::Context,
+    ) -> Result<(), MipError> {
+        if !gpu
+            .texture_format_usage(desc.format)
+            .contains(gpu::TextureUsage::STORAGE)
+        {
+            return Err(MipError::UnsupportedFormat(desc.format));
+        }
+        if desc.dimension != gpu::TextureDimension::D2 {
+            return Err(MipError::UnsupportedDimension(desc.dimension));
+        }
+        if desc.sample_count != 1 {
+            return Err(MipError::Multisampled(desc.sample_count));
+        }
+        let missing = (gpu::TextureUsage::RESOURCE | gpu::TextureUsage::STORAGE) - desc.usage;
+        if !missing.is_empty() {
+            return Err(MipError::MissingUsage(missing));
+        }
+        self.free_retired(gpu);
+        if desc.mip_level_count <= 1 {
+            return Ok(());
+        }
+        let linear = desc.format.to_linear();
+        let pipeline = self.pipeline(desc.format, filter, gpu)?;
+        let wg_size = pipeline.get_workgroup_size();
+        let mut used = Vec::new();
+
+        for level in 1..desc.mip_level_count {
+            let size = desc.size.at_mip_level(level);
+            let groups = [
+                size.width.div_ceil(wg_size[0]),
+                size.height.div_ceil(wg_size[1]),
+                1,
+            ];
+            let mut views = Vec::with_capacity(2 * desc.array_layer_count as usize);
+            for layer in 0..desc.array_layer_count {
+                let view = |base_mip_level, format| {
+                    gpu.create_texture_view(
+                        texture,
+                        gpu::TextureViewDesc {
+                            name: &format!("{}-mip{}-layer{}", desc.name, base_mip_level, layer),
+                            format,
+                            dimension: gpu::ViewDimension::D2,
+                            subresources: &gpu::TextureSubresources {
+                                base_mip_level,
+                                mip_level_count: NonZeroU32::new(1),
+                                base_array_layer: layer,
+                                array_layer_count: NonZeroU32::new(1),
+                            },
+                        },
+                    )
+                };
+                views.push(MipData {
+                    source_mip: view(level - 1, desc.format),
+                    target_mip: view(level, linear),
+                });
+            }
+
+            {
+                let mut pass = encoder.compute(&format!("mip{level}"));
+                let mut pc = pass.with(pipeline);
+                for data in views.iter() {
+                    pc.bind(0, data);
+                    pc.dispatch(groups);
+                }
+            }
+            used.extend(
+                views
+                    .into_iter()
+                    .flat_map(|data| [data.source_mip, data.target_mip]),
+            );
+        }
+        self.active.extend(used);
+        Ok(())
+    }
+
+    fn free_retired(&mut self, gpu: &gpu::Context) {
+        self.retired.retain_mut(|&mut (ref mut views, ref sp)| {
+            if !gpu.wait_for(sp, 0) {
+                return true;
+            }
+            for view in views.drain(..) {
+                gpu.destroy_texture_view(view);
+            }
+            false
+        });
+    }
+
+    /// Mark the views of the generated mips as used by GPU with a given sync point.
+    /// They are destroyed once it's reached.
+    pub fn flush(&mut self, sp: &gpu::SyncPoint) {
+        if !self.active.is_empty() {
+            self.retired
+                .push((std::mem::take(&mut self.active), sp.clone()));
+        }
+    }
+}
@@ -0,0 +1,88 @@
+// Prepended by `MipGenerator`:
+// const SRGB: bool - encode the result into sRGB before storing
+// const KAISER: bool - use the Kaiser-windowed sinc instead of the box filter
+
+const PI: f32 = 3.14159265;
+const KAISER_RADIUS: f32 = 2.0;
+const KAISER_BETA: f32 = 4.0;
+
+var source_mip: texture_2d<f32>;
+var target_mip: texture_storage_2d<STORAGE_FORMAT, write>;
+
+fn bessel_i0(x: f32) -> f32 {
+    var sum = 1.0;
+    var term = 1.0;
+    for (var k = 1; k < 16; k += 1) {
+        term *= 0.5 * x / f32(k);
+        sum += term * term;
+    }
+    return sum;
+}
+
+fn kaiser(t: f32) -> f32 {
+    let r = t / KAISER_RADIUS;
+    if (abs(r) >= 1.0) {
+        return 0.0;
+    }
+    let window = bessel_i0(KAISER_BETA * sqrt(1.0 - r * r)) / bessel_i0(KAISER_BETA);
+    if (abs(t) < 1e-5) {
+        return window;
+    }
+    return window * sin(PI * t) / (PI * t);
+}
+
+// Weight of the source texel `i` for the target texel `x`,
+// where `scale` is the source size divided by the target size.
+fn weight(i: i32, x: u32, scale: f32) -> f32 {
+    let start = f32(x) * scale;
+    if (KAISER) {
+        let center = start + 0.5 * scale;
+        return kaiser((f32(i) + 0.5 - center) / scale);
+    }
+    // Overlap of the source texel with the target footprint,
+    // fractional for the odd source sizes.
+    return max(0.0, min(f32(i + 1), start + scale) - max(f32(i), start));
+}
+
+// First and last source texels contributing to the target texel `x`.
+fn footprint(x: u32, scale: f32) -> vec2<i32> {
+    let radius = select(0.5, KAISER_RADIUS, KAISER) * scale;
+    let center = (f32(x) + 0.5) * scale;
+    return vec2<i32>(i32(floor(center - radius)), i32(ceil(center + radius)) - 1);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let target_size = textureDimensions(target_mip);
+    if (any(id.xy >= target_size)) {
+        return;
+    }
+    let source_size = vec2<i32>(textureDimensions(source_mip));
+    let scale = vec2<f32>(source_size) / vec2<f32>(target_size);
+    let range_x = footprint(id.x, scale.x);
+    let range_y = footprint(id.y, scale.y);
+
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var y = range_y.x; y <= range_y.y; y += 1) {
+        let weight_y = weight(y, id.y, scale.y);
+        for (var x = range_x.x; x <= range_x.y; x += 1) {
+            let w = weight_y * weight(x, id.x, scale.x);
+            // Kaiser footprint goes past the edges, which are clamped
+            let coord = clamp(vec2<i32>(x, y), vec2<i32>(0), source_size - 1);
+            sum += w * textureLoad(source_mip, coord, 0);
+            total += w;
+        }
+    }
+
+    var color = sum / total;
+    if (SRGB) {
+        color = vec4<f32>(linear_to_srgb(color.rgb), color.a);
+    }
+    textureStore(target_mip, id.xy, color);
+}
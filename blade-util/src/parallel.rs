@@ -0,0 +1,37 @@
+use blade_graphics as gpu;
+use std::sync::{Arc, Mutex};
+
+/// Record a render pass in `count` chunks on the workers of `choir`.
+///
+/// `fun` is called once per chunk with its index, possibly on different threads.
+/// The chunks are executed by the GPU in the index order.
+/// Blocks until all the chunks are recorded, so the choir needs to have workers.
+pub fn render_parallel<F>(
+    encoder: &mut gpu::CommandEncoder,
+    label: &str,
+    targets: gpu::RenderTargetSet,
+    choir: &Arc<choir::Choir>,
+    count: usize,
+    fun: F,
+) where
+    F: Fn(&mut gpu::RenderChunk, usize) + Send + Sync + 'static,
+{
+    let mut pass = encoder.render_parallel(label, targets);
+    let chunks = (0..count)
+        .map(|_| Mutex::new(Some(pass.chunk())))
+        .collect::<Arc<[_]>>();
+
+    let task_chunks = Arc::clone(&chunks);
+    let task = choir
+        .spawn(label.to_string())
+        .init_multi(count as choir::SubIndex, move |_, index| {
+            let mut guard = task_chunks[index as usize].lock().unwrap();
+            fun(guard.as_mut().unwrap(), index as usize);
+        })
+        .run();
+    task.join();
+
+    for slot in chunks.iter() {
+        pass.merge(slot.lock().unwrap().take().unwrap());
+    }
+}
@@ -0,0 +1,117 @@
+//! Проверки генерации мипов без GPU: `cargo test -p blade-util --features null`.
+
+use blade_graphics as gpu;
+use blade_util::{MipError, MipFilter, MipGenerator};
+
+fn init() -> gpu::Context {
+    gpu::Context::init(gpu::ContextDesc::default()).unwrap()
+}
+
+fn texture_desc(
+    format: gpu::TextureFormat,
+    size: gpu::Extent,
+    array_layer_count: u32,
+) -> gpu::TextureDesc<'static> {
+    gpu::TextureDesc {
+        name: "mips",
+        format,
+        size,
+        array_layer_count,
+        mip_level_count: size.max_mip_levels(),
+        sample_count: 1,
+        dimension: gpu::TextureDimension::D2,
+        usage: gpu::TextureUsage::RESOURCE | gpu::TextureUsage::STORAGE,
+        external: None,
+    }
+}
+
+#[test]
+fn odd_sizes_and_layers_are_generated() {
+    let context = init();
+    let mut generator = MipGenerator::new();
+    let mut encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        name: "mips",
+        buffer_count: 1,
+        ..Default::default()
+    });
+    // Нечётный размер, массив и куб
+    let odd = gpu::Extent {
+        width: 5,
+        height: 3,
+        depth: 1,
+    };
+    let cube = gpu::Extent {
+        width: 5,
+        height: 5,
+        depth: 1,
+    };
+    let setups = [
+        (gpu::TextureFormat::Rgba8Unorm, odd, 1),
+        (gpu::TextureFormat::Rgba16Float, odd, 4),
+        (gpu::TextureFormat::Rgba8Unorm, cube, 6),
+    ];
+    assert_eq!(odd.max_mip_levels(), 3);
+
+    encoder.start();
+    let mut textures = Vec::new();
+    for (format, size, array_layer_count) in setups {
+        let desc = texture_desc(format, size, array_layer_count);
+        let texture = context.create_texture(texture_desc(format, size, array_layer_count));
+        // Первый мип заполняет вызывающий код
+        encoder.init_texture(texture);
+        for filter in [MipFilter::Box, MipFilter::Kaiser] {
+            generator
+                .generate(&mut encoder, texture, &desc, filter, &context)
+                .unwrap();
+        }
+        textures.push(texture);
+    }
+    let sp = context.submit(&mut encoder);
+    generator.flush(&sp);
+
+    let errors = context.take_validation_errors();
+    assert!(errors.is_empty(), "{errors:?}");
+
+    // Формат без пары для записи в шейдере отклоняется, а не роняет процесс
+    let bgra = texture_desc(gpu::TextureFormat::Bgra8Unorm, odd, 1);
+    let texture = context.create_texture(texture_desc(gpu::TextureFormat::Bgra8Unorm, odd, 1));
+    encoder.start();
+    assert_eq!(
+        generator.generate(&mut encoder, texture, &bgra, MipFilter::Box, &context),
+        Err(MipError::UnsupportedFormat(gpu::TextureFormat::Bgra8Unorm))
+    );
+    textures.push(texture);
+
+    // Неподходящее описание текстуры проверяется до записи команд
+    let mut volume = texture_desc(gpu::TextureFormat::Rgba8Unorm, odd, 1);
+    volume.dimension = gpu::TextureDimension::D3;
+    assert_eq!(
+        generator.generate(&mut encoder, texture, &volume, MipFilter::Box, &context),
+        Err(MipError::UnsupportedDimension(gpu::TextureDimension::D3))
+    );
+    let mut multisampled = texture_desc(gpu::TextureFormat::Rgba8Unorm, odd, 1);
+    multisampled.sample_count = 4;
+    assert_eq!(
+        generator.generate(
+            &mut encoder,
+            texture,
+            &multisampled,
+            MipFilter::Box,
+            &context
+        ),
+        Err(MipError::Multisampled(4))
+    );
+    let mut sampled = texture_desc(gpu::TextureFormat::Rgba8Unorm, odd, 1);
+    sampled.usage = gpu::TextureUsage::RESOURCE | gpu::TextureUsage::COPY;
+    assert_eq!(
+        generator.generate(&mut encoder, texture, &sampled, MipFilter::Box, &context),
+        Err(MipError::MissingUsage(gpu::TextureUsage::STORAGE))
+    );
+
+    context.wait_for(&sp, !0);
+    generator.destroy(&context);
+    for texture in textures {
+        context.destroy_texture(texture);
+    }
+    context.destroy_command_encoder(&mut encoder);
+}
@@ -6,14 +6,44 @@ struct Globals {
     ambient: vec4<f32>,
     specular_power: f32,
     specular_intensity: f32,
+    normal_mapping: u32,
+};
+
+struct ViewParams {
+    inverse_view_projection: mat4x4<f32>,
+    // Область вида в пикселях окна: x, y, ширина, высота
+    viewport: vec4<f32>,
+    light_count: u32,
+    debug_view: u32,
+};
+
+struct PointLight {
+    position: vec3<f32>,
+    radius: f32,
+    color: vec3<f32>,
 };
 
 var<uniform> globals: Globals;
+var<uniform> view_params: ViewParams;
+var<storage, read> lights: array<PointLight>;
+var normal_map: texture_2d<f32>;
+var normal_sampler: sampler;
+
+// G-buffer отложенного рендера
+var gbuffer_albedo: texture_2d<f32>;
+var gbuffer_normal: texture_2d<f32>;
+var gbuffer_material: texture_2d<f32>;
+var gbuffer_depth: texture_2d<f32>;
+
+// Степень блика хранится в material.r, нормированная на это значение
+const MAX_SPECULAR_POWER: f32 = 128.0;
 
 struct VertexInput {
     pos: vec3<f32>,
     normal: vec3<f32>,
     color: vec3<f32>,
+    uv: vec2<f32>,
+    tangent: vec4<f32>,
 };
 
 struct VertexOutput {
@@ -21,6 +51,8 @@ struct VertexOutput {
     @location(0) color: vec3<f32>,
     @location(1) world_pos: vec3<f32>,
     @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
+    @location(4) uv: vec2<f32>,
 }
 
 @vertex
@@ -29,28 +61,138 @@ fn vs_main(input: VertexInput) -> VertexOutput {
     
     let world_pos = (globals.model_matrix * vec4<f32>(input.pos, 1.0)).xyz;
     let world_normal = (globals.model_matrix * vec4<f32>(input.normal, 0.0)).xyz;
+    let world_tangent = (globals.model_matrix * vec4<f32>(input.tangent.xyz, 0.0)).xyz;
     
     output.position = globals.mvp_matrix * vec4<f32>(input.pos, 1.0);
     output.world_pos = world_pos;
     output.world_normal = normalize(world_normal);
+    output.world_tangent = vec4<f32>(normalize(world_tangent), input.tangent.w);
+    output.uv = input.uv;
     output.color = input.color;
     return output;
 }
 
-@fragment
-fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    let light_dir = normalize(globals.light_pos.xyz - input.world_pos);
-    let view_dir = normalize(-input.world_pos);
+fn surface_normal(input: VertexOutput) -> vec3<f32> {
+    var normal = normalize(input.world_normal);
+    if globals.normal_mapping != 0u {
+        // Базис TBN восстанавливается после интерполяции
+        let tangent = normalize(input.world_tangent.xyz - normal * dot(normal, input.world_tangent.xyz));
+        let bitangent = cross(normal, tangent) * input.world_tangent.w;
+        let sampled = textureSample(normal_map, normal_sampler, input.uv).xyz * 2.0 - 1.0;
+        normal = normalize(mat3x3<f32>(tangent, bitangent, normal) * sampled);
+    }
+    return normal;
+}
+
+// Освещение общее для прямого и отложенного рендера:
+// основной источник по Блинну-Фонгу плюс список точечных источников
+fn shade(albedo: vec3<f32>, normal: vec3<f32>, world_pos: vec3<f32>, specular_power: f32, specular_intensity: f32) -> vec3<f32> {
+    let light_dir = normalize(globals.light_pos.xyz - world_pos);
+    let view_dir = normalize(-world_pos);
     let half_dir = normalize(light_dir + view_dir);
     
-    let diffuse = max(dot(input.world_normal, light_dir), 0.0);
-    let specular = pow(max(dot(input.world_normal, half_dir), 0.0), globals.specular_power);
+    let diffuse = max(dot(normal, light_dir), 0.0);
+    let specular = pow(max(dot(normal, half_dir), 0.0), specular_power);
     
     let ambient_term = globals.ambient.xyz;
-    let diffuse_term = diffuse * globals.light_color.xyz;
-    let specular_term = specular * globals.specular_intensity * globals.light_color.xyz;
+    var diffuse_term = diffuse * globals.light_color.xyz;
+    var specular_term = specular * specular_intensity * globals.light_color.xyz;
+
+    for (var i = 0u; i < view_params.light_count; i += 1u) {
+        let light = lights[i];
+        let offset = light.position - world_pos;
+        let distance = length(offset);
+        if distance >= light.radius {
+            continue;
+        }
+        // Плавное затухание до нуля на границе радиуса
+        let falloff = 1.0 - (distance * distance) / (light.radius * light.radius);
+        let attenuation = falloff * falloff;
+        let point_dir = offset / max(distance, 1e-4);
+        let point_half = normalize(point_dir + view_dir);
+        diffuse_term += max(dot(normal, point_dir), 0.0) * attenuation * light.color;
+        specular_term += pow(max(dot(normal, point_half), 0.0), specular_power) * specular_intensity * attenuation * light.color;
+    }
 
-    let lit_color = input.color * (ambient_term + diffuse_term) + specular_term;
+    return albedo * (ambient_term + diffuse_term) + specular_term;
+}
 
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = surface_normal(input);
+    let lit_color = shade(input.color, normal, input.world_pos, globals.specular_power, globals.specular_intensity);
     return vec4<f32>(lit_color, 1.0);
-}
\ No newline at end of file
+}
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) material: vec4<f32>,
+    @location(3) depth: f32,
+};
+
+// Первый проход отложенного рендера: только свойства поверхности, без освещения
+@fragment
+fn fs_gbuffer(input: VertexOutput) -> GBufferOutput {
+    var output: GBufferOutput;
+    // Альфа отмечает покрытые геометрией пиксели
+    output.albedo = vec4<f32>(input.color, 1.0);
+    output.normal = vec4<f32>(surface_normal(input), 0.0);
+    output.material = vec4<f32>(globals.specular_power / MAX_SPECULAR_POWER, globals.specular_intensity, 0.0, 0.0);
+    output.depth = input.position.z;
+    return output;
+}
+
+// Треугольник на всю область вида без вершинного буфера
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Точка мира по пикселю окна и глубине в текущей области вида
+fn unproject(position: vec2<f32>, depth: f32) -> vec3<f32> {
+    let uv = (position - view_params.viewport.xy) / view_params.viewport.zw;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = view_params.inverse_view_projection * ndc;
+    return world.xyz / world.w;
+}
+
+// Второй проход: освещение по G-buffer либо отладочный вывод одного из каналов
+@fragment
+fn fs_lighting(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    let albedo = textureLoad(gbuffer_albedo, texel, 0);
+    if albedo.a == 0.0 {
+        discard;
+    }
+    let normal = textureLoad(gbuffer_normal, texel, 0).xyz;
+    let material = textureLoad(gbuffer_material, texel, 0);
+    let depth = textureLoad(gbuffer_depth, texel, 0).r;
+    let world_pos = unproject(position.xy, depth);
+
+    switch view_params.debug_view {
+        case 1u: {
+            return vec4<f32>(albedo.rgb, 1.0);
+        }
+        case 2u: {
+            return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+        }
+        case 3u: {
+            return vec4<f32>(material.rgb, 1.0);
+        }
+        case 4u: {
+            // Линейное расстояние вдоль луча относительно плоскости через центр сцены:
+            // перспектива и ортография читаются одинаково, ближе — светлее
+            let near = unproject(position.xy, 0.0);
+            let center_clip = globals.mvp_matrix * vec4<f32>(0.0, 0.0, 0.0, 1.0);
+            let center = unproject(position.xy, center_clip.z / center_clip.w);
+            let offset = length(world_pos - near) - length(center - near);
+            return vec4<f32>(vec3<f32>(clamp(0.5 - 0.5 * offset, 0.0, 1.0)), 1.0);
+        }
+        default: {
+            let lit_color = shade(albedo.rgb, normal, world_pos, material.r * MAX_SPECULAR_POWER, material.g);
+            return vec4<f32>(lit_color, 1.0);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Отложенный рендер: G-buffer и полноэкранный проход освещения.
+//!
+//! Первый проход записывает свойства поверхности в несколько целей,
+//! второй освещает каждый пиксель окна списком источников из
+//! [`crate::lights`]. Пайплайны строятся из `cube.wgsl`, поэтому формулы
+//! освещения те же, что и в прямом `fs_main`.
+
+use blade_graphics as gpu;
+
+const ALBEDO_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba8Unorm;
+const NORMAL_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba16Float;
+const MATERIAL_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba8Unorm;
+const DEPTH_FORMAT: gpu::TextureFormat = gpu::TextureFormat::R32Float;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Renderer {
+    /// Освещение прямо в `fs_main` при растеризации.
+    Forward,
+    /// G-buffer, затем освещение полноэкранным проходом.
+    Deferred,
+}
+
+impl Renderer {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Forward => Self::Deferred,
+            Self::Deferred => Self::Forward,
+        }
+    }
+}
+
+/// Что выводит проход освещения. Значение передаётся в `ViewParams::debug_view`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GBufferView {
+    Lit,
+    Albedo,
+    Normal,
+    Material,
+    Depth,
+}
+
+impl GBufferView {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Lit => Self::Albedo,
+            Self::Albedo => Self::Normal,
+            Self::Normal => Self::Material,
+            Self::Material => Self::Depth,
+            Self::Depth => Self::Lit,
+        }
+    }
+
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+#[derive(blade_macros::ShaderData)]
+struct GBufferData {
+    gbuffer_albedo: gpu::TextureView,
+    gbuffer_normal: gpu::TextureView,
+    gbuffer_material: gpu::TextureView,
+    gbuffer_depth: gpu::TextureView,
+}
+
+struct Target {
+    texture: gpu::Texture,
+    view: gpu::TextureView,
+}
+
+impl Target {
+    fn new(
+        context: &gpu::Context,
+        name: &str,
+        format: gpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let texture = context.create_texture(gpu::TextureDesc {
+            name,
+            format,
+            size: gpu::Extent {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth: 1,
+            },
+            dimension: gpu::TextureDimension::D2,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+            sample_count: 1,
+            external: None,
+        });
+        let view = context.create_texture_view(
+            texture,
+            gpu::TextureViewDesc {
+                name,
+                format,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &gpu::TextureSubresources::default(),
+            },
+        );
+        Self { texture, view }
+    }
+
+    fn destroy(&self, context: &gpu::Context) {
+        context.destroy_texture_view(self.view);
+        context.destroy_texture(self.texture);
+    }
+}
+
+/// Цели G-buffer размером с окно.
+struct GBuffer {
+    albedo: Target,
+    normal: Target,
+    material: Target,
+    depth: Target,
+}
+
+impl GBuffer {
+    fn new(context: &gpu::Context, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self {
+            albedo: Target::new(context, "gbuffer_albedo", ALBEDO_FORMAT, size),
+            normal: Target::new(context, "gbuffer_normal", NORMAL_FORMAT, size),
+            material: Target::new(context, "gbuffer_material", MATERIAL_FORMAT, size),
+            depth: Target::new(context, "gbuffer_depth", DEPTH_FORMAT, size),
+        }
+    }
+
+    fn targets(&self) -> [&Target; 4] {
+        [&self.albedo, &self.normal, &self.material, &self.depth]
+    }
+
+    fn destroy(&self, context: &gpu::Context) {
+        for target in self.targets() {
+            target.destroy(context);
+        }
+    }
+}
+
+pub struct Deferred {
+    pub renderer: Renderer,
+    pub debug_view: GBufferView,
+    gbuffer_pipeline: gpu::RenderPipeline,
+    lighting_pipeline: gpu::RenderPipeline,
+    gbuffer: GBuffer,
+}
+
+impl Deferred {
+    /// Геометрия G-buffer прохода совпадает с прямым пайплайном куба:
+    /// те же `globals_layout` и `depth_stencil`.
+    pub fn new(
+        context: &gpu::Context,
+        shader: &gpu::Shader,
+        globals_layout: &gpu::ShaderDataLayout,
+        depth_stencil: gpu::DepthStencilState,
+        color_format: gpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let target_state = |format| gpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: gpu::ColorWrites::ALL,
+        };
+        let depth_format = depth_stencil.format;
+        let gbuffer_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "gbuffer",
+            data_layouts: &[globals_layout],
+            vertex: shader.at("vs_main"),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &<crate::CubeVertex as gpu::Vertex>::layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_stencil),
+            fragment: Some(shader.at("fs_gbuffer")),
+            color_targets: &[
+                target_state(ALBEDO_FORMAT),
+                target_state(NORMAL_FORMAT),
+                target_state(MATERIAL_FORMAT),
+                target_state(DEPTH_FORMAT),
+            ],
+            multisample_state: gpu::MultisampleState::default(),
+        });
+
+        let gbuffer_layout = <GBufferData as gpu::ShaderData>::layout();
+        let lighting_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "deferred_lighting",
+            data_layouts: &[globals_layout, &gbuffer_layout],
+            vertex: shader.at("vs_fullscreen"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            // Проход освещения делит глубину с обводкой и прозрачными объектами,
+            // но сам её не проверяет и не меняет
+            depth_stencil: Some(gpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: gpu::CompareFunction::Always,
+                stencil: gpu::StencilState::default(),
+                bias: gpu::DepthBiasState::default(),
+            }),
+            fragment: Some(shader.at("fs_lighting")),
+            color_targets: &[target_state(color_format)],
+            multisample_state: gpu::MultisampleState::default(),
+        });
+
+        Self {
+            renderer: Renderer::Forward,
+            debug_view: GBufferView::Lit,
+            gbuffer_pipeline,
+            lighting_pipeline,
+            gbuffer: GBuffer::new(context, size),
+        }
+    }
+
+    pub fn resize(&mut self, context: &gpu::Context, size: winit::dpi::PhysicalSize<u32>) {
+        self.gbuffer.destroy(context);
+        self.gbuffer = GBuffer::new(context, size);
+    }
+
+    /// Проход G-buffer. `draw` рисует геометрию всех видов выданным пайплайном.
+    pub fn render_gbuffer(
+        &self,
+        encoder: &mut gpu::CommandEncoder,
+        depth_view: gpu::TextureView,
+        draw: impl FnOnce(&mut gpu::RenderCommandEncoder, &gpu::RenderPipeline),
+    ) {
+        for target in self.gbuffer.targets() {
+            encoder.init_texture(target.texture);
+        }
+        let clear = |view, color| gpu::RenderTarget {
+            view,
+            init_op: gpu::InitOp::Clear(color),
+            finish_op: gpu::FinishOp::Store,
+        };
+        let mut pass = encoder.render(
+            "gbuffer",
+            gpu::RenderTargetSet {
+                colors: &[
+                    // Прозрачная альфа отмечает пиксели фона
+                    clear(
+                        self.gbuffer.albedo.view,
+                        gpu::TextureColor::TransparentBlack,
+                    ),
+                    clear(
+                        self.gbuffer.normal.view,
+                        gpu::TextureColor::TransparentBlack,
+                    ),
+                    clear(
+                        self.gbuffer.material.view,
+                        gpu::TextureColor::TransparentBlack,
+                    ),
+                    clear(self.gbuffer.depth.view, gpu::TextureColor::White),
+                ],
+                depth_stencil: Some(clear(depth_view, gpu::TextureColor::White)),
+            },
+        );
+        draw(&mut pass, &self.gbuffer_pipeline);
+    }
+
+    /// Освещает текущую область вида по G-buffer.
+    /// `globals` — те же данные, что получает пайплайн прямого рендера.
+    pub fn draw_lighting<D: gpu::ShaderData>(
+        &self,
+        pass: &mut gpu::RenderCommandEncoder,
+        globals: &D,
+    ) {
+        let mut pc = pass.with(&self.lighting_pipeline);
+        pc.bind(0, globals);
+        pc.bind(
+            1,
+            &GBufferData {
+                gbuffer_albedo: self.gbuffer.albedo.view,
+                gbuffer_normal: self.gbuffer.normal.view,
+                gbuffer_material: self.gbuffer.material.view,
+                gbuffer_depth: self.gbuffer.depth.view,
+            },
+        );
+        pc.draw(0, 3, 0, 1);
+    }
+
+    pub fn destroy(&mut self, context: &gpu::Context) {
+        context.destroy_render_pipeline(&mut self.gbuffer_pipeline);
+        context.destroy_render_pipeline(&mut self.lighting_pipeline);
+        self.gbuffer.destroy(context);
+    }
+}
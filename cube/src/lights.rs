@@ -0,0 +1,81 @@
+//! Набор точечных источников света вокруг сцены.
+//!
+//! Список загружается в storage-буфер каждый кадр и используется
+//! одинаково прямым и отложенным рендером, чтобы их можно было сравнивать.
+
+use blade_graphics as gpu;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+pub const LIGHT_COUNT: usize = 256;
+/// Радиус действия одного источника.
+const LIGHT_RADIUS: f32 = 0.7;
+/// Яркость одного источника: сотни огней не должны пересвечивать сцену.
+const LIGHT_INTENSITY: f32 = 0.5;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct PointLight {
+    position: [f32; 3],
+    radius: f32,
+    color: [f32; 3],
+    _pad: f32,
+}
+
+/// Насыщенный цвет по оттенку из [0, 1).
+fn hue_to_rgb(hue: f32) -> Vec3 {
+    let h = hue * 6.0;
+    let r = (h - 3.0).abs() - 1.0;
+    let g = 2.0 - (h - 2.0).abs();
+    let b = 2.0 - (h - 4.0).abs();
+    Vec3::new(r, g, b).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+pub struct Lights {
+    belt: blade_util::BufferBelt,
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Self {
+            belt: blade_util::BufferBelt::new(blade_util::BufferBeltDescriptor {
+                memory: gpu::Memory::Shared,
+                min_chunk_size: (LIGHT_COUNT * std::mem::size_of::<PointLight>()) as u64,
+                alignment: gpu::limits::STORAGE_BUFFER_ALIGNMENT,
+            }),
+        }
+    }
+
+    /// Расставляет источники по сферической спирали, вращающейся со временем,
+    /// и загружает их на GPU.
+    pub fn upload(&mut self, context: &gpu::Context, elapsed: f32) -> gpu::BufferPiece {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+        let lights = (0..LIGHT_COUNT)
+            .map(|i| {
+                let t = (i as f32 + 0.5) / LIGHT_COUNT as f32;
+                let y = 1.0 - 2.0 * t;
+                let ring = (1.0 - y * y).sqrt();
+                // Соседние источники вращаются с разной скоростью и на разной высоте
+                let shell = 0.9 + 0.5 * (i as f32 * 0.618).fract();
+                let speed = 0.2 + 0.15 * (i % 3) as f32;
+                let angle = i as f32 * golden_angle + elapsed * speed;
+                let position = shell * Vec3::new(ring * angle.cos(), y, ring * angle.sin());
+                PointLight {
+                    position: position.to_array(),
+                    radius: LIGHT_RADIUS,
+                    color: (hue_to_rgb((i as f32 * 0.618).fract()) * LIGHT_INTENSITY).to_array(),
+                    _pad: 0.0,
+                }
+            })
+            .collect::<Vec<_>>();
+        self.belt.alloc_pod(&lights, context)
+    }
+
+    pub fn flush(&mut self, sync_point: &gpu::SyncPoint) {
+        self.belt.flush(sync_point);
+    }
+
+    pub fn destroy(&mut self, context: &gpu::Context) {
+        self.belt.destroy(context);
+    }
+}
@@ -21,7 +21,7 @@ macro_rules! depth {
     (desc $size:expr) => {
         gpu::TextureDesc {
             name: "depth_texture",
-            format: Depth32Float,
+            format: DEPTH_FORMAT,
             size: gpu::Extent {
                 width: $size.width,
                 height: $size.height,
@@ -40,7 +40,7 @@ macro_rules! depth {
     (view_desc) => {
         gpu::TextureViewDesc {
             name: "depth_view",
-            format: gpu::TextureFormat::Depth32Float,
+            format: DEPTH_FORMAT,
             dimension: gpu::ViewDimension::D2,
             subresources: &gpu::TextureSubresources::default(),
         }
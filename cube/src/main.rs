@@ -1,61 +1,75 @@
 #![windows_subsystem = "windows"]
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{Window, WindowAttributes, WindowId};
 
-use blade_graphics::{self as gpu, Vertex, TextureFormat::Depth32Float};
-use blade_util::create_static_buffer;
+use blade_graphics::{self as gpu, Vertex};
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 use std::time::Instant;
 
 use blade_graphics::background_color_vulkan::set_background_color;
 
 #[macro_use]
 mod macros;
-
-// Вершинные данные с нормалями
-#[repr(C)]
-#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+mod deferred;
+mod lights;
+mod mesh;
+mod normal_map;
+mod stats;
+mod transparency;
+mod views;
+
+use deferred::{Deferred, Renderer};
+use lights::{LIGHT_COUNT, Lights};
+use mesh::Mesh;
+use normal_map::NormalMap;
+use stats::FrameStats;
+use transparency::{Transparency, TransparencyMode};
+use views::{Cameras, Layout, ViewKind, ViewRect};
+
+// Вершинные данные с нормалями и текстурными координатами
+#[derive(Clone, Copy, Debug)]
 struct VertexData {
     pos: [f32; 3],
     normal: [f32; 3],
     color: [f32; 3],
+    uv: [f32; 2],
 }
 
 const VERTICES: &[VertexData] = &[
     // Front face (+Z) - красный
-    VertexData { pos: [-0.5, -0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0] },
-    VertexData { pos: [ 0.5, -0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0] },
-    VertexData { pos: [ 0.5,  0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0] },
-    VertexData { pos: [-0.5,  0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0] },
+    VertexData { pos: [-0.5, -0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0], uv: [0.0, 1.0] },
+    VertexData { pos: [ 0.5, -0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0], uv: [1.0, 1.0] },
+    VertexData { pos: [ 0.5,  0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0], uv: [1.0, 0.0] },
+    VertexData { pos: [-0.5,  0.5,  0.5], normal: [ 0.0,  0.0,  1.0], color: [1.0, 0.0, 0.0], uv: [0.0, 0.0] },
     // Back face (-Z) - зеленый
-    VertexData { pos: [ 0.5, -0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0] },
-    VertexData { pos: [-0.5, -0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0] },
-    VertexData { pos: [-0.5,  0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0] },
-    VertexData { pos: [ 0.5,  0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0] },
+    VertexData { pos: [ 0.5, -0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0], uv: [0.0, 1.0] },
+    VertexData { pos: [-0.5, -0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0], uv: [1.0, 1.0] },
+    VertexData { pos: [-0.5,  0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0], uv: [1.0, 0.0] },
+    VertexData { pos: [ 0.5,  0.5, -0.5], normal: [ 0.0,  0.0, -1.0], color: [0.0, 1.0, 0.0], uv: [0.0, 0.0] },
     // Left face (-X) - синий
-    VertexData { pos: [-0.5, -0.5, -0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0] },
-    VertexData { pos: [-0.5, -0.5,  0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0] },
-    VertexData { pos: [-0.5,  0.5,  0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0] },
-    VertexData { pos: [-0.5,  0.5, -0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0] },
+    VertexData { pos: [-0.5, -0.5, -0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0], uv: [0.0, 1.0] },
+    VertexData { pos: [-0.5, -0.5,  0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0], uv: [1.0, 1.0] },
+    VertexData { pos: [-0.5,  0.5,  0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0], uv: [1.0, 0.0] },
+    VertexData { pos: [-0.5,  0.5, -0.5], normal: [-1.0,  0.0,  0.0], color: [0.0, 0.0, 1.0], uv: [0.0, 0.0] },
     // Right face (+X) - желтый
-    VertexData { pos: [ 0.5, -0.5,  0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0] },
-    VertexData { pos: [ 0.5, -0.5, -0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0] },
-    VertexData { pos: [ 0.5,  0.5, -0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0] },
-    VertexData { pos: [ 0.5,  0.5,  0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0] },
+    VertexData { pos: [ 0.5, -0.5,  0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0], uv: [0.0, 1.0] },
+    VertexData { pos: [ 0.5, -0.5, -0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0], uv: [1.0, 1.0] },
+    VertexData { pos: [ 0.5,  0.5, -0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0], uv: [1.0, 0.0] },
+    VertexData { pos: [ 0.5,  0.5,  0.5], normal: [ 1.0,  0.0,  0.0], color: [1.0, 1.0, 0.0], uv: [0.0, 0.0] },
     // Top face (+Y) - голубой
-    VertexData { pos: [-0.5,  0.5, -0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0] },
-    VertexData { pos: [-0.5,  0.5,  0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0] },
-    VertexData { pos: [ 0.5,  0.5,  0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0] },
-    VertexData { pos: [ 0.5,  0.5, -0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0] },
+    VertexData { pos: [-0.5,  0.5, -0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0], uv: [0.0, 1.0] },
+    VertexData { pos: [-0.5,  0.5,  0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0], uv: [1.0, 1.0] },
+    VertexData { pos: [ 0.5,  0.5,  0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0], uv: [1.0, 0.0] },
+    VertexData { pos: [ 0.5,  0.5, -0.5], normal: [ 0.0,  1.0,  0.0], color: [0.0, 1.0, 1.0], uv: [0.0, 0.0] },
     // Bottom face (-Y) - пурпурный
-    VertexData { pos: [-0.5, -0.5,  0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0] },
-    VertexData { pos: [-0.5, -0.5, -0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0] },
-    VertexData { pos: [ 0.5, -0.5, -0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0] },
-    VertexData { pos: [ 0.5, -0.5,  0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0] },
+    VertexData { pos: [-0.5, -0.5,  0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0], uv: [0.0, 1.0] },
+    VertexData { pos: [-0.5, -0.5, -0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0], uv: [1.0, 1.0] },
+    VertexData { pos: [ 0.5, -0.5, -0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0], uv: [1.0, 0.0] },
+    VertexData { pos: [ 0.5, -0.5,  0.5], normal: [ 0.0, -1.0,  0.0], color: [1.0, 0.0, 1.0], uv: [0.0, 0.0] },
 ];
 
 const INDICES: &[u16] = &[
@@ -73,9 +87,16 @@ const INDICES: &[u16] = &[
     20, 21, 22, 22, 23, 20,
 ];
 
+// Глубина с трафаретом: выделенные объекты помечают свои пиксели для обводки
+const DEPTH_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Depth32FloatStencil8Uint;
+// Значение трафарета для пикселей выделенных объектов
+const SELECTION_STENCIL: u32 = 1;
+// Масштаб копии объекта, из которой получается обводка
+const OUTLINE_SCALE: f32 = 1.08;
+
 // Uniform-данные с параметрами света
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, blade_macros::ShaderStruct)]
 struct Globals {
     mvp_matrix: [[f32; 4]; 4],
     model_matrix: [[f32; 4]; 4],
@@ -84,26 +105,88 @@ struct Globals {
     ambient: [f32; 4],
     specular_power: f32,
     specular_intensity: f32,
-    _pad: [f32; 2],
+    normal_mapping: u32,
+    // Добивка до размера структуры в шейдере, поля с `_` не сверяются
+    _pad: u32,
+}
+
+// Параметры отложенного освещения для вида. Отдельный uniform,
+// чтобы каждый остался в пределах `gpu::limits::PLAIN_DATA_SIZE`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, blade_macros::ShaderStruct)]
+struct ViewParams {
+    inverse_view_projection: [[f32; 4]; 4],
+    viewport: [f32; 4],
+    light_count: u32,
+    debug_view: u32,
+    _pad: [u32; 2],
 }
 
 #[derive(blade_macros::ShaderData)]
 struct CubeUniforms {
     globals: Globals,
+    view_params: ViewParams,
+    lights: gpu::BufferPiece,
+    normal_map: gpu::TextureView,
+    normal_sampler: gpu::Sampler,
+}
+
+// Uniform-данные обводки выделения
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, blade_macros::ShaderStruct)]
+struct Outline {
+    mvp_matrix: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+#[derive(blade_macros::ShaderData)]
+struct OutlineUniforms {
+    outline: Outline,
 }
 
-#[derive(blade_macros::Vertex)]
+// Вершина на GPU: касательные достраиваются из VERTICES при запуске
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, blade_macros::Vertex)]
 struct CubeVertex {
     pos: [f32; 3],
     normal: [f32; 3],
     color: [f32; 3],
+    uv: [f32; 2],
+    tangent: [f32; 4],
+}
+
+fn cube_vertices() -> Vec<CubeVertex> {
+    let positions = VERTICES.iter().map(|v| v.pos).collect::<Vec<_>>();
+    let normals = VERTICES.iter().map(|v| v.normal).collect::<Vec<_>>();
+    let uvs = VERTICES.iter().map(|v| v.uv).collect::<Vec<_>>();
+    let tangents = mesh::generate_tangents(&positions, &normals, &uvs, INDICES);
+    VERTICES
+        .iter()
+        .zip(tangents)
+        .map(|(v, tangent)| CubeVertex {
+            pos: v.pos,
+            normal: v.normal,
+            color: v.color,
+            uv: v.uv,
+            tangent,
+        })
+        .collect()
 }
 
 // Состояние приложения
 struct AppState {
     light_pos: Vec3,
-    camera_pos: Vec3,
+    cameras: Cameras,
+    layout: Layout,
     cube_rotation: f32,
+    selected: bool,
+    normal_mapping: bool,
+    // Основной куб рисуется полупрозрачным вместе с кольцом
+    glass_cube: bool,
+    // Последняя позиция курсора в пикселях окна
+    cursor: Option<Vec2>,
+    // Вид, захваченный перетаскиванием мышью
+    drag: Option<(ViewKind, ViewRect)>,
 }
 
 // Основная структура приложения
@@ -111,9 +194,14 @@ struct CubeApp {
     context: gpu::Context,
     surface: gpu::Surface,
     pipeline: gpu::RenderPipeline,
+    outline_pipeline: gpu::RenderPipeline,
     command_encoder: gpu::CommandEncoder,
-    vertex_buf: gpu::Buffer,
-    index_buf: gpu::Buffer,
+    mesh: Mesh,
+    normal_map: NormalMap,
+    transparency: Transparency,
+    deferred: Deferred,
+    lights: Lights,
+    stats: FrameStats,
     depth_texture: gpu::Texture,
     depth_view: gpu::TextureView,
     window_size: winit::dpi::PhysicalSize<u32>,
@@ -144,8 +232,14 @@ impl CubeApp {
             presentation: true,
             overlay: false,
             capture: false,
-            timing: false,
+            timing: true,
             device_id: 0,
+            // Запись трассы вызовов API, если задана переменная окружения
+            trace_path: std::env::var_os("BLADE_TRACE").map(Into::into),
+            // В отладочной сборке забытые `destroy_*` попадут в отчёт об утечках
+            track_resources: cfg!(debug_assertions),
+            // Скомпилированные шейдеры и конвейеры переживают перезапуск
+            pipeline_cache_path: Some(std::env::temp_dir().join("blade-cube-cache")),
         }).unwrap();
 
         let window_size = window.inner_size();
@@ -158,19 +252,33 @@ impl CubeApp {
         let shader_source = include_str!("cube.wgsl").to_string();
         let shader = context.create_shader(gpu::ShaderDesc {
             source: &shader_source,
+            ..Default::default()
         });
 
         let uniform_layout = <CubeUniforms as gpu::ShaderData>::layout();
 
-        let vertex_buf = create_static_buffer(&context, "cube_vertex", VERTICES);
-        context.sync_buffer(vertex_buf);
-
-        let index_buf = create_static_buffer(&context, "cube_index", INDICES);
-        context.sync_buffer(index_buf);
+        let mesh = Mesh::new(&context, "cube", &cube_vertices(), INDICES);
 
         let (depth_texture, depth_view) = depth!(create context, window_size);
 
         // Пайплайн
+        let mark_stencil = gpu::StencilFaceState {
+            pass_op: gpu::StencilOperation::Replace,
+            ..gpu::StencilFaceState::IGNORE
+        };
+        let opaque_depth_stencil = gpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: gpu::CompareFunction::Less,
+            // Каждый нарисованный пиксель получает значение из set_stencil_reference
+            stencil: gpu::StencilState {
+                front: mark_stencil,
+                back: mark_stencil,
+                read_mask: 0xFF,
+                write_mask: 0xFF,
+            },
+            bias: gpu::DepthBiasState::default(),
+        };
         let pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
             name: "cube",
             data_layouts: &[&uniform_layout],
@@ -185,14 +293,63 @@ impl CubeApp {
                 cull_mode: Some(gpu::Face::Back),
                 ..Default::default()
             },
+            depth_stencil: Some(opaque_depth_stencil.clone()),
+            fragment: Some(shader.at("fs_main")),
+            color_targets: &[gpu::ColorTargetState {
+                format: surface.info().format,
+                blend: None,
+                write_mask: gpu::ColorWrites::ALL,
+            }],
+            multisample_state: gpu::MultisampleState::default(),
+        });
+        let deferred = Deferred::new(
+            &context,
+            &shader,
+            &uniform_layout,
+            opaque_depth_stencil,
+            surface.info().format,
+            window_size,
+        );
+
+        // Обводка: увеличенная копия рисуется поверх всего,
+        // но только там, где трафарет не помечен самим объектом
+        let outline_source = include_str!("outline.wgsl").to_string();
+        let outline_shader = context.create_shader(gpu::ShaderDesc {
+            source: &outline_source,
+            ..Default::default()
+        });
+        let outline_layout = <OutlineUniforms as gpu::ShaderData>::layout();
+        let outside_stencil = gpu::StencilFaceState {
+            compare: gpu::CompareFunction::NotEqual,
+            ..gpu::StencilFaceState::IGNORE
+        };
+        let outline_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "outline",
+            data_layouts: &[&outline_layout],
+            vertex: outline_shader.at("vs_outline"),
+            vertex_fetches: &[gpu::VertexFetchState {
+                layout: &CubeVertex::layout(),
+                instanced: false,
+            }],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: Some(gpu::Face::Back),
+                ..Default::default()
+            },
             depth_stencil: Some(gpu::DepthStencilState {
-                format: gpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: gpu::CompareFunction::Less,
-                stencil: gpu::StencilState::default(),
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: gpu::CompareFunction::Always,
+                stencil: gpu::StencilState {
+                    front: outside_stencil,
+                    back: outside_stencil,
+                    read_mask: 0xFF,
+                    write_mask: 0,
+                },
                 bias: gpu::DepthBiasState::default(),
             }),
-            fragment: Some(shader.at("fs_main")),
+            fragment: Some(outline_shader.at("fs_outline")),
             color_targets: &[gpu::ColorTargetState {
                 format: surface.info().format,
                 blend: None,
@@ -201,22 +358,42 @@ impl CubeApp {
             multisample_state: gpu::MultisampleState::default(),
         });
 
-        let command_encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
+        let transparency = Transparency::new(&context, surface.info().format, DEPTH_FORMAT, window_size);
+
+        let mut command_encoder = context.create_command_encoder(gpu::CommandEncoderDesc {
             name: "main",
             buffer_count: 2,
+            ..Default::default()
         });
+        let normal_map = NormalMap::new(&context, &mut command_encoder);
 
         // Начальное состояние
         let state = AppState {
             light_pos: Vec3::new(2.0, 3.0, 2.0),
-            camera_pos: Vec3::new(1.9, 2.0, 1.9),
+            cameras: Cameras::new(Vec3::new(1.9, 2.0, 1.9)),
+            layout: Layout::Single,
             cube_rotation: 0.0,
+            selected: false,
+            normal_mapping: true,
+            glass_cube: false,
+            cursor: None,
+            drag: None,
         };
 
         println!("=== Управление источником света ===");
         println!("WASD: движение по X/Z (плоскость)");
         println!("Q/E: вверх/вниз");
         println!("R: сброс позиции");
+        println!("Пробел: выделение куба (обводка)");
+        println!("N: карта нормалей вкл/выкл");
+        println!("F: прямой / отложенный рендер ({} источников)", LIGHT_COUNT);
+        println!("G: канал G-buffer для отладки (освещение, альбедо, нормали, материал, глубина)");
+        println!("T: прозрачность сортировкой / weighted blended OIT");
+        println!("C: стеклянный куб вкл/выкл");
+        println!("=== Камеры ===");
+        println!("V: один вид / четыре вида (сверху, спереди, сбоку, перспектива)");
+        println!("ЛКМ + движение: вращение перспективы или сдвиг ортографического вида");
+        println!("Колесо мыши: масштаб вида под курсором");
         println!("Начальная позиция света: {:?}", state.light_pos);
 
         // Мой костыль. Цвет фона
@@ -226,9 +403,14 @@ impl CubeApp {
             context,
             surface,
             pipeline,
+            outline_pipeline,
             command_encoder,
-            vertex_buf,
-            index_buf,
+            mesh,
+            normal_map,
+            transparency,
+            deferred,
+            lights: Lights::new(),
+            stats: FrameStats::new(),
             depth_texture,
             depth_view,
             window_size,
@@ -253,6 +435,55 @@ impl CubeApp {
                 self.state.light_pos = Vec3::new(2.0, 3.0, 2.0);
                 println!("Сброс позиции света");
             }
+            KeyCode::Space => {
+                self.state.selected = !self.state.selected;
+                println!("Куб выделен: {}", self.state.selected);
+                return;
+            }
+            KeyCode::KeyF => {
+                self.deferred.renderer = self.deferred.renderer.toggle();
+                println!("Рендер: {:?}", self.deferred.renderer);
+                return;
+            }
+            KeyCode::KeyG => {
+                self.deferred.debug_view = self.deferred.debug_view.next();
+                println!("Вывод G-buffer: {:?}", self.deferred.debug_view);
+                if self.deferred.renderer == Renderer::Forward {
+                    println!("(виден только в отложенном рендере, F)");
+                }
+                return;
+            }
+            KeyCode::KeyN => {
+                self.state.normal_mapping = !self.state.normal_mapping;
+                println!("Карта нормалей: {}", self.state.normal_mapping);
+                return;
+            }
+            KeyCode::KeyT => {
+                self.transparency.mode = self.transparency.mode.toggle();
+                println!("Прозрачность: {:?}", self.transparency.mode);
+                return;
+            }
+            KeyCode::KeyC => {
+                self.state.glass_cube = !self.state.glass_cube;
+                println!("Стеклянный куб: {}", self.state.glass_cube);
+                return;
+            }
+            KeyCode::KeyV => {
+                self.state.layout = self.state.layout.toggle();
+                self.state.drag = None;
+                println!("Раскладка видов: {:?}", self.state.layout);
+                return;
+            }
+            KeyCode::KeyP => {
+                // Открывается в chrome://tracing или Perfetto
+                let path = "cube-trace.json";
+                let trace = self.command_encoder.timings().to_chrome_trace();
+                match std::fs::write(path, trace) {
+                    Ok(()) => println!("Времена кадра записаны в {}", path),
+                    Err(e) => println!("Не удалось записать {}: {}", path, e),
+                }
+                return;
+            }
             _ => return,
         }
         
@@ -262,6 +493,42 @@ impl CubeApp {
             self.state.light_pos.z);
     }
 
+    fn handle_cursor_moved(&mut self, position: winit::dpi::PhysicalPosition<f64>) {
+        let cursor = Vec2::new(position.x as f32, position.y as f32);
+        if let (Some(prev), Some((kind, rect))) = (self.state.cursor, self.state.drag) {
+            self.state.cameras.drag(kind, rect, cursor - prev);
+        }
+        self.state.cursor = Some(cursor);
+    }
+
+    fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        if button != MouseButton::Left {
+            return;
+        }
+        // Вид захватывается при нажатии, чтобы перетаскивание
+        // не перескакивало в соседний квадрант
+        self.state.drag = match (state, self.state.cursor) {
+            (ElementState::Pressed, Some(cursor)) => self.view_at(cursor),
+            _ => None,
+        };
+    }
+
+    fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta) {
+        let steps = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+        };
+        if let Some((kind, _)) = self.state.cursor.and_then(|cursor| self.view_at(cursor)) {
+            self.state.cameras.zoom(kind, steps);
+        }
+    }
+
+    fn view_at(&self, cursor: Vec2) -> Option<(ViewKind, ViewRect)> {
+        self.state
+            .layout
+            .view_at(self.window_size, cursor.x as f64, cursor.y as f64)
+    }
+
     fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
         self.window_size = size;
 
@@ -270,6 +537,8 @@ impl CubeApp {
         self.context.destroy_texture(self.depth_texture);
 
         (self.depth_texture, self.depth_view) = depth!(create self.context, size);
+        self.transparency.resize(&self.context, size);
+        self.deferred.resize(&self.context, size);
 
         let config = Self::make_surface_config(size);
         self.context.reconfigure_surface(&mut self.surface, config);
@@ -284,38 +553,79 @@ impl CubeApp {
         let elapsed = self.start_time.elapsed().as_secs_f32();
         self.state.cube_rotation = elapsed * 0.5;
         
-        // Матрицы трансформации
-        let aspect = self.window_size.width as f32 / self.window_size.height as f32;
-        let projection = Mat4::perspective_rh(45.0_f32.to_radians(), aspect, 0.1, 100.0);
-        let view = Mat4::look_at_rh(
-            self.state.camera_pos,
-            Vec3::ZERO,
-            Vec3::Y,
-        );
+        // Матрица модели общая для всех видов
         let model = Mat4::from_rotation_y(self.state.cube_rotation);
-        let mvp = projection * view * model;
-
-        // Параметры освещения (позицию можжно изменять с клавиатуры)
-        let uniforms = CubeUniforms {
-            globals: Globals {
-                mvp_matrix: mvp.to_cols_array_2d(),
-                model_matrix: model.to_cols_array_2d(),
-                light_pos: [self.state.light_pos.x, self.state.light_pos.y, self.state.light_pos.z, 1.0],
-                light_color: [1.5, 1.5, 1.5, 1.0],
-                ambient: [0.2, 0.2, 0.2, 1.0],
-                specular_power: 8.0,
-                specular_intensity: 0.2,
-                _pad: [0.0; 2],
-            },
-        };
+        let views = self.state.layout.views(self.window_size);
+        let glass_cube = self.state.glass_cube;
+        self.transparency
+            .update(elapsed, self.state.light_pos, glass_cube.then_some(self.state.cube_rotation));
+        let lights = self.lights.upload(&self.context, elapsed);
+        // Стеклянный куб не попадает в непрозрачную геометрию, поэтому G-buffer не нужен
+        let use_gbuffer = self.deferred.renderer == Renderer::Deferred && !glass_cube;
+        // Невыделенные объекты сбрасывают метку в трафарете
+        let stencil_reference = if self.state.selected { SELECTION_STENCIL } else { 0 };
+
+        // Параметры каждого вида общие для G-buffer и основного прохода
+        let view_data = views
+            .iter()
+            .map(|&(kind, rect)| {
+                let view_projection = self.state.cameras.view_projection(kind, rect.aspect());
+                let mvp = view_projection * model;
+
+                // Параметры освещения (позицию можжно изменять с клавиатуры)
+                let uniforms = CubeUniforms {
+                    globals: Globals {
+                        mvp_matrix: mvp.to_cols_array_2d(),
+                        model_matrix: model.to_cols_array_2d(),
+                        light_pos: [self.state.light_pos.x, self.state.light_pos.y, self.state.light_pos.z, 1.0],
+                        light_color: [1.5, 1.5, 1.5, 1.0],
+                        ambient: [0.2, 0.2, 0.2, 1.0],
+                        specular_power: 8.0,
+                        specular_intensity: 0.2,
+                        normal_mapping: self.state.normal_mapping as u32,
+                        _pad: 0,
+                    },
+                    view_params: ViewParams {
+                        inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+                        viewport: [rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32],
+                        light_count: LIGHT_COUNT as u32,
+                        debug_view: self.deferred.debug_view.index(),
+                        _pad: [0; 2],
+                    },
+                    lights,
+                    normal_map: self.normal_map.view,
+                    normal_sampler: self.normal_map.sampler,
+                };
+                (rect, view_projection, uniforms)
+            })
+            .collect::<Vec<_>>();
 
         // Рендер
         let frame = self.surface.acquire_frame();
 
         self.command_encoder.start();
+        self.stats.record(
+            format!("{:?}, источников: {}", self.deferred.renderer, LIGHT_COUNT),
+            self.command_encoder.timings(),
+            self.command_encoder.stats(),
+        );
         self.command_encoder.init_texture(frame.texture());
         self.command_encoder.init_texture(self.depth_texture);
 
+        if use_gbuffer {
+            let mesh = &self.mesh;
+            self.deferred.render_gbuffer(&mut self.command_encoder, self.depth_view, |pass, pipeline| {
+                for (rect, _, uniforms) in view_data.iter() {
+                    pass.set_viewport(&rect.viewport());
+                    pass.set_scissor_rect(&rect.scissor());
+                    pass.set_stencil_reference(stencil_reference);
+                    let mut pc = pass.with(pipeline);
+                    pc.bind(0, uniforms);
+                    mesh.draw(&mut pc, 1);
+                }
+            });
+        }
+
         let mut pass = self.command_encoder.render(
             "cube",
             gpu::RenderTargetSet {
@@ -324,33 +634,83 @@ impl CubeApp {
                     init_op: gpu::InitOp::Clear(gpu::TextureColor::OpaqueBlack),
                     finish_op: gpu::FinishOp::Store,
                 }],
+                // После прохода G-buffer глубина и трафарет уже заполнены
                 depth_stencil: Some(gpu::RenderTarget {
                     view: self.depth_view,
-                    init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                    init_op: if use_gbuffer {
+                        gpu::InitOp::Load
+                    } else {
+                        gpu::InitOp::Clear(gpu::TextureColor::White)
+                    },
                     finish_op: gpu::FinishOp::Store,
                 }),
             },
         );
 
-        let mut rc = pass.with(&self.pipeline);
-        
-        rc.bind(0, &uniforms);
-        rc.bind_vertex(0, self.vertex_buf.at(0));
-        rc.draw_indexed(
-            self.index_buf.at(0),
-            gpu::IndexType::U16,
-            INDICES.len() as u32,
-            0,
-            0,
-            1,
-        );
-        
-        drop(rc);
+        // Сцена рисуется в каждую область окна со своей камерой
+        for (index, &(rect, view_projection, ref uniforms)) in view_data.iter().enumerate() {
+            pass.begin_scope(&format!("вид {}", index));
+            pass.set_viewport(&rect.viewport());
+            pass.set_scissor_rect(&rect.scissor());
+            pass.set_stencil_reference(stencil_reference);
+
+            if glass_cube {
+                // Куб рисуется ниже вместе с прозрачными экземплярами
+            } else if use_gbuffer {
+                self.deferred.draw_lighting(&mut pass, uniforms);
+            } else {
+                let mut rc = pass.with(&self.pipeline);
+                rc.bind(0, uniforms);
+                self.mesh.draw(&mut rc, 1);
+            }
+
+            // Обводке нужна метка трафарета, которую ставит только непрозрачный куб
+            if self.state.selected && !glass_cube {
+                let mvp = view_projection * model;
+                let outline = OutlineUniforms {
+                    outline: Outline {
+                        mvp_matrix: (mvp * Mat4::from_scale(Vec3::splat(OUTLINE_SCALE))).to_cols_array_2d(),
+                        color: [1.0, 0.6, 0.1, 1.0],
+                    },
+                };
+
+                let mut oc = pass.with(&self.outline_pipeline);
+                oc.set_stencil_reference(SELECTION_STENCIL);
+                oc.bind(0, &outline);
+                self.mesh.draw(&mut oc, 1);
+            }
+
+            if self.transparency.mode == TransparencyMode::Sorted {
+                self.transparency
+                    .draw_sorted(&self.context, &mut pass, &self.mesh, view_projection);
+            }
+            pass.end_scope();
+        }
+
         drop(pass);
 
+        // OIT накапливается в отдельных целях после всей непрозрачной геометрии
+        if self.transparency.mode == TransparencyMode::WeightedOit {
+            let oit_views = view_data
+                .iter()
+                .map(|&(rect, view_projection, _)| (rect, view_projection))
+                .collect::<Vec<_>>();
+            self.transparency.render_oit(
+                &self.context,
+                &mut self.command_encoder,
+                &self.mesh,
+                self.depth_view,
+                &oit_views,
+            );
+            self.transparency
+                .composite(&mut self.command_encoder, frame.texture_view());
+        }
+
         self.command_encoder.present(frame);
         let sync_point = self.context.submit(&mut self.command_encoder);
-        
+        self.transparency.flush(&sync_point);
+        self.lights.flush(&sync_point);
+
         if let Some(sp) = self.prev_sync_point.take() {
             self.context.wait_for(&sp, !0);
         }
@@ -362,31 +722,27 @@ impl CubeApp {
             self.context.wait_for(&sp, !0);
         }
 
-        self.context.destroy_buffer(self.vertex_buf);
-        self.context.destroy_buffer(self.index_buf);
+        self.mesh.destroy(&self.context);
+        self.normal_map.destroy(&self.context);
+        self.transparency.destroy(&self.context);
+        self.deferred.destroy(&self.context);
+        self.lights.destroy(&self.context);
         self.context.destroy_texture_view(self.depth_view);
         self.context.destroy_texture(self.depth_texture);
         self.context.destroy_command_encoder(&mut self.command_encoder);
         self.context.destroy_render_pipeline(&mut self.pipeline);
+        self.context.destroy_render_pipeline(&mut self.outline_pipeline);
         self.context.destroy_surface(&mut self.surface);
     }
 }
 
 // App структура для winit
+#[derive(Default)]
 struct App {
     window: Option<Window>,
     cube: Option<CubeApp>,
 }
 
-impl Default for App {
-    fn default() -> Self {
-        Self {
-            window: None,
-            cube: None,
-        }
-    }
-}
-
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_some() {
@@ -394,7 +750,7 @@ impl ApplicationHandler for App {
         }
 
         let window_attrs = WindowAttributes::default()
-            .with_title("Blade Cube - Movable Ligh (WASD+QE, R)")
+            .with_title("Blade Cube - Movable Ligh (WASD+QE, R), Views (V, mouse), Normal map (N), Transparency (T), Glass (C), Deferred (F, G)")
             .with_inner_size(winit::dpi::LogicalSize::new(500.0, 500.0));
         
         let window = event_loop
@@ -438,13 +794,23 @@ impl ApplicationHandler for App {
             WindowEvent::KeyboardInput {
                 event,
                 ..
-            } => {
-                if event.state == winit::event::ElementState::Pressed {
-                    if let PhysicalKey::Code(key_code) = event.physical_key {
-                        cube.handle_key(key_code);
-                    }
+            } if event.state == ElementState::Pressed => {
+                if let PhysicalKey::Code(key_code) = event.physical_key {
+                    cube.handle_key(key_code);
                 }
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                cube.handle_cursor_moved(position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                cube.state.cursor = None;
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                cube.handle_mouse_button(button, state);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                cube.handle_mouse_wheel(delta);
+            }
             WindowEvent::Resized(size) => {
                 cube.resize(size);
                 if let Some(window) = self.window.as_ref() {
@@ -0,0 +1,167 @@
+//! Индексированная геометрия в буферах GPU и генерация касательных.
+
+use blade_graphics as gpu;
+use glam::{Vec2, Vec3};
+use std::ops::Range;
+
+/// Вершинный и индексный буферы одного меша.
+#[derive(Clone, Copy, Debug)]
+pub struct Mesh {
+    pub vertex_buf: gpu::Buffer,
+    pub index_buf: gpu::Buffer,
+    pub index_count: u32,
+}
+
+impl Mesh {
+    pub fn new<V: bytemuck::Pod>(
+        context: &gpu::Context,
+        name: &str,
+        vertices: &[V],
+        indices: &[u16],
+    ) -> Self {
+        let vertex_buf =
+            blade_util::create_static_buffer(context, &format!("{name}_vertex"), vertices);
+        context.sync_buffer(vertex_buf);
+        let index_buf =
+            blade_util::create_static_buffer(context, &format!("{name}_index"), indices);
+        context.sync_buffer(index_buf);
+        Self {
+            vertex_buf,
+            index_buf,
+            index_count: indices.len() as u32,
+        }
+    }
+
+    /// Рисует `instance_count` копий меша; вершины привязываются к слоту 0.
+    pub fn draw(&self, pc: &mut gpu::PipelineEncoder, instance_count: u32) {
+        self.draw_indices(pc, 0..self.index_count, instance_count);
+    }
+
+    /// Рисует только треугольники из диапазона индексов `indices`.
+    pub fn draw_indices(
+        &self,
+        pc: &mut gpu::PipelineEncoder,
+        indices: Range<u32>,
+        instance_count: u32,
+    ) {
+        let index_size = std::mem::size_of::<u16>() as u64;
+        pc.bind_vertex(0, self.vertex_buf.at(0));
+        pc.draw_indexed(
+            self.index_buf.at(indices.start as u64 * index_size),
+            gpu::IndexType::U16,
+            indices.end - indices.start,
+            0,
+            0,
+            instance_count,
+        );
+    }
+
+    pub fn destroy(&self, context: &gpu::Context) {
+        context.destroy_buffer(self.vertex_buf);
+        context.destroy_buffer(self.index_buf);
+    }
+}
+
+/// Строит касательные для индексированного треугольного меша по позициям,
+/// нормалям и текстурным координатам (метод Ленгьеля).
+///
+/// Касательная ортогонализуется к нормали, а в `w` хранится знак
+/// бинормали: `bitangent = cross(normal, tangent.xyz) * tangent.w`.
+pub fn generate_tangents<I: Copy + Into<u32>>(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[I],
+) -> Vec<[f32; 4]> {
+    assert_eq!(positions.len(), normals.len());
+    assert_eq!(positions.len(), uvs.len());
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i].into() as usize);
+        let edge1 = Vec3::from(positions[b]) - Vec3::from(positions[a]);
+        let edge2 = Vec3::from(positions[c]) - Vec3::from(positions[a]);
+        let duv1 = Vec2::from(uvs[b]) - Vec2::from(uvs[a]);
+        let duv2 = Vec2::from(uvs[c]) - Vec2::from(uvs[a]);
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        // Вырожденная развёртка не даёт направления
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) / det;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / det;
+        for index in [a, b, c] {
+            tangents[index] += tangent;
+            bitangents[index] += bitangent;
+        }
+    }
+
+    normals
+        .iter()
+        .zip(tangents.iter().zip(bitangents.iter()))
+        .map(|(&normal, (&tangent, &bitangent))| {
+            let normal = Vec3::from(normal);
+            // Ортогонализация Грама-Шмидта; для вершин без развёртки подходит любой перпендикуляр
+            let tangent = (tangent - normal * normal.dot(tangent))
+                .try_normalize()
+                .unwrap_or_else(|| normal.any_orthonormal_vector());
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            tangent.extend(handedness).to_array()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate_tangents;
+    use glam::{Vec3, Vec4, Vec4Swizzles};
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [-1.0, -1.0, 0.0],
+        [1.0, -1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0],
+    ];
+    const NORMALS: [[f32; 3]; 4] = [[0.0, 0.0, 1.0]; 4];
+    const INDICES: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+    // Касательная квада в плоскости XY вдоль `expected`, ортогональна нормали
+    // и имеет знак бинормали `handedness`
+    fn check_quad(uvs: &[[f32; 2]; 4], expected: Vec3, handedness: f32) {
+        let tangents = generate_tangents(&POSITIONS, &NORMALS, uvs, &INDICES);
+        assert_eq!(tangents.len(), POSITIONS.len());
+        for (tangent, normal) in tangents.iter().zip(NORMALS) {
+            let tangent = Vec4::from(*tangent);
+            let normal = Vec3::from(normal);
+            assert!(
+                tangent.xyz().dot(normal).abs() < 1e-5,
+                "{tangent} is not orthogonal"
+            );
+            assert!((tangent.xyz().length() - 1.0).abs() < 1e-5);
+            assert!(
+                tangent.xyz().abs_diff_eq(expected, 1e-5),
+                "{tangent} != {expected}"
+            );
+            assert_eq!(tangent.w, handedness);
+        }
+    }
+
+    #[test]
+    fn quad_tangents() {
+        // U вправо, V вниз - бинормаль смотрит в -Y, против cross(N, T)
+        let uvs = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+        check_quad(&uvs, Vec3::X, -1.0);
+    }
+
+    #[test]
+    fn mirrored_quad_tangents() {
+        // Зеркальная по U развёртка разворачивает касательную и знак бинормали
+        let uvs = [[1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0]];
+        check_quad(&uvs, Vec3::NEG_X, 1.0);
+    }
+}
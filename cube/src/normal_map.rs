@@ -0,0 +1,113 @@
+//! Процедурная карта нормалей в касательном пространстве.
+//!
+//! Карта строится из поля высот (плитки со скошенными краями и заклёпкой
+//! в центре) и загружается на GPU один раз при запуске.
+
+use blade_graphics as gpu;
+use glam::Vec3;
+
+const SIZE: u32 = 256;
+const TILES: f32 = 2.0;
+/// Множитель наклона нормалей относительно градиента высоты по UV.
+const STRENGTH: f32 = 0.08;
+
+/// Высота рельефа в точке `(u, v)` из диапазона [0, 1).
+fn height(u: f32, v: f32) -> f32 {
+    let (x, y) = ((u * TILES).fract(), (v * TILES).fract());
+    // Фаска по краям плитки
+    let edge = x.min(1.0 - x).min(y).min(1.0 - y);
+    let bevel = (edge / 0.08).min(1.0);
+    // Полусферическая заклёпка в центре
+    let distance = ((x - 0.5).powi(2) + (y - 0.5).powi(2)).sqrt();
+    let rivet = (1.0 - (distance / 0.15).powi(2)).max(0.0).sqrt() * 0.5;
+    0.5 * bevel + rivet
+}
+
+/// Пиксели карты RGBA8: нормаль из [-1, 1] переведена в [0, 255].
+fn generate() -> Vec<[u8; 4]> {
+    let texel = 1.0 / SIZE as f32;
+    let mut pixels = Vec::with_capacity((SIZE * SIZE) as usize);
+    for row in 0..SIZE {
+        for column in 0..SIZE {
+            let u = (column as f32 + 0.5) * texel;
+            let v = (row as f32 + 0.5) * texel;
+            // Строки текстуры идут вдоль роста v, то есть вдоль бинормали
+            let du = (height((u + texel).fract(), v) - height((u - texel).rem_euclid(1.0), v))
+                / (2.0 * texel);
+            let dv = (height(u, (v + texel).fract()) - height(u, (v - texel).rem_euclid(1.0)))
+                / (2.0 * texel);
+            let normal = Vec3::new(-du * STRENGTH, -dv * STRENGTH, 1.0).normalize();
+            let encoded = (normal * 0.5 + 0.5) * 255.0;
+            pixels.push([encoded.x as u8, encoded.y as u8, encoded.z as u8, 255]);
+        }
+    }
+    pixels
+}
+
+pub struct NormalMap {
+    pub texture: gpu::Texture,
+    pub view: gpu::TextureView,
+    pub sampler: gpu::Sampler,
+}
+
+impl NormalMap {
+    /// Создаёт текстуру и дожидается окончания её загрузки.
+    pub fn new(context: &gpu::Context, encoder: &mut gpu::CommandEncoder) -> Self {
+        let format = gpu::TextureFormat::Rgba8Unorm;
+        let extent = gpu::Extent {
+            width: SIZE,
+            height: SIZE,
+            depth: 1,
+        };
+        let texture = context.create_texture(gpu::TextureDesc {
+            name: "normal_map",
+            format,
+            size: extent,
+            dimension: gpu::TextureDimension::D2,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            usage: gpu::TextureUsage::COPY | gpu::TextureUsage::RESOURCE,
+            sample_count: 1,
+            external: None,
+        });
+        let view = context.create_texture_view(
+            texture,
+            gpu::TextureViewDesc {
+                name: "normal_map",
+                format,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &gpu::TextureSubresources::default(),
+            },
+        );
+        let sampler = context.create_sampler(gpu::SamplerDesc {
+            name: "normal_map",
+            address_modes: [gpu::AddressMode::Repeat; 3],
+            mag_filter: gpu::FilterMode::Linear,
+            min_filter: gpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let staging = blade_util::create_static_buffer(context, "normal_map_staging", &generate());
+        encoder.start();
+        encoder.init_texture(texture);
+        {
+            let mut transfer = encoder.transfer("upload_normal_map");
+            transfer.copy_buffer_to_texture(staging.into(), SIZE * 4, texture.into(), extent);
+        }
+        let sync_point = context.submit(encoder);
+        context.wait_for(&sync_point, !0);
+        context.destroy_buffer(staging);
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    pub fn destroy(&self, context: &gpu::Context) {
+        context.destroy_sampler(self.sampler);
+        context.destroy_texture_view(self.view);
+        context.destroy_texture(self.texture);
+    }
+}
@@ -0,0 +1,21 @@
+struct Outline {
+    mvp_matrix: mat4x4<f32>,
+    color: vec4<f32>,
+};
+
+var<uniform> outline: Outline;
+
+struct OutlineInput {
+    pos: vec3<f32>,
+};
+
+// Увеличенная копия объекта, видимая только там, где трафарет не помечен
+@vertex
+fn vs_outline(input: OutlineInput) -> @builtin(position) vec4<f32> {
+    return outline.mvp_matrix * vec4<f32>(input.pos, 1.0);
+}
+
+@fragment
+fn fs_outline() -> @location(0) vec4<f32> {
+    return outline.color;
+}
@@ -0,0 +1,56 @@
+//! Средние времена кадра для сравнения режимов рендера.
+
+use blade_graphics as gpu;
+use std::time::{Duration, Instant};
+
+/// Как часто печатать накопленную статистику.
+const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct FrameStats {
+    label: String,
+    started: Instant,
+    frames: u32,
+    gpu_time: Duration,
+    barriers: u32,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            label: String::new(),
+            started: Instant::now(),
+            frames: 0,
+            gpu_time: Duration::ZERO,
+            barriers: 0,
+        }
+    }
+
+    /// Учитывает кадр с GPU-временами его проходов и числом барьеров.
+    /// При смене `label` накопленное сбрасывается, чтобы режимы не смешивались.
+    pub fn record(&mut self, label: String, timings: &gpu::Timings, stats: &gpu::CommandStats) {
+        if label != self.label {
+            *self = Self::new();
+            self.label = label;
+        }
+        self.frames += 1;
+        self.gpu_time += timings.gpu_total();
+        self.barriers += stats.barriers;
+
+        let elapsed = self.started.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return;
+        }
+        let frames = self.frames as f32;
+        println!(
+            "{}: {:.0} кадров/с ({:.2} мс), GPU {:.2} мс/кадр, барьеров {:.1}/кадр",
+            self.label,
+            frames / elapsed.as_secs_f32(),
+            elapsed.as_secs_f32() * 1000.0 / frames,
+            self.gpu_time.as_secs_f32() * 1000.0 / frames,
+            self.barriers as f32 / frames,
+        );
+        let label = std::mem::take(&mut self.label);
+        *self = Self::new();
+        self.label = label;
+    }
+}
@@ -0,0 +1,464 @@
+//! Полупрозрачные кубы вокруг основного и два способа их смешивания.
+//!
+//! [`TransparencyMode::Sorted`] рисует грани всех экземпляров от дальней
+//! к ближней с обычным [`gpu::BlendState::ALPHA_BLENDING`], так что задние
+//! грани куба ложатся раньше передних. [`TransparencyMode::WeightedOit`] не зависит от порядка:
+//! экземпляры рисуются одним вызовом в две цели (накопление и revealage),
+//! после чего отдельный проход сводит их поверх кадра.
+
+use blade_graphics as gpu;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Quat, Vec3};
+
+use crate::mesh::Mesh;
+use crate::views::ViewRect;
+
+const ACCUM_FORMAT: gpu::TextureFormat = gpu::TextureFormat::Rgba16Float;
+const REVEALAGE_FORMAT: gpu::TextureFormat = gpu::TextureFormat::R16Float;
+/// Кольцо экземпляров: радиус, размер и цвета с прозрачностью.
+const RING_RADIUS: f32 = 1.2;
+const INSTANCE_SCALE: f32 = 0.35;
+const TINTS: &[[f32; 4]] = &[
+    [1.0, 0.2, 0.2, 0.5],
+    [1.0, 0.8, 0.1, 0.4],
+    [0.2, 1.0, 0.3, 0.6],
+    [0.1, 0.8, 1.0, 0.35],
+    [0.3, 0.3, 1.0, 0.55],
+    [1.0, 0.3, 1.0, 0.45],
+];
+/// Цвет основного куба, когда он стеклянный.
+const GLASS_TINT: [f32; 4] = [0.8, 0.9, 1.0, 0.3];
+/// Нормали граней в порядке индексов меша куба, по 6 индексов на грань.
+const FACE_NORMALS: [Vec3; 6] = [
+    Vec3::Z,
+    Vec3::NEG_Z,
+    Vec3::NEG_X,
+    Vec3::X,
+    Vec3::Y,
+    Vec3::NEG_Y,
+];
+const FACE_INDEX_COUNT: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Сортировка экземпляров от дальнего к ближнему для каждого вида.
+    Sorted,
+    /// Weighted blended order-independent transparency.
+    WeightedOit,
+}
+
+impl TransparencyMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Sorted => Self::WeightedOit,
+            Self::WeightedOit => Self::Sorted,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, blade_macros::Vertex)]
+struct TransparentInstance {
+    offset: [f32; 3],
+    scale: f32,
+    tint: [f32; 4],
+    // Поворот вокруг Y в радианах
+    rotation: f32,
+}
+
+impl TransparentInstance {
+    fn face_center(&self, face: usize) -> Vec3 {
+        let rotation = Quat::from_rotation_y(self.rotation);
+        Vec3::from_array(self.offset) + rotation * FACE_NORMALS[face] * (0.5 * self.scale)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable, blade_macros::ShaderStruct)]
+struct Scene {
+    view_projection: [[f32; 4]; 4],
+    light_pos: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+}
+
+#[derive(blade_macros::ShaderData)]
+struct SceneUniforms {
+    scene: Scene,
+}
+
+#[derive(blade_macros::ShaderData)]
+struct CompositeData {
+    accum_texture: gpu::TextureView,
+    revealage_texture: gpu::TextureView,
+}
+
+/// Цели накопления OIT размером с окно.
+struct OitTargets {
+    accum: gpu::Texture,
+    accum_view: gpu::TextureView,
+    revealage: gpu::Texture,
+    revealage_view: gpu::TextureView,
+}
+
+impl OitTargets {
+    fn new(context: &gpu::Context, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        let (accum, accum_view) = Self::create_target(context, "oit_accum", ACCUM_FORMAT, size);
+        let (revealage, revealage_view) =
+            Self::create_target(context, "oit_revealage", REVEALAGE_FORMAT, size);
+        Self {
+            accum,
+            accum_view,
+            revealage,
+            revealage_view,
+        }
+    }
+
+    fn create_target(
+        context: &gpu::Context,
+        name: &str,
+        format: gpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> (gpu::Texture, gpu::TextureView) {
+        let texture = context.create_texture(gpu::TextureDesc {
+            name,
+            format,
+            size: gpu::Extent {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth: 1,
+            },
+            dimension: gpu::TextureDimension::D2,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            usage: gpu::TextureUsage::TARGET | gpu::TextureUsage::RESOURCE,
+            sample_count: 1,
+            external: None,
+        });
+        let view = context.create_texture_view(
+            texture,
+            gpu::TextureViewDesc {
+                name,
+                format,
+                dimension: gpu::ViewDimension::D2,
+                subresources: &gpu::TextureSubresources::default(),
+            },
+        );
+        (texture, view)
+    }
+
+    fn destroy(&self, context: &gpu::Context) {
+        context.destroy_texture_view(self.accum_view);
+        context.destroy_texture(self.accum);
+        context.destroy_texture_view(self.revealage_view);
+        context.destroy_texture(self.revealage);
+    }
+}
+
+pub struct Transparency {
+    pub mode: TransparencyMode,
+    sorted_pipeline: gpu::RenderPipeline,
+    oit_pipeline: gpu::RenderPipeline,
+    composite_pipeline: gpu::RenderPipeline,
+    targets: OitTargets,
+    // Данные экземпляров пишутся заново каждый кадр
+    belt: blade_util::BufferBelt,
+    instances: Vec<TransparentInstance>,
+    light_pos: Vec3,
+}
+
+impl Transparency {
+    pub fn new(
+        context: &gpu::Context,
+        color_format: gpu::TextureFormat,
+        depth_format: gpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self {
+        let source = include_str!("transparent.wgsl").to_string();
+        let shader = context.create_shader(gpu::ShaderDesc {
+            source: &source,
+            ..Default::default()
+        });
+        let scene_layout = <SceneUniforms as gpu::ShaderData>::layout();
+        let composite_layout = <CompositeData as gpu::ShaderData>::layout();
+        let vertex_fetches = [
+            gpu::VertexFetchState {
+                layout: &<crate::CubeVertex as gpu::Vertex>::layout(),
+                instanced: false,
+            },
+            gpu::VertexFetchState {
+                layout: &<TransparentInstance as gpu::Vertex>::layout(),
+                instanced: true,
+            },
+        ];
+        // Прозрачные объекты проверяют глубину непрозрачных, но не пишут её
+        let depth_stencil = gpu::DepthStencilState {
+            format: depth_format,
+            depth_write_enabled: false,
+            depth_compare: gpu::CompareFunction::Less,
+            stencil: gpu::StencilState::default(),
+            bias: gpu::DepthBiasState::default(),
+        };
+
+        // Порядок задаёт сортировка граней, поэтому видны обе стороны
+        let sorted_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "transparent_sorted",
+            data_layouts: &[&scene_layout],
+            vertex: shader.at("vs_transparent"),
+            vertex_fetches: &vertex_fetches,
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_stencil.clone()),
+            fragment: Some(shader.at("fs_sorted")),
+            color_targets: &[gpu::ColorTargetState {
+                format: color_format,
+                blend: Some(gpu::BlendState::ALPHA_BLENDING),
+                write_mask: gpu::ColorWrites::ALL,
+            }],
+            multisample_state: gpu::MultisampleState::default(),
+        });
+
+        // revealage *= (1 - alpha) для каждого фрагмента
+        let revealage_component = gpu::BlendComponent {
+            src_factor: gpu::BlendFactor::Zero,
+            dst_factor: gpu::BlendFactor::OneMinusSrc,
+            operation: gpu::BlendOperation::Add,
+        };
+        let oit_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "transparent_oit",
+            data_layouts: &[&scene_layout],
+            vertex: shader.at("vs_transparent"),
+            vertex_fetches: &vertex_fetches,
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                front_face: gpu::FrontFace::Ccw,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth_stencil),
+            fragment: Some(shader.at("fs_oit")),
+            color_targets: &[
+                gpu::ColorTargetState {
+                    format: ACCUM_FORMAT,
+                    blend: Some(gpu::BlendState::ADDITIVE),
+                    write_mask: gpu::ColorWrites::ALL,
+                },
+                gpu::ColorTargetState {
+                    format: REVEALAGE_FORMAT,
+                    blend: Some(gpu::BlendState {
+                        color: revealage_component,
+                        alpha: revealage_component,
+                    }),
+                    write_mask: gpu::ColorWrites::ALL,
+                },
+            ],
+            multisample_state: gpu::MultisampleState::default(),
+        });
+
+        let composite_pipeline = context.create_render_pipeline(gpu::RenderPipelineDesc {
+            name: "oit_composite",
+            data_layouts: &[&composite_layout],
+            vertex: shader.at("vs_composite"),
+            vertex_fetches: &[],
+            primitive: gpu::PrimitiveState {
+                topology: gpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            fragment: Some(shader.at("fs_composite")),
+            color_targets: &[gpu::ColorTargetState {
+                format: color_format,
+                blend: Some(gpu::BlendState::ALPHA_BLENDING),
+                write_mask: gpu::ColorWrites::ALL,
+            }],
+            multisample_state: gpu::MultisampleState::default(),
+        });
+
+        Self {
+            mode: TransparencyMode::Sorted,
+            sorted_pipeline,
+            oit_pipeline,
+            composite_pipeline,
+            targets: OitTargets::new(context, size),
+            belt: blade_util::BufferBelt::new(blade_util::BufferBeltDescriptor {
+                memory: gpu::Memory::Shared,
+                min_chunk_size: 0x1000,
+                alignment: std::mem::size_of::<TransparentInstance>() as u64,
+            }),
+            instances: Vec::new(),
+            light_pos: Vec3::ZERO,
+        }
+    }
+
+    pub fn resize(&mut self, context: &gpu::Context, size: winit::dpi::PhysicalSize<u32>) {
+        self.targets.destroy(context);
+        self.targets = OitTargets::new(context, size);
+    }
+
+    /// Обновляет положение кольца экземпляров и источника света на текущий кадр.
+    ///
+    /// `glass_cube` - поворот основного куба, если он рисуется полупрозрачным
+    /// вместе с кольцом.
+    pub fn update(&mut self, elapsed: f32, light_pos: Vec3, glass_cube: Option<f32>) {
+        let step = std::f32::consts::TAU / TINTS.len() as f32;
+        self.instances = TINTS
+            .iter()
+            .enumerate()
+            .map(|(i, &tint)| {
+                let angle = i as f32 * step - elapsed * 0.3;
+                TransparentInstance {
+                    offset: [RING_RADIUS * angle.cos(), 0.0, RING_RADIUS * angle.sin()],
+                    scale: INSTANCE_SCALE,
+                    tint,
+                    rotation: 0.0,
+                }
+            })
+            .chain(glass_cube.map(|rotation| TransparentInstance {
+                offset: [0.0; 3],
+                scale: 1.0,
+                tint: GLASS_TINT,
+                rotation,
+            }))
+            .collect();
+        self.light_pos = light_pos;
+    }
+
+    fn uniforms(&self, view_projection: Mat4) -> SceneUniforms {
+        SceneUniforms {
+            scene: Scene {
+                view_projection: view_projection.to_cols_array_2d(),
+                light_pos: self.light_pos.extend(1.0).to_array(),
+                light_color: [1.5, 1.5, 1.5, 1.0],
+                ambient: [0.2, 0.2, 0.2, 1.0],
+            },
+        }
+    }
+
+    /// Рисует грани всех экземпляров от дальней к ближней в уже начатый проход
+    /// с текущими областью вида и трафаретом.
+    ///
+    /// Кубы выпуклые и не пересекаются, так что порядка центров граней достаточно.
+    pub fn draw_sorted(
+        &mut self,
+        context: &gpu::Context,
+        pass: &mut gpu::RenderCommandEncoder,
+        mesh: &Mesh,
+        view_projection: Mat4,
+    ) {
+        let mut faces = self
+            .instances
+            .iter()
+            .enumerate()
+            .flat_map(|(index, instance)| {
+                (0..FACE_NORMALS.len()).map(move |face| {
+                    let depth = view_projection.project_point3(instance.face_center(face)).z;
+                    (depth, index as u64, face as u32)
+                })
+            })
+            .collect::<Vec<_>>();
+        faces.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let uniforms = self.uniforms(view_projection);
+        let instance_buf = self.belt.alloc_pod(&self.instances, context);
+        let stride = std::mem::size_of::<TransparentInstance>() as u64;
+        let mut pc = pass.with(&self.sorted_pipeline);
+        pc.bind(0, &uniforms);
+        for (_, index, face) in faces {
+            pc.bind_vertex(
+                1,
+                instance_buf.buffer.at(instance_buf.offset + index * stride),
+            );
+            mesh.draw_indices(
+                &mut pc,
+                face * FACE_INDEX_COUNT..(face + 1) * FACE_INDEX_COUNT,
+                1,
+            );
+        }
+    }
+
+    /// Накопление OIT для всех видов поверх глубины непрозрачной сцены.
+    pub fn render_oit(
+        &mut self,
+        context: &gpu::Context,
+        encoder: &mut gpu::CommandEncoder,
+        mesh: &Mesh,
+        depth_view: gpu::TextureView,
+        views: &[(ViewRect, Mat4)],
+    ) {
+        encoder.init_texture(self.targets.accum);
+        encoder.init_texture(self.targets.revealage);
+        let instance_buf = self.belt.alloc_pod(&self.instances, context);
+
+        let mut pass = encoder.render(
+            "oit_accumulate",
+            gpu::RenderTargetSet {
+                colors: &[
+                    gpu::RenderTarget {
+                        view: self.targets.accum_view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::TransparentBlack),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                    gpu::RenderTarget {
+                        view: self.targets.revealage_view,
+                        init_op: gpu::InitOp::Clear(gpu::TextureColor::White),
+                        finish_op: gpu::FinishOp::Store,
+                    },
+                ],
+                depth_stencil: Some(gpu::RenderTarget {
+                    view: depth_view,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }),
+            },
+        );
+        for &(rect, view_projection) in views.iter() {
+            pass.set_viewport(&rect.viewport());
+            pass.set_scissor_rect(&rect.scissor());
+            let mut pc = pass.with(&self.oit_pipeline);
+            pc.bind(0, &self.uniforms(view_projection));
+            pc.bind_vertex(1, instance_buf);
+            mesh.draw(&mut pc, self.instances.len() as u32);
+        }
+    }
+
+    /// Сводит цели OIT поверх `target`.
+    pub fn composite(&self, encoder: &mut gpu::CommandEncoder, target: gpu::TextureView) {
+        let mut pass = encoder.render(
+            "oit_composite",
+            gpu::RenderTargetSet {
+                colors: &[gpu::RenderTarget {
+                    view: target,
+                    init_op: gpu::InitOp::Load,
+                    finish_op: gpu::FinishOp::Store,
+                }],
+                depth_stencil: None,
+            },
+        );
+        let mut pc = pass.with(&self.composite_pipeline);
+        pc.bind(
+            0,
+            &CompositeData {
+                accum_texture: self.targets.accum_view,
+                revealage_texture: self.targets.revealage_view,
+            },
+        );
+        pc.draw(0, 3, 0, 1);
+    }
+
+    /// Освобождает память экземпляров после завершения кадра на GPU.
+    pub fn flush(&mut self, sync_point: &gpu::SyncPoint) {
+        self.belt.flush(sync_point);
+    }
+
+    pub fn destroy(&mut self, context: &gpu::Context) {
+        context.destroy_render_pipeline(&mut self.sorted_pipeline);
+        context.destroy_render_pipeline(&mut self.oit_pipeline);
+        context.destroy_render_pipeline(&mut self.composite_pipeline);
+        self.targets.destroy(context);
+        self.belt.destroy(context);
+    }
+}
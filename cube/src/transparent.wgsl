@@ -0,0 +1,100 @@
+struct Scene {
+    view_projection: mat4x4<f32>,
+    light_pos: vec4<f32>,
+    light_color: vec4<f32>,
+    ambient: vec4<f32>,
+};
+
+var<uniform> scene: Scene;
+
+struct VertexInput {
+    pos: vec3<f32>,
+    normal: vec3<f32>,
+};
+
+// Данные экземпляра из второго (instanced) вершинного буфера
+struct InstanceInput {
+    offset: vec3<f32>,
+    scale: f32,
+    tint: vec4<f32>,
+    rotation: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_pos: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) tint: vec4<f32>,
+};
+
+@vertex
+fn vs_transparent(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    // Поворот вокруг Y, как у матрицы модели основного куба
+    let c = cos(instance.rotation);
+    let s = sin(instance.rotation);
+    let rotation = mat3x3<f32>(vec3<f32>(c, 0.0, -s), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(s, 0.0, c));
+    let world_pos = instance.offset + rotation * vertex.pos * instance.scale;
+    var out: VertexOutput;
+    out.position = scene.view_projection * vec4<f32>(world_pos, 1.0);
+    out.world_pos = world_pos;
+    out.world_normal = rotation * vertex.normal;
+    out.tint = instance.tint;
+    return out;
+}
+
+// Диффузное освещение; задние грани освещаются с развёрнутой нормалью
+fn shade(input: VertexOutput, front_facing: bool) -> vec4<f32> {
+    var normal = normalize(input.world_normal);
+    if !front_facing {
+        normal = -normal;
+    }
+    let light_dir = normalize(scene.light_pos.xyz - input.world_pos);
+    let diffuse = max(dot(normal, light_dir), 0.0) * scene.light_color.rgb;
+    return vec4<f32>(input.tint.rgb * (scene.ambient.rgb + diffuse), input.tint.a);
+}
+
+// Отсортированный путь: обычное смешивание по альфе
+@fragment
+fn fs_sorted(input: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    return shade(input, front_facing);
+}
+
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: vec4<f32>,
+};
+
+// Weighted blended OIT (McGuire, Bavoil 2013): накопление взвешенного
+// цвета и произведение (1 - alpha) в отдельной цели
+@fragment
+fn fs_oit(input: VertexOutput, @builtin(front_facing) front_facing: bool) -> OitOutput {
+    let color = shade(input, front_facing);
+    let depth = input.position.z;
+    let weight = clamp(color.a * max(1e-2, 3e3 * pow(1.0 - depth, 3.0)), 1e-2, 3e3);
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = vec4<f32>(color.a);
+    return out;
+}
+
+var accum_texture: texture_2d<f32>;
+var revealage_texture: texture_2d<f32>;
+
+// Треугольник на весь экран без вершинного буфера
+@vertex
+fn vs_composite(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    let revealage = textureLoad(revealage_texture, texel, 0).r;
+    if revealage >= 1.0 {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, texel, 0);
+    let average = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+    return vec4<f32>(average, 1.0 - revealage);
+}
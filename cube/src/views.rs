@@ -0,0 +1,231 @@
+//! Раскладка окна на области просмотра и камеры для каждой из них.
+//!
+//! В режиме [`Layout::Quad`] окно делится на четыре квадранта, как в
+//! DCC-редакторах: ортографические виды сверху, спереди и сбоку плюс
+//! перспективная камера. Ввод мыши направляется в квадрант под курсором.
+
+use blade_graphics as gpu;
+use glam::{Mat4, Vec2, Vec3};
+
+/// Расстояние от центра сцены до ортографических камер.
+const ORTHO_DISTANCE: f32 = 10.0;
+/// Чувствительность вращения перспективной камеры (радиан на пиксель).
+const ORBIT_SPEED: f32 = 0.01;
+/// Множитель масштаба на один шаг колеса мыши.
+const ZOOM_STEP: f32 = 0.9;
+
+/// Камера, которая рисуется в отдельной области окна.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewKind {
+    /// Ортографический вид сверху (вдоль -Y).
+    Top,
+    /// Ортографический вид спереди (вдоль -Z).
+    Front,
+    /// Ортографический вид сбоку (вдоль -X).
+    Side,
+    /// Перспективная камера, вращающаяся вокруг центра сцены.
+    Perspective,
+}
+
+impl ViewKind {
+    /// Индекс ортографического вида в [`Cameras`], если вид ортографический.
+    fn ortho_index(self) -> Option<usize> {
+        match self {
+            Self::Top => Some(0),
+            Self::Front => Some(1),
+            Self::Side => Some(2),
+            Self::Perspective => None,
+        }
+    }
+}
+
+/// Прямоугольная область окна в пикселях (начало координат — левый верхний угол).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl ViewRect {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x as f64
+            && y >= self.y as f64
+            && x < (self.x + self.w) as f64
+            && y < (self.y + self.h) as f64
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.w as f32 / self.h.max(1) as f32
+    }
+
+    pub fn viewport(&self) -> gpu::Viewport {
+        gpu::Viewport {
+            x: self.x as f32,
+            y: self.y as f32,
+            w: self.w as f32,
+            h: self.h as f32,
+            depth: 0.0..1.0,
+        }
+    }
+
+    pub fn scissor(&self) -> gpu::ScissorRect {
+        gpu::ScissorRect {
+            x: self.x as i32,
+            y: self.y as i32,
+            w: self.w,
+            h: self.h,
+        }
+    }
+}
+
+/// Раскладка окна на области просмотра.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Одна перспективная камера на всё окно.
+    Single,
+    /// Четыре квадранта: сверху, спереди, сбоку и перспектива.
+    Quad,
+}
+
+impl Layout {
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Single => Self::Quad,
+            Self::Quad => Self::Single,
+        }
+    }
+
+    /// Возвращает виды и их области для окна заданного размера.
+    pub fn views(self, size: winit::dpi::PhysicalSize<u32>) -> Vec<(ViewKind, ViewRect)> {
+        match self {
+            Self::Single => vec![(
+                ViewKind::Perspective,
+                ViewRect {
+                    x: 0,
+                    y: 0,
+                    w: size.width,
+                    h: size.height,
+                },
+            )],
+            Self::Quad => {
+                // Нечётный остаток пикселя достаётся правым и нижним квадрантам
+                let left = size.width / 2;
+                let top = size.height / 2;
+                let right = size.width - left;
+                let bottom = size.height - top;
+                vec![
+                    (ViewKind::Top, ViewRect { x: 0, y: 0, w: left, h: top }),
+                    (ViewKind::Front, ViewRect { x: left, y: 0, w: right, h: top }),
+                    (ViewKind::Side, ViewRect { x: 0, y: top, w: left, h: bottom }),
+                    (
+                        ViewKind::Perspective,
+                        ViewRect { x: left, y: top, w: right, h: bottom },
+                    ),
+                ]
+            }
+        }
+    }
+
+    /// Находит вид под курсором.
+    pub fn view_at(
+        self,
+        size: winit::dpi::PhysicalSize<u32>,
+        x: f64,
+        y: f64,
+    ) -> Option<(ViewKind, ViewRect)> {
+        self.views(size)
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x, y))
+    }
+}
+
+/// Параметры всех камер: орбита перспективной камеры,
+/// масштаб и сдвиг каждого ортографического вида.
+#[derive(Clone, Debug)]
+pub struct Cameras {
+    orbit_yaw: f32,
+    orbit_pitch: f32,
+    orbit_distance: f32,
+    ortho_half_height: [f32; 3],
+    ortho_pan: [Vec2; 3],
+}
+
+impl Cameras {
+    /// Создаёт камеры, где перспективная смотрит на центр сцены из `eye`.
+    pub fn new(eye: Vec3) -> Self {
+        let orbit_distance = eye.length();
+        Self {
+            orbit_yaw: eye.x.atan2(eye.z),
+            orbit_pitch: (eye.y / orbit_distance).asin(),
+            orbit_distance,
+            ortho_half_height: [1.5; 3],
+            ortho_pan: [Vec2::ZERO; 3],
+        }
+    }
+
+    /// Позиция перспективной камеры.
+    pub fn eye(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.orbit_yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.orbit_pitch.sin_cos();
+        self.orbit_distance * Vec3::new(cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw)
+    }
+
+    /// Матрица вида-проекции для вида с заданным соотношением сторон.
+    pub fn view_projection(&self, kind: ViewKind, aspect: f32) -> Mat4 {
+        let (dir, up) = match kind {
+            ViewKind::Top => (Vec3::Y, Vec3::NEG_Z),
+            ViewKind::Front => (Vec3::Z, Vec3::Y),
+            ViewKind::Side => (Vec3::X, Vec3::Y),
+            ViewKind::Perspective => {
+                let projection = Mat4::perspective_rh(45.0_f32.to_radians(), aspect, 0.1, 100.0);
+                let view = Mat4::look_at_rh(self.eye(), Vec3::ZERO, Vec3::Y);
+                return projection * view;
+            }
+        };
+        let index = kind.ortho_index().unwrap();
+        let half_h = self.ortho_half_height[index];
+        let half_w = half_h * aspect;
+        let pan = self.ortho_pan[index];
+        let projection = Mat4::orthographic_rh(
+            pan.x - half_w,
+            pan.x + half_w,
+            pan.y - half_h,
+            pan.y + half_h,
+            0.1,
+            2.0 * ORTHO_DISTANCE,
+        );
+        let view = Mat4::look_at_rh(dir * ORTHO_DISTANCE, Vec3::ZERO, up);
+        projection * view
+    }
+
+    /// Перетаскивание мышью: вращает перспективную камеру
+    /// или сдвигает ортографический вид вслед за курсором.
+    pub fn drag(&mut self, kind: ViewKind, rect: ViewRect, delta: Vec2) {
+        match kind.ortho_index() {
+            Some(index) => {
+                let world_per_pixel = 2.0 * self.ortho_half_height[index] / rect.h.max(1) as f32;
+                self.ortho_pan[index] += Vec2::new(-delta.x, delta.y) * world_per_pixel;
+            }
+            None => {
+                let limit = 89.0_f32.to_radians();
+                self.orbit_yaw -= delta.x * ORBIT_SPEED;
+                self.orbit_pitch = (self.orbit_pitch + delta.y * ORBIT_SPEED).clamp(-limit, limit);
+            }
+        }
+    }
+
+    /// Приближение (положительные шаги) или отдаление вида колесом мыши.
+    pub fn zoom(&mut self, kind: ViewKind, steps: f32) {
+        let factor = ZOOM_STEP.powf(steps);
+        match kind.ortho_index() {
+            Some(index) => {
+                self.ortho_half_height[index] = (self.ortho_half_height[index] * factor).clamp(0.1, 50.0);
+            }
+            None => {
+                self.orbit_distance = (self.orbit_distance * factor).clamp(0.5, 50.0);
+            }
+        }
+    }
+}